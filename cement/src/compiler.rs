@@ -1,23 +1,29 @@
 use std::fs::{self, File};
-use std::io::Write;
+use std::io::{BufReader, BufWriter, Write};
 use std::panic::Location;
-use std::path::{absolute, PathBuf};
+use std::path::{absolute, Path, PathBuf};
 
 use irony_cmt::{
-  Assign, CmtIR, EntityEnum, EntityId, Environ, HwInput, HwInstance, HwModule, HwOutput,
-  OpEnum, OpId, PassEnum, PassManagerTrait, Region, RegionId, RemoveEventPass,
-  RemoveSelectPass, RemoveUnaryPass, ReorderPass,
+  emit_firrtl, emit_sv_modules, emit_yosys_json, sv_source_map, Assign, BoolAttr, CmtIR,
+  Diagnostic, EntityId, Environ, FlattenPass, HwInput, HwInstance, HwModule, HwOutput,
+  IronyError, OpEnum, OpId, PassEnum, PassManagerTrait, PassPipeline, PassStatistics,
+  Region, RegionId, SnapshotFormat, SymbolTable,
 };
 
 use crate::gir;
-use crate::hcl::Interface;
+pub use crate::gir::conflict::{ConflictKind, ConflictReport, EventConflict};
+pub use crate::gir::latency::{Cycles, Latency, LatencyReport, SynthLatency};
+pub use crate::gir::pipeline::{parse_pipeline, GirPass, GirPassStat};
+pub use crate::gir::schedule::{BranchSchedule, ParSchedule, ScheduleReport};
+pub use crate::gir::timing::{
+  EventTiming, SelectTiming, SynthTiming, TimingAssertion, TimingReport, Window,
+  WireTiming,
+};
+use crate::hcl::{Interface, LatencyRange};
 
 mod module_stack;
 use module_stack::*;
 
-mod symbol_table;
-use symbol_table::*;
-
 mod tcl;
 pub use tcl::*;
 
@@ -45,8 +51,38 @@ pub struct Cmtc {
   pub module_stack: ModuleStack,
 
   pub ip_tcls: TclTable,
+
+  pub latency_expects: Vec<(EntityId, LatencyRange, Location<'static>)>,
+
+  /// Errors of statements lowered otherwise than requested, e.g. loops which can't be
+  /// unrolled, returned by `elaborate`
+  pub stmt_errors: Vec<IronyError>,
+
+  /// `if` statements whose branches are padded to the same latency
+  pub balanced_ifs: Vec<EntityId>,
+
+  /// Resources used by events, declared with `Event::uses_resource`
+  pub event_resources: Vec<(EntityId, String)>,
+
+  /// Statistics of the gir passes of the last elaboration
+  pub gir_pass_stats: Vec<GirPassStat>,
+
+  /// Statistics of the ir passes run so far
+  pub ir_pass_stats: Vec<PassStatistics>,
+
+  /// Modules inlined into the modules instantiating them, marked with
+  /// `Cmtc::inline_module`
+  pub inline_modules: Vec<OpId>,
 }
 
+/// IR passes lowering an elaborated design to the `hw`, `comb` and `seq` dialects, and
+/// removing the dead code left by the lowering. `CmtcConfig::ir_passes` run before `dce`
+pub const LOWERING_PIPELINE: &str = "reorder,remove-event,remove-select,remove-unary,dce";
+
+/// IR passes simplifying a design after flattening, run by `elaborate` if modules are
+/// inlined
+pub const FLATTEN_CLEANUP_PIPELINE: &str = "canonicalize,cse,dce";
+
 impl Cmtc {
   pub fn new(config: CmtcConfig) -> Self {
     Cmtc {
@@ -55,6 +91,13 @@ impl Cmtc {
       symbol_table: SymbolTable::default(),
       module_stack: ModuleStack::default(),
       ip_tcls: TclTable::default(),
+      latency_expects: Vec::new(),
+      stmt_errors: Vec::new(),
+      balanced_ifs: Vec::new(),
+      event_resources: Vec::new(),
+      gir_pass_stats: Vec::new(),
+      ir_pass_stats: Vec::new(),
+      inline_modules: Vec::new(),
     }
   }
 
@@ -71,64 +114,120 @@ impl Cmtc {
     self.module_stack.current_module()
   }
 
-  fn run_reorder_passes(&mut self) {
-    let mut pass_manager = irony_cmt::PassManager::default();
-    let start_ops = self.module_op_id_iter().collect::<Vec<_>>();
-    pass_manager.add_passes(vec![PassEnum::ReorderPass(ReorderPass)], vec![start_ops]);
-    pass_manager.run_passes(&mut self.ir).expect("must run passes successfully");
+  /// Run a pipeline of ir passes on every module, like `reorder,remove-event`
+  pub fn run_passes(&mut self, pipeline: &str) -> Result<(), IronyError> {
+    self.run_pipeline(PassPipeline::parse(pipeline)?)
   }
 
-  fn run_passes(&mut self, passes: Vec<PassEnum>, start_ops: Vec<Vec<OpId>>) {
+  /// `LOWERING_PIPELINE`, with the passes of `config.ir_passes` before `dce`
+  fn lowering_pipeline(&self) -> Result<PassPipeline<PassEnum>, IronyError> {
+    let mut pipeline = PassPipeline::parse(LOWERING_PIPELINE)?;
+    let dce = pipeline.0.pop();
+    pipeline.0.extend(PassPipeline::parse(&self.config.ir_passes)?.0);
+    pipeline.0.extend(dce);
+    Ok(pipeline)
+  }
+
+  fn run_pipeline(&mut self, pipeline: PassPipeline<PassEnum>) -> Result<(), IronyError> {
     let mut pass_manager = irony_cmt::PassManager::default();
-    pass_manager.add_passes(passes, start_ops);
-    pass_manager.run_passes(&mut self.ir).expect("must run passes successfully");
+    pass_manager.add_pipeline(pipeline);
+    pass_manager.dump_dir = self.config.dump_ir_dir.to_owned();
+    let result = pass_manager.run_passes(&mut self.ir);
+    for stat in pass_manager.statistics() {
+      if self.config.ir_stats {
+        eprintln!("{}", stat);
+      }
+      self.ir_pass_stats.push(stat.to_owned());
+    }
+    result?;
+    if self.config.verify_ir {
+      if let Err(diagnostics) = self.verify() {
+        let message =
+          diagnostics.iter().map(|x| x.to_string()).collect::<Vec<_>>().join("\n");
+        return Err(
+          IronyError::new(format!("ir verification failed:\n{}", message))
+            .with_op(diagnostics[0].op)
+            .with_pass("verify"),
+        );
+      }
+    }
+    Ok(())
+  }
+
+  /// Mark the module being defined to be inlined into the modules instantiating it
+  /// by `elaborate`
+  pub fn inline_module(&mut self) {
+    let module = self.get_current_module_ip().expect("must be called in a module");
+    self.inline_modules.push(module);
+  }
+
+  /// The flatten pass for `config.flatten`, `config.flatten_max_ops` and the modules
+  /// marked with `Cmtc::inline_module`, if any module is to be inlined
+  fn flatten_pass(&self) -> Option<FlattenPass> {
+    if self.config.flatten {
+      Some(FlattenPass::default())
+    } else if self.config.flatten_max_ops.is_some() || !self.inline_modules.is_empty() {
+      Some(FlattenPass {
+        modules: Some(self.inline_modules.to_owned()),
+        max_ops: self.config.flatten_max_ops,
+      })
+    } else {
+      None
+    }
   }
 
-  pub fn print(&mut self) {
-    self.run_reorder_passes();
+  /// Check the constraints of every op in the IR
+  pub fn verify(&self) -> Result<(), Vec<Diagnostic>> { self.ir.verify() }
+
+  pub fn print(&mut self) -> Result<(), IronyError> {
+    self.run_passes("reorder")?;
     self
       .module_op_id_iter()
       .for_each(|module_op_id| println!("{}", self.ir.print_op(module_op_id)));
+    Ok(())
   }
 
-  pub fn print_with_passes(&mut self, passes: Vec<PassEnum>, start_ops: Vec<Vec<OpId>>) {
-    self.run_passes(passes, start_ops);
+  pub fn print_with_passes(&mut self, pipeline: &str) -> Result<(), IronyError> {
+    self.run_passes(pipeline)?;
     self
       .module_op_id_iter()
       .for_each(|module_op_id| println!("{}", self.ir.print_op(module_op_id)));
+    Ok(())
   }
 
-  pub fn print_common(&mut self) {
-    self.run_passes(
-      vec![ReorderPass.into(), RemoveEventPass.into(), RemoveSelectPass.into()],
-      vec![
-        self.module_op_id_iter().collect(),
-        self.module_op_id_iter().collect(),
-        self.module_op_id_iter().collect(),
-      ],
-    );
+  pub fn print_common(&mut self) -> Result<(), IronyError> {
+    self.run_passes("reorder,remove-event,remove-select")?;
     self
       .module_op_id_iter()
       .for_each(|module_op_id| println!("{}", self.ir.print_op(module_op_id)));
+    Ok(())
   }
 
-  pub fn elaborate(&mut self) {
-    self.run_gir_passes();
-
-    self.run_passes(
-      vec![
-        ReorderPass.into(),
-        RemoveEventPass.into(),
-        RemoveSelectPass.into(),
-        RemoveUnaryPass.into(),
-      ],
-      vec![
-        self.module_op_id_iter().collect(),
-        self.module_op_id_iter().collect(),
-        self.module_op_id_iter().collect(),
-        self.module_op_id_iter().collect(),
-      ],
-    );
+  pub fn elaborate(&mut self) -> Result<(), IronyError> {
+    if let Some(err) = self.stmt_errors.first() {
+      return Err(err.to_owned());
+    }
+    gir::latency::check_latency_expects(self)?;
+    if self.config.warn_conflicts || self.config.onehot {
+      let conflicts = self.conflict_report();
+      if self.config.warn_conflicts {
+        for conflict in conflicts.simultaneous() {
+          eprintln!("warning: {}", conflict);
+        }
+      }
+      if self.config.onehot {
+        gir::conflict::add_onehot_checks(self, &conflicts)?;
+      }
+    }
+    self.run_gir_passes()?;
+
+    let mut pipeline = self.lowering_pipeline()?;
+    if let Some(flatten) = self.flatten_pass() {
+      // the inlined ports are connected by wires, and the inlined modules are dead
+      pipeline.0.push(flatten.into());
+      pipeline.0.extend(PassPipeline::parse(FLATTEN_CLEANUP_PIPELINE)?.0);
+    }
+    self.run_pipeline(pipeline)
   }
 
   fn clean_workspace(&mut self) {
@@ -143,42 +242,100 @@ impl Cmtc {
     }
   }
 
-  pub fn print_to_file(&mut self) -> PathBuf {
-    self.run_passes(
-      vec![
-        ReorderPass.into(),
-        RemoveEventPass.into(),
-        RemoveSelectPass.into(),
-        RemoveUnaryPass.into(),
-      ],
-      vec![
-        self.module_op_id_iter().collect(),
-        self.module_op_id_iter().collect(),
-        self.module_op_id_iter().collect(),
-        self.module_op_id_iter().collect(),
-      ],
-    );
-    let path_dir = self.config.workspace_path();
-    fs::create_dir_all(path_dir.to_owned()).expect("must create the target directory");
+  pub fn print_to_file(&mut self) -> Result<PathBuf, IronyError> {
+    self.run_pipeline(self.lowering_pipeline()?)?;
+    let path_dir = absolute_dir(&self.config.workspace_path())?;
+    println!("generate circt code to {}", path_dir.to_str().unwrap());
 
-    println!(
-      "generate circt code to {}",
-      absolute(path_dir.to_owned()).expect("convert absolute path").to_str().unwrap()
-    );
-
-    let file_path =
-      absolute(path_dir.to_owned().join("modules.mlir")).expect("convert absolute path");
-    let mut file =
-      File::create(file_path.to_owned()).expect("must create target file for modules");
+    let file_path = path_dir.join("modules.mlir");
+    let mut text = String::new();
     for module_op_id in self.module_op_id_iter() {
-      writeln!(file, "{}", self.ir.print_op(module_op_id))
-        .expect("must write to target file");
+      text.push_str(&format!("{}\n", self.ir.print_op(module_op_id)));
+    }
+    write_file(&file_path, text)?;
+    Ok(file_path)
+  }
+
+  fn generate_verilog_to_files(&mut self) -> Result<(), IronyError> {
+    match self.config.sv_backend {
+      SvBackend::Native => self.emit_verilog_to_files(),
+      SvBackend::CirctOpt => self.export_verilog_with_circt(),
+    }
+  }
+
+  /// Write every module but extern ones to `<module>.sv` in the workspace with the
+  /// built-in emitter, and the source locations of their lines to `source_map.json`
+  fn emit_verilog_to_files(&mut self) -> Result<(), IronyError> {
+    self.run_pipeline(self.lowering_pipeline()?)?;
+    let path_dir = absolute_dir(&self.config.workspace_path())?;
+    println!("generate SystemVerilog to {}", path_dir.to_str().unwrap());
+
+    let modules = emit_sv_modules(&self.ir)?;
+    for module in modules.iter() {
+      write_file(&path_dir.join(format!("{}.sv", module.name)), &module.text)?;
     }
-    file_path
+    write_file(&path_dir.join("source_map.json"), sv_source_map(&modules))
+  }
+
+  /// Write the hierarchy of every top module to `<module>.fir` in the workspace as a
+  /// FIRRTL circuit, to be used by Chisel-ecosystem tools
+  pub fn generate_firrtl(&mut self) -> Result<Vec<PathBuf>, IronyError> {
+    self.run_pipeline(self.lowering_pipeline()?)?;
+    let path_dir = self.config.workspace_path();
+    create_dir(&path_dir)?;
+
+    let mut paths = vec![];
+    for module_op_id in self.module_op_id_iter().collect::<Vec<_>>() {
+      let OpEnum::HwModule(module) = self.ir.get_op(module_op_id) else { unreachable!() };
+      if module.top != Some(BoolAttr(true)) || module.is_extern == Some(BoolAttr(true)) {
+        continue;
+      }
+      let path = path_dir.join(format!("{}.fir", module.name.as_ref().unwrap().0));
+      write_file(&path, emit_firrtl(&self.ir, module_op_id)?)?;
+      paths.push(path);
+    }
+    Ok(paths)
+  }
+
+  /// Write every module to `modules.json` in the workspace as a Yosys JSON netlist,
+  /// for Yosys-based lint, netlistsvg and place and route. The workspace also holds
+  /// the SystemVerilog files, and `modules.mlir` only with `SvBackend::CirctOpt`
+  pub fn generate_yosys_json(&mut self) -> Result<PathBuf, IronyError> {
+    self.run_pipeline(self.lowering_pipeline()?)?;
+    let path_dir = self.config.workspace_path();
+    create_dir(&path_dir)?;
+
+    let file_path = path_dir.join("modules.json");
+    write_file(&file_path, emit_yosys_json(&self.ir)?)?;
+    Ok(file_path)
+  }
+
+  /// Write the IR to `path` as a snapshot keeping the ids of its ops, entities and
+  /// regions: JSON for `.json` files to be exchanged, compact binary otherwise
+  pub fn save_ir<P: AsRef<Path>>(&self, path: P) -> Result<(), IronyError> {
+    let path = path.as_ref();
+    let file = File::create(path).map_err(|err| {
+      IronyError::new(format!("can't create {}: {}", path.display(), err))
+    })?;
+    self.ir.save_snapshot(BufWriter::new(file), SnapshotFormat::of_path(path))
   }
 
-  fn generate_verilog_to_files(&mut self) {
-    let mlir_file_path = self.print_to_file();
+  /// Replace the IR with a snapshot written by `save_ir`, to reuse an elaborated
+  /// design without running its generators again; the state of the compiler outside
+  /// the IR, like the IPs to generate, isn't part of the snapshot
+  pub fn load_ir<P: AsRef<Path>>(&mut self, path: P) -> Result<(), IronyError> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|err| {
+      IronyError::new(format!("can't open {}: {}", path.display(), err))
+    })?;
+    let mut ir = CmtIR::new();
+    ir.load_snapshot(BufReader::new(file), SnapshotFormat::of_path(path))?;
+    self.ir = ir;
+    Ok(())
+  }
+
+  fn export_verilog_with_circt(&mut self) -> Result<(), IronyError> {
+    let mlir_file_path = self.print_to_file()?;
     let file_dir = mlir_file_path.parent().unwrap();
     let mut command = std::process::Command::new(self.config.circt_opt.to_owned());
 
@@ -189,10 +346,16 @@ impl Cmtc {
     command.arg(lower_seq).arg(export_verilog).arg(mlir_file_path.to_str().unwrap());
 
     println!("{:?}", command);
-    let output = command.output().expect("must run circt-opt");
+    let output = command.output().map_err(|err| {
+      IronyError::new(format!("can't run {}: {}", self.config.circt_opt.display(), err))
+    })?;
     if !output.status.success() {
-      panic!("circt-opt failed");
+      return Err(IronyError::new(format!(
+        "circt-opt failed: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+      )));
     }
+    Ok(())
   }
 
   fn generate_ip_tcl(&mut self) {
@@ -244,24 +407,70 @@ impl Cmtc {
     // TODO: Fill this!
   }
 
-  pub fn generate_workspace(&mut self) {
+  pub fn generate_workspace(&mut self) -> Result<(), IronyError> {
     self.clean_workspace();
-    self.generate_verilog_to_files();
+    self.generate_verilog_to_files()?;
+    if self.config.yosys_json {
+      self.generate_yosys_json()?;
+    }
     self.generate_ip_tcl();
     self.generate_other_tcl();
+    Ok(())
   }
 
-  pub fn run_gir_passes(&mut self) {
-    let graph = gir::passes::all_passes(self);
+  pub fn run_gir_passes(&mut self) -> Result<(), IronyError> {
+    let graph = gir::passes::all_passes(self)?;
     gir::passes::retrieve_cmtc(self, graph);
+    Ok(())
+  }
+
+  /// Render the FSMs of synthesized statements with their encoded states,
+  /// transitions and events to a Graphviz DOT file. Must be called before
+  /// elaboration.
+  pub fn dump_fsm_dot<P: AsRef<Path>>(&self, path: P) -> Result<(), IronyError> {
+    write_file(path.as_ref(), gir::dot::cmtc_fsm_dot(self))
   }
 
-  pub fn simulate<FuncT, FutureT>(&mut self, test_func: FuncT)
+  /// Statically analyze the latency of every synthesized statement. Must be called
+  /// before elaboration.
+  pub fn latency_report(&self) -> LatencyReport { gir::latency::latency_report(self) }
+
+  /// Statically analyze the cycles in which events, guarded wires and selects of
+  /// every synthesized statement are active. Must be called before elaboration.
+  pub fn timing_report(&self) -> TimingReport { gir::timing::timing_report(self) }
+
+  /// Find events that drive the same wire and may be active in the same cycle. Must
+  /// be called before elaboration.
+  pub fn conflict_report(&self) -> ConflictReport { gir::conflict::conflict_report(self) }
+
+  /// Schedule the branches of `par` statements that share resources, regardless of
+  /// `CmtcConfig::schedule_par`. Must be called before elaboration.
+  pub fn schedule_report(&self) -> ScheduleReport { gir::schedule::schedule_report(self) }
+
+  pub fn simulate<FuncT, FutureT>(&mut self, test_func: FuncT) -> Result<(), IronyError>
   where
     FuncT: FnOnce(SimCoroInterface) -> FutureT,
     FutureT: Future<Output = ()> + Send + 'static,
   {
-    self.elaborate();
-    Simulator::new(self).test(test_func)
+    self.elaborate()?;
+    Simulator::new(self).test(test_func);
+    Ok(())
   }
 }
+
+fn create_dir(path: &Path) -> Result<(), IronyError> {
+  fs::create_dir_all(path)
+    .map_err(|err| IronyError::new(format!("can't create {}: {}", path.display(), err)))
+}
+
+/// Create the directory `path` and return its absolute path
+fn absolute_dir(path: &Path) -> Result<PathBuf, IronyError> {
+  create_dir(path)?;
+  absolute(path)
+    .map_err(|err| IronyError::new(format!("can't resolve {}: {}", path.display(), err)))
+}
+
+fn write_file<C: AsRef<[u8]>>(path: &Path, contents: C) -> Result<(), IronyError> {
+  fs::write(path, contents)
+    .map_err(|err| IronyError::new(format!("can't write {}: {}", path.display(), err)))
+}
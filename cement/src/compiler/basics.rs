@@ -208,10 +208,18 @@ impl CmtcBasics for Cmtc {
     outputs: Vec<Option<EntityId>>,
   ) -> () {
     let target_module_op_id = if self.config.deduplicate {
-      self
+      let unique = self
         .ir
         .hash_op(target_module_op_id)
-        .expect("cannot be None for module deduplication")
+        .expect("cannot be None for module deduplication");
+      // a duplicate marked to be inlined marks the module replacing it
+      if unique != target_module_op_id
+        && self.inline_modules.contains(&target_module_op_id)
+        && !self.inline_modules.contains(&unique)
+      {
+        self.inline_modules.push(unique);
+      }
+      unique
     } else {
       target_module_op_id
     };
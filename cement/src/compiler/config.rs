@@ -1,9 +1,13 @@
 use std::path::{Path, PathBuf};
 
+use irony_cmt::IronyError;
+
+use crate::gir::pipeline::{parse_pipeline, GirPass};
 use crate::preclude::CfgXilinxIP;
 
 pub enum CfgValue {
   Bool(bool),
+  Usize(usize),
   String(String),
   PathBuf(PathBuf),
   XilinxIp(CfgXilinxIP),
@@ -13,6 +17,10 @@ impl From<bool> for CfgValue {
   fn from(value: bool) -> Self { CfgValue::Bool(value) }
 }
 
+impl From<usize> for CfgValue {
+  fn from(value: usize) -> Self { CfgValue::Usize(value) }
+}
+
 impl From<String> for CfgValue {
   fn from(value: String) -> Self { CfgValue::String(value) }
 }
@@ -25,10 +33,55 @@ impl From<PathBuf> for CfgValue {
   fn from(value: PathBuf) -> Self { CfgValue::PathBuf(value) }
 }
 
+/// How `Cmtc::generate_workspace` generates SystemVerilog from the IR
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SvBackend {
+  /// The built-in emitter, writing one `.sv` file per module
+  Native,
+  /// `circt-opt --export-split-verilog`, found at `CmtcConfig::circt_opt`
+  CirctOpt,
+}
+
 pub struct CmtcConfig {
   pub deduplicate: bool,
   pub debug: bool,
+  /// Check at runtime that events driving the same wire, which can't be proven
+  /// exclusive, are onehot
   pub onehot: bool,
+  /// Warn about events driving the same wire in the same cycle in `Cmtc::elaborate`
+  pub warn_conflicts: bool,
+  /// Dump the gir graph to a DOT file in this directory after every gir pass
+  pub dump_gir_dir: Option<PathBuf>,
+  /// Control synthesis passes run on GIR, in order
+  pub gir_passes: Vec<GirPass>,
+  /// Error from parsing the `gir_passes` of `from_dict`, returned by `gir_pipeline`
+  gir_passes_error: Option<IronyError>,
+  /// Print the time and node count of every gir pass
+  pub gir_stats: bool,
+  /// Check the structure of the gir graph after every gir pass
+  pub verify_gir: bool,
+  /// Check the constraints of every op in the IR after every group of IR passes
+  pub verify_ir: bool,
+  /// IR passes simplifying the lowered design, run before the dead code is removed in
+  /// `Cmtc::elaborate` and the emitters, like `canonicalize,cse`
+  pub ir_passes: String,
+  /// Dump the IR to this directory before the first IR pass and after every IR pass
+  pub dump_ir_dir: Option<PathBuf>,
+  /// Print the time and op count of every IR pass
+  pub ir_stats: bool,
+  /// Inline every module but extern ones into the modules instantiating them
+  pub flatten: bool,
+  /// Inline the modules with at most this many ops, besides the modules marked with
+  /// `Cmtc::inline_module`
+  pub flatten_max_ops: Option<usize>,
+  /// Balance every `if` statement, as if they were all marked with `Stmt::balanced`
+  pub balance_if: bool,
+  /// Delay or serialize `par` branches which use the same resource in the same cycle
+  pub schedule_par: bool,
+  pub sv_backend: SvBackend,
+  /// Also write the Yosys JSON netlist of the modules to `modules.json` in the
+  /// workspace, next to their SystemVerilog files, in `Cmtc::generate_workspace`
+  pub yosys_json: bool,
   pub circt_opt: PathBuf,
   workspace_dir: PathBuf,
   workspace_name: String,
@@ -50,6 +103,22 @@ impl Default for CmtcConfig {
       deduplicate: true,
       debug: false,
       onehot: false,
+      warn_conflicts: false,
+      dump_gir_dir: None,
+      gir_passes: GirPass::default_pipeline(),
+      gir_passes_error: None,
+      gir_stats: false,
+      verify_gir: false,
+      verify_ir: false,
+      ir_passes: String::new(),
+      dump_ir_dir: None,
+      ir_stats: false,
+      flatten: false,
+      flatten_max_ops: None,
+      balance_if: false,
+      schedule_par: false,
+      sv_backend: SvBackend::Native,
+      yosys_json: false,
       circt_opt: PathBuf::from(circt_path).join("circt-opt"),
       workspace_dir: Path::new("./build").to_path_buf(),
       workspace_name: "ws".to_string(),
@@ -72,6 +141,30 @@ impl CmtcConfig {
     }
   }
 
+  /// Add `pass` to the gir pipeline, after the enabled passes that precede it in
+  /// `GirPass::ALL`
+  pub fn enable_gir_pass(mut self, pass: GirPass) -> Self {
+    if !self.gir_passes.contains(&pass) {
+      let order = |x: &GirPass| GirPass::ALL.iter().position(|y| y == x).unwrap();
+      let pos = self.gir_passes.iter().take_while(|x| order(x) < order(&pass)).count();
+      self.gir_passes.insert(pos, pass);
+    }
+    self
+  }
+
+  /// The gir passes to run, or the error of an unknown pass name in `gir_passes`
+  pub fn gir_pipeline(&self) -> Result<&[GirPass], IronyError> {
+    match &self.gir_passes_error {
+      Some(err) => Err(err.clone()),
+      None => Ok(&self.gir_passes),
+    }
+  }
+
+  pub fn disable_gir_pass(mut self, pass: GirPass) -> Self {
+    self.gir_passes.retain(|x| *x != pass);
+    self
+  }
+
   pub fn from_dict(dict: Vec<(String, CfgValue)>) -> Self {
     let mut config = CmtcConfig::default();
     for (key, value) in dict {
@@ -91,6 +184,94 @@ impl CmtcConfig {
             config.onehot = b;
           }
         },
+        "warn_conflicts" => {
+          if let CfgValue::Bool(b) = value {
+            config.warn_conflicts = b;
+          }
+        },
+        "dump_gir_dir" => match value {
+          CfgValue::String(s) => {
+            config.dump_gir_dir = Some(PathBuf::from(s));
+          },
+          CfgValue::PathBuf(p) => {
+            config.dump_gir_dir = Some(p);
+          },
+          _ => panic!("dump_gir_dir must be a string or a PathBuf"),
+        },
+        "gir_passes" => {
+          if let CfgValue::String(s) = value {
+            match parse_pipeline(&s) {
+              Ok(passes) => config.gir_passes = passes,
+              Err(err) => config.gir_passes_error = Some(err),
+            }
+          }
+        },
+        "gir_stats" => {
+          if let CfgValue::Bool(b) = value {
+            config.gir_stats = b;
+          }
+        },
+        "verify_gir" => {
+          if let CfgValue::Bool(b) = value {
+            config.verify_gir = b;
+          }
+        },
+        "verify_ir" => {
+          if let CfgValue::Bool(b) = value {
+            config.verify_ir = b;
+          }
+        },
+        "ir_passes" => {
+          if let CfgValue::String(s) = value {
+            config.ir_passes = s;
+          }
+        },
+        "dump_ir_dir" => match value {
+          CfgValue::String(s) => {
+            config.dump_ir_dir = Some(PathBuf::from(s));
+          },
+          CfgValue::PathBuf(p) => {
+            config.dump_ir_dir = Some(p);
+          },
+          _ => panic!("dump_ir_dir must be a string or a PathBuf"),
+        },
+        "ir_stats" => {
+          if let CfgValue::Bool(b) = value {
+            config.ir_stats = b;
+          }
+        },
+        "flatten" => {
+          if let CfgValue::Bool(b) = value {
+            config.flatten = b;
+          }
+        },
+        "flatten_max_ops" => {
+          if let CfgValue::Usize(n) = value {
+            config.flatten_max_ops = Some(n);
+          }
+        },
+        "balance_if" => {
+          if let CfgValue::Bool(b) = value {
+            config.balance_if = b;
+          }
+        },
+        "schedule_par" => {
+          if let CfgValue::Bool(b) = value {
+            config.schedule_par = b;
+          }
+        },
+        "sv_backend" => match value {
+          CfgValue::String(s) if s == "native" => config.sv_backend = SvBackend::Native,
+          CfgValue::String(s) if s == "circt-opt" => {
+            config.sv_backend = SvBackend::CirctOpt
+          },
+          _ => panic!("sv_backend must be \"native\" or \"circt-opt\""),
+        },
+        "yosys_json" => {
+          if let CfgValue::Bool(b) = value {
+            config.yosys_json = b;
+          }
+        },
         "circt_opt" => {
           if let CfgValue::String(s) = value {
             config.circt_opt = PathBuf::from(s);
@@ -3,14 +3,21 @@ use std::panic::Location;
 use irony_cmt::{ArrayAttr, EntityId, Environ, StmtSynth, StringAttr};
 
 use super::Cmtc;
-use crate::preclude::{Stmt, StmtProtocol};
+use crate::preclude::{LatencyRange, Stmt, StmtProtocol};
 
 pub trait CmtcStmt {
+  #[track_caller]
   fn synthesize<P: StmtProtocol>(&mut self, stmt: Stmt, protocol: P);
+  /// Synthesize `stmt`, which must take `latency` cycles, see `Stmt::expect_latency`
+  #[track_caller]
+  fn synthesize_expecting<P: StmtProtocol, T: Into<LatencyRange>>(
+    &mut self, stmt: Stmt, protocol: P, latency: T,
+  );
   fn add_stmt(&mut self, name: Option<String>) -> EntityId;
 }
 
 impl CmtcStmt for Cmtc {
+  #[track_caller]
   fn synthesize<P: StmtProtocol>(&mut self, stmt: Stmt, protocol: P) {
     let stmt_entity_id = stmt.to(self);
 
@@ -31,6 +38,13 @@ impl CmtcStmt for Cmtc {
     );
   }
 
+  #[track_caller]
+  fn synthesize_expecting<P: StmtProtocol, T: Into<LatencyRange>>(
+    &mut self, stmt: Stmt, protocol: P, latency: T,
+  ) {
+    self.synthesize(stmt.expect_latency(latency), protocol);
+  }
+
   #[track_caller]
   fn add_stmt(&mut self, name: Option<String>) -> EntityId {
     let raw_name = name.or(Some("stmt".to_string())).unwrap();
@@ -1,7 +1,15 @@
 //! Graph-based IRs
 
+mod balance;
 mod build_fsm;
 pub mod component;
+pub mod conflict;
 pub mod construction;
+pub mod dot;
 pub mod expr;
+pub mod latency;
 pub mod passes;
+pub mod pipeline;
+pub mod schedule;
+pub mod timing;
+pub mod verify;
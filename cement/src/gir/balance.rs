@@ -0,0 +1,123 @@
+//! Latency balancing of `if` statements
+//!
+//! The shorter branch of a balanced `if` statement is followed by idle steps, so
+//! that both branches take the same number of cycles. An `if` without `else` gets an
+//! `else` branch of idle steps, as its implicit `else` takes one cycle. Inner
+//! statements are balanced first, so that outer ones see their padded latency.
+
+use std::collections::HashMap;
+use std::panic::Location;
+
+use itertools::Itertools;
+use tgraph::typed_graph::{Context, Graph, NodeIndex, Transaction};
+
+use super::component::*;
+use super::latency::{ast_latency, ast_post_order, entity_name};
+use crate::compiler::Cmtc;
+
+/// Balance the `if` statements of `cmtc` marked with `Stmt::balanced`, or every `if`
+/// statement with static branch latencies if `CmtcConfig::balance_if` is set
+pub(super) fn balance_ifs(
+  cmtc: &Cmtc, ctx: &Context, graph: &mut Graph<Component>,
+  stmt2node: &HashMap<usize, NodeIndex>,
+) {
+  if !cmtc.config.balance_if && cmtc.balanced_ifs.is_empty() {
+    return;
+  }
+  let node2stmt: HashMap<_, _> = stmt2node.iter().map(|(k, v)| (*v, *k)).collect();
+  let marked: Vec<_> = cmtc.balanced_ifs.iter().map(|x| stmt2node[&x.0]).collect();
+
+  let mut asts = Vec::new();
+  let synths = AstSynth::iter_by_type(graph).sorted_by_key(|(_, x)| x.stmt.0);
+  for (_, synth) in synths {
+    ast_post_order(graph, synth.body, &mut asts);
+  }
+
+  for ast in asts {
+    let (then, alt, location) = match graph.get_node(ast).unwrap() {
+      Component::AstIf(x) => (x.then, None, x.location),
+      Component::AstIfElse(x) => (x.then, Some(x.alt), x.location),
+      _ => continue,
+    };
+    let required = marked.contains(&ast);
+    if !required && !cmtc.config.balance_if {
+      continue;
+    }
+
+    let then_latency = ast_latency(cmtc, graph, then);
+    let alt_latency = alt.map_or(Some(1), |x| ast_latency(cmtc, graph, x).as_const());
+    let (Some(then_cycles), Some(alt_cycles)) = (then_latency.as_const(), alt_latency)
+    else {
+      if required {
+        let stmt = node2stmt
+          .get(&ast)
+          .map_or("if".to_string(), |x| entity_name(cmtc, irony_cmt::EntityId(*x)));
+        panic!(
+          "{}: can't balance stmt `{}`, the latency of its branches is not static",
+          location, stmt
+        );
+      }
+      continue;
+    };
+
+    let mut trans = Transaction::new(ctx);
+    match alt {
+      _ if then_cycles == alt_cycles => continue,
+      // never an `if` without `else`, whose then branch takes at least one cycle
+      _ if then_cycles < alt_cycles => {
+        let then = pad(&mut trans, then, alt_cycles - then_cycles, location);
+        trans.mut_node(ast, move |x| {
+          if let Component::AstIfElse(y) = x {
+            y.then = then;
+          }
+        });
+      },
+      Some(alt) => {
+        let alt = pad(&mut trans, alt, then_cycles - alt_cycles, location);
+        trans.mut_node(ast, move |x| {
+          if let Component::AstIfElse(y) = x {
+            y.alt = alt;
+          }
+        });
+      },
+      // the implicit `else` branch is replaced by idle steps
+      None => {
+        let alt = idle_steps(&mut trans, then_cycles, location);
+        trans.update_node(ast, move |x| match x {
+          Component::AstIf(y) => Component::AstIfElse(AstIfElse {
+            cond: y.cond,
+            then: y.then,
+            alt,
+            location: y.location,
+          }),
+          x => x,
+        });
+      },
+    }
+    graph.commit(trans);
+  }
+}
+
+/// A sequence of `ast` followed by `cycles` idle steps
+fn pad(
+  trans: &mut Transaction<Component>, ast: NodeIndex, cycles: usize,
+  location: Location<'static>,
+) -> NodeIndex {
+  let idle = idle_steps(trans, cycles, location);
+  trans.new_node(Component::AstSeq(AstSeq { children: vec![ast, idle], location }))
+}
+
+fn idle_steps(
+  trans: &mut Transaction<Component>, cycles: usize, location: Location<'static>,
+) -> NodeIndex {
+  let children = (0..cycles)
+    .map(|_| {
+      trans.new_node(Component::AstStep(AstStep {
+        events: Default::default(),
+        waits: Vec::new(),
+        location,
+      }))
+    })
+    .collect();
+  trans.new_node(Component::AstSeq(AstSeq { children, location }))
+}
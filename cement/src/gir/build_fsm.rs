@@ -22,6 +22,7 @@ pub(super) fn traverse_ast<'a>(
     },
     Component::AstFor(ast_for) => make_ast_for(ctx, ast_for, graph, region),
     Component::AstWhile(ast_while) => make_ast_while(ctx, ast_while, graph, region),
+    Component::AstCall(call) => make_ast_call(ctx, call, region),
     _ => panic!("Unknown node in AST!"),
   }
 }
@@ -98,6 +99,65 @@ fn make_ast_step<'a>(
   (idle_idx, leaf, fsm)
 }
 
+/// Assert `go` of the callee for one cycle, then wait until it's `done`, as the
+/// callee is only done in its idle state
+fn make_ast_call<'a>(
+  ctx: &Context, call: &AstCall, region: NodeIndex,
+) -> (NodeIndex, NodeIndex, Graph<Component>) {
+  let mut fsm = Graph::new(ctx);
+  let mut fsm_trans = Transaction::new(ctx);
+
+  let (idle_idx, mut idle_state) = empty_state(&mut fsm_trans);
+  let (go_idx, mut go_state) = new_state(&mut fsm_trans, &HashSet::from([call.go]));
+  let (wait_idx, mut wait_state) = empty_state(&mut fsm_trans);
+  let go_leaf = fsm_trans.new_node(Component::LeafNode(LeafNode { state: go_idx }));
+  let wait_leaf = fsm_trans.new_node(Component::LeafNode(LeafNode { state: wait_idx }));
+  let root = fsm_trans.new_node(Component::ExcNode(ExcNode {
+    children: vec![go_leaf, wait_leaf],
+    encoding: Vec::new(),
+  }));
+
+  let true_lit = new_true(&mut fsm_trans, region, call.location);
+  let not_done = new_not(&mut fsm_trans, call.done, region, 1, call.location);
+
+  // Idle to Go
+  new_simple_transistion(
+    &mut fsm_trans,
+    true_lit,
+    &HashSet::new(),
+    (idle_idx, &mut idle_state),
+    (go_idx, &mut go_state),
+  );
+  // the callee leaves its idle state in the cycle after go
+  new_simple_transistion(
+    &mut fsm_trans,
+    true_lit,
+    &HashSet::new(),
+    (go_idx, &mut go_state),
+    (wait_idx, &mut wait_state),
+  );
+  new_simple_transistion(
+    &mut fsm_trans,
+    call.done,
+    &HashSet::new(),
+    (wait_idx, &mut wait_state),
+    (idle_idx, &mut idle_state),
+  );
+  new_self_transistion(
+    &mut fsm_trans,
+    not_done,
+    &HashSet::new(),
+    (wait_idx, &mut wait_state),
+  );
+
+  fsm_trans.fill_back_node(idle_idx, Component::State(idle_state));
+  fsm_trans.fill_back_node(go_idx, Component::State(go_state));
+  fsm_trans.fill_back_node(wait_idx, Component::State(wait_state));
+  fsm.commit(fsm_trans);
+
+  (idle_idx, root, fsm)
+}
+
 fn make_ast_seq(
   ctx: &Context, seq: &AstSeq, graph: &Graph<Component>, region: NodeIndex,
 ) -> (NodeIndex, NodeIndex, Graph<Component>) {
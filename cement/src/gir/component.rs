@@ -40,6 +40,7 @@ pub enum Component {
   // AstLoop(AstLoop),
   AstWhile(AstWhile),
   AstFor(AstFor),
+  AstCall(AstCall),
   AstSynth(AstSynth),
   // FSM
   FSM(FSM),
@@ -297,10 +298,23 @@ pub struct AstFor {
   location: Location<'static>,
 }
 
+/// Run the FSM synthesized from `callee` in an instance, by asserting `go` and
+/// waiting for `done`
+#[derive(TypedNode, Clone, Debug)]
+#[StructFields(pub)]
+pub struct AstCall {
+  go: NodeIndex,
+  done: NodeIndex,
+  /// The `AstSynth` of the callee, empty if it isn't synthesized with `GoDone`
+  callee: NodeIndex,
+  location: Location<'static>,
+}
+
 #[derive(TypedNode, Clone, Debug)]
 #[StructFields(pub)]
 pub struct AstSynth {
   body: NodeIndex,
+  stmt: EntityId,
   clock: EntityId,
   prot_evts: Vec<NodeIndex>,
   region: NodeIndex,
@@ -0,0 +1,209 @@
+//! Detection of events that drive the same wire in the same cycle
+//!
+//! Candidates of a `select` guarded by different events are prioritized implicitly
+//! if the events fire together. Two events can only be active together if they are
+//! attached to the same FSM state, or to states under different branches of a
+//! `ParNode` of the same FSM.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::panic::Location;
+
+use irony_cmt::{EntityId, Environ, IronyError, ItprtCondCheck, Op, OpEnum, OpId};
+use tgraph::typed_graph::{Context, Graph, NodeIndex};
+
+use super::component::*;
+use super::latency::entity_name;
+use super::passes::{load_graph, make_fsms};
+use super::timing::{entity_location, EventDrivers};
+use crate::compiler::Cmtc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictKind {
+  /// The events can be active in the same cycle
+  Simultaneous,
+  /// Exclusivity of the events can't be proven, e.g. they are not scheduled by the
+  /// same statement
+  Unproven,
+}
+
+/// Two events driving the same wire through a `select`
+#[derive(Clone, Debug)]
+pub struct EventConflict {
+  pub kind: ConflictKind,
+  pub wire: String,
+  pub location: Location<'static>,
+  pub events: [(String, Location<'static>); 2],
+  select: OpId,
+}
+
+impl fmt::Display for EventConflict {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let kind = match self.kind {
+      ConflictKind::Simultaneous => "may be active in the same cycle",
+      ConflictKind::Unproven => "are not proven exclusive",
+    };
+    write!(
+      f,
+      "events {} ({}) and {} ({}) driving {} ({}) {}",
+      self.events[0].0,
+      self.events[0].1,
+      self.events[1].0,
+      self.events[1].1,
+      self.wire,
+      self.location,
+      kind
+    )
+  }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ConflictReport {
+  pub conflicts: Vec<EventConflict>,
+}
+
+impl ConflictReport {
+  pub fn simultaneous(&self) -> impl Iterator<Item = &EventConflict> {
+    self.conflicts.iter().filter(|x| x.kind == ConflictKind::Simultaneous)
+  }
+
+  pub fn unproven(&self) -> impl Iterator<Item = &EventConflict> {
+    self.conflicts.iter().filter(|x| x.kind == ConflictKind::Unproven)
+  }
+}
+
+impl fmt::Display for ConflictReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for conflict in self.conflicts.iter() {
+      writeln!(f, "{}", conflict)?;
+    }
+    Ok(())
+  }
+}
+
+/// Path from the state root of an FSM to a state, as `(node, index of child)`
+type StatePath = Vec<(NodeIndex, usize)>;
+
+pub fn conflict_report(cmtc: &Cmtc) -> ConflictReport {
+  let ctx = Context::new();
+  let (mut graph, _) = load_graph(cmtc, &ctx);
+  let trans = make_fsms(&ctx, &graph);
+  graph.commit(trans);
+
+  // the FSM and state paths of each event
+  let mut event_states: HashMap<usize, Vec<(NodeIndex, StatePath)>> = HashMap::new();
+  for (fsm_idx, fsm) in FSM::iter_by_type(&graph) {
+    let mut paths = Vec::new();
+    collect_state_paths(&graph, fsm.state_root, &mut Vec::new(), &mut paths);
+    for (state, path) in paths {
+      let state = State::get_by_type(&graph, state).unwrap();
+      for event in state.events.iter() {
+        let entity_id = Event::get_by_type(&graph, *event).unwrap().entity_id;
+        event_states.entry(entity_id.0).or_default().push((fsm_idx, path.to_owned()));
+      }
+    }
+  }
+
+  let kind = |a: EntityId, b: EntityId| -> Option<ConflictKind> {
+    if a == b {
+      return Some(ConflictKind::Simultaneous);
+    }
+    let (Some(xs), Some(ys)) = (event_states.get(&a.0), event_states.get(&b.0)) else {
+      return Some(ConflictKind::Unproven);
+    };
+    let mut result = None;
+    for (fsm_x, x) in xs.iter() {
+      for (fsm_y, y) in ys.iter() {
+        if fsm_x != fsm_y {
+          result = Some(ConflictKind::Unproven);
+        } else if may_coexist(&graph, x, y) {
+          return Some(ConflictKind::Simultaneous);
+        }
+      }
+    }
+    result
+  };
+
+  let drivers = EventDrivers::new(cmtc);
+  let mut report = ConflictReport::default();
+  for (op_id, op) in cmtc.ir.op_table.iter() {
+    let OpEnum::TmpSelect(select) = op else {
+      continue;
+    };
+    let guards: Vec<_> = drivers.select_guards(select).into_iter().flatten().collect();
+    for (i, a) in guards.iter().enumerate() {
+      for b in guards[i + 1..].iter() {
+        if let Some(kind) = kind(*a, *b) {
+          let lhs = select.lhs.unwrap();
+          report.conflicts.push(EventConflict {
+            kind,
+            wire: entity_name(cmtc, lhs),
+            location: entity_location(cmtc, lhs),
+            events: [
+              (entity_name(cmtc, *a), entity_location(cmtc, *a)),
+              (entity_name(cmtc, *b), entity_location(cmtc, *b)),
+            ],
+            select: OpId(*op_id),
+          });
+        }
+      }
+    }
+  }
+
+  report
+}
+
+/// Check that the events guarding each `select` with unproven conflicts are onehot
+/// at runtime, by adding `ItprtCondCheck`s next to the `select`
+pub fn add_onehot_checks(
+  cmtc: &mut Cmtc, report: &ConflictReport,
+) -> Result<(), IronyError> {
+  let mut selects: Vec<_> = report.unproven().map(|x| x.select).collect();
+  selects.dedup();
+
+  let drivers = EventDrivers::new(cmtc);
+  for select_id in selects {
+    let OpEnum::TmpSelect(select) = cmtc.ir.get_op(select_id) else {
+      return Err(IronyError::new("conflicts must come from selects".to_string()));
+    };
+    let conds = drivers.select_guards(select).into_iter().flatten().map(Some).collect();
+    // no event is active while the FSM is idle
+    let check = ItprtCondCheck::new(conds, Some(true.into()), Some(true.into()));
+    let region = cmtc.ir.get_op(select_id).get_parent();
+    cmtc.ir.begin_region(region);
+    cmtc.ir.add_op(check.into());
+    cmtc.ir.end_region();
+  }
+  Ok(())
+}
+
+fn collect_state_paths(
+  graph: &Graph<Component>, node: NodeIndex, path: &mut StatePath,
+  paths: &mut Vec<(NodeIndex, StatePath)>,
+) {
+  let children = match graph.get_node(node).unwrap() {
+    Component::LeafNode(leaf) => {
+      paths.push((leaf.state, path.to_owned()));
+      return;
+    },
+    Component::ExcNode(exc) => &exc.children,
+    Component::ParNode(par) => &par.children,
+    _ => panic!("not a node of the state tree"),
+  };
+  for (i, child) in children.iter().enumerate() {
+    path.push((node, i));
+    collect_state_paths(graph, *child, path, paths);
+    path.pop();
+  }
+}
+
+/// Whether two states of the same FSM can be active in the same cycle
+fn may_coexist(graph: &Graph<Component>, x: &StatePath, y: &StatePath) -> bool {
+  for ((node, i), (_, j)) in x.iter().zip(y.iter()) {
+    if i != j {
+      return matches!(graph.get_node(*node).unwrap(), Component::ParNode(_));
+    }
+  }
+  // the same state
+  true
+}
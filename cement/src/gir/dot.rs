@@ -0,0 +1,235 @@
+//! Graphviz DOT rendering of gir graphs
+//!
+//! `fsm_dot` draws every FSM as a cluster of its states and transitions, and
+//! `graph_dot` draws every node of the graph with an edge for each of its sources.
+
+use std::fmt::Write;
+
+use irony_cmt::Environ;
+use itertools::Itertools;
+use tgraph::typed_graph::{Context, Graph, NodeEnum, NodeIndex};
+
+use super::component::*;
+use super::latency::entity_name;
+use super::passes::{
+  fsm_encoding_1, fsm_encoding_2, generate_go_done, load_graph, make_fsms,
+};
+use crate::compiler::{Cmtc, CmtcBasics};
+use crate::utils::{bits_str, usize_to_bitvec};
+
+/// Build and encode the FSMs of `cmtc`, and render them
+pub fn cmtc_fsm_dot(cmtc: &Cmtc) -> String {
+  let ctx = Context::new();
+  let (mut graph, _) = load_graph(cmtc, &ctx);
+  graph.commit(make_fsms(&ctx, &graph));
+  graph.commit(generate_go_done(&ctx, &graph));
+  graph.commit(fsm_encoding_1(&ctx, &graph));
+  graph.commit(fsm_encoding_2(&ctx, &graph));
+  fsm_dot(cmtc, &graph)
+}
+
+/// Render the FSMs of `graph`, which must have been built by `make_fsms`
+pub fn fsm_dot(cmtc: &Cmtc, graph: &Graph<Component>) -> String {
+  let mut dot = String::new();
+  writeln!(dot, "digraph fsm {{").unwrap();
+  writeln!(dot, "  node [shape=box];").unwrap();
+
+  for (fsm_idx, fsm) in FSM::iter_by_type(graph) {
+    writeln!(dot, "  subgraph cluster_{} {{", fsm_idx.0).unwrap();
+    writeln!(dot, "    label={};", quote(&fsm_name(cmtc, graph, fsm))).unwrap();
+
+    for state_idx in fsm.states.iter().sorted_by_key(|x| x.0) {
+      let (events, encoding) = match graph.get_node(*state_idx).unwrap() {
+        Component::State(state) => (&state.events, None),
+        Component::EncodedState(state) => (&state.events, Some(&state.encoding)),
+        _ => panic!("not a state"),
+      };
+      let mut label = if *state_idx == fsm.idle_state {
+        "idle".to_string()
+      } else {
+        format!("s{}", state_idx.0)
+      };
+      if let Some(encoding) = encoding {
+        let bits = encoding
+          .iter()
+          .map(|(start, end, encode)| {
+            format!(
+              "[{}:{}]={}",
+              end,
+              start,
+              bits_str(usize_to_bitvec(end - start, *encode))
+            )
+          })
+          .join(" ");
+        write!(label, "\n{}", bits).unwrap();
+      }
+      for event in events.iter().sorted_by_key(|x| x.0) {
+        write!(label, "\n{}", event_name(cmtc, graph, *event)).unwrap();
+      }
+      let shape = if *state_idx == fsm.idle_state { ", shape=doublecircle" } else { "" };
+      writeln!(dot, "    n{} [label={}{}];", state_idx.0, quote(&label), shape).unwrap();
+    }
+
+    for transit_idx in fsm.transitions.iter().sorted_by_key(|x| x.0) {
+      let transit = Transition::get_by_type(graph, *transit_idx).unwrap();
+      let mut label = expr_str(cmtc, graph, transit.cond);
+      for act in transit.acts.iter().sorted_by_key(|x| x.0) {
+        if let Some(assign) = Assign::get_by_type(graph, *act) {
+          write!(
+            label,
+            "\n{} := {}",
+            expr_str(cmtc, graph, assign.lhs),
+            expr_str(cmtc, graph, assign.rhs)
+          )
+          .unwrap();
+        }
+      }
+      for from in transit.froms.iter().sorted_by_key(|x| x.0) {
+        writeln!(dot, "    n{} -> n{} [label={}];", from.0, transit.to.0, quote(&label))
+          .unwrap();
+      }
+    }
+    writeln!(dot, "  }}").unwrap();
+  }
+
+  writeln!(dot, "}}").unwrap();
+  dot
+}
+
+/// Render every node of `graph`, with an edge from each node to its sources
+pub fn graph_dot(cmtc: &Cmtc, graph: &Graph<Component>) -> String {
+  let mut dot = String::new();
+  writeln!(dot, "digraph gir {{").unwrap();
+  writeln!(dot, "  node [shape=box];").unwrap();
+
+  for (&idx, node) in graph.iter_nodes().sorted_by_key(|(x, _)| x.0) {
+    let mut label = format!("{}: {}", idx.0, type_name(node));
+    match node {
+      Component::Wire(Wire { entity_id: Some(id), .. }) => {
+        write!(label, "\n{}", entity_name(cmtc, *id)).unwrap();
+      },
+      Component::Event(event) => {
+        write!(label, "\n{}", entity_name(cmtc, event.entity_id)).unwrap();
+      },
+      Component::Literal(_)
+      | Component::UnaryOp(_)
+      | Component::BinaryOp(_)
+      | Component::ReduceOp(_) => {
+        write!(label, "\n{}", expr_str(cmtc, graph, idx)).unwrap();
+      },
+      _ => {},
+    }
+    writeln!(dot, "  n{} [label={}];", idx.0, quote(&label)).unwrap();
+  }
+
+  for (&idx, node) in graph.iter_nodes().sorted_by_key(|(x, _)| x.0) {
+    for (source, field) in node.iter_source().sorted_by_key(|(x, _)| x.0) {
+      if source.is_empty() {
+        continue;
+      }
+      writeln!(
+        dot,
+        "  n{} -> n{} [label={}];",
+        idx.0,
+        source.0,
+        quote(&field_name(field))
+      )
+      .unwrap();
+    }
+  }
+
+  writeln!(dot, "}}").unwrap();
+  dot
+}
+
+fn fsm_name(cmtc: &Cmtc, graph: &Graph<Component>, fsm: &FSM) -> String {
+  let region = Region::get_by_type(graph, fsm.region).unwrap().region.unwrap();
+  let module = cmtc.get_module_name(cmtc.ir.get_region_use(region).unwrap());
+  match AstSynth::iter_by_type(graph).find(|(_, x)| x.body == fsm.ast) {
+    Some((_, synth)) => {
+      format!("{}.{} ({})", module, entity_name(cmtc, synth.stmt), fsm.location)
+    },
+    None => format!("{} ({})", module, fsm.location),
+  }
+}
+
+fn event_name(cmtc: &Cmtc, graph: &Graph<Component>, idx: NodeIndex) -> String {
+  match graph.get_node(idx) {
+    Some(Component::Event(event)) => entity_name(cmtc, event.entity_id),
+    _ => format!("n{}", idx.0),
+  }
+}
+
+/// Render the expression rooted at `idx`, with named wires as leaves
+fn expr_str(cmtc: &Cmtc, graph: &Graph<Component>, idx: NodeIndex) -> String {
+  if idx.is_empty() {
+    return "1".to_string();
+  }
+  match graph.get_node(idx).unwrap() {
+    Component::Wire(Wire { entity_id: Some(id), .. }) => entity_name(cmtc, *id),
+    Component::Literal(lit) => {
+      format!("{}'b{}", lit.width, bits_str(lit.value.to_owned()))
+    },
+    Component::UnaryOp(op) => {
+      let ty = match op.ty {
+        UnaryOpType::Neg => "-",
+        UnaryOpType::Not => "!",
+      };
+      format!("{}{}", ty, expr_str(cmtc, graph, op.operand))
+    },
+    Component::BinaryOp(op) => {
+      let ty = match op.ty {
+        BinaryOpType::Add => "+",
+        BinaryOpType::And => "&",
+        BinaryOpType::Or => "|",
+        BinaryOpType::Xor => "^",
+        BinaryOpType::Eq => "==",
+        BinaryOpType::Neq => "!=",
+        BinaryOpType::Lt => "<",
+        BinaryOpType::Le => "<=",
+        BinaryOpType::Gt => ">",
+        BinaryOpType::Ge => ">=",
+      };
+      format!(
+        "({} {} {})",
+        expr_str(cmtc, graph, op.operand1),
+        ty,
+        expr_str(cmtc, graph, op.operand2)
+      )
+    },
+    Component::ReduceOp(op) => {
+      let ty = match op.ty {
+        ReduceOpType::Sum => " + ",
+        ReduceOpType::And => " & ",
+        ReduceOpType::Or => " | ",
+      };
+      format!("({})", op.operands.iter().map(|x| expr_str(cmtc, graph, *x)).join(ty))
+    },
+    Component::IndexOp(op) => format!(
+      "{}[{}:{}]",
+      expr_str(cmtc, graph, op.operand),
+      expr_str(cmtc, graph, op.high),
+      expr_str(cmtc, graph, op.low)
+    ),
+    _ => format!("n{}", idx.0),
+  }
+}
+
+/// Name of the variant of a node, e.g. `Wire`
+fn type_name(node: &Component) -> String {
+  let debug = format!("{:?}", node);
+  debug.split('(').next().unwrap().to_string()
+}
+
+/// Name of the field of a source, e.g. `Region` for `Wire(Region)`
+fn field_name<T: std::fmt::Debug>(field: T) -> String {
+  let debug = format!("{:?}", field);
+  match debug.split_once('(') {
+    Some((_, inner)) => inner.strip_suffix(')').unwrap_or(inner).to_string(),
+    None => debug,
+  }
+}
+
+fn quote(s: &str) -> String {
+  format!("\"{}\"", s.replace('"', "\\\"").replace('\n', "\\n"))
+}
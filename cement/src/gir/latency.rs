@@ -0,0 +1,482 @@
+//! Static latency analysis on the statement ASTs of GIR
+//!
+//! The latency of a statement is the number of clock cycles its FSM spends outside
+//! of the idle state, i.e. from the cycle after `go` is accepted to the cycle `done`
+//! is asserted.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::panic::Location;
+
+use irony_cmt::{AttributeEnum, Entity, EntityId, Environ, IronyError};
+use tgraph::typed_graph::{Context, Graph, NodeIndex};
+
+use super::component::*;
+use super::passes::load_graph;
+use crate::compiler::{Cmtc, CmtcBasics};
+
+/// A symbolic number of clock cycles
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Cycles {
+  Const(usize),
+  /// Value of a wire used as a loop bound
+  Var(String),
+  Add(Vec<Cycles>),
+  /// Saturating subtraction
+  Sub(Box<Cycles>, Box<Cycles>),
+  Mul(Vec<Cycles>),
+  CeilDiv(Box<Cycles>, Box<Cycles>),
+  Min(Vec<Cycles>),
+  Max(Vec<Cycles>),
+  /// Depends on runtime conditions, e.g. `while` loops or waits at exit
+  Unbounded,
+}
+
+impl Cycles {
+  pub fn is_const(&self) -> bool { matches!(self, Cycles::Const(_)) }
+
+  pub fn is_unbounded(&self) -> bool { matches!(self, Cycles::Unbounded) }
+
+  pub fn as_const(&self) -> Option<usize> {
+    match self {
+      Cycles::Const(x) => Some(*x),
+      _ => None,
+    }
+  }
+
+  /// Evaluate the cycle count with the given values of loop-bound wires. Returns
+  /// `None` if the count is unbounded or depends on a wire missing in `vars`.
+  pub fn eval(&self, vars: &HashMap<String, usize>) -> Option<usize> {
+    let all = |xs: &Vec<Cycles>| -> Option<Vec<usize>> {
+      xs.iter().map(|x| x.eval(vars)).collect()
+    };
+    match self {
+      Cycles::Const(x) => Some(*x),
+      Cycles::Var(name) => vars.get(name).copied(),
+      Cycles::Add(xs) => all(xs).map(|xs| xs.into_iter().sum()),
+      Cycles::Sub(a, b) => Some(a.eval(vars)?.saturating_sub(b.eval(vars)?)),
+      Cycles::Mul(xs) => all(xs).map(|xs| xs.into_iter().product()),
+      Cycles::CeilDiv(a, b) => Some(a.eval(vars)?.div_ceil(b.eval(vars)?)),
+      Cycles::Min(xs) => all(xs).and_then(|xs| xs.into_iter().min()),
+      Cycles::Max(xs) => all(xs).and_then(|xs| xs.into_iter().max()),
+      Cycles::Unbounded => None,
+    }
+  }
+
+  pub fn add(self, other: Cycles) -> Cycles {
+    let mut c = 0;
+    let mut terms = vec![];
+    for x in [self, other] {
+      match x {
+        Cycles::Unbounded => return Cycles::Unbounded,
+        Cycles::Const(x) => c += x,
+        Cycles::Add(xs) => {
+          for x in xs {
+            match x {
+              Cycles::Const(x) => c += x,
+              x => terms.push(x),
+            }
+          }
+        },
+        x => terms.push(x),
+      }
+    }
+    if c != 0 || terms.is_empty() {
+      terms.push(Cycles::Const(c));
+    }
+    if terms.len() == 1 {
+      terms.pop().unwrap()
+    } else {
+      Cycles::Add(terms)
+    }
+  }
+
+  pub fn sub(self, other: Cycles) -> Cycles {
+    match (self, other) {
+      (Cycles::Const(a), Cycles::Const(b)) => Cycles::Const(a.saturating_sub(b)),
+      (a, Cycles::Const(0)) => a,
+      (Cycles::Unbounded, _) => Cycles::Unbounded,
+      (a, b) if a == b => Cycles::Const(0),
+      (a, b) => Cycles::Sub(Box::new(a), Box::new(b)),
+    }
+  }
+
+  pub fn mul(self, other: Cycles) -> Cycles {
+    match (self, other) {
+      (Cycles::Const(0), _) | (_, Cycles::Const(0)) => Cycles::Const(0),
+      (Cycles::Unbounded, _) | (_, Cycles::Unbounded) => Cycles::Unbounded,
+      (Cycles::Const(a), Cycles::Const(b)) => Cycles::Const(a * b),
+      (Cycles::Const(1), x) | (x, Cycles::Const(1)) => x,
+      (Cycles::Mul(mut xs), Cycles::Mul(ys)) => {
+        xs.extend(ys);
+        Cycles::Mul(xs)
+      },
+      (Cycles::Mul(mut xs), y) | (y, Cycles::Mul(mut xs)) => {
+        xs.push(y);
+        Cycles::Mul(xs)
+      },
+      (a, b) => Cycles::Mul(vec![a, b]),
+    }
+  }
+
+  pub fn ceil_div(self, other: Cycles) -> Cycles {
+    match (self, other) {
+      (Cycles::Const(a), Cycles::Const(b)) => Cycles::Const(a.div_ceil(b)),
+      (a, Cycles::Const(1)) => a,
+      (Cycles::Unbounded, _) => Cycles::Unbounded,
+      (a, b) => Cycles::CeilDiv(Box::new(a), Box::new(b)),
+    }
+  }
+
+  pub fn min(self, other: Cycles) -> Cycles {
+    let mut c: Option<usize> = None;
+    let mut terms = vec![];
+    for x in [self, other] {
+      let xs = match x {
+        Cycles::Min(xs) => xs,
+        x => vec![x],
+      };
+      for x in xs {
+        match x {
+          Cycles::Unbounded => {},
+          Cycles::Const(x) => c = Some(c.map_or(x, |c| c.min(x))),
+          x => {
+            if !terms.contains(&x) {
+              terms.push(x)
+            }
+          },
+        }
+      }
+    }
+    Self::fold_extreme(c, terms, Cycles::Unbounded, Cycles::Min)
+  }
+
+  pub fn max(self, other: Cycles) -> Cycles {
+    let mut c: Option<usize> = None;
+    let mut terms = vec![];
+    for x in [self, other] {
+      let xs = match x {
+        Cycles::Max(xs) => xs,
+        x => vec![x],
+      };
+      for x in xs {
+        match x {
+          Cycles::Unbounded => return Cycles::Unbounded,
+          Cycles::Const(x) => c = Some(c.map_or(x, |c| c.max(x))),
+          x => {
+            if !terms.contains(&x) {
+              terms.push(x)
+            }
+          },
+        }
+      }
+    }
+    Self::fold_extreme(c, terms, Cycles::Const(0), Cycles::Max)
+  }
+
+  fn fold_extreme(
+    c: Option<usize>, mut terms: Vec<Cycles>, empty: Cycles,
+    wrap: fn(Vec<Cycles>) -> Cycles,
+  ) -> Cycles {
+    if let Some(c) = c {
+      terms.push(Cycles::Const(c));
+    }
+    match terms.len() {
+      0 => empty,
+      1 => terms.pop().unwrap(),
+      _ => wrap(terms),
+    }
+  }
+}
+
+impl fmt::Display for Cycles {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let join = |f: &mut fmt::Formatter<'_>, xs: &Vec<Cycles>, sep: &str| -> fmt::Result {
+      for (i, x) in xs.iter().enumerate() {
+        if i != 0 {
+          write!(f, "{}", sep)?;
+        }
+        match x {
+          Cycles::Add(_) | Cycles::Sub(..) if sep != ", " => write!(f, "({})", x)?,
+          _ => write!(f, "{}", x)?,
+        }
+      }
+      Ok(())
+    };
+    match self {
+      Cycles::Const(x) => write!(f, "{}", x),
+      Cycles::Var(name) => write!(f, "{}", name),
+      Cycles::Add(xs) => join(f, xs, " + "),
+      Cycles::Sub(a, b) => match b.as_ref() {
+        Cycles::Add(_) | Cycles::Sub(..) => write!(f, "{} - ({})", a, b),
+        _ => write!(f, "{} - {}", a, b),
+      },
+      Cycles::Mul(xs) => join(f, xs, " * "),
+      Cycles::CeilDiv(a, b) => write!(f, "ceil(({}) / ({}))", a, b),
+      Cycles::Min(xs) => {
+        write!(f, "min(")?;
+        join(f, xs, ", ")?;
+        write!(f, ")")
+      },
+      Cycles::Max(xs) => {
+        write!(f, "max(")?;
+        join(f, xs, ", ")?;
+        write!(f, ")")
+      },
+      Cycles::Unbounded => write!(f, "unbounded"),
+    }
+  }
+}
+
+/// Minimum and maximum latency of a statement
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Latency {
+  pub min: Cycles,
+  pub max: Cycles,
+}
+
+impl Latency {
+  pub fn exact(cycles: Cycles) -> Self { Latency { min: cycles.clone(), max: cycles } }
+
+  /// The minimum and maximum latency are the same and bounded
+  pub fn is_exact(&self) -> bool { self.min == self.max && !self.max.is_unbounded() }
+
+  /// Both bounds are known constants at compile time
+  pub fn is_static(&self) -> bool { self.min.is_const() && self.max.is_const() }
+
+  /// The number of cycles, if it is exact and known at compile time
+  pub fn as_const(&self) -> Option<usize> {
+    if self.is_exact() { self.max.as_const() } else { None }
+  }
+
+  pub(super) fn then(self, other: Latency) -> Latency {
+    Latency {
+      min: self.min.add(other.min),
+      max: self.max.add(other.max),
+    }
+  }
+
+  pub(super) fn either(self, other: Latency) -> Latency {
+    Latency {
+      min: self.min.min(other.min),
+      max: self.max.max(other.max),
+    }
+  }
+
+  pub(super) fn join(self, other: Latency) -> Latency {
+    Latency {
+      min: self.min.max(other.min),
+      max: self.max.max(other.max),
+    }
+  }
+
+  pub(super) fn repeat(self, times: Cycles) -> Latency {
+    Latency {
+      min: self.min.mul(times.clone()),
+      max: self.max.mul(times),
+    }
+  }
+}
+
+impl fmt::Display for Latency {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.is_exact() {
+      write!(f, "{} cycles", self.min)
+    } else {
+      write!(f, "[{}, {}] cycles", self.min, self.max)
+    }
+  }
+}
+
+/// Latency of one synthesized statement
+#[derive(Clone, Debug)]
+pub struct SynthLatency {
+  pub module: String,
+  pub stmt: String,
+  pub location: Location<'static>,
+  pub latency: Latency,
+}
+
+impl fmt::Display for SynthLatency {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}.{} ({}): {}", self.module, self.stmt, self.location, self.latency)
+  }
+}
+
+/// Latencies of all synthesized statements in a compiler
+#[derive(Clone, Debug, Default)]
+pub struct LatencyReport {
+  pub synths: Vec<SynthLatency>,
+}
+
+impl LatencyReport {
+  pub fn get(&self, module: &str, stmt: &str) -> Option<&Latency> {
+    self.synths.iter().find(|x| x.module == module && x.stmt == stmt).map(|x| &x.latency)
+  }
+}
+
+impl fmt::Display for LatencyReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for synth in self.synths.iter() {
+      writeln!(f, "{}", synth)?;
+    }
+    Ok(())
+  }
+}
+
+pub fn latency_report(cmtc: &Cmtc) -> LatencyReport {
+  let ctx = Context::new();
+  let (graph, _) = load_graph(cmtc, &ctx);
+
+  let mut synths: Vec<_> = AstSynth::iter_by_type(&graph).map(|(_, x)| x).collect();
+  synths.sort_by_key(|x| x.stmt.0);
+
+  LatencyReport {
+    synths: synths
+      .into_iter()
+      .map(|synth| {
+        let region = Region::get_by_type(&graph, synth.region).unwrap().region.unwrap();
+        SynthLatency {
+          module: cmtc.get_module_name(cmtc.ir.get_region_use(region).unwrap()),
+          stmt: entity_name(cmtc, synth.stmt),
+          location: synth.location,
+          latency: ast_latency(cmtc, &graph, synth.body),
+        }
+      })
+      .collect(),
+  }
+}
+
+/// Check the latency of statements annotated with `Stmt::expect_latency`
+pub fn check_latency_expects(cmtc: &Cmtc) -> Result<(), IronyError> {
+  if cmtc.latency_expects.is_empty() {
+    return Ok(());
+  }
+  let ctx = Context::new();
+  let (graph, stmt2node) = load_graph(cmtc, &ctx);
+
+  for (stmt, expect, location) in cmtc.latency_expects.iter() {
+    let latency = ast_latency(cmtc, &graph, stmt2node[&stmt.0]);
+    let satisfied = match (latency.min.as_const(), latency.max.as_const()) {
+      (Some(min), Some(max)) => expect.min <= min && max <= expect.max,
+      _ => false,
+    };
+    if !satisfied {
+      return Err(IronyError::new(format!(
+        "{}: latency of stmt `{}` is {}, expected {}",
+        location,
+        entity_name(cmtc, *stmt),
+        latency,
+        expect
+      )));
+    }
+  }
+  Ok(())
+}
+
+pub fn ast_latency(cmtc: &Cmtc, graph: &Graph<Component>, ast: NodeIndex) -> Latency {
+  match graph.get_node(ast).unwrap() {
+    Component::AstStep(step) => {
+      if step.waits.is_empty() {
+        Latency::exact(Cycles::Const(1))
+      } else {
+        Latency {
+          min: Cycles::Const(1),
+          max: Cycles::Unbounded,
+        }
+      }
+    },
+    Component::AstSeq(seq) => seq
+      .children
+      .iter()
+      .map(|x| ast_latency(cmtc, graph, *x))
+      .fold(Latency::exact(Cycles::Const(0)), Latency::then),
+    Component::AstPar(par) => par
+      .children
+      .iter()
+      .map(|x| ast_latency(cmtc, graph, *x))
+      .fold(Latency::exact(Cycles::Const(0)), Latency::join),
+    // the empty else branch takes one cycle
+    Component::AstIf(stmt_if) => {
+      ast_latency(cmtc, graph, stmt_if.then).either(Latency::exact(Cycles::Const(1)))
+    },
+    Component::AstIfElse(stmt_if) => {
+      ast_latency(cmtc, graph, stmt_if.then).either(ast_latency(cmtc, graph, stmt_if.alt))
+    },
+    Component::AstFor(stmt_for) => {
+      ast_latency(cmtc, graph, stmt_for.body).repeat(for_trips(cmtc, graph, stmt_for))
+    },
+    // the condition is checked before entering the body
+    Component::AstWhile(stmt_while) => {
+      let body = ast_latency(cmtc, graph, stmt_while.body);
+      Latency {
+        min: body.min.min(Cycles::Const(1)),
+        max: Cycles::Unbounded,
+      }
+    },
+    // one cycle asserting go, the callee's statement, and one cycle seeing done
+    Component::AstCall(call) => match AstSynth::get_by_type(graph, call.callee) {
+      Some(callee) => {
+        ast_latency(cmtc, graph, callee.body).then(Latency::exact(Cycles::Const(2)))
+      },
+      None => Latency {
+        min: Cycles::Const(3),
+        max: Cycles::Unbounded,
+      },
+    },
+    _ => panic!("not an AST node"),
+  }
+}
+
+/// Statement ASTs under `ast`, children before their parents. A body shared by
+/// several statements is visited once.
+pub(super) fn ast_post_order(
+  graph: &Graph<Component>, ast: NodeIndex, order: &mut Vec<NodeIndex>,
+) {
+  if order.contains(&ast) {
+    return;
+  }
+  let children = match graph.get_node(ast).unwrap() {
+    Component::AstStep(_) | Component::AstCall(_) => vec![],
+    Component::AstSeq(x) => x.children.to_owned(),
+    Component::AstPar(x) => x.children.to_owned(),
+    Component::AstIf(x) => vec![x.then],
+    Component::AstIfElse(x) => vec![x.then, x.alt],
+    Component::AstFor(x) => vec![x.body],
+    Component::AstWhile(x) => vec![x.body],
+    _ => panic!("not an AST node"),
+  };
+  for child in children {
+    ast_post_order(graph, child, order);
+  }
+  order.push(ast);
+}
+
+/// Number of iterations of a `for` loop
+pub(super) fn for_trips(
+  cmtc: &Cmtc, graph: &Graph<Component>, stmt_for: &AstFor,
+) -> Cycles {
+  let start = bound_cycles(cmtc, graph, stmt_for.start, stmt_for.c_start);
+  let end = bound_cycles(cmtc, graph, stmt_for.end, stmt_for.c_end);
+  let step = Cycles::Const(stmt_for.c_step.max(1));
+  // the body is executed at least once
+  end.sub(start).ceil_div(step).max(Cycles::Const(1))
+}
+
+fn bound_cycles(
+  cmtc: &Cmtc, graph: &Graph<Component>, wire: NodeIndex, c: usize,
+) -> Cycles {
+  if wire.is_empty() {
+    Cycles::Const(c)
+  } else {
+    let wire = Wire::get_by_type(graph, wire).unwrap();
+    Cycles::Var(entity_name(cmtc, wire.entity_id.unwrap()))
+  }
+}
+
+pub(super) fn entity_name(cmtc: &Cmtc, id: EntityId) -> String {
+  match cmtc.ir.get_entity(id).get_attr("name") {
+    Some(AttributeEnum::StringAttr(x)) => x.0,
+    _ => format!("{}", id.0),
+  }
+}
@@ -2,24 +2,30 @@
 
 use core::panic;
 use std::collections::{hash_map, HashMap, HashSet};
+use std::fs;
 use std::panic::Location;
 
 use irony_cmt::{
   self, AttributeEnum, BoolAttr, ConstantAttr, DataTypeEnum, Entity, EntityEnum,
-  EntityId, Environ, IREvent, IRWire, LocationAttr, OpEnum, OpId, RegionId, StringAttr,
-  UIntType,
+  EntityId, Environ, IREvent, IRWire, IronyError, LocationAttr, OpEnum, OpId, RegionId,
+  StringAttr, UIntType,
 };
 use tgraph::typed_graph::{Context, Graph, NodeIndex, Transaction};
 
+use super::balance::balance_ifs;
 use super::build_fsm::*;
 use super::component::{Component, *};
 use super::construction::*;
+use super::dot::graph_dot;
+use super::pipeline::run_pipeline;
+use super::schedule::schedule_pars;
 use crate::compiler::Cmtc;
 use crate::utils::*;
 
 struct TmpStorage {
   entity2node: HashMap<usize, NodeIndex>,
   region2node: HashMap<usize, NodeIndex>,
+  stmt2node: HashMap<usize, NodeIndex>,
 }
 impl TmpStorage {
   fn get_entity(&self, entity: EntityId) -> NodeIndex {
@@ -31,54 +37,64 @@ impl TmpStorage {
   }
 }
 
-pub fn all_passes(cmtc: &mut Cmtc) -> Graph<Component> {
-  let ctx = Context::new();
-  let mut graph = Graph::<Component>::new(&ctx);
+/// Load regions, entities and statement ASTs of `cmtc` into a new graph, and schedule
+/// `par` statements if `CmtcConfig::schedule_par` is set. Also returns the AST node of
+/// each statement entity.
+pub fn load_graph(
+  cmtc: &Cmtc, ctx: &Context,
+) -> (Graph<Component>, HashMap<usize, NodeIndex>) {
+  let (mut graph, mut stmt2node) = load_unscheduled_graph(cmtc, ctx);
+  if cmtc.config.schedule_par {
+    schedule_pars(cmtc, ctx, &mut graph, &mut stmt2node);
+  }
+  (graph, stmt2node)
+}
+
+/// Load regions, entities and statement ASTs of `cmtc` into a new graph, and balance
+/// `if` statements
+pub(super) fn load_unscheduled_graph(
+  cmtc: &Cmtc, ctx: &Context,
+) -> (Graph<Component>, HashMap<usize, NodeIndex>) {
+  let mut graph = Graph::<Component>::new(ctx);
   let mut tmp = TmpStorage {
     entity2node: HashMap::new(),
     region2node: HashMap::new(),
+    stmt2node: HashMap::new(),
   };
 
   // eprintln!("Load Regions");
-  graph.commit(load_regions(cmtc, &ctx, &mut tmp));
+  graph.commit(load_regions(cmtc, ctx, &mut tmp));
   // eprintln!("Load Entities");
-  graph.commit(load_entities(cmtc, &ctx, &mut tmp));
+  graph.commit(load_entities(cmtc, ctx, &mut tmp));
   // eprintln!("Get event signal");
-  graph.commit(get_event_signal(cmtc, &ctx, &mut tmp));
+  graph.commit(get_event_signal(cmtc, ctx, &mut tmp));
   // eprintln!("Load Ast");
-  graph.commit(load_ast(cmtc, &ctx, &graph, &tmp));
-  // eprintln!("Make FSM");
-  graph.commit(make_fsms(&ctx, &graph));
-  // eprintln!("Generate go done");
-  graph.commit(generate_go_done(&ctx, &graph));
-  // eprintln!("Fsm encoding 1");
-  graph.commit(fsm_encoding_1(&ctx, &graph));
-  // eprintln!("Fsm encoding 2");
-  graph.commit(fsm_encoding_2(&ctx, &graph));
-  // eprintln!("State encode expr");
-  graph.commit(state_encode_expr(&ctx, &graph));
-  // eprintln!("Make state event");
-  graph.commit(make_state_event(&ctx, &graph));
-  // eprintln!("Make transition event");
-  graph.commit(make_transition_event(&ctx, &graph));
-  // eprintln!("Merge event trigger");
-  graph.commit(merge_event_trigger(&ctx, &graph));
-
-  // eprintln!("Cond0 prop");
-  // while let Some(trans) = cond0prop(&ctx, &graph) {
-  // graph.commit(trans);
-  // }
-
-  // eprintln!("Remove reduce");
-  graph.commit(replace_reduce(&ctx, &graph));
+  let trans = load_ast(cmtc, ctx, &graph, &mut tmp);
+  graph.commit(trans);
+  balance_ifs(cmtc, ctx, &mut graph, &tmp.stmt2node);
 
-  // eprintln!("Expr2Wire");
-  graph.commit(expr2wire(&ctx, &graph));
+  (graph, tmp.stmt2node)
+}
 
-  // !eprintln("Merge select node");
-  graph.commit(merge_select_node(&ctx, &graph));
+pub fn all_passes(cmtc: &mut Cmtc) -> Result<Graph<Component>, IronyError> {
+  let ctx = Context::new();
+  let (mut graph, _) = load_graph(cmtc, &ctx);
+  cmtc.gir_pass_stats = run_pipeline(cmtc, &ctx, &mut graph)?;
+  Ok(graph)
+}
 
-  return graph;
+/// Write `graph` to `<dump_gir_dir>/<n>_<pass>.dot` if `dump_gir_dir` is configured
+pub(super) fn dump_graph(
+  cmtc: &Cmtc, graph: &Graph<Component>, n: usize, pass: &str,
+) -> Result<(), IronyError> {
+  let Some(dir) = &cmtc.config.dump_gir_dir else {
+    return Ok(());
+  };
+  fs::create_dir_all(dir)
+    .map_err(|err| IronyError::new(format!("can't create {}: {}", dir.display(), err)))?;
+  let path = dir.join(format!("{:02}_{}.dot", n, pass));
+  fs::write(&path, graph_dot(cmtc, graph))
+    .map_err(|err| IronyError::new(format!("can't write {}: {}", path.display(), err)))
 }
 
 fn load_regions<'a>(
@@ -108,7 +124,7 @@ fn load_entities<'a>(
           parent_id: tmp.get_region(event.parent.unwrap()),
           entity_id: EntityId(event.id),
           signal: NodeIndex::empty(),
-          location: event.location.as_ref().unwrap().0,
+          location: static_location(event.location.as_ref().unwrap()),
         };
         tmp.entity2node.insert(event.id, trans.new_node(Component::Event(x)));
       },
@@ -118,7 +134,7 @@ fn load_entities<'a>(
           width,
           region: tmp.get_region(wire.parent.unwrap()),
           entity_id: Some(EntityId(wire.id)),
-          location: wire.location.as_ref().unwrap().0,
+          location: static_location(wire.location.as_ref().unwrap()),
         };
         tmp.entity2node.insert(wire.id, trans.new_node(Component::Wire(x)));
       },
@@ -149,7 +165,7 @@ fn get_event_signal<'a>(
   trans
 }
 fn load_ast<'a>(
-  cmtc: &Cmtc, ctx: &Context, graph: &Graph<Component>, tmp: &TmpStorage,
+  cmtc: &Cmtc, ctx: &Context, graph: &Graph<Component>, tmp: &mut TmpStorage,
 ) -> Transaction<'a, Component> {
   let mut trans: Transaction<'_, Component> = Transaction::new(ctx);
   let mut entity_ids = HashMap::new();
@@ -178,6 +194,9 @@ fn load_ast<'a>(
       OpEnum::StmtPar(x) => {
         entity_ids.insert(x.lhs.unwrap().0, trans.alloc_node());
       },
+      OpEnum::StmtCall(x) => {
+        entity_ids.insert(x.lhs.unwrap().0, trans.alloc_node());
+      },
       OpEnum::StmtStep(step) => {
         entity_ids.insert(
           step.lhs.unwrap().0,
@@ -208,6 +227,7 @@ fn load_ast<'a>(
           node,
           Component::AstSynth(AstSynth {
             body: entity_ids[&synth.stmt.unwrap().0],
+            stmt: synth.stmt.unwrap(),
             clock: synth.clk.unwrap(),
             prot_evts: synth
               .protocol_events
@@ -333,14 +353,77 @@ fn load_ast<'a>(
           }),
         );
       },
+      OpEnum::StmtCall(call) => {
+        let node = *entity_ids.get(&call.lhs.unwrap().0).unwrap();
+        let done =
+          Event::get_by_type(graph, tmp.get_entity(call.done.unwrap())).unwrap().signal;
+        let callee = callee_synth(cmtc, call.callee_go.unwrap())
+          .map_or(NodeIndex::empty(), |x| synth_ids[&x.0]);
+        trans.fill_back_node(
+          node,
+          Component::AstCall(AstCall {
+            go: tmp.get_entity(call.go.unwrap()),
+            done,
+            callee,
+            location: cmtc_get_entity_location(cmtc, call.lhs.unwrap()),
+          }),
+        );
+      },
       _ => {},
     }
   }
 
+  tmp.stmt2node = entity_ids;
+
   trans
 }
 
-fn make_fsms<'a>(ctx: &Context, graph: &Graph<Component>) -> Transaction<'a, Component> {
+/// The `StmtSynth` whose go event is the port of the instanced module connected to
+/// `callee_go`, an input of an instance
+fn callee_synth(cmtc: &Cmtc, callee_go: EntityId) -> Option<OpId> {
+  let (instance, index) =
+    cmtc.ir.get_uses(callee_go).into_iter().find_map(|x| match cmtc.ir.get_op(x) {
+      OpEnum::HwInstance(instance) => {
+        let index = instance.inputs.iter().position(|y| *y == Some(callee_go))?;
+        Some((instance, index))
+      },
+      _ => None,
+    })?;
+  let module = match cmtc.ir.get_op(instance.target_op_id.as_ref().unwrap().0) {
+    OpEnum::HwModule(module) => module,
+    _ => return None,
+  };
+  let region = cmtc.ir.get_region(module.body.unwrap());
+  let port = region.op_children.iter().find_map(|x| match cmtc.ir.get_op(*x) {
+    OpEnum::HwInput(input) => input.inputs[index],
+    _ => None,
+  })?;
+
+  let go_events: Vec<_> = cmtc
+    .ir
+    .op_table
+    .iter()
+    .filter_map(|(_, op)| match op {
+      OpEnum::EventSignal(x) if x.signal == Some(port) => x.event,
+      _ => None,
+    })
+    .collect();
+  cmtc.ir.op_table.iter().find_map(|(id, op)| match op {
+    OpEnum::StmtSynth(synth)
+      if synth
+        .protocol_events
+        .first()
+        .is_some_and(|x| go_events.contains(&x.unwrap())) =>
+    {
+      Some(OpId(*id))
+    },
+    _ => None,
+  })
+}
+
+pub(super) fn make_fsms<'a>(
+  ctx: &Context, graph: &Graph<Component>,
+) -> Transaction<'a, Component> {
   let mut trans: Transaction<'_, Component> = Transaction::new(ctx);
 
   for (_, synth) in AstSynth::iter_by_type(graph) {
@@ -378,7 +461,7 @@ fn make_fsms<'a>(ctx: &Context, graph: &Graph<Component>) -> Transaction<'a, Com
   trans
 }
 
-fn generate_go_done<'a>(
+pub(super) fn generate_go_done<'a>(
   ctx: &Context, graph: &Graph<Component>,
 ) -> Transaction<'a, Component> {
   let mut trans: Transaction<'_, Component> = Transaction::new(ctx);
@@ -433,7 +516,7 @@ fn generate_go_done<'a>(
 
   trans
 }
-fn fsm_encoding_1<'a>(
+pub(super) fn fsm_encoding_1<'a>(
   ctx: &Context, graph: &Graph<Component>,
 ) -> Transaction<'a, Component> {
   let mut trans = Transaction::new(ctx);
@@ -449,7 +532,7 @@ fn fsm_encoding_1<'a>(
   }
   trans
 }
-fn fsm_encoding_2<'a>(
+pub(super) fn fsm_encoding_2<'a>(
   ctx: &Context, graph: &Graph<Component>,
 ) -> Transaction<'a, Component> {
   let mut trans = Transaction::new(ctx);
@@ -559,7 +642,7 @@ fn traverse_encoding_2<'a>(
 //   trans
 // }
 
-fn state_encode_expr<'a>(
+pub(super) fn state_encode_expr<'a>(
   ctx: &Context, graph: &Graph<Component>,
 ) -> Transaction<'a, Component> {
   let mut trans = Transaction::new(ctx);
@@ -596,7 +679,7 @@ fn state_encode_expr<'a>(
   trans
 }
 
-fn make_state_event<'a>(
+pub(super) fn make_state_event<'a>(
   ctx: &Context, graph: &Graph<Component>,
 ) -> Transaction<'a, Component> {
   let mut trans = Transaction::new(ctx);
@@ -623,7 +706,7 @@ fn make_state_event<'a>(
   trans
 }
 
-fn make_transition_event<'a>(
+pub(super) fn make_transition_event<'a>(
   ctx: &Context, graph: &Graph<Component>,
 ) -> Transaction<'a, Component> {
   let mut trans = Transaction::new(ctx);
@@ -701,7 +784,7 @@ fn make_transition_event<'a>(
   trans
 }
 
-fn cond0prop<'a>(
+pub(super) fn cond0prop<'a>(
   ctx: &Context, graph: &Graph<Component>,
 ) -> Option<Transaction<'a, Component>> {
   let mut trans = Transaction::new(ctx);
@@ -725,7 +808,7 @@ fn cond0prop<'a>(
     None
   }
 }
-fn replace_reduce<'a>(
+pub(super) fn replace_reduce<'a>(
   ctx: &Context, graph: &Graph<Component>,
 ) -> Transaction<'a, Component> {
   let mut trans = Transaction::new(ctx);
@@ -746,7 +829,9 @@ fn replace_reduce<'a>(
   trans
 }
 
-fn expr2wire<'a>(ctx: &Context, graph: &Graph<Component>) -> Transaction<'a, Component> {
+pub(super) fn expr2wire<'a>(
+  ctx: &Context, graph: &Graph<Component>,
+) -> Transaction<'a, Component> {
   let mut trans = Transaction::new(ctx);
 
   for (i, node) in graph.iter_nodes() {
@@ -777,7 +862,7 @@ fn expr2wire<'a>(ctx: &Context, graph: &Graph<Component>) -> Transaction<'a, Com
   trans
 }
 
-fn merge_event_trigger<'a>(
+pub(super) fn merge_event_trigger<'a>(
   ctx: &Context, graph: &Graph<Component>,
 ) -> Transaction<'a, Component> {
   let mut trigger_map = HashMap::new();
@@ -806,9 +891,9 @@ fn merge_event_trigger<'a>(
   trans
 }
 
-fn merge_select_node<'a>(
+pub(super) fn merge_select_node<'a>(
   ctx: &Context, graph: &Graph<Component>,
-) -> Transaction<'a, Component> {
+) -> Result<Transaction<'a, Component>, IronyError> {
   let mut trans = Transaction::new(ctx);
 
   let mut default_assign = HashMap::new();
@@ -816,7 +901,10 @@ fn merge_select_node<'a>(
     if let hash_map::Entry::Vacant(x) = default_assign.entry(assign.lhs) {
       x.insert((id, assign.rhs));
     } else {
-      panic!("Multiple assign into the same lhs!");
+      return Err(IronyError::new(format!(
+        "{}: multiple assigns into the same wire",
+        Wire::get_by_type(graph, assign.lhs).unwrap().location
+      )));
     }
   }
 
@@ -830,10 +918,15 @@ fn merge_select_node<'a>(
   }
 
   for (lhs, cases) in cond_assigns {
-    let (assign_id, default_val) =
-      *default_assign.get(&lhs).expect("No default value for select!");
+    let wire = Wire::get_by_type(graph, lhs).unwrap();
+    let Some((assign_id, default_val)) = default_assign.get(&lhs).copied() else {
+      return Err(IronyError::new(format!(
+        "{}: no default value for select",
+        wire.location
+      )));
+    };
     trans.remove_node(assign_id);
-    let region = Wire::get_by_type(graph, lhs).unwrap().region;
+    let region = wire.region;
     trans.new_node(Component::Select(Select {
       parent_id: region,
       lhs,
@@ -843,7 +936,7 @@ fn merge_select_node<'a>(
     }));
   }
 
-  trans
+  Ok(trans)
 }
 
 fn make_tree_reduce<'a>(
@@ -906,7 +999,7 @@ pub fn retrieve_cmtc(cmtc: &mut Cmtc, graph: Graph<Component>) {
             Some(typ),
             Some(StringAttr(format!("GenWire{}", idx.0))),
             Some(BoolAttr(false)),
-            Some(LocationAttr(wire.location)),
+            Some(LocationAttr::from(&wire.location)),
           )),
         ),
       );
@@ -942,7 +1035,7 @@ pub fn retrieve_cmtc(cmtc: &mut Cmtc, graph: Graph<Component>) {
         None,
         Some(irony_cmt::StringAttr(format!("GenEvent{}", idx.0))),
         Some(irony_cmt::BoolAttr(false)),
-        Some(irony_cmt::LocationAttr(event.location)),
+        Some(irony_cmt::LocationAttr::from(&event.location)),
       )),
     );
     event_map.insert(idx, event_entity);
@@ -1101,8 +1194,14 @@ fn cmtc_get_entity_location(cmtc: &Cmtc, id: EntityId) -> Location<'static> {
   if let AttributeEnum::LocationAttr(x) =
     cmtc.ir.get_entity(id).get_attr("location").unwrap()
   {
-    x.0
+    static_location(&x)
   } else {
     panic!()
   }
 }
+
+/// The location of an entity in the gir, which has no static location to keep when
+/// loaded from a snapshot: the gir locates it here instead
+pub(super) fn static_location(location: &LocationAttr) -> Location<'static> {
+  location.caller().unwrap_or(*Location::caller())
+}
@@ -0,0 +1,194 @@
+//! Configurable pipeline of the control synthesis passes on GIR
+//!
+//! The passes run in the order given by `CmtcConfig::gir_passes`. Each pass is
+//! timed, and the number of nodes before and after it is recorded in a
+//! `GirPassStat`.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use irony_cmt::IronyError;
+use tgraph::typed_graph::{Context, Graph};
+
+use super::component::*;
+use super::passes::*;
+use super::verify::verify_graph;
+use crate::compiler::Cmtc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GirPass {
+  MakeFsms,
+  GenerateGoDone,
+  FsmEncoding1,
+  FsmEncoding2,
+  StateEncodeExpr,
+  MakeStateEvent,
+  MakeTransitionEvent,
+  MergeEventTrigger,
+  /// Fold operations whose operands are all empty conditions, until a fixpoint
+  Cond0Prop,
+  ReplaceReduce,
+  Expr2Wire,
+  MergeSelectNode,
+}
+
+impl GirPass {
+  /// Every pass, in the order they can be applied
+  pub const ALL: [GirPass; 12] = [
+    GirPass::MakeFsms,
+    GirPass::GenerateGoDone,
+    GirPass::FsmEncoding1,
+    GirPass::FsmEncoding2,
+    GirPass::StateEncodeExpr,
+    GirPass::MakeStateEvent,
+    GirPass::MakeTransitionEvent,
+    GirPass::MergeEventTrigger,
+    GirPass::Cond0Prop,
+    GirPass::ReplaceReduce,
+    GirPass::Expr2Wire,
+    GirPass::MergeSelectNode,
+  ];
+
+  /// Passes enabled by default, i.e. all passes but `cond0prop`
+  pub fn default_pipeline() -> Vec<GirPass> {
+    GirPass::ALL.into_iter().filter(|x| *x != GirPass::Cond0Prop).collect()
+  }
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      GirPass::MakeFsms => "make_fsms",
+      GirPass::GenerateGoDone => "generate_go_done",
+      GirPass::FsmEncoding1 => "fsm_encoding_1",
+      GirPass::FsmEncoding2 => "fsm_encoding_2",
+      GirPass::StateEncodeExpr => "state_encode_expr",
+      GirPass::MakeStateEvent => "make_state_event",
+      GirPass::MakeTransitionEvent => "make_transition_event",
+      GirPass::MergeEventTrigger => "merge_event_trigger",
+      GirPass::Cond0Prop => "cond0prop",
+      GirPass::ReplaceReduce => "replace_reduce",
+      GirPass::Expr2Wire => "expr2wire",
+      GirPass::MergeSelectNode => "merge_select_node",
+    }
+  }
+
+  /// Apply the pass, returns the number of committed transactions
+  pub fn run(
+    &self, ctx: &Context, graph: &mut Graph<Component>,
+  ) -> Result<usize, IronyError> {
+    let trans = match self {
+      GirPass::MakeFsms => make_fsms(ctx, graph),
+      GirPass::GenerateGoDone => generate_go_done(ctx, graph),
+      GirPass::FsmEncoding1 => fsm_encoding_1(ctx, graph),
+      GirPass::FsmEncoding2 => fsm_encoding_2(ctx, graph),
+      GirPass::StateEncodeExpr => state_encode_expr(ctx, graph),
+      GirPass::MakeStateEvent => make_state_event(ctx, graph),
+      GirPass::MakeTransitionEvent => make_transition_event(ctx, graph),
+      GirPass::MergeEventTrigger => merge_event_trigger(ctx, graph),
+      GirPass::Cond0Prop => {
+        let mut runs = 0;
+        while let Some(trans) = cond0prop(ctx, graph) {
+          graph.commit(trans);
+          runs += 1;
+        }
+        return Ok(runs);
+      },
+      GirPass::ReplaceReduce => replace_reduce(ctx, graph),
+      GirPass::Expr2Wire => expr2wire(ctx, graph),
+      GirPass::MergeSelectNode => merge_select_node(ctx, graph)?,
+    };
+    graph.commit(trans);
+    Ok(1)
+  }
+}
+
+impl fmt::Display for GirPass {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.name())
+  }
+}
+
+impl FromStr for GirPass {
+  type Err = IronyError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    GirPass::ALL.into_iter().find(|x| x.name() == s).ok_or_else(|| {
+      let names: Vec<_> = GirPass::ALL.iter().map(GirPass::name).collect();
+      IronyError::new(format!(
+        "unknown gir pass `{}`, expected one of: {}",
+        s,
+        names.join(", ")
+      ))
+    })
+  }
+}
+
+/// Parse a comma-separated list of pass names, e.g. `make_fsms,generate_go_done`
+pub fn parse_pipeline(s: &str) -> Result<Vec<GirPass>, IronyError> {
+  s.split(',').map(str::trim).filter(|x| !x.is_empty()).map(str::parse).collect()
+}
+
+#[derive(Clone, Debug)]
+pub struct GirPassStat {
+  pub pass: GirPass,
+  pub time: Duration,
+  pub nodes_before: usize,
+  pub nodes_after: usize,
+  /// Number of committed transactions, more than one for fixpoint passes
+  pub runs: usize,
+}
+
+impl fmt::Display for GirPassStat {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{:<24} {:>12?} {:>8} -> {:<8} runs: {}",
+      self.pass.name(),
+      self.time,
+      self.nodes_before,
+      self.nodes_after,
+      self.runs
+    )
+  }
+}
+
+/// Run the passes configured in `cmtc` on `graph`
+pub fn run_pipeline(
+  cmtc: &Cmtc, ctx: &Context, graph: &mut Graph<Component>,
+) -> Result<Vec<GirPassStat>, IronyError> {
+  let passes = cmtc.config.gir_pipeline()?;
+  let mut stats = Vec::new();
+  if cmtc.config.verify_gir {
+    verify(graph, "load_graph")?;
+  }
+  dump_graph(cmtc, graph, 0, "load_graph")?;
+
+  for (i, pass) in passes.iter().enumerate() {
+    let nodes_before = graph.len();
+    let start = Instant::now();
+    let runs = pass.run(ctx, graph)?;
+    let stat = GirPassStat {
+      pass: *pass,
+      time: start.elapsed(),
+      nodes_before,
+      nodes_after: graph.len(),
+      runs,
+    };
+    if cmtc.config.gir_stats {
+      eprintln!("{}", stat);
+    }
+    stats.push(stat);
+
+    if cmtc.config.verify_gir {
+      verify(graph, pass.name())?;
+    }
+    dump_graph(cmtc, graph, i + 1, pass.name())?;
+  }
+  Ok(stats)
+}
+
+fn verify(graph: &Graph<Component>, pass: &str) -> Result<(), IronyError> {
+  verify_graph(graph).map_err(|err| {
+    IronyError::new(format!("gir verification failed after `{}`: {}", pass, err))
+  })
+}
@@ -0,0 +1,281 @@
+//! Resource-constrained scheduling of `par` branches
+//!
+//! Events may be annotated with the resources they use, e.g. a BRAM port, with
+//! `Event::uses_resource`. Each resource can be used by one event per cycle. The
+//! branches of a `par` are list scheduled, longest latency first: every branch
+//! starts at the earliest cycle where it uses no resource taken by the branches
+//! scheduled before it, and is delayed by empty steps until then. A branch whose
+//! resource usage isn't known at compile time is run after the `par` instead.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::panic::Location;
+
+use itertools::Itertools;
+use tgraph::typed_graph::{Context, Graph, NodeIndex, Transaction};
+
+use super::component::*;
+use super::latency::{ast_latency, ast_post_order, entity_name, Cycles, Latency};
+use super::passes::load_unscheduled_graph;
+use super::timing::{visit, Window};
+use crate::compiler::Cmtc;
+
+/// Windows with more activations than this are not enumerated
+const MAX_CYCLES: usize = 1 << 16;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BranchSchedule {
+  /// Index of the branch in the `par`
+  pub branch: usize,
+  pub resources: Vec<String>,
+  /// Number of cycles the branch is delayed
+  pub delay: usize,
+  /// The branch is run after the `par`, as its resource usage is not static
+  pub serialized: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct ParSchedule {
+  pub stmt: String,
+  pub location: Location<'static>,
+  /// Branches in the order they are scheduled
+  pub branches: Vec<BranchSchedule>,
+}
+
+impl ParSchedule {
+  pub fn branch(&self, branch: usize) -> Option<&BranchSchedule> {
+    self.branches.iter().find(|x| x.branch == branch)
+  }
+}
+
+impl fmt::Display for ParSchedule {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "{} ({}):", self.stmt, self.location)?;
+    for x in self.branches.iter() {
+      write!(f, "  branch {} [{}]: ", x.branch, x.resources.join(", "))?;
+      if x.serialized {
+        writeln!(f, "after the par")?;
+      } else {
+        writeln!(f, "delayed {} cycles", x.delay)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Schedules of all `par` statements with branches sharing resources
+#[derive(Clone, Debug, Default)]
+pub struct ScheduleReport {
+  pub pars: Vec<ParSchedule>,
+}
+
+impl fmt::Display for ScheduleReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for par in self.pars.iter() {
+      write!(f, "{}", par)?;
+    }
+    Ok(())
+  }
+}
+
+/// Cycles relative to the start of a branch in which it uses each resource, `None`
+/// if they are not known at compile time
+type Usage = HashMap<String, Option<BTreeSet<usize>>>;
+
+pub fn schedule_report(cmtc: &Cmtc) -> ScheduleReport {
+  let ctx = Context::new();
+  let (mut graph, mut stmt2node) = load_unscheduled_graph(cmtc, &ctx);
+  schedule_pars(cmtc, &ctx, &mut graph, &mut stmt2node)
+}
+
+/// Schedule every `par` of the ASTs in `graph`, inner ones first. A `par` with
+/// serialized branches is replaced in `stmt2node` by the sequence running them.
+pub(super) fn schedule_pars(
+  cmtc: &Cmtc, ctx: &Context, graph: &mut Graph<Component>,
+  stmt2node: &mut HashMap<usize, NodeIndex>,
+) -> ScheduleReport {
+  let mut resources: HashMap<usize, Vec<String>> = HashMap::new();
+  for (event, resource) in cmtc.event_resources.iter() {
+    resources.entry(event.0).or_default().push(resource.to_owned());
+  }
+  let node2stmt: HashMap<_, _> = stmt2node.iter().map(|(k, v)| (*v, *k)).collect();
+
+  let mut asts = Vec::new();
+  let synths = AstSynth::iter_by_type(graph).sorted_by_key(|(_, x)| x.stmt.0);
+  for (_, synth) in synths {
+    ast_post_order(graph, synth.body, &mut asts);
+  }
+  let pars: Vec<_> = asts
+    .into_iter()
+    .filter(|x| matches!(graph.get_node(*x), Some(Component::AstPar(_))))
+    .collect();
+
+  let mut report = ScheduleReport::default();
+  for par_idx in pars {
+    let par = AstPar::get_by_type(graph, par_idx).unwrap().to_owned();
+    let usages: Vec<_> =
+      par.children.iter().map(|x| branch_usage(cmtc, graph, *x, &resources)).collect();
+
+    // only schedule branches sharing a resource with another one
+    let shared: Vec<_> = usages
+      .iter()
+      .flat_map(|x| x.keys())
+      .counts()
+      .into_iter()
+      .filter(|(_, n)| *n > 1)
+      .map(|(x, _)| x.to_owned())
+      .collect();
+    if shared.is_empty() {
+      continue;
+    }
+
+    let latencies: Vec<_> =
+      par.children.iter().map(|x| ast_latency(cmtc, graph, *x)).collect();
+    let order = (0..par.children.len())
+      .sorted_by_key(|i| (std::cmp::Reverse(priority(&latencies[*i])), *i));
+
+    // cycles each resource is taken in, `None` if taken during the whole `par`
+    let mut taken: HashMap<&String, Option<BTreeSet<usize>>> = HashMap::new();
+    let mut branches = Vec::new();
+    for i in order {
+      let usage: Vec<_> = usages[i].iter().filter(|(x, _)| shared.contains(x)).collect();
+      let mut schedule = BranchSchedule {
+        branch: i,
+        resources: usages[i].keys().sorted().cloned().collect(),
+        delay: 0,
+        serialized: false,
+      };
+      let unknown = usage.iter().any(|(x, cycles)| {
+        taken.contains_key(x) && (cycles.is_none() || taken[x].is_none())
+      });
+      if unknown {
+        schedule.serialized = true;
+      } else {
+        schedule.delay = (0..)
+          .find(|d| {
+            usage.iter().all(|(x, cycles)| match (taken.get(x), cycles) {
+              (Some(Some(t)), Some(cycles)) => {
+                cycles.iter().all(|c| !t.contains(&(c + d)))
+              },
+              _ => true,
+            })
+          })
+          .unwrap();
+        for (x, cycles) in usage {
+          let delayed = cycles.as_ref().map(|y| y.iter().map(|c| c + schedule.delay));
+          match (taken.entry(x).or_insert(Some(BTreeSet::new())), delayed) {
+            (Some(t), Some(delayed)) => t.extend(delayed),
+            (t, _) => *t = None,
+          }
+        }
+      }
+      branches.push(schedule);
+    }
+
+    let stmt = node2stmt
+      .get(&par_idx)
+      .map_or("par".to_string(), |x| entity_name(cmtc, irony_cmt::EntityId(*x)));
+    let schedule = ParSchedule { stmt, location: par.location, branches };
+    let (trans, seq) = rewrite_par(ctx, par_idx, &par, &schedule);
+    graph.commit(trans);
+    if let (Some(seq), Some(stmt)) = (seq, node2stmt.get(&par_idx)) {
+      stmt2node.insert(*stmt, seq);
+    }
+    report.pars.push(schedule);
+  }
+  report
+}
+
+fn branch_usage(
+  cmtc: &Cmtc, graph: &Graph<Component>, branch: NodeIndex,
+  resources: &HashMap<usize, Vec<String>>,
+) -> Usage {
+  let mut activity = HashMap::new();
+  let start = Latency::exact(Cycles::Const(1));
+  visit(cmtc, graph, branch, start, &Vec::new(), false, &mut activity);
+
+  let mut usage: Usage = HashMap::new();
+  for (event, windows) in activity {
+    let entity_id = Event::get_by_type(graph, event).unwrap().entity_id;
+    for resource in resources.get(&entity_id.0).into_iter().flatten() {
+      let cycles = usage.entry(resource.to_owned()).or_insert(Some(BTreeSet::new()));
+      for window in windows.iter() {
+        match (window_cycles(window), cycles.as_mut()) {
+          (Some(x), Some(cycles)) => cycles.extend(x),
+          _ => *cycles = None,
+        }
+      }
+    }
+  }
+  usage
+}
+
+/// Every cycle a window may be active in
+fn window_cycles(window: &Window) -> Option<Vec<usize>> {
+  let mut cycles = vec![window.first.min.as_const()?];
+  if !window.first.is_exact() {
+    return None;
+  }
+  for (stride, count) in window.repeats.iter() {
+    let (stride, count) = (stride.as_const()?, count.as_const()?);
+    if cycles.len() * count > MAX_CYCLES {
+      return None;
+    }
+    cycles =
+      (0..count).flat_map(|i| cycles.iter().map(move |c| c + i * stride)).collect();
+  }
+  Some(cycles)
+}
+
+/// Branches with longer latencies are scheduled first
+fn priority(latency: &Latency) -> usize { latency.max.as_const().unwrap_or(usize::MAX) }
+
+/// Delay the branches of `par` by empty steps, and run the serialized ones in a
+/// sequence after it, which is also returned
+fn rewrite_par<'a>(
+  ctx: &Context, par_idx: NodeIndex, par: &AstPar, schedule: &ParSchedule,
+) -> (Transaction<'a, Component>, Option<NodeIndex>) {
+  let mut trans = Transaction::new(ctx);
+  let mut children = Vec::new();
+  let mut serialized = Vec::new();
+  for (i, child) in par.children.iter().enumerate() {
+    let x = schedule.branch(i);
+    match x {
+      Some(x) if x.serialized => serialized.push(*child),
+      Some(x) if x.delay > 0 => {
+        let mut seq: Vec<_> = (0..x.delay)
+          .map(|_| {
+            trans.new_node(Component::AstStep(AstStep {
+              events: Default::default(),
+              waits: Vec::new(),
+              location: par.location,
+            }))
+          })
+          .collect();
+        seq.push(*child);
+        children.push(
+          trans.new_node(Component::AstSeq(AstSeq {
+            children: seq,
+            location: par.location,
+          })),
+        );
+      },
+      _ => children.push(*child),
+    }
+  }
+
+  trans.mut_node(par_idx, move |x| {
+    if let Component::AstPar(y) = x {
+      y.children = children;
+    }
+  });
+  if serialized.is_empty() {
+    return (trans, None);
+  }
+  let mut seq = vec![par_idx];
+  seq.extend(serialized);
+  let seq =
+    trans.new_node(Component::AstSeq(AstSeq { children: seq, location: par.location }));
+  trans.redirect_node(par_idx, seq);
+  (trans, Some(seq))
+}
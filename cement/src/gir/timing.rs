@@ -0,0 +1,443 @@
+//! Cycle-level timing analysis of events and the wires they drive
+//!
+//! Cycles are counted relative to the accepted `go`: the FSM leaves the idle state
+//! in cycle 1. While the FSM is idle, including the cycles in reset, no event is
+//! active and every guarded wire keeps its default value.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::panic::Location;
+
+use irony_cmt::{AttributeEnum, Entity, EntityId, Environ, OpEnum, OpId, TmpSelect};
+use tgraph::typed_graph::{Context, Graph, NodeIndex};
+
+use super::component::*;
+use super::latency::{ast_latency, entity_name, for_trips, Cycles, Latency};
+use super::passes::{load_graph, static_location};
+use crate::compiler::{Cmtc, CmtcBasics};
+
+/// Cycles in which an event may be active. The first activation falls in `first`,
+/// and is repeated by every enclosing loop with an exact latency.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Window {
+  pub first: Latency,
+  /// `(stride, count)` of the enclosing loops, outermost first
+  pub repeats: Vec<(Cycles, Cycles)>,
+  /// Only active if the conditions of enclosing branches hold
+  pub conditional: bool,
+}
+
+impl Window {
+  /// All activations happen in cycles known at compile time, possibly in terms of
+  /// loop bounds
+  pub fn is_static(&self) -> bool {
+    self.first.is_exact()
+      && self
+        .repeats
+        .iter()
+        .all(|(stride, count)| !stride.is_unbounded() && !count.is_unbounded())
+  }
+
+  /// The earliest cycle the window may be active
+  pub fn earliest(&self) -> Cycles { self.first.min.clone() }
+
+  /// The latest cycle the window may be active
+  pub fn latest(&self) -> Cycles {
+    self.repeats.iter().fold(self.first.max.clone(), |acc, (stride, count)| {
+      acc.add(stride.clone().mul(count.clone().sub(Cycles::Const(1))))
+    })
+  }
+}
+
+impl fmt::Display for Window {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.first.is_exact() {
+      write!(f, "@{}", self.first.min)?;
+    } else {
+      write!(f, "@[{}, {}]", self.first.min, self.first.max)?;
+    }
+    for (stride, count) in self.repeats.iter() {
+      write!(f, " every {} x{}", stride, count)?;
+    }
+    if self.conditional {
+      write!(f, " if taken")?;
+    }
+    Ok(())
+  }
+}
+
+fn fmt_windows(f: &mut fmt::Formatter<'_>, windows: &[Window]) -> fmt::Result {
+  if windows.is_empty() {
+    return write!(f, "never");
+  }
+  for (i, window) in windows.iter().enumerate() {
+    if i != 0 {
+      write!(f, ", ")?;
+    }
+    write!(f, "{}", window)?;
+  }
+  Ok(())
+}
+
+/// Activity of an `event!`
+#[derive(Clone, Debug)]
+pub struct EventTiming {
+  pub name: String,
+  pub location: Location<'static>,
+  pub windows: Vec<Window>,
+}
+
+/// Activity of a wire guarded by an event
+#[derive(Clone, Debug)]
+pub struct WireTiming {
+  pub name: String,
+  pub location: Location<'static>,
+  pub event: String,
+  pub windows: Vec<Window>,
+}
+
+/// Activity of each candidate of a `select`. The default is taken in every other
+/// cycle, including idle and reset.
+#[derive(Clone, Debug)]
+pub struct SelectTiming {
+  pub name: String,
+  pub location: Location<'static>,
+  /// Candidate value and the cycles it is selected in. `None` if the condition is
+  /// not driven by an event of the statement.
+  pub cases: Vec<(String, Option<Vec<Window>>)>,
+  pub default: Option<String>,
+}
+
+/// Bounds on the activity of an event that can't be determined statically, to be
+/// checked at runtime. Only reported: no check is added to the IR.
+#[derive(Clone, Debug)]
+pub struct TimingAssertion {
+  pub event: String,
+  pub location: Location<'static>,
+  pub earliest: Cycles,
+  /// `Cycles::Unbounded` if the event may be active until `done`
+  pub latest: Cycles,
+}
+
+impl fmt::Display for TimingAssertion {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.latest.is_unbounded() {
+      write!(f, "assert {} only from cycle {} until done", self.event, self.earliest)
+    } else {
+      write!(
+        f,
+        "assert {} only in cycles [{}, {}]",
+        self.event, self.earliest, self.latest
+      )
+    }
+  }
+}
+
+/// Timing of one synthesized statement
+#[derive(Clone, Debug)]
+pub struct SynthTiming {
+  pub module: String,
+  pub stmt: String,
+  pub location: Location<'static>,
+  pub events: Vec<EventTiming>,
+  pub wires: Vec<WireTiming>,
+  pub selects: Vec<SelectTiming>,
+  pub assertions: Vec<TimingAssertion>,
+}
+
+impl SynthTiming {
+  pub fn event(&self, name: &str) -> Option<&EventTiming> {
+    self.events.iter().find(|x| x.name == name)
+  }
+
+  pub fn wire(&self, name: &str) -> Option<&WireTiming> {
+    self.wires.iter().find(|x| x.name == name)
+  }
+}
+
+impl fmt::Display for SynthTiming {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "{}.{} ({}):", self.module, self.stmt, self.location)?;
+    for event in self.events.iter() {
+      write!(f, "  event {}: ", event.name)?;
+      fmt_windows(f, &event.windows)?;
+      writeln!(f)?;
+    }
+    for wire in self.wires.iter() {
+      write!(f, "  wire {} (by {}): ", wire.name, wire.event)?;
+      fmt_windows(f, &wire.windows)?;
+      writeln!(f)?;
+    }
+    for select in self.selects.iter() {
+      writeln!(f, "  select {}:", select.name)?;
+      for (value, windows) in select.cases.iter() {
+        write!(f, "    {}: ", value)?;
+        match windows {
+          Some(windows) => fmt_windows(f, windows)?,
+          None => write!(f, "unknown")?,
+        }
+        writeln!(f)?;
+      }
+      match &select.default {
+        Some(default) => writeln!(f, "    {}: otherwise", default)?,
+        None => writeln!(f, "    undefined: otherwise")?,
+      }
+    }
+    for assertion in self.assertions.iter() {
+      writeln!(f, "  {}", assertion)?;
+    }
+    Ok(())
+  }
+}
+
+/// Timing of all synthesized statements in a compiler
+#[derive(Clone, Debug, Default)]
+pub struct TimingReport {
+  pub synths: Vec<SynthTiming>,
+}
+
+impl fmt::Display for TimingReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for synth in self.synths.iter() {
+      write!(f, "{}", synth)?;
+    }
+    Ok(())
+  }
+}
+
+pub fn timing_report(cmtc: &Cmtc) -> TimingReport {
+  let ctx = Context::new();
+  let (graph, _) = load_graph(cmtc, &ctx);
+
+  let drivers = EventDrivers::new(cmtc);
+  // the selects with a candidate selected by each event
+  let mut selects_of: HashMap<usize, Vec<OpId>> = HashMap::new();
+  for select_id in drivers.selects.iter() {
+    let OpEnum::TmpSelect(select) = cmtc.ir.get_op(*select_id) else { unreachable!() };
+    for event in drivers.select_guards(select).into_iter().flatten() {
+      selects_of.entry(event.0).or_default().push(*select_id);
+    }
+  }
+
+  let mut synths: Vec<_> = AstSynth::iter_by_type(&graph).map(|(_, x)| x).collect();
+  synths.sort_by_key(|x| x.stmt.0);
+
+  let mut report = TimingReport::default();
+  for synth in synths {
+    let mut activity = HashMap::new();
+    visit(
+      cmtc,
+      &graph,
+      synth.body,
+      Latency::exact(Cycles::Const(1)),
+      &Vec::new(),
+      false,
+      &mut activity,
+    );
+
+    // keyed by entity
+    let mut windows: HashMap<usize, Vec<Window>> = HashMap::new();
+    for (node, x) in activity {
+      let event = Event::get_by_type(&graph, node).unwrap();
+      windows.entry(event.entity_id.0).or_default().extend(x);
+    }
+    let mut event_ids: Vec<_> = windows.keys().copied().collect();
+    event_ids.sort();
+
+    let events: Vec<_> = event_ids
+      .iter()
+      .map(|id| EventTiming {
+        name: entity_name(cmtc, EntityId(*id)),
+        location: entity_location(cmtc, EntityId(*id)),
+        windows: windows[id].to_owned(),
+      })
+      .collect();
+
+    let mut wires = Vec::new();
+    for id in event_ids.iter() {
+      for wire in drivers.guarded.get(id).into_iter().flatten() {
+        wires.push(WireTiming {
+          name: entity_name(cmtc, *wire),
+          location: entity_location(cmtc, *wire),
+          event: entity_name(cmtc, EntityId(*id)),
+          windows: windows[id].to_owned(),
+        });
+      }
+    }
+
+    let mut select_ids: Vec<_> = event_ids
+      .iter()
+      .flat_map(|id| selects_of.get(id).into_iter().flatten())
+      .copied()
+      .collect();
+    select_ids.sort_by_key(|x| x.0);
+    select_ids.dedup();
+
+    let mut selects = Vec::new();
+    for select_id in select_ids {
+      let OpEnum::TmpSelect(select) = cmtc.ir.get_op(select_id) else { unreachable!() };
+      let cases: Vec<_> = drivers
+        .select_guards(select)
+        .into_iter()
+        .zip(select.values.iter())
+        .map(|(event, value)| {
+          let windows = event.and_then(|x| windows.get(&x.0)).cloned();
+          (entity_name(cmtc, value.unwrap()), windows)
+        })
+        .collect();
+      let lhs = select.lhs.unwrap();
+      selects.push(SelectTiming {
+        name: entity_name(cmtc, lhs),
+        location: entity_location(cmtc, lhs),
+        cases,
+        default: select.default.map(|x| entity_name(cmtc, x)),
+      });
+    }
+
+    let assertions = events
+      .iter()
+      .filter(|x| !x.windows.iter().all(Window::is_static))
+      .map(|x| TimingAssertion {
+        event: x.name.to_owned(),
+        location: x.location,
+        earliest: x.windows.iter().map(Window::earliest).reduce(Cycles::min).unwrap(),
+        latest: x.windows.iter().map(Window::latest).reduce(Cycles::max).unwrap(),
+      })
+      .collect();
+
+    let region = Region::get_by_type(&graph, synth.region).unwrap().region.unwrap();
+    report.synths.push(SynthTiming {
+      module: cmtc.get_module_name(cmtc.ir.get_region_use(region).unwrap()),
+      stmt: entity_name(cmtc, synth.stmt),
+      location: synth.location,
+      events,
+      wires,
+      selects,
+      assertions,
+    });
+  }
+
+  report
+}
+
+/// How `event!`s drive wires in the IR
+pub(super) struct EventDrivers {
+  /// Wires assigned in the body of each `event!`
+  pub guarded: HashMap<usize, Vec<EntityId>>,
+  pub selects: Vec<OpId>,
+  guard_of: HashMap<usize, EntityId>,
+  signal2event: HashMap<usize, EntityId>,
+}
+
+impl EventDrivers {
+  pub fn new(cmtc: &Cmtc) -> Self {
+    let mut guarded: HashMap<usize, Vec<EntityId>> = HashMap::new();
+    let mut signal2event = HashMap::new();
+    let mut selects = Vec::new();
+    for (id, op) in cmtc.ir.op_table.iter() {
+      match op {
+        OpEnum::TmpWhen(when) => {
+          let wires = guarded.entry(when.cond.unwrap().0).or_default();
+          for op_id in cmtc.ir.get_region(when.body.unwrap()).op_children.iter() {
+            // skip the temporary wires of expressions
+            if let OpEnum::Assign(assign) = cmtc.ir.get_op(*op_id) {
+              wires.extend(assign.lhs);
+            }
+          }
+        },
+        OpEnum::EventSignal(x) => {
+          signal2event.insert(x.signal.unwrap().0, x.event.unwrap());
+        },
+        OpEnum::TmpSelect(_) => selects.push(OpId(*id)),
+        _ => {},
+      }
+    }
+    let mut guard_of = HashMap::new();
+    for (event, wires) in guarded.iter() {
+      for wire in wires {
+        guard_of.insert(wire.0, EntityId(*event));
+      }
+    }
+    EventDrivers { guarded, selects, guard_of, signal2event }
+  }
+
+  /// The event selecting each candidate of a `select`, if any
+  pub fn select_guards(&self, select: &TmpSelect) -> Vec<Option<EntityId>> {
+    select
+      .conds
+      .iter()
+      .zip(select.values.iter())
+      .map(|(cond, value)| match cond {
+        Some(cond) => self.signal2event.get(&cond.0).copied(),
+        None => self.guard_of.get(&value.unwrap().0).copied(),
+      })
+      .collect()
+  }
+}
+
+pub(super) fn visit(
+  cmtc: &Cmtc, graph: &Graph<Component>, ast: NodeIndex, at: Latency,
+  repeats: &Vec<(Cycles, Cycles)>, conditional: bool,
+  activity: &mut HashMap<NodeIndex, Vec<Window>>,
+) {
+  match graph.get_node(ast).unwrap() {
+    Component::AstStep(step) => {
+      for event in step.events.iter() {
+        activity.entry(*event).or_default().push(Window {
+          first: at.clone(),
+          repeats: repeats.to_owned(),
+          conditional,
+        });
+      }
+    },
+    Component::AstSeq(seq) => {
+      let mut at = at;
+      for child in seq.children.iter() {
+        visit(cmtc, graph, *child, at.clone(), repeats, conditional, activity);
+        at = at.then(ast_latency(cmtc, graph, *child));
+      }
+    },
+    Component::AstPar(par) => {
+      for child in par.children.iter() {
+        visit(cmtc, graph, *child, at.clone(), repeats, conditional, activity);
+      }
+    },
+    Component::AstIf(stmt_if) => {
+      visit(cmtc, graph, stmt_if.then, at, repeats, true, activity);
+    },
+    Component::AstIfElse(stmt_if) => {
+      visit(cmtc, graph, stmt_if.then, at.clone(), repeats, true, activity);
+      visit(cmtc, graph, stmt_if.alt, at, repeats, true, activity);
+    },
+    Component::AstFor(stmt_for) => {
+      let body = ast_latency(cmtc, graph, stmt_for.body);
+      if body.is_exact() {
+        let mut repeats = repeats.to_owned();
+        repeats.push((body.min, for_trips(cmtc, graph, stmt_for)));
+        visit(cmtc, graph, stmt_for.body, at, &repeats, conditional, activity);
+      } else {
+        let at = Latency { min: at.min, max: Cycles::Unbounded };
+        visit(cmtc, graph, stmt_for.body, at, repeats, conditional, activity);
+      }
+    },
+    Component::AstWhile(stmt_while) => {
+      let at = Latency { min: at.min, max: Cycles::Unbounded };
+      visit(cmtc, graph, stmt_while.body, at, repeats, true, activity);
+    },
+    Component::AstCall(call) => {
+      activity.entry(call.go).or_default().push(Window {
+        first: at,
+        repeats: repeats.to_owned(),
+        conditional,
+      });
+    },
+    _ => panic!("not an AST node"),
+  }
+}
+
+pub(super) fn entity_location(cmtc: &Cmtc, id: EntityId) -> Location<'static> {
+  match cmtc.ir.get_entity(id).get_attr("location") {
+    Some(AttributeEnum::LocationAttr(x)) => static_location(&x),
+    _ => panic!("entity has no location"),
+  }
+}
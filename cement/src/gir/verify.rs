@@ -0,0 +1,53 @@
+//! Structural checks of GIR, run between passes if `CmtcConfig::verify_gir` is set
+
+use tgraph::typed_graph::{Graph, NodeEnum, NodeIndex};
+
+use super::component::*;
+
+/// Check that every source of a node exists, and that every FSM only refers to its
+/// own states and transitions
+pub fn verify_graph(graph: &Graph<Component>) -> Result<(), String> {
+  for (idx, node) in graph.iter_nodes() {
+    for (source, field) in node.iter_source() {
+      if !source.is_empty() && graph.get_node(source).is_none() {
+        return Err(format!(
+          "{:?} of node {} refers to removed node {}",
+          field, idx.0, source.0
+        ));
+      }
+    }
+  }
+
+  for (fsm_idx, fsm) in FSM::iter_by_type(graph) {
+    let is_state = |x: NodeIndex| {
+      matches!(graph.get_node(x), Some(Component::State(_) | Component::EncodedState(_)))
+    };
+    if !fsm.states.contains(&fsm.idle_state) {
+      return Err(format!("idle state of FSM {} is not one of its states", fsm_idx.0));
+    }
+    if let Some(x) = fsm.states.iter().find(|x| !is_state(**x)) {
+      return Err(format!("node {} of FSM {} is not a state", x.0, fsm_idx.0));
+    }
+    if !matches!(graph.get_node(fsm.state_root), Some(Component::ExcNode(_))) {
+      return Err(format!("state root of FSM {} is not an ExcNode", fsm_idx.0));
+    }
+    for transit_idx in fsm.transitions.iter() {
+      let Some(transit) = Transition::get_by_type(graph, *transit_idx) else {
+        return Err(format!(
+          "node {} of FSM {} is not a transition",
+          transit_idx.0, fsm_idx.0
+        ));
+      };
+      for x in transit.froms.iter().chain([&transit.to]) {
+        if !fsm.states.contains(x) {
+          return Err(format!(
+            "transition {} of FSM {} refers to node {}, which is not its state",
+            transit_idx.0, fsm_idx.0, x.0
+          ));
+        }
+      }
+    }
+  }
+
+  Ok(())
+}
@@ -14,6 +14,12 @@ impl Event {
     c.specify_event_eq_signal(self, t);
   }
 
+  /// Declare that the event uses `resource`, e.g. a BRAM port, which only one event
+  /// can use in a cycle. See `CmtcConfig::schedule_par`.
+  pub fn uses_resource(&self, resource: &str, c: &mut Cmtc) {
+    c.event_resources.push((self.entity_id.to_owned(), resource.to_string()));
+  }
+
   pub fn expr(&self) -> Expr<B<1>> {
     Expr {
       ifc: B1,
@@ -1,8 +1,16 @@
-use super::Event;
-use crate::preclude::{Cmtc, CmtcBasics, CmtcStmt};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::panic::Location;
+
+use super::{ConnectExpr, Event, ToExpr, B, I, O};
+use crate::preclude::{Cmtc, CmtcBasics, CmtcEvent, CmtcStmt};
 
 mod protocol;
-use irony_cmt::{EntityId, StmtFor, StmtIf, StmtSeq, StmtStep, StmtWhile};
+mod unroll;
+use irony_cmt::{
+  EntityId, IronyError, StmtCall, StmtFor, StmtIf, StmtPar, StmtSeq, StmtStep, StmtWhile,
+};
 pub use protocol::*;
 
 pub struct Stmt {
@@ -10,7 +18,181 @@ pub struct Stmt {
   pub ast: StmtAst,
 }
 
+/// Inclusive range of clock cycles
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LatencyRange {
+  pub min: usize,
+  pub max: usize,
+}
+
+impl From<usize> for LatencyRange {
+  fn from(value: usize) -> Self { LatencyRange { min: value, max: value } }
+}
+
+impl From<RangeInclusive<usize>> for LatencyRange {
+  fn from(value: RangeInclusive<usize>) -> Self {
+    LatencyRange { min: *value.start(), max: *value.end() }
+  }
+}
+
+impl fmt::Display for LatencyRange {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.min == self.max {
+      write!(f, "{} cycles", self.min)
+    } else {
+      write!(f, "[{}, {}] cycles", self.min, self.max)
+    }
+  }
+}
+
 impl Stmt {
+  /// Require the synthesized statement to take `latency` cycles, e.g. `4` or `2..=5`.
+  /// `Cmtc::elaborate` fails if the latency analysis disagrees.
+  #[track_caller]
+  pub fn expect_latency<T: Into<LatencyRange>>(self, latency: T) -> Self {
+    Stmt {
+      name: self.name.to_owned(),
+      ast: StmtAst::Expect(ExpectStmt {
+        stmt: Box::new(self),
+        latency: latency.into(),
+        location: *Location::caller(),
+      }),
+    }
+  }
+
+  /// Replicate the body of a `for` statement with constant bounds for every induction
+  /// value
+  pub fn unroll_full(self) -> Self { self.with_unroll(Unroll::Full) }
+
+  /// Replicate the body of a `for` statement with constant bounds `factor` times per
+  /// iteration. The trips left are run by a remainder loop. `Cmtc::elaborate` fails
+  /// if `factor` is 0.
+  pub fn unroll(self, factor: usize) -> Self { self.with_unroll(Unroll::Factor(factor)) }
+
+  /// Pad the shorter branch of an `if` statement with idle steps, so that both
+  /// branches take the same number of cycles. See also `CmtcConfig::balance_if`.
+  pub fn balanced(self) -> Result<Self, IronyError> {
+    match self.ast {
+      StmtAst::Expect(expect) => {
+        let stmt = Box::new(expect.stmt.balanced()?);
+        Ok(Stmt {
+          ast: StmtAst::Expect(ExpectStmt { stmt, ..expect }),
+          ..self
+        })
+      },
+      StmtAst::If(if_stmt) => Ok(Stmt {
+        ast: StmtAst::If(IfStmt { balanced: true, ..if_stmt }),
+        ..self
+      }),
+      _ => Err(IronyError::new(format!(
+        "only if statements can be balanced, not `{}`",
+        self.name.as_deref().unwrap_or("stmt")
+      ))),
+    }
+  }
+
+  /// Events the statement and its children step or wait for
+  fn events(&self, events: &mut Vec<Event>) {
+    match &self.ast {
+      StmtAst::Step(StepStmt { events: x, wait_at_exit }) => {
+        events.extend(x.iter().chain(wait_at_exit).cloned())
+      },
+      StmtAst::Seq(SeqStmt { stmts }) | StmtAst::Par(ParStmt { stmts }) => {
+        stmts.iter().for_each(|x| x.events(events))
+      },
+      StmtAst::If(IfStmt { cond, then_stmt, else_stmt, .. }) => {
+        events.push(cond.to_owned());
+        then_stmt.events(events);
+        else_stmt.iter().for_each(|x| x.events(events));
+      },
+      StmtAst::For(ForStmt { do_stmt, .. }) => do_stmt.events(events),
+      StmtAst::While(WhileStmt { cond, do_stmt }) => {
+        events.push(cond.to_owned());
+        do_stmt.events(events);
+      },
+      StmtAst::Call(_) => {},
+      StmtAst::Expect(ExpectStmt { stmt, .. }) => stmt.events(events),
+    }
+  }
+
+  /// Whether the statement or its children call an instance
+  fn has_call(&self) -> bool {
+    match &self.ast {
+      StmtAst::Step(_) => false,
+      StmtAst::Seq(SeqStmt { stmts }) | StmtAst::Par(ParStmt { stmts }) => {
+        stmts.iter().any(Stmt::has_call)
+      },
+      StmtAst::If(IfStmt { then_stmt, else_stmt, .. }) => {
+        then_stmt.has_call() || else_stmt.as_ref().is_some_and(|x| x.has_call())
+      },
+      StmtAst::For(ForStmt { do_stmt, .. })
+      | StmtAst::While(WhileStmt { do_stmt, .. }) => do_stmt.has_call(),
+      StmtAst::Call(_) => true,
+      StmtAst::Expect(ExpectStmt { stmt, .. }) => stmt.has_call(),
+    }
+  }
+
+  /// Copy of the statement where events and entities are replaced by their copies
+  fn copied(
+    &self, events: &HashMap<EntityId, Event>, entities: &HashMap<EntityId, EntityId>,
+  ) -> Stmt {
+    let event = |x: &Event| events.get(&x.entity_id).unwrap_or(x).to_owned();
+    let entity = |x: &EntityId| *entities.get(x).unwrap_or(x);
+    let bound = |x: &Bound| match x {
+      Bound::Const(x) => Bound::Const(*x),
+      Bound::Var(x) => Bound::Var(entity(x)),
+    };
+    let copied = |x: &Stmt| Box::new(x.copied(events, entities));
+    let stmts = |x: &Vec<Stmt>| x.iter().map(|x| x.copied(events, entities)).collect();
+    let ast = match &self.ast {
+      StmtAst::Step(StepStmt { events, wait_at_exit }) => StmtAst::Step(StepStmt {
+        events: events.iter().map(event).collect(),
+        wait_at_exit: wait_at_exit.iter().map(event).collect(),
+      }),
+      StmtAst::Seq(SeqStmt { stmts: x }) => StmtAst::Seq(SeqStmt { stmts: stmts(x) }),
+      StmtAst::Par(ParStmt { stmts: x }) => StmtAst::Par(ParStmt { stmts: stmts(x) }),
+      StmtAst::If(IfStmt { cond, then_stmt, else_stmt, balanced }) => StmtAst::If(IfStmt {
+        cond: event(cond),
+        then_stmt: copied(then_stmt),
+        else_stmt: else_stmt.as_deref().map(copied),
+        balanced: *balanced,
+      }),
+      StmtAst::For(for_stmt) => StmtAst::For(ForStmt {
+        indvar_rd: entity(&for_stmt.indvar_rd),
+        indvar_wr: entity(&for_stmt.indvar_wr),
+        start: bound(&for_stmt.start),
+        end: bound(&for_stmt.end),
+        incr: for_stmt.incr,
+        step: for_stmt.step,
+        do_stmt: copied(&for_stmt.do_stmt),
+        unroll: for_stmt.unroll,
+      }),
+      StmtAst::While(WhileStmt { cond, do_stmt }) => {
+        StmtAst::While(WhileStmt { cond: event(cond), do_stmt: copied(do_stmt) })
+      },
+      StmtAst::Call(_) => unreachable!("loops with calls aren't unrolled"),
+      StmtAst::Expect(ExpectStmt { stmt, latency, location }) => {
+        StmtAst::Expect(ExpectStmt {
+          stmt: copied(stmt),
+          latency: *latency,
+          location: *location,
+        })
+      },
+    };
+    Stmt { name: self.name.to_owned(), ast }
+  }
+
+  fn with_unroll(self, unroll: Unroll) -> Self {
+    if let StmtAst::Expect(expect) = self.ast {
+      let stmt = Box::new(expect.stmt.with_unroll(unroll));
+      return Stmt { ast: StmtAst::Expect(ExpectStmt { stmt, ..expect }), ..self };
+    }
+    let StmtAst::For(for_stmt) = self.ast else {
+      panic!("only for statements can be unrolled");
+    };
+    Stmt { ast: StmtAst::For(ForStmt { unroll: Some(unroll), ..for_stmt }), ..self }
+  }
+
   #[track_caller]
   pub fn to(self, c: &mut Cmtc) -> EntityId {
     match self.ast {
@@ -35,8 +217,11 @@ impl Stmt {
         c.add_op(StmtSeq::new(Some(entity_id.to_owned()), stmts_entity_ids.into_iter().map(|x| Some(x)).collect()).into());
         entity_id
       },
-      StmtAst::If(IfStmt { cond, then_stmt, else_stmt }) => {
+      StmtAst::If(IfStmt { cond, then_stmt, else_stmt, balanced }) => {
         let entity_id = c.add_stmt(self.name);
+        if balanced {
+          c.balanced_ifs.push(entity_id.to_owned());
+        }
         let cond_entity_id = cond.entity_id;
         let then_entity_id = then_stmt.to(c);
         let else_entity_id = else_stmt.map(|x| x.to(c));
@@ -51,6 +236,9 @@ impl Stmt {
         );
         entity_id
       },
+      StmtAst::For(for_stmt @ ForStmt { unroll: Some(_), .. }) => {
+        for_stmt.unrolled(c, self.name)
+      },
       StmtAst::For(ForStmt {
         indvar_rd,
         indvar_wr,
@@ -59,6 +247,7 @@ impl Stmt {
         incr,
         step,
         do_stmt,
+        unroll: _,
       }) => {
         let entity_id = c.add_stmt(self.name);
         let (start, const_start) = match start {
@@ -108,7 +297,38 @@ impl Stmt {
         for stmt in stmts {
           stmts_entity_ids.push(stmt.to(c));
         }
-        c.add_op(StmtSeq::new(Some(entity_id.to_owned()), stmts_entity_ids.into_iter().map(|x| Some(x)).collect()).into());
+        c.add_op(
+          StmtPar::new(
+            Some(entity_id.to_owned()),
+            stmts_entity_ids.into_iter().map(Some).collect(),
+          )
+          .into(),
+        );
+        entity_id
+      },
+      StmtAst::Call(CallStmt { go: go_port, done: done_port }) => {
+        let name = self.name.to_owned().unwrap_or("call".to_string());
+        let entity_id = c.add_stmt(self.name);
+        let callee_go = go_port.v_ir_entity_id[0];
+        // go is asserted by the FSM of the caller, done is the level of the callee
+        let go = c.add_event(Some(format!("{}_go", name)));
+        go_port.connect_expr(go.expr(), c);
+        let done = c.add_event(Some(format!("{}_done", name)));
+        c.specify_event_eq_signal(&done, done_port);
+        c.add_op(
+          StmtCall::new(
+            Some(entity_id.to_owned()),
+            Some(go.entity_id),
+            Some(done.entity_id),
+            callee_go,
+          )
+          .into(),
+        );
+        entity_id
+      },
+      StmtAst::Expect(ExpectStmt { stmt, latency, location }) => {
+        let entity_id = stmt.to(c);
+        c.latency_expects.push((entity_id, latency, location));
         entity_id
       },
     }
@@ -122,6 +342,8 @@ pub enum StmtAst {
   For(ForStmt),
   While(WhileStmt),
   Par(ParStmt),
+  Call(CallStmt),
+  Expect(ExpectStmt),
 }
 
 pub struct StepStmt {
@@ -136,10 +358,19 @@ pub struct ParStmt {
   pub stmts: Vec<Stmt>,
 }
 
+/// Run the statement synthesized with `GoDone` in an instance, through the go/done
+/// ports of the instance, e.g. `stmt!(call child.protocol)`
+pub struct CallStmt {
+  pub go: O<B<1>>,
+  pub done: I<B<1>>,
+}
+
 pub struct IfStmt {
   pub cond: Event,
   pub then_stmt: Box<Stmt>,
   pub else_stmt: Option<Box<Stmt>>,
+  /// Both branches take the same number of cycles, see `Stmt::balanced`
+  pub balanced: bool,
 }
 
 pub enum Bound {
@@ -155,6 +386,13 @@ pub struct ForStmt {
   pub incr: bool,
   pub step: usize,
   pub do_stmt: Box<Stmt>,
+  pub unroll: Option<Unroll>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unroll {
+  Full,
+  Factor(usize),
 }
 
 pub struct WhileStmt {
@@ -162,16 +400,27 @@ pub struct WhileStmt {
   pub do_stmt: Box<Stmt>,
 }
 
+/// A statement required to take `latency` cycles, see `Stmt::expect_latency`
+pub struct ExpectStmt {
+  pub stmt: Box<Stmt>,
+  pub latency: LatencyRange,
+  pub location: Location<'static>,
+}
+
 #[macro_export]
 macro_rules! stmt {
 
+    // Match for a statement with an expected latency
+    (expect_latency = $latency:expr; $($stmt:tt)*) => {
+        stmt!($($stmt)*).expect_latency($latency)
+    };
     // Match for Seq statement
     (seq { $({$($stmt:tt)*})+ }) => {
         Stmt {
             name: Some("seq".to_string()),
             ast: StmtAst::Seq(SeqStmt {
                 stmts: vec![$(stmt!($($stmt)*)),*],
-            })
+            }),
         }
     };
     // Match for Par statement
@@ -180,7 +429,14 @@ macro_rules! stmt {
             name: Some("par".to_string()),
             ast: StmtAst::Par(ParStmt {
                 stmts: vec![$(stmt!($($stmt)*)),*],
-            })
+            }),
+        }
+    };
+    // Match for Call statement
+    (call $ifc:expr) => {
+        Stmt {
+            name: Some("call".to_string()),
+            ast: StmtAst::Call(CallStmt::from($ifc)),
         }
     };
     // Match for If statement
@@ -191,7 +447,8 @@ macro_rules! stmt {
                 cond: $cond,
                 then_stmt: Box::new(stmt!($then_stmt)),
                 else_stmt: $(Some(Box::new(stmt!($else_stmt))))?,
-            })
+                balanced: false,
+            }),
         }
     };
     // Match for For statement
@@ -206,7 +463,8 @@ macro_rules! stmt {
                 incr: $incr,
                 step: $step,
                 do_stmt: Box::new(stmt!($($do_stmt)*)),
-            })
+                unroll: None,
+            }),
         }
     };
     // Match for While statement
@@ -216,7 +474,7 @@ macro_rules! stmt {
             ast: StmtAst::While(WhileStmt {
                 cond: $cond,
                 do_stmt: Box::new(stmt!($($do_stmt)*)),
-            })
+            }),
         }
     };
     // Terminal case for an individual statement
@@ -226,7 +484,7 @@ macro_rules! stmt {
             ast: StmtAst::Step(StepStmt {
                 events: vec![$($stmt),*],
                 wait_at_exit: vec![$($($exit)*)?],
-            })
+            }),
         }
     };
 }
@@ -19,6 +19,10 @@ pub struct GoDoIfc {
   pub done: flip!(B<1>),
 }
 
+impl From<GoDoIfcFlipImpl> for CallStmt {
+  fn from(value: GoDoIfcFlipImpl) -> Self { CallStmt { go: value.go, done: value.done } }
+}
+
 impl GoDone {
   pub fn new(clk: I<Clk>, go: Event, done: Event) -> Self {
     assert!(clk.v_ir_entity_id.len() == 1, "Clk wire has one entity-id");
@@ -0,0 +1,436 @@
+use std::collections::HashMap;
+use std::panic::Location;
+
+use irony_cmt::{
+  AttributeEnum, CombVariadic, CombVariadicPredicate, Entity, EntityId, Environ,
+  EventDef, IronyError, Op, OpEnum, OpId, ReducerTrait, Region, RegionId, StmtFor,
+  StmtSeq, StringAttr, TmpSelect, TmpWhen,
+};
+
+use super::{Bound, ForStmt, Stmt, StmtAst, Unroll};
+use crate::hcl::{BitsValue, DataValue, Event};
+use crate::preclude::{Cmtc, CmtcBasics, CmtcStmt};
+use crate::utils::usize_to_bitvec;
+
+impl ForStmt {
+  /// Lower a loop of `n` trips unrolled by factor `k` to
+  ///
+  /// ```text
+  /// seq {
+  ///   for (i = start; i < start + n / k * k * step; i += k * step) {
+  ///     body(i); body(i + step); ... body(i + (k - 1) * step)
+  ///   }
+  ///   for (i = start + n / k * k * step; i < end; i += step) body(i)
+  /// }
+  /// ```
+  ///
+  /// where `body(v)` is a copy of the body reading `v` instead of the induction
+  /// variable, with its own events. A full unroll has no loop left, and runs
+  /// `body(start); body(start + step); ...` with constant induction values: the events
+  /// of the body are replaced by their copies, and the induction variable isn't
+  /// written anymore.
+  ///
+  /// A loop which can't be unrolled is lowered as is, and `Cmtc::elaborate` returns
+  /// the error, tagged with the location synthesizing it.
+  #[track_caller]
+  pub(super) fn unrolled(self, c: &mut Cmtc, name: Option<String>) -> EntityId {
+    let (start, end) = match self.unroll_bounds() {
+      Ok(bounds) => bounds,
+      Err(err) => {
+        c.stmt_errors.push(IronyError::new(format!("{}: {}", Location::caller(), err)));
+        let ast = StmtAst::For(ForStmt { unroll: None, ..self });
+        return Stmt { name, ast }.to(c);
+      },
+    };
+    let step = self.step.max(1);
+    let trips = ((end.saturating_sub(start) + step - 1) / step).max(1);
+    let factor = match self.unroll.unwrap() {
+      Unroll::Full => trips,
+      Unroll::Factor(x) => x.min(trips),
+    };
+    if self.unroll == Some(Unroll::Factor(1)) {
+      let ast = StmtAst::For(ForStmt { unroll: None, ..self });
+      return Stmt { name, ast }.to(c);
+    }
+    let name = name.unwrap_or("for".to_string());
+    let var = self.indvar_rd;
+    let copies = BodyCopies::new(c, var);
+
+    let mut stmts = Vec::new();
+    if factor == trips {
+      for k in 0..trips {
+        let value = copies.constant(c, start + k * step);
+        stmts.push(copies.copy(c, &self.do_stmt, value));
+      }
+      copies.remove(c, &self.do_stmt, self.indvar_wr);
+    } else {
+      let main_end = start + trips / factor * factor * step;
+      let mut group = vec![self.do_stmt.copied(&HashMap::new(), &HashMap::new()).to(c)];
+      for j in 1..factor {
+        let value = copies.offset(c, j * step);
+        group.push(copies.copy(c, &self.do_stmt, value));
+      }
+      let group_id = c.add_stmt(Some(format!("{}_group", name)));
+      c.add_op(
+        StmtSeq::new(Some(group_id), group.into_iter().map(Some).collect()).into(),
+      );
+      let main = (start, main_end, factor * step);
+      stmts.push(self.add_for(c, name.to_owned(), main, group_id));
+
+      if main_end < end {
+        let body = self.do_stmt.copied(&HashMap::new(), &HashMap::new()).to(c);
+        let remainder = format!("{}_remainder", name);
+        stmts.push(self.add_for(c, remainder, (main_end, end, step), body));
+      }
+    }
+
+    let entity_id = c.add_stmt(Some(name));
+    c.add_op(StmtSeq::new(Some(entity_id), stmts.into_iter().map(Some).collect()).into());
+    entity_id
+  }
+
+  /// Constant bounds of the loop, or why it can't be unrolled
+  fn unroll_bounds(&self) -> Result<(usize, usize), &'static str> {
+    let (Bound::Const(start), Bound::Const(end)) = (&self.start, &self.end) else {
+      return Err("only for statements with constant bounds can be unrolled");
+    };
+    if self.unroll == Some(Unroll::Factor(0)) {
+      return Err("unroll factor must be positive");
+    }
+    // the body is copied, but a callee can't be run by several copies
+    if self.do_stmt.has_call() {
+      return Err("for statements calling an instance can't be unrolled");
+    }
+    Ok((*start, *end))
+  }
+
+  /// Add a loop over the induction variable, with constant bounds and step
+  /// `(start, end, step)`
+  #[track_caller]
+  fn add_for(
+    &self, c: &mut Cmtc, name: String, (start, end, step): (usize, usize, usize),
+    body: EntityId,
+  ) -> EntityId {
+    let entity_id = c.add_stmt(Some(name));
+    c.add_op(
+      StmtFor::new(
+        Some(entity_id),
+        Some(self.indvar_rd),
+        Some(self.indvar_wr),
+        Some(body),
+        None,
+        None,
+        Some(self.incr.into()),
+        Some(start.into()),
+        Some(end.into()),
+        Some(step.into()),
+      )
+      .into(),
+    );
+    entity_id
+  }
+}
+
+/// Copies of the body of an unrolled loop, each reading its own value of the
+/// induction variable
+struct BodyCopies {
+  /// Induction variable
+  var: EntityId,
+  /// Region of the module, where the events and the wires they depend on are copied
+  region: RegionId,
+  /// Entities of the region defined by a single op which can be copied
+  defs: HashMap<EntityId, OpId>,
+}
+
+impl BodyCopies {
+  fn new(c: &Cmtc, var: EntityId) -> Self {
+    let region =
+      c.ir.get_entity(var).get_parent().expect("induction variable in a module");
+    let mut defs = HashMap::new();
+    let mut redefined = Vec::new();
+    for op_id in c.ir.get_region(region).op_children.iter() {
+      let op = c.ir.get_op(*op_id);
+      for entity in op.get_defs().into_iter().flat_map(|(_, x)| x).flatten() {
+        if defs.insert(entity, *op_id).is_some() {
+          redefined.push(entity);
+        }
+      }
+    }
+    // wires written several times, e.g. by events, and stateful ops aren't copied
+    for entity in redefined {
+      defs.remove(&entity);
+    }
+    defs.retain(|_, op_id| is_pure(c.ir.get_op(*op_id)));
+    BodyCopies { var, region, defs }
+  }
+
+  /// Wire of the constant `value`, typed like the induction variable
+  #[track_caller]
+  fn constant(&self, c: &mut Cmtc, value: usize) -> EntityId {
+    let data_type = c.ir.get_entity(self.var).get_dtype().unwrap();
+    let (wire, _) = c.add_wire(data_type.to_owned(), Some("unroll_value".to_string()));
+    let value = BitsValue {
+      data: usize_to_bitvec(data_type.width(), value),
+    };
+    c.add_constant(wire, data_type, DataValue::Bits(value));
+    wire
+  }
+
+  /// Wire of the induction variable plus `offset`
+  #[track_caller]
+  fn offset(&self, c: &mut Cmtc, offset: usize) -> EntityId {
+    let data_type = c.ir.get_entity(self.var).get_dtype().unwrap();
+    let offset = self.constant(c, offset);
+    let (wire, _) = c.add_wire(data_type, Some("unroll_next".to_string()));
+    c.add_op(
+      CombVariadic::new(
+        Some(wire),
+        vec![Some(self.var), Some(offset)],
+        Some(CombVariadicPredicate::Add),
+      )
+      .into(),
+    );
+    wire
+  }
+
+  /// Lower a copy of `body` whose events, the ops guarded by them and the wires they
+  /// read read `value` instead of the induction variable
+  #[track_caller]
+  fn copy(&self, c: &mut Cmtc, body: &Stmt, value: EntityId) -> EntityId {
+    let mut copy = BodyCopy {
+      copies: self,
+      entities: HashMap::from([(self.var, value)]),
+      events: HashMap::new(),
+      selected: Vec::new(),
+    };
+    let mut events = Vec::new();
+    body.events(&mut events);
+    c.begin_region(self.region);
+    copy.copy_events(c, &events);
+    c.end_region();
+
+    // the copies of the written wires are selected like them
+    for op_id in c.ir.get_region(self.region).op_children.to_owned() {
+      c.ir.get_op_entry(op_id).and_modify(|op| {
+        let OpEnum::TmpSelect(TmpSelect { conds, values, .. }) = op else { return };
+        // `values` may end with the default value, after those of `conds`
+        for (wire, copy) in copy.selected.iter() {
+          if values[..conds.len()].contains(&Some(*wire)) {
+            values.insert(conds.len(), Some(*copy));
+            conds.push(None);
+          }
+        }
+      });
+    }
+    body.copied(&copy.events, &copy.entities).to(c)
+  }
+
+  /// Remove the events of `body`, the ops they guard and the wires they write, and
+  /// the writes of the induction variable, which a fully unrolled loop replaces
+  fn remove(&self, c: &mut Cmtc, body: &Stmt, indvar_wr: EntityId) {
+    let mut events = Vec::new();
+    body.events(&mut events);
+    let events = events.into_iter().map(|x| x.entity_id).collect::<Vec<_>>();
+    let mut removed = events.to_owned();
+    for op_id in c.ir.get_region(self.region).op_children.to_owned() {
+      let event = match c.ir.get_op(op_id) {
+        OpEnum::EventDef(x) => x.event,
+        OpEnum::EventSignal(x) => x.event,
+        OpEnum::EventPort(x) => x.event,
+        OpEnum::TmpWhen(x) => x.cond,
+        _ => None,
+      };
+      if !event.is_some_and(|x| events.contains(&x)) {
+        continue;
+      }
+      if let OpEnum::TmpWhen(TmpWhen { body: Some(body), .. }) = c.ir.get_op(op_id) {
+        let region = c.ir.get_region(*body);
+        for op_id in region.op_children.iter() {
+          let defs = c.ir.get_op(*op_id).get_defs().into_iter().flat_map(|(_, x)| x);
+          removed.extend(defs.flatten().filter(|x| !region.entity_children.contains(x)));
+        }
+      }
+      c.ir.delete_op_and_all(op_id);
+    }
+    c.ir.get_region_entry(self.region).and_modify(|region| {
+      region.entity_children.retain(|x| !removed.contains(x));
+    });
+    c.event_resources.retain(|(x, _)| !events.contains(x));
+
+    // the induction variable isn't written, unless by another loop
+    let uses = c.ir.get_uses(indvar_wr);
+    if uses.iter().all(|x| matches!(c.ir.get_op(*x), OpEnum::TmpSelect(_))) {
+      removed.push(indvar_wr);
+    }
+    for op_id in c.ir.get_region(self.region).op_children.to_owned() {
+      c.ir.get_op_entry(op_id).and_modify(|op| {
+        let OpEnum::TmpSelect(TmpSelect { conds, values, .. }) = op else { return };
+        let rest = values.split_off(conds.len());
+        let cases = conds
+          .iter()
+          .zip(values.iter())
+          .filter(|(_, value)| !value.is_some_and(|x| removed.contains(&x)));
+        (*conds, *values) = cases.map(|(cond, value)| (*cond, *value)).unzip();
+        values.extend(rest);
+      });
+    }
+  }
+}
+
+/// Copy of the body of an unrolled loop for one value of the induction variable
+struct BodyCopy<'a> {
+  copies: &'a BodyCopies,
+  /// Copies of the entities, or the entities themselves if they don't depend on the
+  /// induction variable
+  entities: HashMap<EntityId, EntityId>,
+  events: HashMap<EntityId, Event>,
+  /// Wires written by the events and their copies
+  selected: Vec<(EntityId, EntityId)>,
+}
+
+impl BodyCopy<'_> {
+  fn copy_events(&mut self, c: &mut Cmtc, events: &[Event]) {
+    for event in events {
+      if self.events.contains_key(&event.entity_id) {
+        continue;
+      }
+      let copy = fresh_entity(c, event.entity_id);
+      c.add_op(EventDef::new(Some(copy)).into());
+      let name = match c.ir.get_entity(copy).get_attr("name") {
+        Some(AttributeEnum::StringAttr(StringAttr(name))) => name,
+        _ => event.name.to_owned(),
+      };
+      self.entities.insert(event.entity_id, copy);
+      self.events.insert(event.entity_id, Event { entity_id: copy, name });
+      let resources = c.event_resources.iter().filter(|(x, _)| *x == event.entity_id);
+      let resources = resources.map(|(_, x)| (copy, x.to_owned())).collect::<Vec<_>>();
+      c.event_resources.extend(resources);
+    }
+
+    // the signals and ports events are equal to, and the ops they guard
+    for op_id in c.ir.get_region(self.copies.region).op_children.to_owned() {
+      let event = match c.ir.get_op(op_id) {
+        OpEnum::EventSignal(x) => x.event,
+        OpEnum::EventPort(x) => x.event,
+        OpEnum::TmpWhen(x) => x.cond,
+        _ => None,
+      };
+      if event.is_some_and(|x| self.events.contains_key(&x)) {
+        self.copy_op(c, op_id);
+      }
+    }
+  }
+
+  /// Copy `op_id` to the current region, with its regions
+  fn copy_op(&mut self, c: &mut Cmtc, op_id: OpId) {
+    let op = c.ir.get_op(op_id).to_owned();
+    for entity in op.get_uses().into_iter().flat_map(|(_, x)| x).flatten() {
+      self.copy_entity(c, entity);
+    }
+    let mut op = op.reduce_def_use(&mut CopyReducer(&self.entities));
+    if let OpEnum::TmpWhen(when) = &mut op {
+      when.body = when.body.map(|x| self.copy_region(c, x));
+    }
+    c.add_op(op);
+  }
+
+  /// Copy `region`, the body of a `TmpWhen`. The wires of the module it defines, which
+  /// are selected by the wires events write, are copied to the module region
+  fn copy_region(&mut self, c: &mut Cmtc, region: RegionId) -> RegionId {
+    let Region {
+      isolated, op_children, entity_children, ..
+    } = c.ir.get_region(region).to_owned();
+    let copy = c.ir.add_region(Region::new(isolated));
+    c.begin_region(copy);
+    for entity in entity_children {
+      let x = fresh_entity(c, entity);
+      self.entities.insert(entity, x);
+    }
+    for op_id in op_children {
+      let op = c.ir.get_op(op_id).to_owned();
+      for def in op.get_defs().into_iter().flat_map(|(_, x)| x).flatten() {
+        if !self.entities.contains_key(&def) {
+          c.begin_region(self.copies.region);
+          let x = fresh_entity(c, def);
+          c.end_region();
+          self.entities.insert(def, x);
+          self.selected.push((def, x));
+        }
+      }
+      self.copy_op(c, op_id);
+    }
+    c.end_region();
+    copy
+  }
+
+  /// Copy of `entity` if it depends on the induction variable, copying the ops it
+  /// depends on, or `entity` itself
+  fn copy_entity(&mut self, c: &mut Cmtc, entity: EntityId) -> EntityId {
+    if let Some(x) = self.entities.get(&entity) {
+      return *x;
+    }
+    self.entities.insert(entity, entity);
+    let Some(op_id) = self.copies.defs.get(&entity).copied() else {
+      return entity;
+    };
+    let op = c.ir.get_op(op_id).to_owned();
+    let uses = op.get_uses().into_iter().flat_map(|(_, x)| x).flatten();
+    let copied = uses.filter(|x| self.copy_entity(c, *x) != *x).count();
+    if copied == 0 {
+      return entity;
+    }
+
+    c.begin_region(self.copies.region);
+    for def in op.get_defs().into_iter().flat_map(|(_, x)| x).flatten() {
+      let x = fresh_entity(c, def);
+      self.entities.insert(def, x);
+    }
+    c.add_op(op.reduce_def_use(&mut CopyReducer(&self.entities)));
+    c.end_region();
+    self.entities[&entity]
+  }
+}
+
+struct CopyReducer<'a>(&'a HashMap<EntityId, EntityId>);
+
+impl ReducerTrait for CopyReducer<'_> {
+  fn reduce_entity(&mut self, id: EntityId) -> usize {
+    self.0.get(&id).copied().unwrap_or(id).0
+  }
+
+  fn reduce_op(&mut self, id: OpId) -> usize { id.0 }
+}
+
+/// Add a copy of `entity` to the current region, with a legal name
+fn fresh_entity(c: &mut Cmtc, entity: EntityId) -> EntityId {
+  let mut copy = c.ir.get_entity(entity).to_owned();
+  if let Some(AttributeEnum::StringAttr(StringAttr(name))) = copy.get_attr("name") {
+    let name = c.symbol_table.get_legal_name_in_region(&c.ir, &name);
+    copy.set_attrs(vec![("name".to_string(), StringAttr(name).into())]);
+  }
+  c.ir.add_entity(copy)
+}
+
+/// Ops whose results only depend on their operands
+fn is_pure(op: &OpEnum) -> bool {
+  matches!(
+    op,
+    OpEnum::Assign(_)
+      | OpEnum::TmpUnary(_)
+      | OpEnum::HwBitCast(_)
+      | OpEnum::HwArrayConcat(_)
+      | OpEnum::HwArrayCreate(_)
+      | OpEnum::HwArrayGet(_)
+      | OpEnum::HwArraySlice(_)
+      | OpEnum::HwStructCreate(_)
+      | OpEnum::HwStructExtract(_)
+      | OpEnum::HwStructInject(_)
+      | OpEnum::HwStructExplode(_)
+      | OpEnum::CombVariadic(_)
+      | OpEnum::CombBinary(_)
+      | OpEnum::CombICmp(_)
+      | OpEnum::CombExtract(_)
+      | OpEnum::CombConcat(_)
+      | OpEnum::CombMux2(_)
+  )
+}
@@ -2,7 +2,7 @@ use std::collections::HashMap;
 use std::future::Future;
 use std::sync::{Arc, RwLock};
 
-use irony_cmt::CmtIR;
+use irony_cmt::{AnalysisManager, CmtIR};
 
 use crate::compiler::Cmtc;
 
@@ -35,7 +35,8 @@ impl Simulator {
 
     let top = get_top(dut);
 
-    let (cycle, inputs, outputs) = make_simulator(dut, top, &container);
+    let mut analyses = AnalysisManager::default();
+    let (cycle, inputs, outputs) = make_simulator(dut, top, &container, &mut analyses);
 
     let io_table = get_io_name(&dut.ir, top, inputs, outputs);
 
@@ -1,11 +1,11 @@
 use core::panic;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 
 use bitvec::prelude as bv;
 use irony_cmt::{
-  ArrayAttr, AttributeEnum, DataTypeEnum, EntityEnum, EntityId, Environ, HwInstance, Op,
-  OpEnum, OpId,
+  AnalysisManager, ArrayAttr, AttributeEnum, DataTypeEnum, EntityEnum, EntityId, Environ,
+  HwInstance, Op, OpEnum, OpId, TopoOrder,
 };
 
 use super::events::*;
@@ -27,17 +27,19 @@ pub(super) fn get_top(dut: &Cmtc) -> OpId {
 
 pub(super) fn make_simulator(
   dut: &Cmtc, module: OpId, container: &Arc<RwLock<SimStateContainer>>,
+  analyses: &mut AnalysisManager,
 ) -> (SimCycle, Vec<StateId>, Vec<StateId>) {
   let mut inputs = Vec::new();
   let mut outputs = Vec::new();
   let mut cycle = SimCycle::empty();
   let state_table = alloc_local_states(&dut.ir, module, container);
 
-  let OpEnum::HwModule(module) = dut.ir.get_op(module) else { panic!() };
-  let region = dut.ir.get_region(module.body.unwrap());
-  let ops = top_sort(&dut.ir, region.get_op_children());
+  // instances of the same module share the order
+  let ops = analyses
+    .get::<TopoOrder, _>(&dut.ir, module)
+    .unwrap_or_else(|err| panic!("{}", err));
 
-  for id in &ops {
+  for id in &ops.0 {
     let op = dut.ir.get_op(*id);
     match op {
       OpEnum::Assign(assign) => cycle.comb_events.push(Box::new(AssignEvent {
@@ -47,7 +49,7 @@ pub(super) fn make_simulator(
       })),
       OpEnum::HwModule(_) => panic!("Nested module declaration is not supported"),
       OpEnum::HwInstance(instance) => {
-        cycle.merge(instance_submodule(dut, instance, container, &state_table))
+        cycle.merge(instance_submodule(dut, instance, container, &state_table, analyses))
       },
       OpEnum::HwInput(input) => inputs
         .extend(input.inputs.iter().filter_map(|x| *x).map(|x| state_table[&x].clone())),
@@ -213,23 +215,40 @@ pub(super) fn make_simulator(
         rhs0: state_table[mux.op0.as_ref().unwrap()],
         rhs1: state_table[mux.op1.as_ref().unwrap()],
       })),
+      OpEnum::ItprtCondCheck(check) => cycle.comb_events.push(Box::new(CondCheckEvent {
+        container: Arc::clone(container),
+        conds: check.conds.iter().map(|x| state_table[x.as_ref().unwrap()]).collect(),
+        has_default: check.has_default.as_ref().unwrap().0,
+        onehot: check.onehot.as_ref().unwrap().0,
+      })),
       OpEnum::SeqCompReg(seq) => {
         // TODO: add multiple clock support?
+        // every register samples its input before any of them is updated
+        let output = state_table[seq.output.as_ref().unwrap()];
+        let next = {
+          let data = output.read_from(container);
+          container.write().unwrap().alloc(data)
+        };
         if let Some(reset) = seq.reset {
           cycle.reg_events.push(Box::new(MuxAssignEvent {
             container: Arc::clone(container),
-            lhs: state_table[seq.input.as_ref().unwrap()],
+            lhs: next,
             sel: state_table[&reset],
-            rhs0: state_table[seq.output.as_ref().unwrap()],
-            rhs1: state_table[seq.reset_val.as_ref().unwrap()],
+            rhs0: state_table[seq.reset_val.as_ref().unwrap()],
+            rhs1: state_table[seq.input.as_ref().unwrap()],
           }));
         } else {
           cycle.reg_events.push(Box::new(AssignEvent {
             container: Arc::clone(container),
-            lhs: state_table[seq.input.as_ref().unwrap()],
-            rhs: state_table[seq.output.as_ref().unwrap()],
+            lhs: next,
+            rhs: state_table[seq.input.as_ref().unwrap()],
           }))
         }
+        cycle.commit_events.push(Box::new(AssignEvent {
+          container: Arc::clone(container),
+          lhs: output,
+          rhs: next,
+        }))
       },
       _ => {},
     }
@@ -240,10 +259,14 @@ pub(super) fn make_simulator(
 
 fn instance_submodule(
   dut: &Cmtc, instance: &HwInstance, container: &Arc<RwLock<SimStateContainer>>,
-  state_table: &HashMap<EntityId, StateId>,
+  state_table: &HashMap<EntityId, StateId>, analyses: &mut AnalysisManager,
 ) -> SimCycle {
-  let (instance_cycle, instance_in, instance_out) =
-    make_simulator(dut, instance.target_op_id.as_ref().unwrap().0, &container);
+  let (instance_cycle, instance_in, instance_out) = make_simulator(
+    dut,
+    instance.target_op_id.as_ref().unwrap().0,
+    &container,
+    analyses,
+  );
 
   let mut cycle = SimCycle::empty();
   // Assign instance inputs
@@ -332,73 +355,6 @@ pub(super) fn get_io_name(
   result
 }
 
-fn top_sort(ir: &CmtIR, ops: Vec<OpId>) -> Vec<OpId> {
-  let mut result = Vec::new();
-
-  let mut undef_set = HashMap::new();
-  let mut use_map = HashMap::new();
-  let mut que = VecDeque::new();
-  let mut visited = HashSet::new();
-  let mut def_set = HashSet::new();
-
-  for x in &ops {
-    let op = ir.get_op(*x);
-    let undef = undef_set.entry(*x).or_insert(HashSet::new());
-    for (_, es) in op.get_uses() {
-      for eopt in es {
-        eopt.map(|e| {
-          use_map.entry(e).or_insert(Vec::new()).push(*x);
-          undef.insert(e);
-        });
-      }
-    }
-    for (_, es) in op.get_uses() {
-      for eopt in es {
-        eopt.map(|e| def_set.insert(e));
-      }
-    }
-    if undef.len() == 0 {
-      que.push_back(*x);
-      visited.insert(*x);
-    }
-  }
-  assert_eq!(use_map.len(), def_set.len());
-  for e in use_map.keys() {
-    if !def_set.contains(e) {
-      panic!("An entity that is used is never defined!");
-    }
-  }
-
-  while let Some(x) = que.pop_front() {
-    result.push(x);
-    let op = ir.get_op(x);
-    // println!("{:?}", op);
-    for (_, es) in op.get_defs() {
-      for eopt in es {
-        eopt.map(|e| {
-          for y in &use_map[&e] {
-            let undef = undef_set.get_mut(y).unwrap();
-            undef.remove(&e);
-            if undef.len() == 0 && !visited.contains(y) {
-              que.push_back(*y);
-              visited.insert(*y);
-            }
-          }
-        });
-      }
-    }
-  }
-
-  for (e, s) in undef_set {
-    if s.len() > 0 {
-      println!("{:?} {:?}", ir.get_op(e), s);
-    }
-  }
-
-  assert!(result.len() == ops.len());
-
-  result
-}
 fn get_width(ir: &CmtIR, id: EntityId) -> usize {
   if let EntityEnum::IRWire(x) = ir.get_entity(id) {
     if let DataTypeEnum::UInt(arr) = x.dtype.as_ref().unwrap() {
@@ -119,6 +119,7 @@ impl CastEvent {
   }
 }
 
+/// `lhs = sel ? rhs0 : rhs1`, like `comb.mux`
 #[StructFields(pub)]
 #[derive(Clone)]
 pub struct MuxAssignEvent {
@@ -133,9 +134,9 @@ impl SimEvent for MuxAssignEvent {
   fn run(&self) {
     let sel = self.sel.read_from(&self.container).as_bool();
     let data = if sel {
-      self.rhs1.read_from(&self.container)
-    } else {
       self.rhs0.read_from(&self.container)
+    } else {
+      self.rhs1.read_from(&self.container)
     };
     self.lhs.write_to(data, &self.container);
   }
@@ -152,6 +153,37 @@ impl Debug for MuxAssignEvent {
   }
 }
 
+#[StructFields(pub)]
+#[derive(Clone)]
+pub struct CondCheckEvent {
+  container: Arc<RwLock<SimStateContainer>>,
+  conds: Vec<StateId>,
+  has_default: bool,
+  onehot: bool,
+}
+
+impl SimEvent for CondCheckEvent {
+  fn run(&self) {
+    let active = self
+      .conds
+      .iter()
+      .filter(|x| x.read_from(&self.container).as_bool())
+      .count();
+    assert!(!self.onehot || active <= 1, "{} conditions are active, expected onehot", active);
+    assert!(self.has_default || active >= 1, "no condition is active without default");
+  }
+}
+
+impl Debug for CondCheckEvent {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("CondCheckEvent")
+      .field("conds", &self.conds)
+      .field("has_default", &self.has_default)
+      .field("onehot", &self.onehot)
+      .finish()
+  }
+}
+
 #[StructFields(pub)]
 #[derive(Clone)]
 pub struct CombVariadicEvent {
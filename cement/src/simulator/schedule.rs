@@ -8,6 +8,7 @@ use super::events::*;
 pub struct SimCycle {
   comb_events: Vec<BoxEvent>,
   reg_events: Vec<BoxEvent>,
+  commit_events: Vec<BoxEvent>,
   poke_events: Vec<BoxEvent>,
   keep_poke_events: Vec<BoxEvent>,
   peek_events: Vec<BoxEvent>,
@@ -18,6 +19,7 @@ impl SimCycle {
     SimCycle {
       comb_events: Vec::new(),
       reg_events: Vec::new(),
+      commit_events: Vec::new(),
       poke_events: Vec::new(),
       keep_poke_events: Vec::new(),
       peek_events: Vec::new(),
@@ -39,6 +41,9 @@ impl SimCycle {
     for evt in &self.reg_events {
       evt.run();
     }
+    for evt in &self.commit_events {
+      evt.run();
+    }
     for evt in &self.peek_events {
       evt.run();
     }
@@ -48,6 +53,7 @@ impl SimCycle {
   pub fn merge(&mut self, other: Self) {
     self.comb_events.extend(other.comb_events.into_iter());
     self.reg_events.extend(other.reg_events.into_iter());
+    self.commit_events.extend(other.commit_events.into_iter());
     self.poke_events.extend(other.poke_events.into_iter());
     self.keep_poke_events.extend(other.keep_poke_events.into_iter());
     self.peek_events.extend(other.peek_events.into_iter());
@@ -64,6 +70,10 @@ impl Debug for SimCycle {
     for evt in &self.reg_events {
       write!(f, "    {:?},\n", evt)?;
     }
+    write!(f, "  ],\ncommit_events:[\n")?;
+    for evt in &self.commit_events {
+      write!(f, "    {:?},\n", evt)?;
+    }
     write!(f, "  ],\npoke_events:[\n")?;
     for evt in &self.poke_events {
       write!(f, "    {:?},\n", evt)?;
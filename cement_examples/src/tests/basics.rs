@@ -36,7 +36,7 @@ pub fn test_top_pass() -> Result<(), ()> {
 
   TopPass::default().top_m(&mut c);
 
-  c.print();
+  c.print().unwrap();
   
 
   Simulator::new(&c).test(async move |dut| {
@@ -51,6 +51,66 @@ pub fn test_top_pass() -> Result<(), ()> {
   Ok(())
 }
 
+fn flat_modules(c: &Cmtc) -> Vec<String> {
+  c.module_op_id_iter().map(|module| c.ir.print_op(module)).collect()
+}
+
+#[test]
+fn test_top_pass_flatten() {
+  let mut c = Cmtc::new(config! { flatten => true });
+  TopPass::default().top_m(&mut c);
+  c.elaborate().unwrap();
+
+  let modules = flat_modules(&c);
+  assert_eq!(modules.len(), 1);
+  assert!(!modules[0].contains("hw.instance"));
+
+  Simulator::new(&c).test(async move |dut| {
+    dut.poke("i", StateData::new_usize(0, 8));
+    dut.poke("pass.i", StateData::new_usize(2, 8));
+    dut.step().await;
+    assert_eq!(dut.peek("o"), StateData::new_usize(1, 8));
+    assert_eq!(dut.peek("pass.o"), StateData::new_usize(2, 8));
+  });
+}
+
+module! { Pass =>
+    pass_inline_m(module) {
+        c.inline_module();
+        module.o %= module.i + 0.lit(B8);
+    }
+}
+
+#[interface(Default)]
+pub(crate) struct TopPassInline {
+  i: B<8>,
+  o: Flip<B<8>>,
+}
+
+module! { TopPassInline =>
+    top_inline_m(module) {
+        let pass = instance!(pass_inline_m(Pass::default()));
+        let pass1 = instance!(pass_m(Pass::default()));
+        pass.i %= module.i;
+        pass1.i %= pass.o;
+        module.o %= pass1.o;
+    }
+}
+
+#[test]
+fn test_inline_module() {
+  let mut c = Cmtc::new(CmtcConfig::default());
+  TopPassInline::default().top_inline_m(&mut c);
+  c.elaborate().unwrap();
+
+  // only the instance of the module marked inline is inlined
+  let modules = flat_modules(&c);
+  assert_eq!(modules.len(), 2);
+  let top = modules.iter().find(|x| x.contains("@top_inline_m")).unwrap();
+  assert_eq!(top.matches("hw.instance").count(), 1);
+  assert!(top.contains("@pass_m"));
+}
+
 #[interface]
 struct TopBits {
   i: Bits,
@@ -123,7 +183,7 @@ fn test_tuple_concat() {
 
   ConcatTuple::new().concat_tuple_m(&mut c);
 
-  c.print();
+  c.print().unwrap();
 
   Simulator::new(&c).test(async move |dut| {
     dut.poke("i.0", StateData::new_usize(0b11, 2));
@@ -151,7 +211,7 @@ module! {
 fn test_extract_2bits() {
   let mut c = Cmtc::new(CmtcConfig::default());
   Extract2Bits::default().extract_2bits_m(&mut c);
-  c.print();
+  c.print().unwrap();
 
   Simulator::new(&c).test(async move|dut|{
     dut.poke("i", StateData::new_usize(0b10110001, 8));
@@ -173,7 +233,7 @@ module! {
 fn test_pass_with_wire() {
   let mut c = Cmtc::new(CmtcConfig::default());
   Pass::default().pass_with_wire_m(&mut c);
-  c.print();
+  c.print().unwrap();
 
   Simulator::new(&c).test(async move|dut|{
     dut.poke("i", StateData::new_usize(98, 8));
@@ -13,5 +13,5 @@ module! { Pass(c) =>
 fn test_cast_b_bits() {
   let mut c = Cmtc::new(CmtcConfig::default());
   Pass::default().cast_b_bits_m(&mut c);
-  c.print();
+  c.print().unwrap();
 }
@@ -30,7 +30,7 @@ mod array {
   fn test_arr_to_array() {
     let mut c = Cmtc::new(CmtcConfig::default());
     ArrToArray::new().arr_to_arry(&mut c);
-    c.print();
+    c.print().unwrap();
   }
 
   #[interface(Default)]
@@ -51,7 +51,7 @@ mod array {
   fn test_arr_constant() {
     let mut c = Cmtc::new(CmtcConfig::default());
     ArrConstantIfc::default().arr_constant_m(&mut c);
-    c.print();
+    c.print().unwrap();
   }
 
   #[interface(Default)]
@@ -72,7 +72,7 @@ mod array {
   fn test_arr_create() {
     let mut c = Cmtc::new(CmtcConfig::default());
     ArrCreate::default().arr_create_m(&mut c);
-    c.print();
+    c.print().unwrap();
   }
 
   #[interface(Default)]
@@ -93,7 +93,7 @@ mod array {
   fn test_arr_concat() {
     let mut c = Cmtc::new(CmtcConfig::default());
     ArrConcat::default().arr_concat_m(&mut c);
-    c.print();
+    c.print().unwrap();
   }
 
   #[interface(Default)]
@@ -114,7 +114,7 @@ mod array {
   fn test_arr_slice() {
     let mut c = Cmtc::new(CmtcConfig::default());
     ArrSlice::default().arr_slice_m(&mut c);
-    c.print();
+    c.print().unwrap();
   }
 
   #[interface(Default)]
@@ -135,7 +135,7 @@ mod array {
   fn test_arr_get() {
     let mut c = Cmtc::new(CmtcConfig::default());
     ArrGet::default().arr_get_m(&mut c);
-    c.print();
+    c.print().unwrap();
   }
 }
 
@@ -165,7 +165,7 @@ mod struct_ty {
   fn test_pair_pass() {
     let mut c = Cmtc::new(CmtcConfig::default());
     PairPass::<B<8>>::default().pair_pass_m(&mut c);
-    c.print();
+    c.print().unwrap();
   }
 
   #[interface(Default, Copy)]
@@ -187,7 +187,7 @@ mod struct_ty {
   fn test_create_pair() {
     let mut c = Cmtc::new(CmtcConfig::default());
     CreatePair::default().create_pair_m(&mut c);
-    c.print();
+    c.print().unwrap();
   }
 
   #[interface(Default, Copy)]
@@ -207,7 +207,7 @@ mod struct_ty {
   fn test_extract_pair() {
     let mut c = Cmtc::new(CmtcConfig::default());
     ExtractPair::default().extract_pair_m(&mut c);
-    c.print();
+    c.print().unwrap();
   }
 
   #[interface(Default, Copy)]
@@ -228,7 +228,7 @@ mod struct_ty {
   fn test_inject_pair() {
     let mut c = Cmtc::new(CmtcConfig::default());
     InjectPair::default().inject_pair_m(&mut c);
-    c.print();
+    c.print().unwrap();
   }
 
   #[interface(Default, Copy)]
@@ -248,7 +248,7 @@ mod struct_ty {
   fn test_explode_pair() {
     let mut c = Cmtc::new(CmtcConfig::default());
     ExplodePair::default().explode_pair_m(&mut c);
-    c.print();
+    c.print().unwrap();
   }
 }
 
@@ -273,7 +273,7 @@ mod tuple {
   fn test_tuple_add() {
     let mut c = Cmtc::new(CmtcConfig::default());
     Tuple2To1::default().tuple_add_m(&mut c);
-    c.print();
+    c.print().unwrap();
   }
 
   #[interface(Default, Copy)]
@@ -292,7 +292,7 @@ mod tuple {
   fn test_tuple_const() {
     let mut c = Cmtc::new(CmtcConfig::default());
     TupleO::default().tuple_const(&mut c);
-    c.print();
+    c.print().unwrap();
   }
 }
 
@@ -348,6 +348,6 @@ mod signal {
     let mut c = Cmtc::new(CmtcConfig::default());
     DoubleO::<Valid<B<8>>>::default().double_valid_const_m(&mut c);
     // c.print();
-    c.generate_workspace()
+    c.generate_workspace().unwrap()
   }
 }
@@ -1,5 +1,6 @@
 use __core::ops::Not;
 use cmt::preclude::*;
+use cmt::simulator::*;
 
 #[interface(Default)]
 struct ClkPass {
@@ -28,31 +29,21 @@ module! {
 fn test_clk_pass() {
   let mut c = Cmtc::new(CmtcConfig::default());
   ClkPass::default().pass_odd_m(&mut c);
-  c.print();
+  c.print().unwrap();
 }
 
 #[test]
 fn test_pass_odd_remove_event() {
   let mut c = Cmtc::new(CmtcConfig::default());
   ClkPass::default().pass_odd_m(&mut c);
-  c.print_with_passes(vec![ReorderPass.into(), RemoveEventPass.into()], vec![
-    c.module_op_id_iter().collect(),
-    c.module_op_id_iter().collect(),
-  ]);
+  c.print_with_passes("reorder,remove-event").unwrap();
 }
 
 #[test]
 fn test_pass_odd_remove_event_select() {
   let mut c = Cmtc::new(CmtcConfig::default());
   ClkPass::default().pass_odd_m(&mut c);
-  c.print_with_passes(
-    vec![ReorderPass.into(), RemoveEventPass.into(), RemoveSelectPass.into()],
-    vec![
-      c.module_op_id_iter().collect(),
-      c.module_op_id_iter().collect(),
-      c.module_op_id_iter().collect(),
-    ],
-  );
+  c.print_with_passes("reorder,remove-event,remove-select").unwrap();
 }
 
 module! {
@@ -76,5 +67,121 @@ fn test_pass_not_odd() {
     workspace_dir => "./build"
   });
   ClkPass::default().pass_not_odd_m(&mut cmtc);
-  cmtc.generate_workspace()
+  cmtc.generate_workspace().unwrap()
+}
+
+#[test]
+fn test_pass_not_odd_mux() {
+  let dir = std::path::PathBuf::from("./build").join(function_dir_path!());
+  let mut cmtc = Cmtc::new(config! { workspace_dir => dir.join("native") });
+  ClkPass::default().pass_not_odd_m(&mut cmtc);
+  cmtc.generate_workspace().unwrap();
+  let ws = cmtc.config.workspace_path();
+  let sv = std::fs::read_to_string(ws.join("pass_not_odd_m.sv")).unwrap();
+  // `store.mux(io.i, reg.rd)` gives `io.i` if `store` is set
+  assert!(sv.contains("  assign mux = not_ ? i : reg_r_port;"));
+  // the register is written by the event if it's set, and keeps its value otherwise
+  assert!(sv.contains("  assign select_mux0 = not_ ? wr : reg_r_port;"));
+
+  // circt-opt exports the same mux, where it's installed
+  let mut cmtc = Cmtc::new(config! {
+    workspace_dir => dir.join("circt"),
+    sv_backend => "circt-opt",
+  });
+  if !cmtc.config.circt_opt.exists() {
+    return;
+  }
+  ClkPass::default().pass_not_odd_m(&mut cmtc);
+  cmtc.generate_workspace().unwrap();
+  let ws = cmtc.config.workspace_path();
+  let circt = std::fs::read_to_string(ws.join("pass_not_odd_m.sv")).unwrap();
+  assert!(circt.contains(" ? i : "));
+}
+
+#[test]
+fn test_pass_not_odd_simulate() {
+  let mut c = Cmtc::new(CmtcConfig::default());
+  ClkPass::default().pass_not_odd_m(&mut c);
+  c.elaborate().unwrap();
+
+  c.print().unwrap();
+  // the interpreter is the reference for the simulator, cycle by cycle
+  let inputs = [0x02, 0x03, 0x05, 0x04, 0x07, 0x08, 0x09];
+  let module = c.module_op_id_iter().next().unwrap();
+  let mut interpreter = Interpreter::new(&c.ir, module).unwrap();
+  let expected: Vec<_> = inputs
+    .iter()
+    .map(|i| {
+      interpreter.poke("i", Value::from_u32(*i, 8)).unwrap();
+      interpreter.step().unwrap();
+      interpreter.peek("o").unwrap().to_u32().unwrap()
+    })
+    .collect();
+  assert_eq!(expected, [0x02, 0x02, 0x02, 0x04, 0x04, 0x08, 0x08]);
+
+  Simulator::new(&c).test(async move |dut| {
+    for (i, o) in inputs.into_iter().zip(expected) {
+      dut.poke("i", StateData::new_usize(i as usize, 8));
+      dut.step().await;
+      assert_eq!(dut.peek("o"), StateData::new_usize(o as usize, 8));
+    }
+  });
+}
+
+module! {
+  ClkPass =>
+  two_writers_m(io) {
+    let wr0 = mut_wire!(io.o.ifc().flip());
+    let wr1 = mut_wire!(io.o.ifc().flip());
+    io.o %= select(false, vec![None, None], vec![wr0.o.expr(), wr1.o.expr()], Some(0.lit(B8)));
+
+    let is_odd = io.i.extract(0, B1).eq(1.lit(B1));
+    let is_big = io.i.extract(7, B1).eq(1.lit(B1));
+    event! { ("store_odd") =>
+      wr0.i %= io.i.to_owned();
+      is_odd
+    };
+    event! { ("store_big") =>
+      wr1.i %= io.i.to_owned();
+      is_big
+    };
+  }
+}
+
+#[test]
+fn test_two_writers_unproven() {
+  let mut c = Cmtc::new(CmtcConfig::default());
+  ClkPass::default().two_writers_m(&mut c);
+  let report = c.conflict_report();
+  assert_eq!(report.simultaneous().count(), 0);
+  assert_eq!(report.unproven().count(), 1);
+}
+
+#[test]
+fn test_two_writers_assert_onehot() {
+  let mut c = Cmtc::new(config! { onehot => true });
+  ClkPass::default().two_writers_m(&mut c);
+  c.elaborate().unwrap();
+
+  let checks: Vec<_> = c
+    .ir
+    .op_table
+    .iter()
+    .filter_map(|(_, op)| match op {
+      OpEnum::ItprtCondCheck(check) => Some(check),
+      _ => None,
+    })
+    .collect();
+  assert_eq!(checks.len(), 1);
+  assert_eq!(checks[0].conds.len(), 2);
+
+  // only one event fires for an odd small input, both for an odd big one
+  let module = c.module_op_id_iter().next().unwrap();
+  let mut interpreter = Interpreter::new(&c.ir, module).unwrap();
+  interpreter.poke("i", Value::from_u32(0x01, 8)).unwrap();
+  interpreter.eval().unwrap();
+  assert_eq!(interpreter.peek("o"), Some(Value::from_u32(0x01, 8)));
+  interpreter.poke("i", Value::from_u32(0x81, 8)).unwrap();
+  let err = interpreter.eval().unwrap_err();
+  assert!(err.message.ends_with("2 of the onehot conditions hold"));
 }
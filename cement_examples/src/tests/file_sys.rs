@@ -11,5 +11,84 @@ fn test_fs() {
     workspace_dir => PathBuf::from("./build").join(function_dir_path!())
   });
   TopPass::default().top_m(&mut cmtc);
-  cmtc.generate_workspace();
+  cmtc.generate_workspace().unwrap();
+
+  let ws = cmtc.config.workspace_path();
+  let top = std::fs::read_to_string(ws.join("top_m.sv")).unwrap();
+  assert!(top.starts_with("module top_m (\n") && top.contains("  pass_m pass ("));
+  assert!(ws.join("pass_m.sv").exists());
+
+  // statements are traced back to where they are built
+  let source_map = std::fs::read_to_string(ws.join("source_map.json")).unwrap();
+  assert!(source_map.contains("\"top_m.sv\": {") && source_map.contains(".rs:"));
+  assert!(top.contains("  // "));
+}
+
+#[test]
+fn test_fs_firrtl() {
+  let mut cmtc = Cmtc::new(config! {
+    workspace_dir => PathBuf::from("./build").join(function_dir_path!())
+  });
+  TopPass::default().top_m(&mut cmtc);
+  let paths = cmtc.generate_firrtl().unwrap();
+  assert_eq!(paths, vec![cmtc.config.workspace_path().join("top_m.fir")]);
+
+  let firrtl = std::fs::read_to_string(&paths[0]).unwrap();
+  assert!(firrtl.contains("circuit top_m :\n  module pass_m :\n"));
+  assert!(firrtl.contains("    inst pass of pass_m\n"));
+}
+
+#[test]
+fn test_fs_yosys_json() {
+  let mut cmtc = Cmtc::new(config! {
+    workspace_dir => PathBuf::from("./build").join(function_dir_path!()),
+    yosys_json => true,
+  });
+  TopPass::default().top_m(&mut cmtc);
+  cmtc.generate_workspace().unwrap();
+
+  // the netlist is next to the SystemVerilog files, the native backend writes no MLIR
+  let ws = cmtc.config.workspace_path();
+  assert!(ws.join("top_m.sv").exists() && !ws.join("modules.mlir").exists());
+  let json = std::fs::read_to_string(ws.join("modules.json")).unwrap();
+  assert!(json.contains("\"top_m\": {") && json.contains("\"pass_m\": {"));
+  assert!(json.contains("\"type\": \"pass_m\"") && json.contains("\"type\": \"$add\""));
+}
+
+#[test]
+fn test_fs_circt_opt() {
+  let mut cmtc = Cmtc::new(config! {
+    workspace_dir => PathBuf::from("./build").join(function_dir_path!()),
+    sv_backend => "circt-opt",
+    circt_opt => "./missing/circt-opt",
+  });
+  TopPass::default().top_m(&mut cmtc);
+  let err = cmtc.generate_workspace().unwrap_err();
+  assert!(err.to_string().contains("can't run ./missing/circt-opt"));
+}
+
+#[test]
+fn test_fs_load_ir() {
+  let dir = PathBuf::from("./build").join(function_dir_path!());
+  let mut cmtc = Cmtc::new(config! { workspace_dir => dir.to_owned() });
+  TopPass::default().top_m(&mut cmtc);
+  std::fs::create_dir_all(&dir).unwrap();
+  cmtc.save_ir(dir.join("top_m.json")).unwrap();
+  cmtc.save_ir(dir.join("top_m.ir")).unwrap();
+  cmtc.generate_workspace().unwrap();
+  let top = std::fs::read_to_string(cmtc.config.workspace_path().join("top_m.sv"));
+  let top = top.unwrap();
+
+  // a loaded design emits the same SystemVerilog, located in the same sources
+  for snapshot in ["top_m.json", "top_m.ir"] {
+    let mut loaded =
+      Cmtc::new(config! { workspace_dir => dir.join(format!("{}_ws", snapshot)) });
+    loaded.load_ir(dir.join(snapshot)).unwrap();
+    loaded.generate_workspace().unwrap();
+    let loaded_top = loaded.config.workspace_path().join("top_m.sv");
+    assert_eq!(std::fs::read_to_string(loaded_top).unwrap(), top);
+  }
+
+  let err = Cmtc::new(CmtcConfig::default()).load_ir(dir.join("missing.ir")).unwrap_err();
+  assert!(err.message.starts_with("can't open "));
 }
@@ -46,19 +46,19 @@ module! {
 fn test_delay() {
   let mut c = Cmtc::new(CmtcConfig::default());
   Clked1To1::new(B4).delay_m(&mut c);
-  c.print();
+  c.print().unwrap();
 }
 
 #[test]
 fn test_delay_k() {
   let mut c = Cmtc::new(CmtcConfig::default());
   Clked1To1::new(B4).delay_k_m(&mut c, 3);
-  c.print();
+  c.print().unwrap();
 }
 
 #[test]
 fn test_delay_instance() {
   let mut c = Cmtc::new(CmtcConfig::default());
   Clked1To1::new(B4).delay_instance_m(&mut c);
-  c.print();
+  c.print().unwrap();
 }
@@ -53,8 +53,8 @@ fn print_sum_k_m() {
   let mut c = Cmtc::new(CmtcConfig::default());
   Clked1To1GoDone::default().sum_k_m(&mut c, 3);
 
-  c.elaborate();
-  c.print();
+  c.elaborate().unwrap();
+  c.print().unwrap();
 }
 
 #[test]
@@ -74,7 +74,8 @@ fn test_sum_k_m() {
     dut.step().await;
     assert_eq!(dut.peek("protocol.done"), StateData::new_bool(true));
     assert_eq!(dut.peek("content.o"), StateData::new_usize(9, 8));
-  });
+  })
+  .unwrap();
 }
 
 module! {
@@ -120,9 +121,10 @@ module! {
                 do_stmt: Box::new(
                     Stmt {
                         name: Some("if".to_string()),
-                        ast: StmtAst::If(IfStmt { cond: if_cond, then_stmt: Box::new(then_step), else_stmt: Some(Box::new(else_step)) })
+                        ast: StmtAst::If(IfStmt { cond: if_cond, then_stmt: Box::new(then_step), else_stmt: Some(Box::new(else_step)), balanced: false })
                     }
-                )
+                ),
+                unroll: None,
             })
         };
 
@@ -139,7 +141,7 @@ module! {
 fn test_for_if_sum_m() {
   let mut c = Cmtc::new(CmtcConfig::default());
   Clked1To1GoDone::default().for_if_sum_m(&mut c, 4);
-  c.print();
+  c.print().unwrap();
 }
 
 module! {
@@ -187,8 +189,8 @@ module! {
 fn test_for_if_sum_macro_m() {
   let mut c = Cmtc::new(CmtcConfig::default());
   Clked1To1GoDone::default().for_if_sum_macro_m(&mut c, 4);
-  c.elaborate();
-  c.print();
+  c.elaborate().unwrap();
+  c.print().unwrap();
   // c.print_common();
 }
 
@@ -253,6 +255,681 @@ module! {
 fn test_while_sum_dyn_m() {
   let mut c = Cmtc::new(CmtcConfig::default());
   ClkedDyn1To1::default().while_sum_dyn_m(&mut c, 4);
-  c.print();
+  c.print().unwrap();
   // c.print_common();
 }
+
+#[test]
+fn test_latency_report() {
+  let mut c = Cmtc::new(CmtcConfig::default());
+  Clked1To1GoDone::default().sum_k_m(&mut c, 3);
+  Clked1To1GoDone::default().for_if_sum_m(&mut c, 4);
+  ClkedDyn1To1::default().while_sum_dyn_m(&mut c, 4);
+
+  let report = c.latency_report();
+  println!("{}", report);
+
+  assert_eq!(report.synths.len(), 3);
+  assert_eq!(report.synths[0].latency, Latency::exact(Cycles::Const(3)));
+  assert_eq!(report.synths[1].latency, Latency::exact(Cycles::Const(4)));
+  assert!(!report.synths[2].latency.is_exact());
+  assert_eq!(report.synths[2].latency.min, Cycles::Const(2));
+  assert_eq!(report.synths[2].latency.max, Cycles::Unbounded);
+}
+
+module! {
+  Clked1To1GoDone(c) =>
+  expect_latency_m(module, expect: LatencyRange, at_synthesize: bool) {
+    let sum = reg!(B8, module.content.clk.to_owned());
+    module.content.o %= sum.rd;
+    let write = event! {
+      sum.wr %= sum.rd + module.content.i.to_owned();
+    };
+    let cond = event!(module.content.i.extract(0, B1).eq(1.lit(B1)));
+
+    let go_event = event!(module.protocol.go);
+    let done_event = event!();
+    module.protocol.done %= done_event.to_owned();
+    let protocol = GoDone::new(module.content.clk, go_event, done_event);
+    if at_synthesize {
+      let stmt = stmt! {
+        seq {
+          { write.to_owned() }
+          { if cond => { write.to_owned() } else write }
+        }
+      };
+      c.synthesize_expecting(stmt, protocol, expect);
+    } else {
+      let stmt = stmt! {
+        expect_latency = expect;
+        seq {
+          { write.to_owned() }
+          { if cond => { write.to_owned() } else write }
+        }
+      };
+      c.synthesize(stmt, protocol);
+    }
+  }
+}
+
+#[test]
+fn test_expect_latency() {
+  for at_synthesize in [false, true] {
+    let mut c = Cmtc::new(CmtcConfig::default());
+    Clked1To1GoDone::default().expect_latency_m(&mut c, 2.into(), at_synthesize);
+    c.elaborate().unwrap();
+  }
+}
+
+#[test]
+fn test_expect_latency_mismatch() {
+  for at_synthesize in [false, true] {
+    let mut c = Cmtc::new(CmtcConfig::default());
+    Clked1To1GoDone::default().expect_latency_m(&mut c, (3..=4).into(), at_synthesize);
+    let err = c.elaborate().unwrap_err();
+    // located where the latency is expected
+    assert!(err.message.starts_with(file!()));
+    assert!(err
+      .message
+      .ends_with("latency of stmt `seq` is 2 cycles, expected [3, 4] cycles"));
+  }
+}
+
+#[test]
+fn test_timing_report() {
+  let mut c = Cmtc::new(CmtcConfig::default());
+  Clked1To1GoDone::default().for_if_sum_m(&mut c, 4);
+  ClkedDyn1To1::default().while_sum_dyn_m(&mut c, 4);
+
+  let report = c.timing_report();
+  println!("{}", report);
+
+  let for_if = &report.synths[0];
+  let acc = Window {
+    first: Latency::exact(Cycles::Const(1)),
+    repeats: vec![(Cycles::Const(1), Cycles::Const(4))],
+    conditional: true,
+  };
+  assert_eq!(for_if.event("acc").unwrap().windows, vec![acc.to_owned()]);
+  assert_eq!(for_if.wire("sum1").unwrap().windows, vec![acc]);
+  assert_eq!(for_if.selects.len(), 1);
+  assert!(for_if.assertions.is_empty());
+
+  let while_sum = &report.synths[1];
+  let output = &while_sum.event("output").unwrap().windows[0];
+  assert_eq!(output.earliest(), Cycles::Const(2));
+  assert!(!output.is_static());
+  assert_eq!(while_sum.assertions.len(), 2);
+}
+
+module! {
+  Clked1To1GoDone(c) =>
+  conflict_m(module, same_step: bool) {
+    let clk = module.content.clk;
+    let sum = reg!(B8, clk.to_owned());
+    module.content.o %= sum.rd.to_owned();
+
+    let sum0 = mut_wire!(sum.wr.ifc().flip());
+    let sum1 = mut_wire!(sum.wr.ifc().flip());
+    let sum2 = mut_wire!(sum.wr.ifc().flip());
+
+    sum.wr %= select(
+      false,
+      vec![None, None, None],
+      vec![sum0.o.expr(), sum1.o.expr(), sum2.o.expr()],
+      None,
+    );
+
+    let acc_shl1 = event! { ("acc_shl1") =>
+      sum0.i %= sum.rd.to_owned() + (module.content.i.to_owned() >> 1.lit(B8));
+    };
+    let acc = event! { ("acc") =>
+      sum1.i %= sum.rd.to_owned() + module.content.i.to_owned();
+    };
+    let is_zero = module.content.i.to_owned().eq(0.lit(B8));
+    event! { ("clear") =>
+      sum2.i %= 0.lit(B8);
+      is_zero
+    };
+
+    let stmt = if same_step {
+      stmt! { seq { { acc, acc_shl1 } } }
+    } else {
+      stmt! { seq { { acc } { acc_shl1 } } }
+    };
+
+    let go_event = event!(module.protocol.go);
+    let done_event = event!();
+    module.protocol.done %= done_event.to_owned();
+    c.synthesize(stmt, GoDone::new(clk, go_event, done_event));
+  }
+}
+
+#[test]
+fn test_conflict_report() {
+  let mut c = Cmtc::new(CmtcConfig::default());
+  Clked1To1GoDone::default().conflict_m(&mut c, true);
+  let report = c.conflict_report();
+  println!("{}", report);
+
+  let simultaneous: Vec<_> = report.simultaneous().collect();
+  assert_eq!(simultaneous.len(), 1);
+  let mut events: Vec<_> = simultaneous[0].events.iter().map(|x| x.0.as_str()).collect();
+  events.sort();
+  assert_eq!(events, vec!["acc", "acc_shl1"]);
+  assert_eq!(report.unproven().count(), 2);
+}
+
+#[test]
+fn test_conflict_exclusive_states() {
+  let mut c = Cmtc::new(CmtcConfig::default());
+  Clked1To1GoDone::default().conflict_m(&mut c, false);
+  let report = c.conflict_report();
+
+  assert_eq!(report.simultaneous().count(), 0);
+  assert_eq!(report.unproven().count(), 2);
+}
+
+#[test]
+fn test_fsm_dot() {
+  let mut c = Cmtc::new(CmtcConfig::default());
+  Clked1To1GoDone::default().for_if_sum_m(&mut c, 4);
+
+  let path = std::path::Path::new("./build/fsm_dot");
+  std::fs::create_dir_all(path).unwrap();
+  c.dump_fsm_dot(path.join("for_if_sum.dot")).unwrap();
+
+  let dot = std::fs::read_to_string(path.join("for_if_sum.dot")).unwrap();
+  println!("{}", dot);
+  assert!(dot.starts_with("digraph fsm {"));
+  assert_eq!(dot.matches("subgraph cluster_").count(), 1);
+  assert_eq!(dot.matches("shape=doublecircle").count(), 1);
+  assert!(dot.contains(" -> "));
+
+  let err = c.dump_fsm_dot(path.join("missing").join("for_if_sum.dot")).unwrap_err();
+  assert!(err.message.starts_with("can't write ./build/fsm_dot/missing/for_if_sum.dot"));
+}
+
+#[test]
+fn test_dump_gir_dir() {
+  let dir = std::path::PathBuf::from("./build/gir_dump");
+  let _ = std::fs::remove_dir_all(&dir);
+  let mut c = Cmtc::new(config! { dump_gir_dir => dir.to_owned() });
+  Clked1To1GoDone::default().sum_k_m(&mut c, 3);
+  c.elaborate().unwrap();
+
+  let dot = std::fs::read_to_string(dir.join("00_load_graph.dot")).unwrap();
+  assert!(dot.starts_with("digraph gir {"));
+  assert!(dir.join("11_merge_select_node.dot").exists());
+}
+
+#[test]
+fn test_dump_gir_dir_error() {
+  // a file stands where the dump directory should be created
+  let file = std::path::PathBuf::from("./build/gir_dump_file");
+  std::fs::create_dir_all("./build").unwrap();
+  std::fs::write(&file, "").unwrap();
+  let mut c = Cmtc::new(config! { dump_gir_dir => file.join("gir") });
+  Clked1To1GoDone::default().sum_k_m(&mut c, 3);
+  let err = c.elaborate().unwrap_err();
+  assert!(err.message.starts_with("can't create ./build/gir_dump_file/gir"));
+}
+
+#[test]
+fn test_gir_pipeline() {
+  let config = config! { gir_stats => true, verify_gir => true }
+    .enable_gir_pass(GirPass::Cond0Prop);
+  let mut c = Cmtc::new(config);
+  Clked1To1GoDone::default().sum_k_m(&mut c, 3);
+  c.elaborate().unwrap();
+
+  let passes: Vec<_> = c.gir_pass_stats.iter().map(|x| x.pass).collect();
+  assert_eq!(passes, GirPass::ALL.to_vec());
+  let make_fsms = &c.gir_pass_stats[0];
+  assert!(make_fsms.nodes_after > make_fsms.nodes_before);
+  assert_eq!(make_fsms.runs, 1);
+}
+
+#[test]
+fn test_gir_pipeline_from_str() {
+  let config = config! {
+    gir_passes => "make_fsms, generate_go_done, fsm_encoding_1, fsm_encoding_2, \
+      state_encode_expr, make_state_event, make_transition_event, merge_event_trigger, \
+      replace_reduce, expr2wire, merge_select_node"
+  };
+  assert_eq!(config.gir_passes, GirPass::default_pipeline());
+  let config = config.enable_gir_pass(GirPass::Cond0Prop);
+  assert_eq!(config.gir_passes[8], GirPass::Cond0Prop);
+  let config = config.disable_gir_pass(GirPass::Cond0Prop);
+  assert_eq!(config.gir_passes, GirPass::default_pipeline());
+}
+
+#[test]
+fn test_gir_pipeline_unknown_pass() {
+  let err = parse_pipeline("make_fsms, make_fsm").unwrap_err();
+  assert!(err.to_string().starts_with("unknown gir pass `make_fsm`"));
+
+  let mut c = Cmtc::new(config! { gir_passes => "make_fsms, make_fsm" });
+  Clked1To1GoDone::default().sum_k_m(&mut c, 3);
+  let err = c.elaborate().unwrap_err();
+  assert!(err.to_string().starts_with("unknown gir pass `make_fsm`"));
+}
+
+#[test]
+fn test_verify_ir() {
+  let mut c = Cmtc::new(config! { verify_ir => true });
+  Clked1To1GoDone::default().sum_k_m(&mut c, 3);
+  c.elaborate().unwrap();
+  assert!(c.verify().is_ok());
+}
+
+#[test]
+fn test_ir_pipeline() {
+  let dir = std::path::PathBuf::from("./build/ir_dump");
+  let _ = std::fs::remove_dir_all(&dir);
+  let mut c = Cmtc::new(config! { ir_stats => true, dump_ir_dir => dir.to_owned() });
+  Clked1To1GoDone::default().sum_k_m(&mut c, 3);
+  c.elaborate().unwrap();
+
+  let passes: Vec<_> = c.ir_pass_stats.iter().map(|x| x.name).collect();
+  let expected = ["reorder", "remove-event", "remove-select", "remove-unary", "dce"];
+  assert_eq!(passes, expected);
+  assert!(c.ir_pass_stats.iter().all(|x| x.runs > 0));
+  let input = std::fs::read_to_string(dir.join("00_input.mlir")).unwrap();
+  assert!(input.contains("hw.module"));
+  assert!(dir.join("05_dce.mlir").exists());
+}
+
+#[test]
+fn test_ir_passes_config() {
+  let mut c = Cmtc::new(config! { ir_passes => "canonicalize,cse" });
+  Clked1To1GoDone::default().sum_k_m(&mut c, 3);
+  c.elaborate().unwrap();
+  let passes: Vec<_> = c.ir_pass_stats.iter().map(|x| x.name).collect();
+  assert_eq!(passes[passes.len() - 3..], ["canonicalize", "cse", "dce"]);
+
+  let mut c = Cmtc::new(config! { ir_passes => "canonicalize, cse, dce, simplify" });
+  Clked1To1GoDone::default().sum_k_m(&mut c, 3);
+  let err = c.elaborate().unwrap_err();
+  assert!(err.to_string().starts_with("unknown pass `simplify`"));
+}
+
+#[test]
+fn test_ir_pipeline_unknown_pass() {
+  let mut c = Cmtc::new(config! {});
+  Clked1To1GoDone::default().sum_k_m(&mut c, 3);
+  let err = c.print_with_passes("reorder, remove-events").unwrap_err();
+  assert!(err.to_string().starts_with("unknown pass `remove-events`"));
+}
+
+module! {
+  Clked1To1GoDone(c) =>
+  unroll_sum_m(module, n: usize, unroll: Option<Unroll>) {
+    let clk = module.content.clk;
+    let sum = reg!(B8, clk.to_owned());
+    let i = reg!(B8, clk.to_owned());
+    module.content.o %= sum.rd.to_owned();
+
+    let acc = event! { ("acc") =>
+      sum.wr %= sum.rd + i.rd.to_owned();
+    };
+    let stmt = stmt! {
+      for i.rd.v_ir_entity_id()[0].unwrap(), i.wr.v_ir_entity_id()[0].unwrap(), Bound::Const(0), Bound::Const(n), true, 1 =>
+        acc
+    };
+    let stmt = match unroll {
+      Some(Unroll::Full) => stmt.unroll_full(),
+      Some(Unroll::Factor(k)) => stmt.unroll(k),
+      None => stmt,
+    };
+
+    let go_event = event!(module.protocol.go);
+    let done_event = event!();
+    module.protocol.done %= done_event.to_owned();
+    c.synthesize(stmt, GoDone::new(clk, go_event, done_event));
+  }
+}
+
+fn count_stmt_for(c: &Cmtc) -> usize {
+  c.ir.op_table.iter().filter(|(_, op)| matches!(op, OpEnum::StmtFor(_))).count()
+}
+
+#[test]
+fn test_unroll_full() {
+  let mut c = Cmtc::new(CmtcConfig::default());
+  Clked1To1GoDone::default().unroll_sum_m(&mut c, 4, Some(Unroll::Full));
+  // a copy of the body for each induction value, and no loop
+  assert_eq!(count_stmt_for(&c), 0);
+
+  let report = c.latency_report();
+  assert_eq!(report.synths[0].latency, Latency::exact(Cycles::Const(4)));
+  let timing = c.timing_report();
+  for event in ["acc_1", "acc_2", "acc_3", "acc_4"] {
+    assert_eq!(timing.synths[0].event(event).unwrap().windows.len(), 1);
+  }
+
+  let path = std::path::Path::new("./build/fsm_dot");
+  std::fs::create_dir_all(path).unwrap();
+  c.dump_fsm_dot(path.join("unroll_full.dot")).unwrap();
+  let dot = std::fs::read_to_string(path.join("unroll_full.dot")).unwrap();
+  // a state for each copy of the body
+  assert_eq!(dot.matches("[label=\"s").count(), 4);
+
+  // the copies add the constant induction values
+  c.elaborate().unwrap();
+  let module = c.module_op_id_iter().next().unwrap();
+  let mut interpreter = Interpreter::new(&c.ir, module).unwrap();
+  interpreter.poke("protocol.go", Value::from_bool(true)).unwrap();
+  interpreter.step().unwrap();
+  interpreter.poke("protocol.go", Value::from_bool(false)).unwrap();
+  for _ in 0..4 {
+    interpreter.step().unwrap();
+  }
+  interpreter.eval().unwrap();
+  assert_eq!(interpreter.peek("protocol.done"), Some(Value::from_bool(true)));
+  assert_eq!(interpreter.peek("content.o"), Some(Value::from_u32(1 + 2 + 3, 8)));
+}
+
+#[test]
+fn test_unroll_partial() {
+  let mut c = Cmtc::new(CmtcConfig::default());
+  Clked1To1GoDone::default().unroll_sum_m(&mut c, 7, Some(Unroll::Factor(3)));
+  // the main loop, with three copies of the body, and the remainder loop
+  assert_eq!(count_stmt_for(&c), 2);
+
+  let report = c.latency_report();
+  assert_eq!(report.synths[0].latency, Latency::exact(Cycles::Const(7)));
+  // `acc` is stepped in both loops, its copies only in the main one
+  let timing = c.timing_report();
+  let windows = |x: &str| timing.synths[0].event(x).unwrap().windows.len();
+  assert_eq!([windows("acc"), windows("acc_1"), windows("acc_2")], [2, 1, 1]);
+}
+
+#[test]
+fn test_unroll_factor_one() {
+  let mut c = Cmtc::new(CmtcConfig::default());
+  Clked1To1GoDone::default().unroll_sum_m(&mut c, 4, Some(Unroll::Factor(1)));
+  assert_eq!(count_stmt_for(&c), 1);
+  let report = c.latency_report();
+  assert_eq!(report.synths[0].latency, Latency::exact(Cycles::Const(4)));
+}
+
+#[test]
+fn test_unroll_factor_zero() {
+  let mut c = Cmtc::new(CmtcConfig::default());
+  Clked1To1GoDone::default().unroll_sum_m(&mut c, 4, Some(Unroll::Factor(0)));
+  // the loop is kept, and the error is reported by `elaborate`
+  assert_eq!(count_stmt_for(&c), 1);
+  let err = c.elaborate().unwrap_err().to_string();
+  assert!(err.contains("src/tests/stmt.rs"));
+  assert!(err.ends_with("unroll factor must be positive"));
+}
+
+module! {
+  Clked1To1GoDone(c) =>
+  shared_bram_m(module, dynamic: bool) {
+    let clk = module.content.clk;
+    let x = reg!(B8, clk.to_owned());
+    let y = reg!(B8, clk.to_owned());
+    let z = reg!(B8, clk.to_owned());
+    let w = reg!(B8, clk.to_owned());
+    module.content.o %= x.rd.to_owned() + y.rd.to_owned() + z.rd.to_owned() + w.rd.to_owned();
+
+    let read_x = event! { ("read_x") =>
+      x.wr %= module.content.i.to_owned();
+    };
+    let incr_w = event! { ("incr_w") =>
+      w.wr %= w.rd.to_owned() + 1.lit(B8);
+    };
+    let read_y = event! { ("read_y") =>
+      y.wr %= module.content.i.to_owned();
+    };
+    let incr_z = event! { ("incr_z") =>
+      z.wr %= z.rd.to_owned() + 1.lit(B8);
+    };
+    read_x.uses_resource("bram", c);
+    read_y.uses_resource("bram", c);
+
+    let stmt = if dynamic {
+      let ready = event!(module.content.i.extract(0, B1).eq(1.lit(B1)));
+      stmt! {
+        par {
+          { seq { { read_x } { incr_w } } }
+          { seq { { incr_z; [ready] } { read_y } } }
+        }
+      }
+    } else {
+      stmt! {
+        par {
+          { seq { { read_x } { incr_w } } }
+          { seq { { read_y } { incr_z } } }
+        }
+      }
+    };
+
+    let go_event = event!(module.protocol.go);
+    let done_event = event!();
+    module.protocol.done %= done_event.to_owned();
+    c.synthesize(stmt, GoDone::new(clk, go_event, done_event));
+  }
+}
+
+#[test]
+fn test_schedule_par() {
+  let mut c = Cmtc::new(CmtcConfig::default());
+  Clked1To1GoDone::default().shared_bram_m(&mut c, false);
+  let report = c.schedule_report();
+  println!("{}", report);
+
+  assert_eq!(report.pars.len(), 1);
+  let par = &report.pars[0];
+  assert_eq!(par.branch(0).unwrap().delay, 0);
+  assert_eq!(par.branch(1).unwrap().delay, 1);
+  assert_eq!(par.branch(1).unwrap().resources, vec!["bram".to_string()]);
+  assert!(par.branches.iter().all(|x| !x.serialized));
+
+  // the schedule only applies if enabled
+  assert_eq!(c.latency_report().synths[0].latency, Latency::exact(Cycles::Const(2)));
+  c.config.schedule_par = true;
+  assert_eq!(c.latency_report().synths[0].latency, Latency::exact(Cycles::Const(3)));
+  assert_eq!(c.conflict_report().simultaneous().count(), 0);
+  c.config.verify_gir = true;
+  c.elaborate().unwrap();
+}
+
+#[test]
+fn test_schedule_par_dynamic() {
+  let mut c = Cmtc::new(config! { schedule_par => true });
+  Clked1To1GoDone::default().shared_bram_m(&mut c, true);
+  let report = c.schedule_report();
+  println!("{}", report);
+
+  // the branch waiting for `ready` is scheduled first, as its latency is unbounded
+  let par = &report.pars[0];
+  assert_eq!(par.branches[0].branch, 1);
+  assert!(par.branch(0).unwrap().serialized);
+  let latency = c.latency_report().synths[0].latency.to_owned();
+  assert_eq!(latency.min, Cycles::Const(4));
+  assert_eq!(latency.max, Cycles::Unbounded);
+}
+
+
+#[test]
+fn test_par_stmt() {
+  let mut c = Cmtc::new(CmtcConfig::default());
+  Clked1To1GoDone::default().shared_bram_m(&mut c, false);
+  // the branches of a par run at once rather than one after the other
+  let pars =
+    c.ir.op_table.iter().filter(|(_, op)| matches!(op, OpEnum::StmtPar(_))).count();
+  assert_eq!(pars, 1);
+
+  c.elaborate().unwrap();
+  let module = c.module_op_id_iter().next().unwrap();
+  let mut interpreter = Interpreter::new(&c.ir, module).unwrap();
+  interpreter.poke("content.i", Value::from_u32(3, 8)).unwrap();
+  interpreter.poke("protocol.go", Value::from_bool(true)).unwrap();
+  interpreter.step().unwrap();
+  interpreter.poke("protocol.go", Value::from_bool(false)).unwrap();
+  for _ in 0..2 {
+    interpreter.step().unwrap();
+  }
+  interpreter.eval().unwrap();
+  assert_eq!(interpreter.peek("protocol.done"), Some(Value::from_bool(true)));
+  assert_eq!(interpreter.peek("content.o"), Some(Value::from_u32(3 + 3 + 1 + 1, 8)));
+}
+
+module! {
+  Clked1To1GoDone(c) =>
+  balanced_if_m(module, has_else: bool, balanced: bool) {
+    let sum = reg!(B8, module.content.clk.to_owned());
+    module.content.o %= sum.rd.to_owned();
+    let acc = event! { ("acc") =>
+      sum.wr %= sum.rd + module.content.i.to_owned();
+    };
+    let cond = event!(module.content.i.extract(0, B1).eq(1.lit(B1)));
+
+    // the then branch takes 3 cycles with an else branch, 2 otherwise
+    let (then_stmt, else_stmt) = if has_else {
+      (stmt! { seq { { acc.to_owned() } { } { } } }, Some(Box::new(stmt! { acc })))
+    } else {
+      (stmt! { seq { { acc.to_owned() } { acc } } }, None)
+    };
+    let stmt = Stmt {
+      name: Some("if".to_string()),
+      ast: StmtAst::If(IfStmt { cond, then_stmt: Box::new(then_stmt), else_stmt, balanced: false }),
+    };
+    let stmt = if balanced { stmt.balanced().unwrap() } else { stmt };
+
+    let go_event = event!(module.protocol.go);
+    let done_event = event!();
+    module.protocol.done %= done_event.to_owned();
+    c.synthesize(stmt, GoDone::new(module.content.clk, go_event, done_event));
+  }
+}
+
+#[test]
+fn test_balanced_if() {
+  let mut c = Cmtc::new(CmtcConfig::default());
+  Clked1To1GoDone::default().balanced_if_m(&mut c, true, false);
+  let latency = c.latency_report().synths[0].latency.to_owned();
+  assert_eq!((latency.min, latency.max), (Cycles::Const(1), Cycles::Const(3)));
+
+  let mut c = Cmtc::new(config! { verify_gir => true });
+  Clked1To1GoDone::default().balanced_if_m(&mut c, true, true);
+  assert_eq!(c.latency_report().synths[0].latency, Latency::exact(Cycles::Const(3)));
+  c.elaborate().unwrap();
+}
+
+#[test]
+fn test_balanced_not_if() {
+  let stmt = Stmt {
+    name: Some("seq".to_string()),
+    ast: StmtAst::Seq(SeqStmt { stmts: vec![] }),
+  };
+  let err = stmt.balanced().err().unwrap();
+  assert_eq!(err.to_string(), "only if statements can be balanced, not `seq`");
+}
+
+#[test]
+fn test_balance_if_config() {
+  let mut c = Cmtc::new(config! { balance_if => true });
+  Clked1To1GoDone::default().balanced_if_m(&mut c, false, false);
+  // the implicit else branch is padded from one cycle to two
+  assert_eq!(c.latency_report().synths[0].latency, Latency::exact(Cycles::Const(2)));
+  let timing = c.timing_report();
+  assert_eq!(timing.synths[0].event("acc").unwrap().windows.len(), 2);
+}
+
+#[interface(Default)]
+pub struct TwoProcs {
+  content: Clked1To1<B<8>>,
+  acc: GoDoIfc,
+  count: GoDoIfc,
+}
+
+module! {
+  TwoProcs(c) =>
+  two_procs_m(module) {
+    let sum = reg!(B8, module.content.clk.to_owned());
+    let cnt = reg!(B8, module.content.clk.to_owned());
+    module.content.o %= sum.rd.to_owned();
+    let acc = event! { ("acc") =>
+      sum.wr %= sum.rd + module.content.i.to_owned();
+    };
+    let inc = event! { ("inc") =>
+      cnt.wr %= cnt.rd + 1.lit(B8);
+    };
+
+    // each synthesized statement has its own go/done ports
+    let acc_go = event!(module.acc.go);
+    let acc_done = event!();
+    module.acc.done %= acc_done.to_owned();
+    let acc_stmt = stmt! { seq { { acc.to_owned() } { acc } } };
+    c.synthesize(acc_stmt, GoDone::new(module.content.clk.to_owned(), acc_go, acc_done));
+
+    let count_go = event!(module.count.go);
+    let count_done = event!();
+    module.count.done %= count_done.to_owned();
+    c.synthesize(stmt! { inc }, GoDone::new(module.content.clk, count_go, count_done));
+  }
+}
+
+module! {
+  Clked1To1GoDone(c) =>
+  call_m(module) {
+    let child = instance!(two_procs_m(TwoProcs::default()));
+    child.content.clk %= module.content.clk.to_owned();
+    child.content.i %= module.content.i.to_owned();
+    module.content.o %= child.content.o;
+
+    let stmt = stmt! { seq { { call child.acc } { call child.count } } };
+    let go_event = event!(module.protocol.go);
+    let done_event = event!();
+    module.protocol.done %= done_event.to_owned();
+    c.synthesize(stmt, GoDone::new(module.content.clk, go_event, done_event));
+  }
+}
+
+module! {
+  Clked1To1GoDone(c) =>
+  unroll_call_m(module) {
+    let child = instance!(two_procs_m(TwoProcs::default()));
+    child.content.clk %= module.content.clk.to_owned();
+    child.content.i %= module.content.i.to_owned();
+    module.content.o %= child.content.o;
+
+    let i = reg!(B8, module.content.clk.to_owned());
+    let stmt = stmt! {
+      for i.rd.v_ir_entity_id()[0].unwrap(), i.wr.v_ir_entity_id()[0].unwrap(), Bound::Const(0), Bound::Const(2), true, 1 =>
+        call child.count
+    };
+    let go_event = event!(module.protocol.go);
+    let done_event = event!();
+    module.protocol.done %= done_event.to_owned();
+    let protocol = GoDone::new(module.content.clk, go_event, done_event);
+    c.synthesize(stmt.unroll_full(), protocol);
+  }
+}
+
+#[test]
+fn test_call_stmt() {
+  let mut c = Cmtc::new(config! { verify_gir => true });
+  Clked1To1GoDone::default().call_m(&mut c);
+  let report = c.latency_report();
+  assert_eq!(report.get("two_procs_m", "seq"), Some(&Latency::exact(Cycles::Const(2))));
+  // go and done of each call take two more cycles than the callee
+  assert_eq!(report.get("call_m", "seq"), Some(&Latency::exact(Cycles::Const(7))));
+  let timing = c.timing_report();
+  let call = timing.synths.iter().find(|x| x.module == "call_m").unwrap();
+  assert_eq!(call.event("call_go").unwrap().windows.len(), 1);
+  c.elaborate().unwrap();
+}
+
+#[test]
+fn test_unroll_call() {
+  let mut c = Cmtc::new(CmtcConfig::default());
+  Clked1To1GoDone::default().unroll_call_m(&mut c);
+  assert_eq!(count_stmt_for(&c), 1);
+  let err = c.elaborate().unwrap_err().to_string();
+  assert!(err.ends_with("for statements calling an instance can't be unrolled"));
+}
@@ -0,0 +1,156 @@
+use std::any::{Any, TypeId};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::{EntityId, Environ, IronyResult, Op, OpId};
+
+/// A fact about an op computed from the IR, which can be cached by an
+/// [`AnalysisManager`] until a pass changes the op
+pub trait Analysis<E: Environ>: Any + Sized {
+  /// Whether the analysis reads ops outside of the op it is computed on, like
+  /// other modules, so that it is invalidated by passes on any op
+  const GLOBAL: bool = false;
+
+  fn compute(env: &E, op: OpId) -> IronyResult<Self>;
+}
+
+/// Analyses kept valid by a pass, declared by [`crate::PassTrait::preserved_analyses`]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum PreservedAnalyses {
+  #[default]
+  None,
+  All,
+  Some(HashSet<TypeId>),
+}
+
+impl PreservedAnalyses {
+  pub fn none() -> Self { Self::None }
+
+  pub fn all() -> Self { Self::All }
+
+  pub fn preserve<A: Any>(self) -> Self {
+    match self {
+      Self::All => Self::All,
+      Self::None => Self::Some(HashSet::from([TypeId::of::<A>()])),
+      Self::Some(mut preserved) => {
+        preserved.insert(TypeId::of::<A>());
+        Self::Some(preserved)
+      },
+    }
+  }
+
+  pub fn is_preserved(&self, analysis: TypeId) -> bool {
+    match self {
+      Self::None => false,
+      Self::All => true,
+      Self::Some(preserved) => preserved.contains(&analysis),
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+struct CachedAnalysis {
+  value: Rc<dyn Any>,
+  global: bool,
+}
+
+/// Cache of analyses keyed by (analysis type, op).
+///
+/// Passes run by a pass manager invalidate the analyses they don't preserve;
+/// the cache must be cleared if the IR is changed by other means.
+#[derive(Clone, Debug, Default)]
+pub struct AnalysisManager {
+  cache: HashMap<(TypeId, OpId), CachedAnalysis>,
+}
+
+impl AnalysisManager {
+  /// Get the analysis of `op`, computing it if it's not cached
+  pub fn get<A, E>(&mut self, env: &E, op: OpId) -> IronyResult<Rc<A>>
+  where
+    A: Analysis<E>,
+    E: Environ,
+  {
+    if let Some(cached) = self.get_cached::<A>(op) {
+      return Ok(cached);
+    }
+    let value = Rc::new(A::compute(env, op)?);
+    self.cache.insert((TypeId::of::<A>(), op), CachedAnalysis {
+      value: value.to_owned(),
+      global: A::GLOBAL,
+    });
+    Ok(value)
+  }
+
+  pub fn get_cached<A: Any>(&self, op: OpId) -> Option<Rc<A>> {
+    self
+      .cache
+      .get(&(TypeId::of::<A>(), op))
+      .and_then(|cached| cached.value.to_owned().downcast::<A>().ok())
+  }
+
+  pub fn is_cached<A: Any>(&self, op: OpId) -> bool {
+    self.cache.contains_key(&(TypeId::of::<A>(), op))
+  }
+
+  /// Invalidate the analyses not in `preserved` after a pass has changed `op`.
+  ///
+  /// Analyses of `op`, of the ops enclosing it and of the ops nested in it are
+  /// invalidated, as well as the global analyses of any op.
+  pub fn invalidate<E: Environ>(
+    &mut self, env: &E, op: OpId, preserved: &PreservedAnalyses,
+  ) {
+    if let PreservedAnalyses::All = preserved {
+      return;
+    }
+    let mut changed = env.walk_ops_from(vec![op]).into_iter().collect::<HashSet<_>>();
+    let mut parent = env.get_op(op).get_parent();
+    while let Some(op) = parent.and_then(|region| env.get_region_use(region)) {
+      changed.insert(op);
+      parent = env.get_op(op).get_parent();
+    }
+    self.cache.retain(|(analysis, op), cached| {
+      preserved.is_preserved(*analysis) || !(cached.global || changed.contains(op))
+    });
+  }
+
+  /// Invalidate the analyses not in `preserved` of all the ops
+  pub fn invalidate_all(&mut self, preserved: &PreservedAnalyses) {
+    self.cache.retain(|(analysis, _), _| preserved.is_preserved(*analysis));
+  }
+
+  pub fn clear(&mut self) { self.cache.clear(); }
+}
+
+/// Ops defining and using each entity, among an op and the ops nested in it
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DefUse {
+  defs: HashMap<EntityId, Vec<OpId>>,
+  uses: HashMap<EntityId, Vec<OpId>>,
+}
+
+impl DefUse {
+  pub fn defs(&self, entity: EntityId) -> &[OpId] {
+    self.defs.get(&entity).map(Vec::as_slice).unwrap_or_default()
+  }
+
+  pub fn uses(&self, entity: EntityId) -> &[OpId] {
+    self.uses.get(&entity).map(Vec::as_slice).unwrap_or_default()
+  }
+}
+
+impl<E: Environ> Analysis<E> for DefUse {
+  fn compute(env: &E, op: OpId) -> IronyResult<Self> {
+    let mut def_use = DefUse::default();
+    for op_id in env.walk_ops_from(vec![op]) {
+      let op = env.get_op(op_id);
+      for (table, entities) in
+        [(&mut def_use.defs, op.get_defs()), (&mut def_use.uses, op.get_uses())]
+      {
+        for entity in entities.into_iter().flat_map(|(_, x)| x).flatten() {
+          table.entry(entity).or_insert_with(Vec::new).push(op_id);
+        }
+      }
+    }
+    Ok(def_use)
+  }
+}
@@ -51,7 +51,8 @@ macro_rules! data_type_enum {
             $(,)?
         }
     ) => {
-        #[derive(Clone, Debug, PartialEq, Hash, Default)]
+        #[derive(Clone, Debug, PartialEq, Hash, Default, irony::serde::Serialize, irony::serde::Deserialize)]
+        #[serde(crate = "irony::serde")]
         pub enum $enum_name {
             #[default]
             Void,
@@ -87,7 +88,8 @@ macro_rules! attribute_enum {
             $(,)?
         }
     ) => {
-        #[derive(Clone, Debug, PartialEq, Hash)]
+        #[derive(Clone, Debug, PartialEq, Hash, irony::serde::Serialize, irony::serde::Deserialize)]
+        #[serde(crate = "irony::serde")]
         pub enum $name {
             None,
             $($variant($variant_ty)),*
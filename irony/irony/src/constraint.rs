@@ -11,7 +11,7 @@ pub trait ConstraintTrait {
     &self, env: &'env E, attrs: Vec<(String, Self::AttributeT)>,
     uses: Vec<(String, Vec<Option<EntityId>>)>,
     defs: Vec<(String, Vec<Option<EntityId>>)>, regions: Vec<(String, Vec<Option<RegionId>>)>,
-  ) -> bool
+  ) -> Result<(), String>
   where
     E: Environ<EntityT = EntityT, AttributeT = Self::AttributeT>,
     EntityT: Entity<DataTypeT = Self::DataTypeT, AttributeT = Self::AttributeT>;
@@ -30,7 +30,7 @@ impl<D: PartialEq, A: Clone + PartialEq> ConstraintTrait for SameTypeConstraint<
     &self, env: &'env E, _attrs: Vec<(String, Self::AttributeT)>,
     uses: Vec<(String, Vec<Option<EntityId>>)>,
     defs: Vec<(String, Vec<Option<EntityId>>)>, _regions: Vec<(String, Vec<Option<RegionId>>)>,
-  ) -> bool
+  ) -> Result<(), String>
   where
     E: Environ<EntityT = EntityT>,
     EntityT: Entity<DataTypeT = Self::DataTypeT>,
@@ -56,9 +56,13 @@ impl<D: PartialEq, A: Clone + PartialEq> ConstraintTrait for SameTypeConstraint<
 
     let mut ty_collect = (uses_tys).chain(defs_tys);
     if let Some(first) = ty_collect.next() {
-      ty_collect.all(|item| item == first)
+      if ty_collect.all(|item| item == first) {
+        Ok(())
+      } else {
+        Err("operands and results must have the same type".to_string())
+      }
     } else {
-      true
+      Ok(())
     }
   }
 }
@@ -80,7 +84,7 @@ impl<D: PartialEq, A> ConstraintTrait for SameTypeOperandConstraint<D, A> {
     &self, env: &'env E, _attrs: Vec<(String, Self::AttributeT)>,
     uses: Vec<(String, Vec<Option<EntityId>>)>,
     _defs: Vec<(String, Vec<Option<EntityId>>)>, _regions: Vec<(String, Vec<Option<RegionId>>)>,
-  ) -> bool
+  ) -> Result<(), String>
   where
     E: Environ<EntityT = EntityT, AttributeT = Self::AttributeT>,
     EntityT: Entity<DataTypeT = Self::DataTypeT, AttributeT = Self::AttributeT>,
@@ -96,9 +100,13 @@ impl<D: PartialEq, A> ConstraintTrait for SameTypeOperandConstraint<D, A> {
     });
 
     if let Some(first) = uses_tys.next() {
-      uses_tys.all(|item| item == first)
+      if uses_tys.all(|item| item == first) {
+        Ok(())
+      } else {
+        Err("operands must have the same type".to_string())
+      }
     } else {
-      true
+      Ok(())
     }
   }
 }
@@ -131,7 +139,7 @@ macro_rules! constraint_def {
                 uses: Vec<(String, Vec<Option<irony::EntityId>>)>,
                 defs: Vec<(String, Vec<Option<irony::EntityId>>)>,
                 regions: Vec<(String, Vec<Option<irony::RegionId>>)>,
-            ) -> bool
+            ) -> Result<(), String>
             where
                 E: irony::Environ<EntityT = EntityT, AttributeT = Self::AttributeT>,
                 EntityT: irony::Entity<DataTypeT = Self::DataTypeT, AttributeT = Self::AttributeT> {
@@ -175,7 +183,7 @@ macro_rules! constraint_struct_impl {
                 uses: Vec<(String, Vec<Option<irony::EntityId>>)>,
                 defs: Vec<(String, Vec<Option<irony::EntityId>>)>,
                 regions: Vec<(String, Vec<Option<irony::RegionId>>)>,
-            ) -> bool
+            ) -> Result<(), String>
             where
                 E: irony::Environ<EntityT = EntityT, AttributeT = Self::AttributeT>,
                 EntityT: irony::Entity<DataTypeT = Self::DataTypeT, AttributeT = Self::AttributeT> {
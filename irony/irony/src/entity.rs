@@ -1,3 +1,5 @@
+use serde::{Deserialize, Serialize};
+
 use super::common::Id;
 use super::environ::Environ;
 use super::operation::OpId;
@@ -36,7 +38,7 @@ pub trait Entity: Id {
   }
 }
 
-#[derive(Clone, Copy, PartialEq, Debug, Default, Hash, Eq)]
+#[derive(Clone, Copy, PartialEq, Debug, Default, Hash, Eq, Serialize, Deserialize)]
 pub struct EntityId(pub usize);
 
 impl From<usize> for EntityId {
@@ -80,7 +82,8 @@ macro_rules! entity_def_one {
 
 
     ($name:ident : ($(attrs = [$($attr:ident: $attr_variant:ident($attr_inner_ty:ty))*],)? data_type = $data_type:ty, attr = $attr_ty:ty)) => {
-        #[derive(Clone, Debug, PartialEq, Hash)]
+        #[derive(Clone, Debug, PartialEq, Hash, irony::serde::Serialize, irony::serde::Deserialize)]
+        #[serde(crate = "irony::serde")]
         pub struct $name {
             pub id: usize,
             pub parent: Option<irony::RegionId>,
@@ -172,7 +175,8 @@ macro_rules! entity_def_one {
 #[macro_export]
 macro_rules! entity_enum {
     ([data_type = $dtype:ty, attr = $attr_ty: ty] $name:ident= $($variant:ident),*) => {
-        #[derive(Clone, Debug, PartialEq, Hash)]
+        #[derive(Clone, Debug, PartialEq, Hash, irony::serde::Serialize, irony::serde::Deserialize)]
+        #[serde(crate = "irony::serde")]
         pub enum $name {
             $($variant($variant)),*
         }
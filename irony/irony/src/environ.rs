@@ -3,7 +3,10 @@ use std::cell::RefMut;
 use super::constraint::ConstraintTrait;
 use super::entity::{Entity, EntityId};
 use super::operation::{Op, OpId};
-use crate::{Id, OpPrinterTrait, ReducerTrait, Region, RegionId, AsBool};
+use crate::{
+  AsBool, Diagnostic, Id, IronyError, InterpreterStateTrait, OpInterpreterTrait, OpPrinterTrait, ReducerTrait,
+  Region, RegionId,
+};
 
 pub trait Environ: Sized {
   type DataTypeT;
@@ -54,28 +57,100 @@ pub trait Environ: Sized {
     &mut self, parent: Option<RegionId>, f: F,
   );
 
-  fn verify_op(&self, op: OpId) -> bool {
-    let op = self.get_op(op);
+  fn verify_op(&self, op_id: OpId) -> Result<(), Vec<Diagnostic>> {
+    let op = self.get_op(op_id);
     let constraints = op.get_constraints();
     let attributes = op.get_attrs();
     let uses = op.get_uses();
     let defs = op.get_defs();
     let regions = op.get_regions();
-    let all_true = constraints.into_iter().all(|constraint| {
-      constraint.verify(
-        self,
-        attributes.to_owned(),
-        defs.to_owned(),
-        uses.to_owned(),
-        regions.to_owned(),
-      )
-    });
-    all_true
+    let diagnostics = constraints
+      .into_iter()
+      .filter_map(|constraint| {
+        constraint
+          .verify(
+            self,
+            attributes.to_owned(),
+            uses.to_owned(),
+            defs.to_owned(),
+            regions.to_owned(),
+          )
+          .err()
+      })
+      .map(|message| self.diagnose_op(op_id, message))
+      .collect::<Vec<_>>();
+    if diagnostics.is_empty() {
+      Ok(())
+    } else {
+      Err(diagnostics)
+    }
   }
 
-  fn print_op(&self, op_id: OpId) -> String {
-    self.verify_op(op_id);
+  /// Verify all the ops, walking into their regions from the top level
+  fn verify(&self) -> Result<(), Vec<Diagnostic>> {
+    let diagnostics = self
+      .walk_ops()
+      .into_iter()
+      .filter_map(|op_id| self.verify_op(op_id).err())
+      .flatten()
+      .collect::<Vec<_>>();
+    if diagnostics.is_empty() {
+      Ok(())
+    } else {
+      Err(diagnostics)
+    }
+  }
 
+  /// All the ops in pre-order, walking into their regions from the top level
+  fn walk_ops(&self) -> Vec<OpId> { self.walk_ops_from(self.get_ops_with_parent(None)) }
+
+  /// `roots` and the ops nested in their regions, in pre-order
+  fn walk_ops_from(&self, roots: Vec<OpId>) -> Vec<OpId> {
+    let mut ops = vec![];
+    let mut stack = roots;
+    stack.reverse();
+    while let Some(op_id) = stack.pop() {
+      ops.push(op_id);
+      let regions = self.get_op(op_id).get_regions();
+      let children = regions
+        .iter()
+        .flat_map(|(_, regions)| regions.iter().flatten())
+        .flat_map(|region| self.get_region(*region).op_children.to_owned())
+        .collect::<Vec<_>>();
+      stack.extend(children.into_iter().rev());
+    }
+    ops
+  }
+
+  fn diagnose_op(&self, op_id: OpId, message: String) -> Diagnostic {
+    let op = self.get_op(op_id);
+    let text = op.get_printer().print(
+      self,
+      op.get_attrs(),
+      op.get_uses(),
+      op.get_defs(),
+      op.get_regions(),
+    );
+    let locations = op
+      .get_uses()
+      .into_iter()
+      .chain(op.get_defs())
+      .flat_map(|(_, ids)| ids.into_iter().flatten())
+      .filter_map(|id| {
+        let location = self.get_entity(id).get_attr("location")?;
+        Some((id, self.print_entity(id), format!("{}", location)))
+      })
+      .collect();
+    Diagnostic {
+      op: op_id,
+      op_name: op.get_op_name(),
+      op_text: text.lines().next().unwrap_or_default().trim().to_string(),
+      message,
+      locations,
+    }
+  }
+
+  fn print_op(&self, op_id: OpId) -> String {
     let op = self.get_op(op_id);
     let printer = op.get_printer();
     let attributes = op.get_attrs();
@@ -86,6 +161,15 @@ pub trait Environ: Sized {
     // println!("op: {:?}", op_id);
     let mut str = printer.print(self, attributes, uses, defs.to_owned(), regions);
 
+    // the op is located where the first of its results is defined
+    let location = defs
+      .iter()
+      .flat_map(|(_, defv)| defv.iter().flatten())
+      .find_map(|def| self.get_entity(*def).get_attr("location"));
+    if let Some(location) = location {
+      str = format!("{} {}", str, crate::utils::print::mlir_location(&location.to_string()));
+    }
+
     for (_def_name, defv) in defs.iter() {
       for def in defv {
         if let Some(entity_id) = def {
@@ -113,6 +197,22 @@ pub trait Environ: Sized {
     str
   }
 
+  /// Evaluate an op with its `interpret` hook, reading and writing the values of its
+  /// entities in `state`
+  fn interpret_op<S>(&self, op_id: OpId, state: &mut S) -> Result<(), String>
+  where S: InterpreterStateTrait<
+      ValueT = <<Self::OpT as Op>::InterpreterT as OpInterpreterTrait>::ValueT,
+    > {
+    let op = self.get_op(op_id);
+    let interpreter = op.get_interpreter();
+    let attributes = op.get_attrs();
+    let uses = op.get_uses();
+    let defs = op.get_defs();
+    let regions = op.get_regions();
+
+    interpreter.interpret(self, state, attributes, uses, defs, regions)
+  }
+
   fn print_entity(&self, entity: EntityId) -> String {
     // TODO: Add better printing for entities
     let entity = self.get_entity(entity);
@@ -155,7 +255,7 @@ pub trait Environ: Sized {
 
   fn dump(&self) -> String;
 
-  fn run_passes(&mut self) -> Result<(), ()>; // -> ???
+  fn run_passes(&mut self) -> Result<(), IronyError>;
 
   #[track_caller]
   fn get_hasher(&self) -> RefMut<crate::FxHasher>;
@@ -243,6 +343,29 @@ macro_rules! environ_def {
         use std::hash::BuildHasher;
         use std::hash::Hasher;
 
+        impl $name {
+            /// Write the op, entity and region tables, keeping the ids of their entries
+            pub fn save_snapshot<W: std::io::Write>(&self, writer: W, format: irony::SnapshotFormat) -> Result<(), irony::IronyError> {
+                let snapshot = irony::Snapshot {
+                    op_table: &self.op_table,
+                    entity_table: &self.entity_table,
+                    region_table: &self.region_table,
+                };
+                format.write(writer, &snapshot)
+            }
+
+            /// Replace the op, entity and region tables with the ones of a snapshot;
+            /// the ops are no longer deduplicated against the ones hashed before
+            pub fn load_snapshot<R: std::io::Read>(&mut self, reader: R, format: irony::SnapshotFormat) -> Result<(), irony::IronyError> {
+                let snapshot: irony::Snapshot<_, _, _> = format.read(reader)?;
+                self.op_table = snapshot.op_table;
+                self.entity_table = snapshot.entity_table;
+                self.region_table = snapshot.region_table;
+                self.op_hash_table.clear();
+                Ok(())
+            }
+        }
+
         impl irony::Environ for $name {
             type DataTypeT = $data_ty;
 
@@ -414,8 +537,8 @@ macro_rules! environ_def {
                 format!("entity table: {:#?}\nregion table: {:#?}\nop table: {:#?}", self.entity_table.get_map(), self.region_table.get_map(), self.op_table.get_map())
             }
 
-            fn run_passes(&mut self) -> Result<(), ()>{
-                let pass_manager = self.pass_manager.clone();
+            fn run_passes(&mut self) -> Result<(), irony::IronyError>{
+                let mut pass_manager = self.pass_manager.clone();
                 pass_manager.run_passes(self)?;
                 Ok(())
             }
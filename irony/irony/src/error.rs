@@ -0,0 +1,62 @@
+use crate::{Entity, EntityId, Environ, OpId};
+
+/// An error raised while transforming the IR, pointing at the op or the entity
+/// that caused it
+#[derive(Clone, Debug, PartialEq)]
+pub struct IronyError {
+  /// Name of the pass which raised the error
+  pub pass: Option<String>,
+  pub op: Option<OpId>,
+  pub entity: Option<EntityId>,
+  pub message: String,
+  /// Source location of the entity, from its `location` attribute
+  pub location: Option<String>,
+}
+
+pub type IronyResult<T> = Result<T, IronyError>;
+
+impl IronyError {
+  pub fn new(message: impl Into<String>) -> Self {
+    Self {
+      pass: None,
+      op: None,
+      entity: None,
+      message: message.into(),
+      location: None,
+    }
+  }
+
+  pub fn with_op(mut self, op: OpId) -> Self {
+    self.op = Some(op);
+    self
+  }
+
+  /// Point the error at `entity`, and name it in the message
+  pub fn with_entity<E: Environ>(mut self, env: &E, entity: EntityId) -> Self {
+    self.entity = Some(entity);
+    self.message = format!("{}: {}", env.print_entity(entity), self.message);
+    self.location = env.get_entity(entity).get_attr("location").map(|x| format!("{}", x));
+    self
+  }
+
+  /// Set the pass, if it's not set by a more specific caller
+  pub fn with_pass(mut self, pass: &str) -> Self {
+    self.pass.get_or_insert_with(|| pass.to_string());
+    self
+  }
+}
+
+impl std::fmt::Display for IronyError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    if let Some(pass) = &self.pass {
+      write!(f, "[{}] ", pass)?;
+    }
+    write!(f, "{}", self.message)?;
+    if let Some(location) = &self.location {
+      write!(f, " at {}", location)?;
+    }
+    Ok(())
+  }
+}
+
+impl std::error::Error for IronyError {}
@@ -1,14 +1,17 @@
 use std::fmt::Debug;
 use std::ops::{Deref, DerefMut};
 
+use serde::{Deserialize, Serialize};
+
 pub type FxHasher = rustc_hash::FxHasher;
 pub type FxHasherBuilder = std::hash::BuildHasherDefault<rustc_hash::FxHasher>;
 pub type FxIndexMap<K, V> = indexmap::IndexMap<K, V, FxHasherBuilder>;
 pub type FxHashSet<K> = std::collections::HashSet<K, FxHasherBuilder>;
 pub type FxHashMap<K, V> = std::collections::HashMap<K, V, FxHasherBuilder>;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct FxMapWithUniqueId<V> {
+  #[serde(rename = "entries")]
   indexmap: FxIndexMap<usize, V>,
   next_id: usize,
 }
@@ -0,0 +1,26 @@
+use crate::{Entity, EntityId, Environ, RegionId};
+
+/// Values of the entities seen by an op while it's interpreted
+pub trait InterpreterStateTrait {
+  type ValueT;
+  fn get_value(&self, entity: EntityId) -> Option<Self::ValueT>;
+  fn set_value(&mut self, entity: EntityId, value: Self::ValueT);
+  /// Set the value `entity` takes after the next clock edge, for stateful ops
+  fn set_next_value(&mut self, entity: EntityId, value: Self::ValueT);
+}
+
+pub trait OpInterpreterTrait {
+  type DataTypeT;
+  type AttributeT: Clone + PartialEq + std::fmt::Display;
+  type ValueT;
+  fn interpret<'env, E, EntityT: Entity, S>(
+    &self, env: &'env E, state: &mut S, attrs: Vec<(String, Self::AttributeT)>,
+    uses: Vec<(String, Vec<Option<EntityId>>)>,
+    defs: Vec<(String, Vec<Option<EntityId>>)>,
+    regions: Vec<(String, Vec<Option<RegionId>>)>,
+  ) -> Result<(), String>
+  where
+    E: Environ<EntityT = EntityT, AttributeT = Self::AttributeT>,
+    EntityT: Entity<DataTypeT = Self::DataTypeT, AttributeT = Self::AttributeT>,
+    S: InterpreterStateTrait<ValueT = Self::ValueT>;
+}
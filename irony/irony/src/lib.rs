@@ -1,27 +1,37 @@
 #![feature(macro_metavar_expr)]
 
+mod analysis;
 mod common;
 mod constraint;
 mod entity;
 mod environ;
+mod error;
+mod interpreter;
 mod operation;
 mod pass;
 mod printer;
 mod region;
+mod snapshot;
+mod verifier;
 
 mod hash;
 
 pub mod utils;
 
+pub use analysis::*;
 pub use common::*;
 pub use constraint::*;
 pub use entity::*;
 pub use environ::*;
+pub use error::*;
+pub use interpreter::*;
 pub use hash::*;
 pub use operation::*;
 pub use pass::*;
 pub use printer::*;
 pub use region::*;
+pub use snapshot::*;
+pub use verifier::*;
 
 pub mod preclude {
   pub use std::cell::{RefCell, RefMut};
@@ -33,4 +43,5 @@ pub mod preclude {
 }
 
 pub use indexmap;
+pub use serde;
 pub use visible::StructFields;
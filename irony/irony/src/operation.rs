@@ -1,9 +1,11 @@
 use std::fmt::Debug;
 
 pub use paste::paste;
+use serde::{Deserialize, Serialize};
 
 use super::common::Id;
 use super::entity::EntityId;
+use crate::interpreter::OpInterpreterTrait;
 use crate::printer::OpPrinterTrait;
 use crate::{ConstraintTrait, Environ, ReducerTrait, RegionId};
 
@@ -18,6 +20,10 @@ pub trait Op: Id + Debug {
     DataTypeT = Self::DataTypeT,
     AttributeT = Self::AttributeT,
   >;
+  type InterpreterT: OpInterpreterTrait<
+    DataTypeT = Self::DataTypeT,
+    AttributeT = Self::AttributeT,
+  >;
 
   fn get_defs(&self) -> Vec<(String, Vec<Option<EntityId>>)>;
   fn get_uses(&self) -> Vec<(String, Vec<Option<EntityId>>)>;
@@ -40,6 +46,8 @@ pub trait Op: Id + Debug {
 
   fn get_printer(&self) -> Self::PrinterT;
 
+  fn get_interpreter(&self) -> Self::InterpreterT;
+
   fn hash_with_reducer(&self, env: &impl Environ, reducer: &mut impl ReducerTrait);
 
   fn reduce_def_use(self, reducer: &mut impl ReducerTrait) -> Self;
@@ -47,7 +55,7 @@ pub trait Op: Id + Debug {
   fn replace_use(&mut self, old: EntityId, new: EntityId) -> ();
 }
 
-#[derive(Clone, Copy, PartialEq, Debug, Hash, Eq, Default)]
+#[derive(Clone, Copy, PartialEq, Debug, Hash, Eq, Default, Serialize, Deserialize)]
 pub struct OpId(pub usize);
 impl From<usize> for OpId {
   fn from(value: usize) -> Self { Self(value) }
@@ -70,7 +78,7 @@ macro_rules! reduce_then_hash {
 #[macro_export]
 macro_rules! op_def {
     (
-        [data_type = $data_ty:ty, attr = $attr_ty:ty, constraint = $constraint_ty:ty]
+        [data_type = $data_ty:ty, attr = $attr_ty:ty, constraint = $constraint_ty:ty, value = $value_ty:ty]
         $name_enum:ident  = {
             $(
                 $name:ident : {
@@ -79,7 +87,8 @@ macro_rules! op_def {
                     $(attrs: [$($attr:ident:$attr_variant:ident($attr_inner_ty:ty)$(($attr_hash:tt))?),*],)?
                     $(regions: [$($region:ident),*$(;$($variadic_region:ident),+)?],)?
                     $(constraints: [$($constraint:expr),*],)?
-                    print: ($($print_tt:tt)*)$(,)?
+                    print: ($($print_tt:tt)*)
+                    $(, interpret: ($($interpret_tt:tt)*))?$(,)?
                 }
             ),*
             $(,)?
@@ -88,7 +97,7 @@ macro_rules! op_def {
 
         $(
             irony::op_def_one! {
-                [data_type = $data_ty, attr = $attr_ty, constraint = $constraint_ty]
+                [data_type = $data_ty, attr = $attr_ty, constraint = $constraint_ty, value = $value_ty]
                 $name: {
                     defs : [$($def),*$(;$($variadic_def),+)?],
                     uses : [$($use),*$(;$($variadic_use),+)?],
@@ -96,6 +105,7 @@ macro_rules! op_def {
                     $(regions: [$($region),*$(;$($variadic_region),+)?],)?
                     $(constraints : [$($constraint),*],)?
                     print: ($($print_tt)*)
+                    $(, interpret: ($($interpret_tt)*))?
                 }
             }
         )*
@@ -110,24 +120,31 @@ macro_rules! op_def {
             $name_enum = $($name),*
         }
 
+        irony::op_interpreter! {
+            [data_type = $data_ty, attr = $attr_ty, value = $value_ty]
+            $name_enum = $($name),*
+        }
+
 
     };
 }
 #[macro_export]
 macro_rules! op_def_one {
     (
-        [data_type = $data_ty:ty, attr = $attr_ty:ty, constraint = $constraint_ty:ty]
+        [data_type = $data_ty:ty, attr = $attr_ty:ty, constraint = $constraint_ty:ty, value = $value_ty:ty]
         $name:ident : {
             defs: [$($def:ident),*$(;$($variadic_def:ident),+)?],
             uses: [$($use:ident),*$(;$($variadic_use:ident),+)?],
             $(attrs: [$($attr:ident:$attr_variant:ident($attr_inner_ty:ty)$(($attr_hash:tt))?),*],)?
             $(regions: [$($region:ident),*$(;$($variadic_region:ident),+)?],)?
             $(constraints: [$($constraint:expr),*],)?
-            print: ($($print_tt:tt)*)$(,)?
+            print: ($($print_tt:tt)*)
+            $(, interpret: ($($interpret_tt:tt)*))?$(,)?
         }
     ) => {
         #[StructFields(pub)]
-        #[derive(PartialEq, Debug, Clone)]
+        #[derive(PartialEq, Debug, Clone, irony::serde::Serialize, irony::serde::Deserialize)]
+        #[serde(crate = "irony::serde")]
         pub struct $name  {
             id: usize,
             op_name: String,
@@ -143,9 +160,11 @@ macro_rules! op_def_one {
                 )?
             )?
 
-            constraints: Vec<$constraint_ty>,
             parent: Option<irony::RegionId>,
+            #[serde(skip)]
             printer: paste!([< $name Printer >]),
+            #[serde(skip)]
+            interpreter: paste!([< $name Interpreter >]),
         }
 
         impl irony::Id for $name {
@@ -163,6 +182,7 @@ macro_rules! op_def_one {
             type ConstraintT = $constraint_ty;
             type AttributeT = $attr_ty;
             type PrinterT = paste!([< $name Printer >]);
+            type InterpreterT = paste!([< $name Interpreter >]);
 
             fn get_defs(&self) -> Vec<(String, Vec<Option<irony::EntityId>>)> {
                 vec![
@@ -202,7 +222,9 @@ macro_rules! op_def_one {
             }
 
             fn get_constraints(&self) -> Vec<Self::ConstraintT> {
-                self.constraints.clone()
+                vec![
+                    $($($constraint),*)?
+                ]
             }
 
             fn uses(&self, entity: irony::EntityId) -> bool {
@@ -256,6 +278,10 @@ macro_rules! op_def_one {
                 self.printer.clone()
             }
 
+            fn get_interpreter(&self) -> Self::InterpreterT {
+                self.interpreter.clone()
+            }
+
 
             fn hash_with_reducer(&self, env: &impl Environ, reducer: &mut impl ReducerTrait) {
 
@@ -381,18 +407,16 @@ macro_rules! op_def_one {
                     $($($region,)*)?
                     $($($($variadic_region,)*)?)?
 
-                    constraints: vec![
-                        $($($constraint),*)?
-                    ],
                     parent: None,
                     printer: paste!([< $name Printer >]),
+                    interpreter: paste!([< $name Interpreter >]),
                 }
 
             }
         }
 
         paste! {
-            #[derive(Clone, Debug, PartialEq, Hash)]
+            #[derive(Clone, Debug, Default, PartialEq, Hash)]
             pub struct [< $name Printer >];
 
             impl OpPrinterTrait for [< $name Printer >] {
@@ -415,6 +439,34 @@ macro_rules! op_def_one {
                     }
             }
 
+            #[derive(Clone, Debug, Default, PartialEq, Hash)]
+            pub struct [< $name Interpreter >];
+
+            impl irony::OpInterpreterTrait for [< $name Interpreter >] {
+                type DataTypeT = $data_ty;
+                type AttributeT = $attr_ty;
+                type ValueT = $value_ty;
+
+                fn interpret<'env, E, EntityT: Entity, S>(
+                    &self,
+                    env: &'env E,
+                    state: &mut S,
+                    attrs: Vec<(String, Self::AttributeT)>,
+                    uses: Vec<(String, Vec<Option<irony::EntityId>>)>,
+                    defs: Vec<(String, Vec<Option<irony::EntityId>>)>,
+                    regions: Vec<(String, Vec<Option<irony::RegionId>>)>,
+                ) -> Result<(), String>
+                where
+                    E: Environ<EntityT = EntityT, AttributeT = Self::AttributeT>,
+                    EntityT: Entity<DataTypeT = Self::DataTypeT, AttributeT = Self::AttributeT>,
+                    S: irony::InterpreterStateTrait<ValueT = Self::ValueT> {
+                        irony::op_interpret_body!(
+                            $name; ($(($($interpret_tt)*))?);
+                            env, state, attrs, uses, defs, regions
+                        )
+                    }
+            }
+
         }
 
     };
@@ -423,7 +475,8 @@ macro_rules! op_def_one {
 #[macro_export]
 macro_rules! op_enum {
     ([data_type = $data_ty:ty, attr = $attr:ty, constraint = $constraint:ty] $name:ident = $($variant:ident),*) => {
-        #[derive(PartialEq, Debug, Clone, Default)]
+        #[derive(PartialEq, Debug, Clone, Default, irony::serde::Serialize, irony::serde::Deserialize)]
+        #[serde(crate = "irony::serde")]
         pub enum $name {
             #[default]
             None,
@@ -458,6 +511,7 @@ macro_rules! op_enum {
             type AttributeT = $attr;
             type ConstraintT = $constraint;
             type PrinterT = paste!([< $name Printer >]);
+            type InterpreterT = paste!([< $name Interpreter >]);
 
             fn get_defs(&self) -> Vec<(String, Vec<Option<irony::EntityId>>)> {
                 match self {
@@ -551,6 +605,13 @@ macro_rules! op_enum {
                 }
             }
 
+            fn get_interpreter(&self) -> Self::InterpreterT {
+                match self {
+                    $name::None => panic!(),
+                    $($name::$variant(inner) => inner.get_interpreter().into()),*
+                }
+            }
+
             fn hash_with_reducer(&self, env: &impl Environ, reducer: &mut impl ReducerTrait) {
                 match self {
                     $name::None => panic!(),
@@ -619,3 +680,64 @@ macro_rules! op_printer {
         }
     };
 }
+
+/// Body of the interpreter of an op, which fails if the op has no `interpret` hook
+#[macro_export]
+macro_rules! op_interpret_body {
+    ($name:ident; (); $($arg:ident),*) => {{
+        $(let _ = $arg;)*
+        Err(format!("`{}` can't be interpreted", stringify!($name)))
+    }};
+    ($name:ident; (($($interpret_tt:tt)*)); $($arg:ident),*) => {{
+        let f = $($interpret_tt)*;
+        f($($arg),*)
+    }};
+}
+
+#[macro_export]
+macro_rules! op_interpreter {
+    (
+        [data_type = $data_ty:ty, attr = $attr:ty, value = $value_ty:ty]
+        $name:ident = $($variant:ident),*
+    ) => {
+        paste! {
+            pub enum [<$name Interpreter>] {
+                $($variant([<$variant Interpreter>])),*
+            }
+
+            impl irony::OpInterpreterTrait for [<$name Interpreter>] {
+                type DataTypeT = $data_ty;
+                type AttributeT = $attr;
+                type ValueT = $value_ty;
+
+                fn interpret<'env, E, EntityT: Entity, S>(
+                    &self,
+                    env: &'env E,
+                    state: &mut S,
+                    attrs: Vec<(String, Self::AttributeT)>,
+                    uses: Vec<(String, Vec<Option<irony::EntityId>>)>,
+                    defs: Vec<(String, Vec<Option<irony::EntityId>>)>,
+                    regions: Vec<(String, Vec<Option<irony::RegionId>>)>,
+                ) -> Result<(), String>
+                where
+                    E: Environ<EntityT = EntityT, AttributeT = Self::AttributeT>,
+                    EntityT: Entity<DataTypeT = Self::DataTypeT, AttributeT = Self::AttributeT>,
+                    S: irony::InterpreterStateTrait<ValueT = Self::ValueT> {
+                        match self {
+                            $([<$name Interpreter>]::$variant(inner) => inner.interpret(env, state, attrs, uses, defs, regions)),*
+                        }
+                    }
+            }
+
+            $(
+
+                impl Into<[<$name Interpreter>]> for [<$variant Interpreter>] {
+                    fn into(self) -> [<$name Interpreter>] {
+                        [<$name Interpreter>]::$variant(self)
+                    }
+                }
+
+            )*
+        }
+    };
+}
@@ -1,39 +1,106 @@
-use crate::{Environ, OpId};
+use std::time::Duration;
+
+use crate::{AnalysisManager, Environ, IronyError, OpId, PreservedAnalyses};
 
 pub trait PassTrait<T: Default, ERR>: Clone {
   type EntityT;
   type OpT;
   // TODO: future features
-  // fn get_statistics_mut(&self) -> &mut PassStatistics;
   // fn get_arguments_str() -> String;
-  // fn get_name_str() -> String;
-  // fn get_description_str() -> String;
+
+  /// Name of the pass in textual pipelines, like `remove-event`
+  fn name(&self) -> &'static str;
+  fn description(&self) -> &'static str;
+
+  /// Analyses still valid after the pass has run on an op
+  fn preserved_analyses(&self) -> PreservedAnalyses { PreservedAnalyses::none() }
 
   fn check_op<E>(&self, env: &E, op: OpId) -> bool
   where E: Environ<EntityT = Self::EntityT, OpT = Self::OpT>;
-  fn run_raw<E>(&self, env: &mut E, op: OpId) -> Result<T, ERR>
+  /// Run the pass on `op`, getting the analyses it needs from `analyses`, which it
+  /// must invalidate itself if it changes `op` before getting them again
+  fn run_raw<E>(
+    &self, env: &mut E, analyses: &mut AnalysisManager, op: OpId,
+  ) -> Result<T, ERR>
   where E: Environ<EntityT = Self::EntityT, OpT = Self::OpT>;
-  fn run_on<E>(&self, env: &mut E, op: OpId) -> Result<T, ERR>
+  fn run_on<E>(
+    &self, env: &mut E, analyses: &mut AnalysisManager, op: OpId,
+  ) -> Result<T, ERR>
   where E: Environ<EntityT = Self::EntityT, OpT = Self::OpT> {
     if self.check_op(env, op) {
-      self.run_raw(env, op)
+      self.run_raw(env, analyses, op)
     } else {
       Ok(T::default())
     }
   }
 }
 
+/// Passes of a dialect which can be looked up by name
+pub trait PassRegistryTrait<T: Default, ERR>: PassTrait<T, ERR> {
+  fn registered() -> Vec<Self>;
+
+  fn lookup(name: &str) -> Result<Self, IronyError> {
+    let registered = Self::registered();
+    match registered.iter().find(|pass| pass.name() == name) {
+      Some(pass) => Ok(pass.to_owned()),
+      None => {
+        let names = registered.iter().map(|pass| pass.name()).collect::<Vec<_>>();
+        Err(IronyError::new(format!(
+          "unknown pass `{}`, expected one of: {}",
+          name,
+          names.join(", ")
+        )))
+      },
+    }
+  }
+}
+
 pub trait PassManagerTrait<T: Default, ERR>: Clone {
   type EntityT;
   type OpT;
   type PassT: PassTrait<T, ERR>;
   fn add_passes(&mut self, passes: Vec<Self::PassT>, start_ops: Vec<Vec<OpId>>);
-  fn run_passes<E>(&self, env: &mut E) -> Result<T, ERR>
+  fn run_passes<E>(&mut self, env: &mut E) -> Result<T, ERR>
   where E: Environ<EntityT = Self::EntityT, OpT = Self::OpT>;
 }
 
-// TODO: future features
-pub struct PassPipeline;
-pub struct PassStatistics;
+/// Passes run in order, which can be parsed from a comma-separated list of
+/// registered names, e.g. `reorder,remove-event,remove-select`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PassPipeline<P>(pub Vec<P>);
+
+impl<P> PassPipeline<P> {
+  pub fn parse<T: Default, ERR>(s: &str) -> Result<Self, IronyError>
+  where P: PassRegistryTrait<T, ERR> {
+    s.split(',')
+      .map(str::trim)
+      .filter(|x| !x.is_empty())
+      .map(P::lookup)
+      .collect::<Result<_, _>>()
+      .map(PassPipeline)
+  }
+}
+
+/// Statistics of a pass run by a pass manager
+#[derive(Clone, Debug)]
+pub struct PassStatistics {
+  pub name: &'static str,
+  pub time: Duration,
+  /// Number of ops reachable from the top level before and after the pass
+  pub ops_before: usize,
+  pub ops_after: usize,
+  /// Number of start ops the pass has run on
+  pub runs: usize,
+}
+
+impl std::fmt::Display for PassStatistics {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "{:<24} {:>12?} {:>8} -> {:<8} runs: {}",
+      self.name, self.time, self.ops_before, self.ops_after, self.runs
+    )
+  }
+}
 
 // TODO: Visiters
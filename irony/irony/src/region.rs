@@ -1,9 +1,11 @@
+use serde::{Deserialize, Serialize};
+
 use super::common::Id;
 use super::entity::EntityId;
 use super::environ::Environ;
 use super::operation::OpId;
 
-#[derive(Debug, Clone, PartialEq, Hash)]
+#[derive(Debug, Clone, PartialEq, Hash, Serialize, Deserialize)]
 pub struct Region {
   pub id: usize,
   pub isolated: bool,
@@ -17,7 +19,7 @@ impl Id for Region {
   fn set_id(&mut self, id: usize) { self.id = id }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct RegionId(pub usize);
 impl Id for RegionId {
   fn id(&self) -> usize { self.0 }
@@ -0,0 +1,86 @@
+use std::io::{Read, Write};
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::{IronyError, IronyResult};
+
+/// Version of the layout of snapshots, bumped whenever the serialized form of the
+/// tables changes
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Op, entity and region tables of an environment, with the ids of their entries, as
+/// written to and read from files
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot<O, E, R> {
+  pub op_table: O,
+  pub entity_table: E,
+  pub region_table: R,
+}
+
+/// A snapshot with the version of its layout, which is checked before the snapshot
+/// is read
+#[derive(Serialize, Deserialize)]
+struct Versioned<T> {
+  version: u32,
+  snapshot: T,
+}
+
+/// Encoding of a snapshot: JSON to be read and exchanged, or a compact binary one
+/// to cache designs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SnapshotFormat {
+  Json,
+  Binary,
+}
+
+impl SnapshotFormat {
+  /// JSON for `.json` files, binary otherwise
+  pub fn of_path<P: AsRef<Path>>(path: P) -> Self {
+    match path.as_ref().extension() {
+      Some(extension) if extension == "json" => SnapshotFormat::Json,
+      _ => SnapshotFormat::Binary,
+    }
+  }
+
+  pub fn write<T: Serialize, W: Write>(self, writer: W, snapshot: &T) -> IronyResult<()> {
+    let versioned = Versioned { version: SNAPSHOT_VERSION, snapshot };
+    match self {
+      SnapshotFormat::Json => {
+        serde_json::to_writer_pretty(writer, &versioned).map_err(|x| x.to_string())
+      },
+      SnapshotFormat::Binary => {
+        bincode::serialize_into(writer, &versioned).map_err(|x| x.to_string())
+      },
+    }
+    .map_err(|err| IronyError::new(format!("can't write the snapshot: {}", err)))
+  }
+
+  pub fn read<T: DeserializeOwned, R: Read>(self, mut reader: R) -> IronyResult<T> {
+    let error =
+      |err: String| IronyError::new(format!("can't read the snapshot: {}", err));
+    let check = |version: u32| match version {
+      SNAPSHOT_VERSION => Ok(()),
+      _ => Err(error(format!(
+        "its version is {}, but version {} is supported",
+        version, SNAPSHOT_VERSION
+      ))),
+    };
+    match self {
+      SnapshotFormat::Json => {
+        let versioned: Versioned<serde_json::Value> =
+          serde_json::from_reader(reader).map_err(|x| error(x.to_string()))?;
+        check(versioned.version)?;
+        serde_json::from_value(versioned.snapshot).map_err(|x| error(x.to_string()))
+      },
+      SnapshotFormat::Binary => {
+        // fields of a struct are encoded one after another
+        let version =
+          bincode::deserialize_from(&mut reader).map_err(|x| error(x.to_string()))?;
+        check(version)?;
+        bincode::deserialize_from(reader).map_err(|x| error(x.to_string()))
+      },
+    }
+  }
+}
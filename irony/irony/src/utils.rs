@@ -19,6 +19,15 @@ pub mod print {
   pub fn from_bits_to_str(bits: Vec<bool>) -> String {
     bits.iter().map(|bit| if *bit { "1" } else { "0" }).collect()
   }
+
+  /// MLIR location `loc("file":line:col)` of a location printed as `file:line:col`
+  pub fn mlir_location(location: &str) -> String {
+    let mut parts = location.rsplitn(3, ':');
+    match (parts.next(), parts.next(), parts.next()) {
+      (Some(col), Some(line), Some(file)) => format!("loc({:?}:{}:{})", file, line, col),
+      _ => format!("loc({:?})", location),
+    }
+  }
 }
 
 pub mod arith {
@@ -0,0 +1,27 @@
+use crate::{EntityId, OpId};
+
+/// A constraint violated by an op, reported by [`crate::Environ::verify_op`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+  pub op: OpId,
+  /// Name of the op, like `HwModule`
+  pub op_name: String,
+  /// First line of the printed op
+  pub op_text: String,
+  pub message: String,
+  /// Printed entities used or defined by the op, with their `location` attributes
+  pub locations: Vec<(EntityId, String, String)>,
+}
+
+impl std::fmt::Display for Diagnostic {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    writeln!(f, "error: `{}` {}", self.op_name, self.message)?;
+    write!(f, "  --> {}", self.op_text)?;
+    for (_, entity, location) in self.locations.iter() {
+      write!(f, "\n  {} at {}", entity, location)?;
+    }
+    Ok(())
+  }
+}
+
+impl std::error::Error for Diagnostic {}
@@ -0,0 +1,214 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use irony::{Analysis, EntityId, Environ, IronyError, IronyResult, Op, OpId};
+
+use crate::{EntityEnum, HwInstance, OpEnum};
+
+/// Entities an op reads combinationally; registers only read theirs at clock edges
+fn comb_uses(op: &OpEnum) -> Vec<EntityId> {
+  match op {
+    OpEnum::SeqCompReg(_) => vec![],
+    op => op.get_uses().into_iter().flat_map(|(_, x)| x).flatten().collect(),
+  }
+}
+
+fn defs(op: &OpEnum) -> Vec<EntityId> {
+  op.get_defs().into_iter().flat_map(|(_, x)| x).flatten().collect()
+}
+
+fn body_ops<E>(env: &E, op: OpId) -> IronyResult<Vec<OpId>>
+where E: Environ<EntityT = EntityEnum, OpT = OpEnum> {
+  match env.get_op(op) {
+    OpEnum::HwModule(module) => Ok(match module.body {
+      Some(body) => env.get_region(body).op_children.to_owned(),
+      None => vec![],
+    }),
+    _ => Err(IronyError::new("analysis must be computed on a module").with_op(op)),
+  }
+}
+
+/// Ops in the body of a module, ordered so that every op comes after the ops
+/// defining the entities it reads combinationally
+#[derive(Clone, Debug, PartialEq)]
+pub struct TopoOrder(pub Vec<OpId>);
+
+impl<E> Analysis<E> for TopoOrder
+where E: Environ<EntityT = EntityEnum, OpT = OpEnum>
+{
+  fn compute(env: &E, op: OpId) -> IronyResult<Self> {
+    let ops = body_ops(env, op)?;
+    let mut defined_by = HashMap::new();
+    for op_id in ops.iter() {
+      for entity in defs(env.get_op(*op_id)) {
+        defined_by.insert(entity, *op_id);
+      }
+    }
+
+    let mut waiting = HashMap::new();
+    let mut users = HashMap::<EntityId, Vec<OpId>>::new();
+    let mut queue = VecDeque::new();
+    for op_id in ops.iter() {
+      let uses = comb_uses(env.get_op(*op_id)).into_iter().collect::<HashSet<_>>();
+      for entity in uses.iter() {
+        if !defined_by.contains_key(entity) {
+          return Err(
+            IronyError::new("entity is used but never defined in the module")
+              .with_op(*op_id)
+              .with_entity(env, *entity),
+          );
+        }
+        users.entry(*entity).or_default().push(*op_id);
+      }
+      if uses.is_empty() {
+        queue.push_back(*op_id);
+      }
+      waiting.insert(*op_id, uses);
+    }
+
+    let mut order = Vec::new();
+    while let Some(op_id) = queue.pop_front() {
+      order.push(op_id);
+      for entity in defs(env.get_op(op_id)) {
+        for user in users.get(&entity).into_iter().flatten() {
+          let uses = waiting.get_mut(user).unwrap();
+          if uses.remove(&entity) && uses.is_empty() {
+            queue.push_back(*user);
+          }
+        }
+      }
+    }
+
+    match ops.iter().find(|op_id| !waiting[op_id].is_empty()) {
+      Some(op_id) => Err(
+        IronyError::new("combinational loop through the op")
+          .with_op(*op_id)
+          .with_entity(env, *waiting[op_id].iter().next().unwrap()),
+      ),
+      None => Ok(TopoOrder(order)),
+    }
+  }
+}
+
+/// Instances in a module and, recursively, in the modules they instantiate
+#[derive(Clone, Debug, PartialEq)]
+pub struct InstanceHierarchy {
+  /// Instance ops with the module containing them and their target module
+  pub instances: Vec<(OpId, OpId, OpId)>,
+  /// Modules in the hierarchy, every module after the modules it instantiates
+  pub modules: Vec<OpId>,
+}
+
+impl InstanceHierarchy {
+  fn visit<E>(&mut self, env: &E, module: OpId, path: &mut Vec<OpId>) -> IronyResult<()>
+  where E: Environ<EntityT = EntityEnum, OpT = OpEnum> {
+    if path.contains(&module) {
+      return Err(
+        IronyError::new("module instantiates itself recursively").with_op(module),
+      );
+    }
+    if self.modules.contains(&module) {
+      return Ok(());
+    }
+    path.push(module);
+    for op_id in body_ops(env, module)? {
+      if let OpEnum::HwInstance(HwInstance { target_op_id: Some(target), .. }) =
+        env.get_op(op_id)
+      {
+        let target = target.0;
+        self.instances.push((op_id, module, target));
+        self.visit(env, target, path)?;
+      }
+    }
+    path.pop();
+    self.modules.push(module);
+    Ok(())
+  }
+
+  /// Instance ops in `module`, with their target modules
+  pub fn children(&self, module: OpId) -> impl Iterator<Item = (OpId, OpId)> + '_ {
+    self
+      .instances
+      .iter()
+      .filter(move |(_, parent, _)| *parent == module)
+      .map(|(instance, _, target)| (*instance, *target))
+  }
+}
+
+impl<E> Analysis<E> for InstanceHierarchy
+where E: Environ<EntityT = EntityEnum, OpT = OpEnum>
+{
+  const GLOBAL: bool = true;
+
+  fn compute(env: &E, op: OpId) -> IronyResult<Self> {
+    let mut hierarchy = InstanceHierarchy { instances: vec![], modules: vec![] };
+    hierarchy.visit(env, op, &mut vec![])?;
+    Ok(hierarchy)
+  }
+}
+
+/// Entities each entity of a module depends on combinationally, through the
+/// ops defining it; instances are assumed to connect all their inputs to all
+/// their outputs
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CombDependencies(pub HashMap<EntityId, Vec<EntityId>>);
+
+impl CombDependencies {
+  pub fn deps(&self, entity: EntityId) -> &[EntityId] {
+    self.0.get(&entity).map(Vec::as_slice).unwrap_or_default()
+  }
+
+  /// A combinational loop, as entities each depending on the next one
+  pub fn find_cycle(&self) -> Option<Vec<EntityId>> {
+    // 1: on the current path, 2: finished
+    let mut state = HashMap::new();
+    let mut entities = self.0.keys().copied().collect::<Vec<_>>();
+    entities.sort_by_key(|entity| entity.0);
+    for root in entities {
+      if state.contains_key(&root) {
+        continue;
+      }
+      let mut path = vec![(root, 0)];
+      state.insert(root, 1);
+      while let Some((entity, next)) = path.last_mut() {
+        let entity = *entity;
+        match self.deps(entity).get(*next) {
+          Some(dep) => {
+            *next += 1;
+            match state.get(dep) {
+              Some(1) => {
+                let start = path.iter().position(|(x, _)| x == dep).unwrap();
+                return Some(path[start..].iter().map(|(x, _)| *x).collect());
+              },
+              Some(_) => {},
+              None => {
+                state.insert(*dep, 1);
+                path.push((*dep, 0));
+              },
+            }
+          },
+          None => {
+            state.insert(entity, 2);
+            path.pop();
+          },
+        }
+      }
+    }
+    None
+  }
+}
+
+impl<E> Analysis<E> for CombDependencies
+where E: Environ<EntityT = EntityEnum, OpT = OpEnum>
+{
+  fn compute(env: &E, op: OpId) -> IronyResult<Self> {
+    let mut deps = CombDependencies::default();
+    for op_id in env.walk_ops_from(body_ops(env, op)?) {
+      let op = env.get_op(op_id);
+      let uses = comb_uses(op);
+      for entity in defs(op) {
+        deps.0.entry(entity).or_default().extend(uses.iter().copied());
+      }
+    }
+    Ok(deps)
+  }
+}
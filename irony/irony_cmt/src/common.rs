@@ -1,15 +1,16 @@
 use std::panic::Location;
 
 use irony::{utils, OpId, AsBool};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, Hash)]
+#[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
 pub struct ClkType;
 
 impl std::fmt::Display for ClkType {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { write!(f, "i1") }
 }
 
-#[derive(Clone, Debug, PartialEq, Hash)]
+#[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
 pub struct UIntType(pub usize);
 
 impl std::fmt::Display for UIntType {
@@ -22,7 +23,7 @@ impl Into<UIntType> for usize {
   fn into(self) -> UIntType { UIntType(self) }
 }
 
-#[derive(Clone, Debug, PartialEq, Hash)]
+#[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
 pub struct StructType(pub Vec<(String, Box<DataTypeEnum>)>);
 
 // TODO: fix this
@@ -41,7 +42,7 @@ impl std::fmt::Display for StructType {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Hash)]
+#[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
 pub struct ArrayType(pub Box<DataTypeEnum>, pub usize);
 
 // TODO: fix this
@@ -51,7 +52,7 @@ impl std::fmt::Display for ArrayType {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Hash)]
+#[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
 pub struct UArrayType(pub Box<DataTypeEnum>, pub usize);
 
 // TODO: fix this
@@ -59,7 +60,7 @@ impl std::fmt::Display for UArrayType {
   fn fmt(&self, _f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { todo!() }
 }
 
-#[derive(Clone, Debug, PartialEq, Hash)]
+#[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
 pub struct SeqHlmemType(pub Box<DataTypeEnum>, pub Vec<usize>);
 
 // TODO: fix this
@@ -67,7 +68,7 @@ impl std::fmt::Display for SeqHlmemType {
   fn fmt(&self, _f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { todo!() }
 }
 
-#[derive(Clone, Debug, PartialEq, Hash)]
+#[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
 pub enum CombVariadicPredicate {
   Add,
   Mul,
@@ -94,7 +95,7 @@ impl std::fmt::Display for CombVariadicPredicate {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Hash)]
+#[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
 pub enum CombUnaryPredicate {
   Not,
   Neg,
@@ -115,7 +116,7 @@ impl std::fmt::Display for CombUnaryPredicate {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Hash)]
+#[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
 pub enum CombBinaryPredicate {
   DivU,
   DivS,
@@ -148,7 +149,7 @@ impl std::fmt::Display for CombBinaryPredicate {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Hash)]
+#[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
 pub enum CombICmpPredicate {
   EQ,
   NE,
@@ -193,7 +194,7 @@ impl std::fmt::Display for CombICmpPredicate {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Hash)]
+#[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
 pub struct StringAttr(pub String);
 
 impl Into<StringAttr> for &str {
@@ -210,7 +211,7 @@ impl std::fmt::Display for StringAttr {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Hash)]
+#[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
 pub struct BoolAttr(pub bool);
 
 impl Into<BoolAttr> for bool {
@@ -223,7 +224,7 @@ impl std::fmt::Display for BoolAttr {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Hash)]
+#[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
 pub struct IdAttr(pub usize);
 
 impl Into<IdAttr> for u32 {
@@ -238,7 +239,7 @@ impl std::fmt::Display for IdAttr {
     write!(f, "{}", self.0)
   }
 }
-#[derive(Clone, Debug, PartialEq, Hash)]
+#[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
 pub struct OpIdAttr(pub OpId);
 
 impl Into<OpIdAttr> for OpId {
@@ -258,7 +259,7 @@ impl std::fmt::Display for OpIdAttr {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Hash)]
+#[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
 pub struct UIntAttr(pub u32);
 
 impl Into<UIntAttr> for u32 {
@@ -278,7 +279,7 @@ impl std::fmt::Display for UIntAttr {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Hash)]
+#[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
 pub struct TypeAttr(pub DataTypeEnum);
 
 impl std::fmt::Display for TypeAttr {
@@ -287,7 +288,7 @@ impl std::fmt::Display for TypeAttr {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Hash)]
+#[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
 pub struct ConstantAttr(pub Vec<bool>);
 impl std::fmt::Display for ConstantAttr {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -313,7 +314,7 @@ impl Into<ConstantAttr> for usize {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Hash)]
+#[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
 pub struct ArrayAttr(pub Vec<AttributeEnum>);
 impl std::fmt::Display for ArrayAttr {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -374,16 +375,55 @@ impl Into<ArrayAttr> for () {
   fn into(self) -> ArrayAttr { ArrayAttr(Vec::<AttributeEnum>::new()) }
 }
 
-#[derive(Clone, Debug, PartialEq, Hash)]
-pub struct LocationAttr(pub Location<'static>);
+/// Source location, like a `std::panic::Location` but which can be loaded from a
+/// snapshot
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LocationAttr {
+  file: String,
+  line: u32,
+  column: u32,
+  /// The location it was made from, unknown for loaded ones
+  #[serde(skip)]
+  caller: Option<Location<'static>>,
+}
+
+impl LocationAttr {
+  pub fn file(&self) -> &str { &self.file }
+
+  pub fn line(&self) -> u32 { self.line }
+
+  pub fn column(&self) -> u32 { self.column }
+
+  pub fn caller(&self) -> Option<Location<'static>> { self.caller }
+}
+
+/// Equal to its loaded copy
+impl PartialEq for LocationAttr {
+  fn eq(&self, other: &Self) -> bool {
+    (&self.file, self.line, self.column) == (&other.file, other.line, other.column)
+  }
+}
+
+impl std::hash::Hash for LocationAttr {
+  fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+    (&self.file, self.line, self.column).hash(state)
+  }
+}
 
 impl From<&Location<'static>> for LocationAttr {
-  fn from(location: &Location<'static>) -> Self { LocationAttr(location.to_owned()) }
+  fn from(location: &Location<'static>) -> Self {
+    LocationAttr {
+      file: location.file().to_owned(),
+      line: location.line(),
+      column: location.column(),
+      caller: Some(*location),
+    }
+  }
 }
 
 impl std::fmt::Display for LocationAttr {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(f, "{}", self.0)
+    write!(f, "{}:{}:{}", self.file, self.line, self.column)
   }
 }
 
@@ -1,14 +1,92 @@
 use core::panic;
 
-use irony::{EntityId, Op};
-
-use crate::BoolAttr;
+use irony::{Entity, EntityId, Environ, Op};
 
 use super::{AttributeEnum, DataTypeEnum};
+use crate::{ArrayAttr, ArrayType, BoolAttr, ConstantAttr, StringAttr, StructType};
 
 pub type SameType = irony::SameTypeConstraint<DataTypeEnum, AttributeEnum>;
 pub type SameTypeOperands = irony::SameTypeOperandConstraint<DataTypeEnum, AttributeEnum>;
 
+fn dtype<E, EntityT>(env: &E, id: Option<EntityId>) -> Result<DataTypeEnum, String>
+where
+  E: Environ<EntityT = EntityT>,
+  EntityT: Entity<DataTypeT = DataTypeEnum, AttributeT = AttributeEnum>,
+{
+  let id = id.ok_or("misses an operand or a result".to_string())?;
+  env.get_entity(id).get_dtype().ok_or(format!("has an untyped {}", env.print_entity(id)))
+}
+
+fn dtypes<E, EntityT>(
+  env: &E, ids: &[Option<EntityId>],
+) -> Result<Vec<DataTypeEnum>, String>
+where
+  E: Environ<EntityT = EntityT>,
+  EntityT: Entity<DataTypeT = DataTypeEnum, AttributeT = AttributeEnum>,
+{
+  ids.iter().map(|id| dtype(env, *id)).collect()
+}
+
+fn field<'t>(typ: &'t DataTypeEnum, name: &str) -> Result<&'t DataTypeEnum, String> {
+  let DataTypeEnum::Struct(StructType(fields)) = typ else {
+    return Err(format!("expects a struct, found {}", typ));
+  };
+  fields
+    .iter()
+    .find(|(field, _)| field == name)
+    .map(|(_, typ)| typ.as_ref())
+    .ok_or(format!("accesses the missing field `{}` of {}", name, typ))
+}
+
+fn array(typ: &DataTypeEnum) -> Result<(&DataTypeEnum, usize), String> {
+  match typ {
+    DataTypeEnum::Array(ArrayType(element, len)) => Ok((element, *len)),
+    _ => Err(format!("expects an array, found {}", typ)),
+  }
+}
+
+fn ensure(cond: bool, message: impl FnOnce() -> String) -> Result<(), String> {
+  if cond {
+    Ok(())
+  } else {
+    Err(message())
+  }
+}
+
+/// Check that a constant attribute has the shape of `typ`, recursively
+fn constant_shape(attr: &AttributeEnum, typ: &DataTypeEnum) -> Result<(), String> {
+  match (attr, typ) {
+    (
+      AttributeEnum::ConstantAttr(ConstantAttr(bits)),
+      DataTypeEnum::UInt(_) | DataTypeEnum::Clk(_),
+    ) => ensure(bits.len() == typ.width(), || {
+      format!("has a {}-bit constant for {}", bits.len(), typ)
+    }),
+    (
+      AttributeEnum::ArrayAttr(ArrayAttr(elements)),
+      DataTypeEnum::Array(ArrayType(element, len)),
+    ) => {
+      ensure(elements.len() == *len, || {
+        format!("has {} elements for {}", elements.len(), typ)
+      })?;
+      elements.iter().try_for_each(|attr| constant_shape(attr, element))
+    },
+    (
+      AttributeEnum::ArrayAttr(ArrayAttr(elements)),
+      DataTypeEnum::Struct(StructType(fields)),
+    ) => {
+      ensure(elements.len() == fields.len(), || {
+        format!("has {} fields for {}", elements.len(), typ)
+      })?;
+      elements
+        .iter()
+        .zip(fields.iter())
+        .try_for_each(|(attr, (_, typ))| constant_shape(attr, typ))
+    },
+    _ => Err(format!("has a constant {} for {}", attr, typ)),
+  }
+}
+
 irony::constraint_def! {
     [data_type = DataTypeEnum, attr = AttributeEnum]
     ConstraintEnum = {
@@ -25,13 +103,18 @@ irony::constraint_def! {
 
             if is_extern {
               // region.is_none()
-              true
+              Ok(())
             } else {
               let region = region.unwrap();
-              irony::utils::extract_vec(&attrs, "arg_names") == super::cmt_utils::extract_input_names(env, region) &&
-              irony::utils::extract_vec(&attrs, "arg_types") == super::cmt_utils::extract_input_types(env, region) &&
-              irony::utils::extract_vec(&attrs, "output_types") == super::cmt_utils::extract_output_types(env, region)
-
+              ensure(irony::utils::extract_vec(&attrs, "arg_names") == super::cmt_utils::extract_input_names(env, region), || {
+                "has arg_names different from the inputs of its body".to_string()
+              })?;
+              ensure(irony::utils::extract_vec(&attrs, "arg_types") == super::cmt_utils::extract_input_types(env, region), || {
+                "has arg_types different from the inputs of its body".to_string()
+              })?;
+              ensure(irony::utils::extract_vec(&attrs, "output_types") == super::cmt_utils::extract_output_types(env, region), || {
+                "has output_types different from the outputs of its body".to_string()
+              })
             }
         }),
         InstanceConstraint(InstanceConstraint ,
@@ -44,51 +127,136 @@ irony::constraint_def! {
             // let target_region= env.get_op(target_op_id.into()).get_regions()[0].1[0];
             let target_attrs = env.get_op(target_op_id.into()).get_attrs();
 
-            irony::utils::extract_vec(&target_attrs, "arg_types") == super::cmt_utils::extract_types(env, uses[0].1.to_owned())
-            &&
-            irony::utils::extract_vec(&target_attrs, "output_types") == super::cmt_utils::extract_types(env, defs[0].1.to_owned())
-
+            ensure(irony::utils::extract_vec(&target_attrs, "arg_types") == super::cmt_utils::extract_types(env, uses[0].1.to_owned()), || {
+                "has inputs different from the arg_types of its module".to_string()
+            })?;
+            ensure(irony::utils::extract_vec(&target_attrs, "output_types") == super::cmt_utils::extract_types(env, defs[0].1.to_owned()), || {
+                "has outputs different from the output_types of its module".to_string()
+            })
         }),
 
         SameTypeConstant(SameTypeConstant,
-            |_, _, _, _, _|  {
-                true
+            |env: &E, attrs: Vec<(String, AttributeEnum)>, _, defs: Vec<(String, Vec<Option<EntityId>>)>, _|  {
+                let value = irony::utils::extract_vec(&attrs, "value").ok_or("misses its value".to_string())?;
+                constant_shape(&value, &dtype(env, defs[0].1[0])?)
         }),
         SameTypeAggregate(SameTypeAggregate,
-            |_, _, _, _, _|  {
-                true
+            |env: &E, attrs: Vec<(String, AttributeEnum)>, _, defs: Vec<(String, Vec<Option<EntityId>>)>, _|  {
+                let value = irony::utils::extract_vec(&attrs, "attrs").ok_or("misses its value".to_string())?;
+                constant_shape(&value, &dtype(env, defs[0].1[0])?)
         }),
         ArrayConcatConstraint(ArrayConcatConstraint ,
-            |_, _, _, _, _|  {
-                true
+            |env: &E, _, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _|  {
+                let lhs = dtype(env, defs[0].1[0])?;
+                let (element, len) = array(&lhs)?;
+                let mut total = 0;
+                for typ in dtypes(env, &uses[0].1)? {
+                    let (operand_element, operand_len) = array(&typ)?;
+                    ensure(operand_element == element, || format!("concats {} into {}", typ, lhs))?;
+                    total += operand_len;
+                }
+                ensure(total == len, || format!("concats {} elements into {}", total, lhs))
         }),
         ArrayCreateConstraint(ArrayCreateConstraint ,
-            |_, _, _, _, _|  {
-                true
+            |env: &E, _, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _|  {
+                let lhs = dtype(env, defs[0].1[0])?;
+                let (element, len) = array(&lhs)?;
+                ensure(uses[0].1.len() == len, || format!("creates {} from {} elements", lhs, uses[0].1.len()))?;
+                dtypes(env, &uses[0].1)?.iter().try_for_each(|typ| {
+                    ensure(typ == element, || format!("creates {} from an element of {}", lhs, typ))
+                })
         }),
         ArrayGetConstraint(ArrayGetConstraint ,
-            |_, _, _, _, _|  {
-                true
+            |env: &E, _, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _|  {
+                let input = dtype(env, uses[0].1[0])?;
+                let (element, _) = array(&input)?;
+                let lhs = dtype(env, defs[0].1[0])?;
+                dtype(env, uses[1].1[0])?;
+                ensure(&lhs == element, || format!("gets {} from {}", lhs, input))
         }),
         ArraySliceConstraint(ArraySliceConstraint ,
-            |_, _, _, _, _|  {
-                true
+            |env: &E, _, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _|  {
+                let input = dtype(env, uses[0].1[0])?;
+                let lhs = dtype(env, defs[0].1[0])?;
+                let (element, len) = array(&input)?;
+                let (lhs_element, lhs_len) = array(&lhs)?;
+                dtype(env, uses[1].1[0])?;
+                ensure(lhs_element == element && lhs_len <= len, || format!("slices {} from {}", lhs, input))
         }),
         StructCreateConstraint(StructCreateConstraint ,
-            |_, _, _, _, _|  {
-                true
+            |env: &E, _, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _|  {
+                let lhs = dtype(env, defs[0].1[0])?;
+                let DataTypeEnum::Struct(StructType(fields)) = &lhs else {
+                    return Err(format!("creates a struct of {}", lhs));
+                };
+                let operands = dtypes(env, &uses[0].1)?;
+                ensure(operands.len() == fields.len(), || format!("creates {} from {} fields", lhs, operands.len()))?;
+                operands.iter().zip(fields.iter()).try_for_each(|(typ, (name, field))| {
+                    ensure(typ == field.as_ref(), || format!("creates the field `{}` of {} from {}", name, lhs, typ))
+                })
         }),
         StructExtractConstraint(StructExtractConstraint ,
-            |_, _, _, _, _|  {
-                true
+            |env: &E, attrs: Vec<(String, AttributeEnum)>, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _|  {
+                let Some(AttributeEnum::StringAttr(StringAttr(name))) = irony::utils::extract_vec(&attrs, "field") else {
+                    return Err("misses its field".to_string());
+                };
+                let input = dtype(env, uses[0].1[0])?;
+                let lhs = dtype(env, defs[0].1[0])?;
+                let typ = field(&input, &name)?;
+                ensure(&lhs == typ, || format!("extracts `{}` of {} as {}", name, input, lhs))
         }),
         StructInjectConstraint(StructInjectConstraint ,
-            |_, _, _, _, _|  {
-                true
+            |env: &E, attrs: Vec<(String, AttributeEnum)>, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _|  {
+                let Some(AttributeEnum::StringAttr(StringAttr(name))) = irony::utils::extract_vec(&attrs, "field") else {
+                    return Err("misses its field".to_string());
+                };
+                let input = dtype(env, uses[0].1[0])?;
+                let new_value = dtype(env, uses[1].1[0])?;
+                let lhs = dtype(env, defs[0].1[0])?;
+                ensure(lhs == input, || format!("injects into {} as {}", input, lhs))?;
+                let typ = field(&input, &name)?;
+                ensure(&new_value == typ, || format!("injects {} into `{}` of {}", new_value, name, input))
         }),
         StructExplodeConstraint(StructExplodeConstraint  ,
-            |_, _, _, _, _|  {
-                true
+            |env: &E, _, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _|  {
+                let input = dtype(env, uses[0].1[0])?;
+                let DataTypeEnum::Struct(StructType(fields)) = &input else {
+                    return Err(format!("explodes {}", input));
+                };
+                let outputs = dtypes(env, &defs[0].1)?;
+                ensure(outputs.len() == fields.len(), || format!("explodes {} into {} outputs", input, outputs.len()))?;
+                outputs.iter().zip(fields.iter()).try_for_each(|(typ, (name, field))| {
+                    ensure(typ == field.as_ref(), || format!("explodes the field `{}` of {} as {}", name, input, typ))
+                })
+        }),
+        Mux2Constraint(Mux2Constraint,
+            |env: &E, _, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _|  {
+                let cond = dtype(env, uses[0].1[0])?;
+                ensure(cond.width() == 1, || format!("selects with a condition of {}", cond))?;
+                let lhs = dtype(env, defs[0].1[0])?;
+                for op in [uses[1].1[0], uses[2].1[0]] {
+                    let typ = dtype(env, op)?;
+                    ensure(typ == lhs, || format!("selects {} as {}", typ, lhs))?;
+                }
+                Ok(())
+        }),
+        CompRegConstraint(CompRegConstraint,
+            |env: &E, _, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _|  {
+                let output = dtype(env, defs[0].1[0])?;
+                let input = dtype(env, uses[0].1[0])?;
+                ensure(input == output, || format!("registers {} as {}", input, output))?;
+                let clk = dtype(env, uses[1].1[0])?;
+                ensure(clk.width() == 1, || format!("is clocked by {}", clk))?;
+                match (uses[2].1[0], uses[3].1[0]) {
+                    (None, None) => Ok(()),
+                    (Some(reset), Some(reset_val)) => {
+                        let reset = dtype(env, Some(reset))?;
+                        ensure(reset.width() == 1, || format!("is reset by {}", reset))?;
+                        let reset_val = dtype(env, Some(reset_val))?;
+                        ensure(reset_val == output, || format!("resets {} to {}", output, reset_val))
+                    },
+                    _ => Err("must have both or neither of reset and reset_val".to_string()),
+                }
         }),
     }
 }
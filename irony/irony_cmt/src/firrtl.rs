@@ -0,0 +1,650 @@
+use std::collections::HashMap;
+
+use irony::{Analysis, Entity, EntityId, Environ, IronyError, IronyResult, Op, OpId};
+
+use crate::interpret::{bitcast, constant};
+use crate::sv::{hex_digits, identifier, module_name, port_names, string_of};
+use crate::{
+  ArrayAttr, ArrayType, AttributeEnum, BoolAttr, CombBinary, CombBinaryPredicate,
+  CombConcat, CombExtract, CombICmp, CombICmpPredicate, CombMux2, CombUnaryPredicate,
+  CombVariadic, CombVariadicPredicate, DataTypeEnum, EntityEnum, HwAggregateConstant,
+  HwArrayConcat, HwArrayCreate, HwArrayGet, HwArraySlice, HwBitCast, HwConstant, HwInput,
+  HwInstance, HwModule, HwOutput, HwStructCreate, HwStructExplode, HwStructExtract,
+  HwStructInject, InstanceHierarchy, OpEnum, OpIdAttr, SeqCompReg, StringAttr,
+  StructType, SymbolTable, TmpUnary, TypeAttr, UIntAttr, UIntType, Value,
+};
+
+/// Keywords of FIRRTL, which can't be used as identifiers
+const KEYWORDS: &str = "\
+  circuit module extmodule intmodule input output wire reg regreset node inst of \
+  connect invalidate when else skip stop printf assert assume cover define defname \
+  parameter public layer layerblock with reset mux validif read write infer mport \
+  cmem smem mem UInt SInt Clock Reset AsyncReset Analog Probe RWProbe const flip";
+
+fn sanitize(name: &str) -> String { identifier(name, KEYWORDS) }
+
+fn firrtl_type(dtype: &DataTypeEnum) -> Result<String, String> {
+  match dtype {
+    DataTypeEnum::Clk(_) => Ok("Clock".into()),
+    DataTypeEnum::UInt(UIntType(width)) => Ok(format!("UInt<{}>", width)),
+    DataTypeEnum::Array(ArrayType(element, len)) => {
+      Ok(format!("{}[{}]", firrtl_type(element)?, len))
+    },
+    DataTypeEnum::Struct(StructType(fields)) => {
+      let fields = fields
+        .iter()
+        .map(|(name, dtype)| Ok(format!("{} : {}", sanitize(name), firrtl_type(dtype)?)))
+        .collect::<Result<Vec<_>, String>>()?;
+      Ok(format!("{{{}}}", fields.join(", ")))
+    },
+    dtype => Err(format!("type {:?} can't be emitted to FIRRTL", dtype)),
+  }
+}
+
+fn literal(bits: &[bool]) -> String {
+  format!("UInt<{}>(0h{})", bits.len(), hex_digits(bits))
+}
+
+fn truncate(expr: String, width: usize) -> String {
+  format!("bits({}, {}, 0)", expr, width - 1)
+}
+
+/// `expr` of type `dtype` as a `UInt`, with element and field 0 at the least
+/// significant bits as in the interpreter
+fn as_uint(expr: &str, dtype: &DataTypeEnum) -> Result<String, String> {
+  let parts = match dtype {
+    DataTypeEnum::UInt(_) => return Ok(expr.to_string()),
+    DataTypeEnum::Clk(_) => return Ok(format!("asUInt({})", expr)),
+    DataTypeEnum::Array(ArrayType(element, len)) => (0..*len)
+      .map(|i| as_uint(&format!("{}[{}]", expr, i), element))
+      .collect::<Result<Vec<_>, String>>()?,
+    DataTypeEnum::Struct(StructType(fields)) => fields
+      .iter()
+      .map(|(name, dtype)| as_uint(&format!("{}.{}", expr, sanitize(name)), dtype))
+      .collect::<Result<Vec<_>, String>>()?,
+    dtype => return Err(format!("type {:?} can't be emitted to FIRRTL", dtype)),
+  };
+  // `cat` puts its first operand at the most significant bits
+  Ok(
+    parts
+      .into_iter()
+      .reduce(|lsbs, x| format!("cat({}, {})", x, lsbs))
+      .unwrap_or_else(|| literal(&[])),
+  )
+}
+
+/// Connect `target` of type `dtype` to the bits of the `UInt` `expr` from `low`,
+/// reversing `as_uint`
+fn connect_bits(
+  target: &str, dtype: &DataTypeEnum, expr: &str, low: usize, body: &mut Vec<String>,
+) -> Result<(), String> {
+  match dtype {
+    DataTypeEnum::UInt(UIntType(0)) => {},
+    DataTypeEnum::UInt(UIntType(width)) => body.push(format!(
+      "connect {}, bits({}, {}, {})",
+      target,
+      expr,
+      low + width - 1,
+      low
+    )),
+    DataTypeEnum::Clk(_) => {
+      body.push(format!("connect {}, asClock(bits({}, {}, {}))", target, expr, low, low))
+    },
+    DataTypeEnum::Array(ArrayType(element, len)) => {
+      for i in 0..*len {
+        let target = format!("{}[{}]", target, i);
+        connect_bits(&target, element, expr, low + i * element.width(), body)?;
+      }
+    },
+    DataTypeEnum::Struct(StructType(fields)) => {
+      let mut low = low;
+      for (name, dtype) in fields {
+        connect_bits(&format!("{}.{}", target, sanitize(name)), dtype, expr, low, body)?;
+        low += dtype.width();
+      }
+    },
+    dtype => return Err(format!("type {:?} can't be emitted to FIRRTL", dtype)),
+  }
+  Ok(())
+}
+
+struct ModuleEmitter<'a, E> {
+  env: &'a E,
+  /// FIRRTL names of the entities
+  names: HashMap<EntityId, String>,
+  table: SymbolTable,
+  /// Declarations of wires, registers and instances, which must come before their uses
+  decls: Vec<String>,
+  body: Vec<String>,
+}
+
+impl<'a, E> ModuleEmitter<'a, E>
+where E: Environ<EntityT = EntityEnum, OpT = OpEnum>
+{
+  fn dtype(&self, entity: EntityId) -> IronyResult<DataTypeEnum> {
+    self.env.get_entity(entity).get_dtype().ok_or_else(|| {
+      IronyError::new("entity must have a type").with_entity(self.env, entity)
+    })
+  }
+
+  fn firrtl_type(&self, entity: EntityId) -> IronyResult<String> {
+    firrtl_type(&self.dtype(entity)?)
+      .map_err(|err| IronyError::new(err).with_entity(self.env, entity))
+  }
+
+  fn width(&self, entity: &Option<EntityId>, op: OpId) -> IronyResult<usize> {
+    let entity = entity.ok_or_else(|| IronyError::new("missing operand").with_op(op))?;
+    Ok(self.dtype(entity)?.width())
+  }
+
+  fn name(&self, entity: &Option<EntityId>, op: OpId) -> IronyResult<String> {
+    entity
+      .and_then(|x| self.names.get(&x).cloned())
+      .ok_or_else(|| IronyError::new("missing operand").with_op(op))
+  }
+
+  fn names(&self, entities: &[Option<EntityId>], op: OpId) -> IronyResult<Vec<String>> {
+    entities.iter().map(|x| self.name(x, op)).collect()
+  }
+
+  /// Name `entity` after itself, declaring it as a wire unless it's a register
+  fn declare(&mut self, entity: EntityId, wire: bool) -> IronyResult<()> {
+    if self.names.contains_key(&entity) {
+      return Ok(());
+    }
+    let name = match self.env.get_entity(entity).get_attr("name") {
+      Some(attr) => sanitize(&string_of(&attr)),
+      None => format!("_{}", entity.0),
+    };
+    let name = self.table.get_legal_name_in(None, &name);
+    if wire {
+      self.decls.push(format!("wire {} : {}", name, self.firrtl_type(entity)?));
+    }
+    self.names.insert(entity, name);
+    Ok(())
+  }
+
+  fn connect(
+    &mut self, lhs: &Option<EntityId>, rhs: String, op: OpId,
+  ) -> IronyResult<()> {
+    let lhs = self.name(lhs, op)?;
+    self.body.push(format!("connect {}, {}", lhs, rhs));
+    Ok(())
+  }
+
+  /// Connect `lhs` to the bits of the `UInt` `expr`, whatever the type of `lhs`
+  fn connect_bits(
+    &mut self, lhs: &Option<EntityId>, expr: String, op: OpId,
+  ) -> IronyResult<()> {
+    let entity = lhs.ok_or_else(|| IronyError::new("missing result").with_op(op))?;
+    match self.dtype(entity)? {
+      DataTypeEnum::UInt(_) => self.connect(lhs, expr, op),
+      dtype => {
+        let lhs = self.name(lhs, op)?;
+        connect_bits(&lhs, &dtype, &expr, 0, &mut self.body)
+          .map_err(|err| IronyError::new(err).with_op(op))
+      },
+    }
+  }
+
+  fn constant(
+    &mut self, lhs: &Option<EntityId>, value: AttributeEnum, op: OpId,
+  ) -> IronyResult<()> {
+    let entity = lhs.ok_or_else(|| IronyError::new("missing result").with_op(op))?;
+    let dtype = self.dtype(entity)?;
+    let flat = DataTypeEnum::UInt(UIntType(dtype.width()));
+    let bits = constant(&value, &dtype)
+      .map(|x| bitcast(&x, &flat))
+      .map_err(|err| IronyError::new(err).with_op(op))?;
+    let Value::Bits(bits) = bits else { unreachable!() };
+    self.connect_bits(lhs, literal(&bits), op)
+  }
+
+  /// `x << y` in the width of `x`, without widening the result by `2^width(y)` bits
+  fn shl(&self, x: String, y: String, width: usize, y_width: usize) -> String {
+    let amount_width = (usize::BITS - (width.max(2) - 1).leading_zeros()) as usize;
+    if y_width <= amount_width {
+      return truncate(format!("dshl({}, {})", x, y), width);
+    }
+    format!(
+      "mux(gt({}, UInt<{}>({})), UInt<{}>(0), {})",
+      y,
+      y_width,
+      width - 1,
+      width,
+      truncate(format!("dshl({}, bits({}, {}, 0))", x, y, amount_width - 1), width)
+    )
+  }
+
+  fn op(&mut self, op_id: OpId) -> IronyResult<()> {
+    let env = self.env;
+    let signed = |x: String| format!("asSInt({})", x);
+    match env.get_op(op_id) {
+      OpEnum::HwInput(_) | OpEnum::HwOutput(_) => {},
+      OpEnum::Assign(op) => {
+        let rhs = self.name(&op.rhs, op_id)?;
+        self.connect(&op.lhs, rhs, op_id)?;
+      },
+      OpEnum::HwBitCast(HwBitCast { lhs, rhs, .. }) => {
+        let dtype = rhs.map(|x| self.dtype(x)).transpose()?;
+        let rhs = self.name(rhs, op_id)?;
+        let rhs = as_uint(&rhs, &dtype.unwrap())
+          .map_err(|err| IronyError::new(err).with_op(op_id))?;
+        self.connect_bits(lhs, rhs, op_id)?;
+      },
+      OpEnum::HwConstant(HwConstant { lhs, value: Some(value), .. }) => {
+        self.constant(lhs, AttributeEnum::ConstantAttr(value.to_owned()), op_id)?;
+      },
+      OpEnum::HwAggregateConstant(HwAggregateConstant {
+        lhs,
+        attrs: Some(value),
+        ..
+      }) => {
+        self.constant(lhs, AttributeEnum::ArrayAttr(value.to_owned()), op_id)?;
+      },
+      OpEnum::SvConstantX(op) => {
+        let lhs = self.name(&op.lhs, op_id)?;
+        self.body.push(format!("invalidate {}", lhs));
+      },
+      OpEnum::HwArrayCreate(HwArrayCreate { lhs, operands, .. }) => {
+        let lhs = self.name(lhs, op_id)?;
+        for (i, operand) in self.names(operands, op_id)?.into_iter().enumerate() {
+          self.body.push(format!("connect {}[{}], {}", lhs, i, operand));
+        }
+      },
+      // the elements of the first operand come first
+      OpEnum::HwArrayConcat(HwArrayConcat { lhs, operands, .. }) => {
+        let lhs = self.name(lhs, op_id)?;
+        let mut index = 0;
+        for operand in operands {
+          let Some(DataTypeEnum::Array(ArrayType(_, len))) =
+            operand.and_then(|x| env.get_entity(x).get_dtype())
+          else {
+            return Err(IronyError::new("must concat arrays").with_op(op_id));
+          };
+          let operand = self.name(operand, op_id)?;
+          for i in 0..len {
+            self.body.push(format!("connect {}[{}], {}[{}]", lhs, index + i, operand, i));
+          }
+          index += len;
+        }
+      },
+      OpEnum::HwArrayGet(HwArrayGet { lhs, array, index, .. }) => {
+        let rhs = format!("{}[{}]", self.name(array, op_id)?, self.name(index, op_id)?);
+        self.connect(lhs, rhs, op_id)?;
+      },
+      OpEnum::HwArraySlice(HwArraySlice { lhs, array, index, .. }) => {
+        let Some(DataTypeEnum::Array(ArrayType(_, len))) =
+          lhs.and_then(|x| env.get_entity(x).get_dtype())
+        else {
+          return Err(IronyError::new("must slice an array").with_op(op_id));
+        };
+        let (lhs, array) = (self.name(lhs, op_id)?, self.name(array, op_id)?);
+        let index = self.name(index, op_id)?;
+        for i in 0..len {
+          let index = match i {
+            0 => index.to_owned(),
+            i => format!("add({}, UInt({}))", index, i),
+          };
+          self.body.push(format!("connect {}[{}], {}[{}]", lhs, i, array, index));
+        }
+      },
+      OpEnum::HwStructCreate(HwStructCreate { lhs, operands, .. }) => {
+        let Some(DataTypeEnum::Struct(StructType(fields))) =
+          lhs.and_then(|x| env.get_entity(x).get_dtype())
+        else {
+          return Err(IronyError::new("must create a struct").with_op(op_id));
+        };
+        let lhs = self.name(lhs, op_id)?;
+        for ((field, _), value) in fields.iter().zip(self.names(operands, op_id)?) {
+          self.body.push(format!("connect {}.{}, {}", lhs, sanitize(field), value));
+        }
+      },
+      OpEnum::HwStructExtract(HwStructExtract {
+        lhs,
+        struct_input,
+        field: Some(StringAttr(field)),
+        ..
+      }) => {
+        let rhs = format!("{}.{}", self.name(struct_input, op_id)?, sanitize(field));
+        self.connect(lhs, rhs, op_id)?;
+      },
+      // the last connection to the field wins
+      OpEnum::HwStructInject(HwStructInject {
+        lhs,
+        struct_input,
+        new_value,
+        field: Some(StringAttr(field)),
+        ..
+      }) => {
+        let lhs = self.name(lhs, op_id)?;
+        self.body.extend([
+          format!("connect {}, {}", lhs, self.name(struct_input, op_id)?),
+          format!(
+            "connect {}.{}, {}",
+            lhs,
+            sanitize(field),
+            self.name(new_value, op_id)?
+          ),
+        ]);
+      },
+      OpEnum::HwStructExplode(HwStructExplode { outputs, struct_input, .. }) => {
+        let Some(DataTypeEnum::Struct(StructType(fields))) =
+          struct_input.and_then(|x| env.get_entity(x).get_dtype())
+        else {
+          return Err(IronyError::new("must explode a struct").with_op(op_id));
+        };
+        let input = self.name(struct_input, op_id)?;
+        for (output, (field, _)) in outputs.iter().zip(fields) {
+          self.connect(output, format!("{}.{}", input, sanitize(&field)), op_id)?;
+        }
+      },
+      // the results of primitive operations are wider than their operands, so they are
+      // truncated to the width of the op
+      OpEnum::CombVariadic(CombVariadic {
+        lhs,
+        operands,
+        predicate: Some(predicate),
+        ..
+      }) => {
+        let width = self.width(lhs, op_id)?;
+        let (operator, widens) = match predicate {
+          CombVariadicPredicate::Add => ("add", true),
+          CombVariadicPredicate::Mul => ("mul", true),
+          CombVariadicPredicate::And => ("and", false),
+          CombVariadicPredicate::Or => ("or", false),
+          CombVariadicPredicate::Xor => ("xor", false),
+        };
+        let rhs = self
+          .names(operands, op_id)?
+          .into_iter()
+          .reduce(|x, y| match widens {
+            true => truncate(format!("{}({}, {})", operator, x, y), width),
+            false => format!("{}({}, {})", operator, x, y),
+          })
+          .ok_or_else(|| IronyError::new("missing operand").with_op(op_id))?;
+        self.connect(lhs, rhs, op_id)?;
+      },
+      OpEnum::CombBinary(CombBinary {
+        lhs,
+        op0,
+        op1,
+        predicate: Some(predicate),
+        ..
+      }) => {
+        let width = self.width(lhs, op_id)?;
+        let (x, y) = (self.name(op0, op_id)?, self.name(op1, op_id)?);
+        let rhs = match predicate {
+          CombBinaryPredicate::Sub => truncate(format!("sub({}, {})", x, y), width),
+          CombBinaryPredicate::DivU => format!("div({}, {})", x, y),
+          CombBinaryPredicate::ModU => format!("rem({}, {})", x, y),
+          CombBinaryPredicate::DivS => {
+            truncate(format!("asUInt(div({}, {}))", signed(x), signed(y)), width)
+          },
+          CombBinaryPredicate::ModS => {
+            format!("asUInt(rem({}, {}))", signed(x), signed(y))
+          },
+          CombBinaryPredicate::Shl => self.shl(x, y, width, self.width(op1, op_id)?),
+          CombBinaryPredicate::ShrU => format!("dshr({}, {})", x, y),
+          CombBinaryPredicate::ShrS => format!("asUInt(dshr({}, {}))", signed(x), y),
+        };
+        self.connect(lhs, rhs, op_id)?;
+      },
+      // FIRRTL has no unknown bits, so case and wildcard equalities are equalities
+      OpEnum::CombICmp(CombICmp {
+        lhs,
+        op0,
+        op1,
+        predicate: Some(predicate),
+        ..
+      }) => {
+        let (x, y) = (self.name(op0, op_id)?, self.name(op1, op_id)?);
+        let (operator, x, y) = match predicate {
+          CombICmpPredicate::EQ | CombICmpPredicate::CEQ | CombICmpPredicate::WEQ => {
+            ("eq", x, y)
+          },
+          CombICmpPredicate::NE | CombICmpPredicate::CNE | CombICmpPredicate::WNE => {
+            ("neq", x, y)
+          },
+          CombICmpPredicate::SLT => ("lt", signed(x), signed(y)),
+          CombICmpPredicate::SLE => ("leq", signed(x), signed(y)),
+          CombICmpPredicate::SGT => ("gt", signed(x), signed(y)),
+          CombICmpPredicate::SGE => ("geq", signed(x), signed(y)),
+          CombICmpPredicate::ULT => ("lt", x, y),
+          CombICmpPredicate::ULE => ("leq", x, y),
+          CombICmpPredicate::UGT => ("gt", x, y),
+          CombICmpPredicate::UGE => ("geq", x, y),
+        };
+        self.connect(lhs, format!("{}({}, {})", operator, x, y), op_id)?;
+      },
+      OpEnum::TmpUnary(TmpUnary { lhs, op, predicate: Some(predicate), .. }) => {
+        let operand = self.name(op, op_id)?;
+        let rhs = match predicate {
+          CombUnaryPredicate::Not => format!("not({})", operand),
+          CombUnaryPredicate::Neg => {
+            truncate(format!("neg({})", operand), self.width(lhs, op_id)?)
+          },
+        };
+        self.connect(lhs, rhs, op_id)?;
+      },
+      OpEnum::CombExtract(CombExtract {
+        lhs, input, low: Some(UIntAttr(low)), ..
+      }) => {
+        let low = *low as usize;
+        let width = self.width(lhs, op_id)?;
+        let rhs =
+          format!("bits({}, {}, {})", self.name(input, op_id)?, low + width - 1, low);
+        self.connect(lhs, rhs, op_id)?;
+      },
+      OpEnum::CombConcat(CombConcat { lhs, operands, .. }) => {
+        let rhs = self
+          .names(operands, op_id)?
+          .into_iter()
+          .rev()
+          .reduce(|lsbs, x| format!("cat({}, {})", x, lsbs))
+          .ok_or_else(|| IronyError::new("missing operand").with_op(op_id))?;
+        self.connect(lhs, rhs, op_id)?;
+      },
+      OpEnum::CombMux2(mux @ CombMux2 { lhs, cond, .. }) => {
+        let (set, clear) = mux.branches();
+        let rhs = format!(
+          "mux({}, {}, {})",
+          self.name(cond, op_id)?,
+          self.name(&set, op_id)?,
+          self.name(&clear, op_id)?
+        );
+        self.connect(lhs, rhs, op_id)?;
+      },
+      OpEnum::SeqCompReg(SeqCompReg { output, input, clk, reset, reset_val, .. }) => {
+        let entity =
+          output.ok_or_else(|| IronyError::new("missing result").with_op(op_id))?;
+        let (name, dtype) = (self.name(output, op_id)?, self.firrtl_type(entity)?);
+        let clk = match clk.map(|x| self.dtype(x)).transpose()? {
+          Some(DataTypeEnum::Clk(_)) => self.name(clk, op_id)?,
+          _ => format!("asClock({})", self.name(clk, op_id)?),
+        };
+        self.decls.push(match reset {
+          Some(_) => format!(
+            "regreset {} : {}, {}, {}, {}",
+            name,
+            dtype,
+            clk,
+            self.name(reset, op_id)?,
+            self.name(reset_val, op_id)?
+          ),
+          None => format!("reg {} : {}, {}", name, dtype, clk),
+        });
+        self.connect(output, self.name(input, op_id)?, op_id)?;
+      },
+      OpEnum::HwInstance(HwInstance {
+        outputs,
+        inputs,
+        target_op_id: Some(OpIdAttr(target)),
+        name: Some(StringAttr(name)),
+        ..
+      }) => {
+        let (args, results, _) = port_names(env, *target, sanitize)?;
+        let name = self.table.get_legal_name_in(None, &sanitize(name));
+        self.decls.push(format!(
+          "inst {} of {}",
+          name,
+          module_name(env, *target, sanitize)?
+        ));
+        for (port, value) in args.iter().zip(self.names(inputs, op_id)?) {
+          self.body.push(format!("connect {}.{}, {}", name, port, value));
+        }
+        for (port, value) in results.iter().zip(self.names(outputs, op_id)?) {
+          self.body.push(format!("connect {}, {}.{}", value, name, port));
+        }
+      },
+      // assertions need a clock in FIRRTL, so condition checks are left out
+      OpEnum::ItprtCondCheck(_) => {},
+      op => {
+        return Err(
+          IronyError::new(format!(
+            "`{}` can't be emitted to FIRRTL, it must be lowered first",
+            op.get_op_name()
+          ))
+          .with_op(op_id),
+        );
+      },
+    }
+    Ok(())
+  }
+}
+
+fn emit_module<E>(env: &E, module: OpId) -> IronyResult<Vec<String>>
+where E: Environ<EntityT = EntityEnum, OpT = OpEnum> {
+  let OpEnum::HwModule(HwModule {
+    is_extern, body, arg_types, output_types, ..
+  }) = env.get_op(module)
+  else {
+    return Err(IronyError::new("must be a module").with_op(module));
+  };
+  let name = module_name(env, module, sanitize)?;
+  let (args, results, table) = port_names(env, module, sanitize)?;
+
+  if *is_extern == Some(BoolAttr(true)) {
+    let (Some(arg_types), Some(output_types)) = (arg_types, output_types) else {
+      return Err(IronyError::new("extern module must have port types").with_op(module));
+    };
+    let types = |types: &ArrayAttr| -> IronyResult<Vec<String>> {
+      types
+        .0
+        .iter()
+        .map(|x| match x {
+          AttributeEnum::TypeAttr(TypeAttr(dtype)) => {
+            firrtl_type(dtype).map_err(|err| IronyError::new(err).with_op(module))
+          },
+          _ => Err(IronyError::new("port types must be types").with_op(module)),
+        })
+        .collect()
+    };
+    let mut lines = vec![format!("extmodule {} :", name)];
+    for (port, dtype) in args.iter().zip(types(arg_types)?) {
+      lines.push(format!("  input {} : {}", port, dtype));
+    }
+    for (port, dtype) in results.iter().zip(types(output_types)?) {
+      lines.push(format!("  output {} : {}", port, dtype));
+    }
+    lines.push(format!("  defname = {}", name));
+    return Ok(lines);
+  }
+
+  let Some(body) = body else {
+    return Err(IronyError::new("module must have a body").with_op(module));
+  };
+  let ops = env.get_region(*body).op_children.to_owned();
+  let mut emitter = ModuleEmitter {
+    env,
+    names: HashMap::new(),
+    table,
+    decls: vec![],
+    body: vec![],
+  };
+  let find = |f: fn(&OpEnum) -> Option<Vec<Option<EntityId>>>| {
+    ops.iter().find_map(|x| f(env.get_op(*x))).unwrap_or_default()
+  };
+  let inputs = find(|x| match x {
+    OpEnum::HwInput(HwInput { inputs, .. }) => Some(inputs.to_owned()),
+    _ => None,
+  });
+  let outputs = find(|x| match x {
+    OpEnum::HwOutput(HwOutput { outputs, .. }) => Some(outputs.to_owned()),
+    _ => None,
+  });
+  let mut defined = HashMap::new();
+  for op_id in ops.iter() {
+    let op = env.get_op(*op_id);
+    if !matches!(op, OpEnum::HwInput(_)) {
+      for entity in op.get_defs().into_iter().flat_map(|(_, x)| x).flatten() {
+        defined.insert(entity, matches!(op, OpEnum::SeqCompReg(_)));
+      }
+    }
+  }
+
+  let mut lines = vec![format!("module {} :", name)];
+  for (entity, name) in inputs.iter().zip(args) {
+    let entity =
+      entity.ok_or_else(|| IronyError::new("missing input").with_op(module))?;
+    lines.push(format!("  input {} : {}", name, emitter.firrtl_type(entity)?));
+    emitter.names.insert(entity, name);
+  }
+  // outputs are driven by the ops defining them, unless they are registers or driven
+  // by inputs or other outputs
+  let mut driven = vec![];
+  for (entity, name) in outputs.iter().zip(results) {
+    let entity =
+      entity.ok_or_else(|| IronyError::new("missing output").with_op(module))?;
+    lines.push(format!("  output {} : {}", name, emitter.firrtl_type(entity)?));
+    if emitter.names.contains_key(&entity) || defined.get(&entity) != Some(&false) {
+      driven.push((name, entity));
+    } else {
+      emitter.names.insert(entity, name);
+    }
+  }
+
+  for op_id in ops.iter() {
+    let op = env.get_op(*op_id);
+    for (_, x) in op.get_defs().into_iter().chain(op.get_uses()) {
+      for entity in x.into_iter().flatten() {
+        emitter.declare(entity, defined.get(&entity) != Some(&true))?;
+      }
+    }
+  }
+  for op_id in ops {
+    emitter.op(op_id)?;
+  }
+  for (name, entity) in driven {
+    let value = emitter.name(&Some(entity), module)?;
+    emitter.body.push(format!("connect {}, {}", name, value));
+  }
+
+  for statements in [emitter.decls, emitter.body] {
+    if !statements.is_empty() {
+      lines.push(String::new());
+      lines.extend(statements.into_iter().map(|x| format!("  {}", x)));
+    }
+  }
+  Ok(lines)
+}
+
+/// Emit the modules in the `hw`, `comb` and `seq` dialects instantiated by `top`, and
+/// `top` itself, as a FIRRTL circuit; extern modules become `extmodule`s, to be
+/// provided by other tools
+pub fn emit_firrtl<E>(env: &E, top: OpId) -> IronyResult<String>
+where E: Environ<EntityT = EntityEnum, OpT = OpEnum> {
+  let hierarchy = <InstanceHierarchy as Analysis<E>>::compute(env, top)?;
+  let mut lines = vec![
+    "FIRRTL version 3.3.0".to_string(),
+    format!("circuit {} :", module_name(env, top, sanitize)?),
+  ];
+  for module in hierarchy.modules.iter() {
+    lines.extend(emit_module(env, *module)?.into_iter().map(|x| match x.is_empty() {
+      true => x,
+      false => format!("  {}", x),
+    }));
+    lines.push(String::new());
+  }
+  Ok(lines.join("\n"))
+}
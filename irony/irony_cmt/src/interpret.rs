@@ -0,0 +1,753 @@
+//! Reference interpreter of `CmtIR`
+//!
+//! Every op of the `hw`, `comb` and `seq` dialects evaluates itself with the
+//! `interpret` hook given in `op_def!`, and the [`Interpreter`] runs a module cycle by
+//! cycle on top of them, as an executable semantics independent of
+//! `cement::simulator`. Values are laid out as in the simulator: bits are little-endian,
+//! the first operand of a `comb.concat` is the most significant one, and a bitcast
+//! flattens elements and fields in order from the least significant bit.
+//!
+//! Predicates with a signed variant are evaluated as two's complement, a division or
+//! modulo by zero gives zero and `sv.constantX` gives zero. Every register is clocked
+//! at each step, whatever its clock, and starts at zero.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+use irony::{Entity, Environ, InterpreterStateTrait};
+
+use crate::*;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Value {
+  /// Little-endian bits of an integer
+  Bits(Vec<bool>),
+  /// Elements of an array or fields of a struct, in order
+  Aggregate(Vec<Value>),
+}
+
+impl Value {
+  pub fn from_u32(value: u32, width: usize) -> Self {
+    Value::Bits((0..width).map(|i| i < 32 && (value >> i) & 1 == 1).collect())
+  }
+
+  pub fn from_bool(value: bool) -> Self { Value::Bits(vec![value]) }
+
+  pub fn zero(dtype: &DataTypeEnum) -> Self {
+    match dtype {
+      DataTypeEnum::Clk(_) => Value::Bits(vec![false]),
+      DataTypeEnum::UInt(UIntType(width)) => Value::Bits(vec![false; *width]),
+      DataTypeEnum::Struct(StructType(fields)) => {
+        Value::Aggregate(fields.iter().map(|(_, x)| Value::zero(x)).collect())
+      },
+      DataTypeEnum::Array(ArrayType(element, len))
+      | DataTypeEnum::UArray(UArrayType(element, len)) => {
+        Value::Aggregate(vec![Value::zero(element); *len])
+      },
+      DataTypeEnum::SeqHlmem(_) | DataTypeEnum::Void => Value::Aggregate(vec![]),
+    }
+  }
+
+  pub fn bits(&self) -> Result<&[bool], String> {
+    match self {
+      Value::Bits(bits) => Ok(bits),
+      Value::Aggregate(_) => Err("expected bits, got an aggregate".to_string()),
+    }
+  }
+
+  /// The integer of the bits, if it fits in a `u32`
+  pub fn to_u32(&self) -> Option<u32> {
+    let bits = self.bits().ok()?;
+    if bits.iter().skip(32).any(|x| *x) {
+      return None;
+    }
+    Some(bits.iter().take(32).enumerate().map(|(i, x)| (*x as u32) << i).sum())
+  }
+
+  pub fn as_bool(&self) -> Result<bool, String> {
+    match self.bits()? {
+      [x] => Ok(*x),
+      x => Err(format!("expected a bool, got {} bits", x.len())),
+    }
+  }
+
+  fn aggregate(&self) -> Result<&[Value], String> {
+    match self {
+      Value::Aggregate(x) => Ok(x),
+      Value::Bits(_) => Err("expected an aggregate, got bits".to_string()),
+    }
+  }
+
+  fn flatten(&self, bits: &mut Vec<bool>) {
+    match self {
+      Value::Bits(x) => bits.extend(x),
+      Value::Aggregate(x) => x.iter().for_each(|x| x.flatten(bits)),
+    }
+  }
+
+  fn unflatten(bits: &mut impl Iterator<Item = bool>, like: &Value) -> Value {
+    match like {
+      Value::Bits(x) => {
+        Value::Bits(x.iter().map(|_| bits.next().unwrap_or(false)).collect())
+      },
+      Value::Aggregate(x) => {
+        Value::Aggregate(x.iter().map(|x| Value::unflatten(bits, x)).collect())
+      },
+    }
+  }
+}
+
+impl fmt::Display for Value {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Value::Bits(bits) => {
+        let bits =
+          bits.iter().rev().map(|x| if *x { '1' } else { '0' }).collect::<String>();
+        write!(f, "{}'b{}", bits.len(), bits)
+      },
+      Value::Aggregate(x) => {
+        write!(f, "[{}]", x.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(", "))
+      },
+    }
+  }
+}
+
+// ------ evaluation of the ops, used by the `interpret` hooks ------
+
+pub(crate) fn read<S: InterpreterStateTrait<ValueT = Value>>(
+  state: &S, entity: Option<EntityId>,
+) -> Result<Value, String> {
+  let entity = entity.ok_or("missing operand".to_string())?;
+  state.get_value(entity).ok_or(format!("no value for entity {}", entity.0))
+}
+
+pub(crate) fn read_all<S: InterpreterStateTrait<ValueT = Value>>(
+  state: &S, entities: &[Option<EntityId>],
+) -> Result<Vec<Value>, String> {
+  entities.iter().map(|x| read(state, *x)).collect()
+}
+
+pub(crate) fn write<S: InterpreterStateTrait<ValueT = Value>>(
+  state: &mut S, entity: Option<EntityId>, value: Value,
+) -> Result<(), String> {
+  state.set_value(entity.ok_or("missing result".to_string())?, value);
+  Ok(())
+}
+
+pub(crate) fn dtype<E: Environ>(
+  env: &E, entity: Option<EntityId>,
+) -> Result<<E::EntityT as Entity>::DataTypeT, String> {
+  let entity = entity.ok_or("missing result".to_string())?;
+  env.get_entity(entity).get_dtype().ok_or(format!("entity {} has no type", entity.0))
+}
+
+/// Value of a constant attribute of type `dtype`, padding or truncating its bits
+pub(crate) fn constant(
+  attr: &AttributeEnum, dtype: &DataTypeEnum,
+) -> Result<Value, String> {
+  match (attr, dtype) {
+    (AttributeEnum::ConstantAttr(ConstantAttr(bits)), _) => {
+      Ok(bitcast(&Value::Bits(bits.to_owned()), dtype))
+    },
+    (
+      AttributeEnum::ArrayAttr(ArrayAttr(x)),
+      DataTypeEnum::Array(ArrayType(element, len)),
+    ) if x.len() == *len => Ok(Value::Aggregate(
+      x.iter().map(|x| constant(x, element)).collect::<Result<_, _>>()?,
+    )),
+    (
+      AttributeEnum::ArrayAttr(ArrayAttr(x)),
+      DataTypeEnum::Struct(StructType(fields)),
+    ) if x.len() == fields.len() => {
+      let fields = x.iter().zip(fields).map(|(x, (_, dtype))| constant(x, dtype));
+      Ok(Value::Aggregate(fields.collect::<Result<_, _>>()?))
+    },
+    (x, _) => Err(format!("{} is not a constant of type {}", x, dtype)),
+  }
+}
+
+pub(crate) fn bitcast(value: &Value, dtype: &DataTypeEnum) -> Value {
+  let mut bits = vec![];
+  value.flatten(&mut bits);
+  Value::unflatten(&mut bits.into_iter(), &Value::zero(dtype))
+}
+
+pub(crate) fn concat(values: &[Value]) -> Result<Value, String> {
+  let mut bits = vec![];
+  for value in values.iter().rev() {
+    bits.extend(value.bits()?);
+  }
+  Ok(Value::Bits(bits))
+}
+
+pub(crate) fn extract(value: &Value, low: usize, width: usize) -> Result<Value, String> {
+  let bits = value.bits()?;
+  if low + width > bits.len() {
+    return Err(format!("extract {}..{} of {} bits", low, low + width, bits.len()));
+  }
+  Ok(Value::Bits(bits[low..low + width].to_vec()))
+}
+
+pub(crate) fn array_concat(values: &[Value]) -> Result<Value, String> {
+  let mut elements = vec![];
+  for value in values {
+    elements.extend(value.aggregate()?.iter().cloned());
+  }
+  Ok(Value::Aggregate(elements))
+}
+
+pub(crate) fn array_get(array: &Value, index: &Value) -> Result<Value, String> {
+  let array = array.aggregate()?;
+  let index = to_index(index)?;
+  array.get(index).cloned().ok_or(format!(
+    "index {} out of an array of {}",
+    index,
+    array.len()
+  ))
+}
+
+pub(crate) fn array_slice(
+  array: &Value, index: &Value, len: usize,
+) -> Result<Value, String> {
+  let array = array.aggregate()?;
+  let index = to_index(index)?;
+  if index + len > array.len() {
+    return Err(format!(
+      "slice {}..{} of an array of {}",
+      index,
+      index + len,
+      array.len()
+    ));
+  }
+  Ok(Value::Aggregate(array[index..index + len].to_vec()))
+}
+
+pub(crate) fn field_index(dtype: &DataTypeEnum, field: &str) -> Result<usize, String> {
+  match dtype {
+    DataTypeEnum::Struct(StructType(fields)) => fields
+      .iter()
+      .position(|(name, _)| name == field)
+      .ok_or(format!("no field `{}` in {}", field, dtype)),
+    _ => Err(format!("{} is not a struct type", dtype)),
+  }
+}
+
+pub(crate) fn struct_get(value: &Value, index: usize) -> Result<Value, String> {
+  Ok(value.aggregate()?[index].to_owned())
+}
+
+pub(crate) fn struct_set(
+  value: &Value, index: usize, field: Value,
+) -> Result<Value, String> {
+  let mut fields = value.aggregate()?.to_vec();
+  fields[index] = field;
+  Ok(Value::Aggregate(fields))
+}
+
+pub(crate) fn unary(
+  predicate: &CombUnaryPredicate, value: &Value,
+) -> Result<Value, String> {
+  let bits = value.bits()?;
+  Ok(Value::Bits(match predicate {
+    CombUnaryPredicate::Not => bits.iter().map(|x| !x).collect(),
+    CombUnaryPredicate::Neg => neg(bits),
+  }))
+}
+
+pub(crate) fn variadic(
+  predicate: &CombVariadicPredicate, values: &[Value],
+) -> Result<Value, String> {
+  let (first, rest) = values.split_first().ok_or("no operand".to_string())?;
+  let mut result = first.bits()?.to_vec();
+  for value in rest {
+    let value = same_width(&result, value)?;
+    result = match predicate {
+      CombVariadicPredicate::Add => add(&result, value),
+      CombVariadicPredicate::Mul => mul(&result, value),
+      CombVariadicPredicate::And => {
+        result.iter().zip(value).map(|(x, y)| *x && *y).collect()
+      },
+      CombVariadicPredicate::Or => {
+        result.iter().zip(value).map(|(x, y)| *x || *y).collect()
+      },
+      CombVariadicPredicate::Xor => {
+        result.iter().zip(value).map(|(x, y)| x ^ y).collect()
+      },
+    };
+  }
+  Ok(Value::Bits(result))
+}
+
+pub(crate) fn binary(
+  predicate: &CombBinaryPredicate, op0: &Value, op1: &Value,
+) -> Result<Value, String> {
+  let x = op0.bits()?;
+  let y = same_width(x, op1)?;
+  Ok(Value::Bits(match predicate {
+    CombBinaryPredicate::Sub => add(x, &neg(y)),
+    CombBinaryPredicate::DivU => divmod(x, y).0,
+    CombBinaryPredicate::ModU => divmod(x, y).1,
+    CombBinaryPredicate::DivS => signed_divmod(x, y).0,
+    CombBinaryPredicate::ModS => signed_divmod(x, y).1,
+    CombBinaryPredicate::Shl => {
+      let n = shift_amount(y);
+      (0..x.len()).map(|i| i >= n && x[i - n]).collect()
+    },
+    CombBinaryPredicate::ShrU => {
+      let n = shift_amount(y);
+      (0..x.len()).map(|i| i + n < x.len() && x[i + n]).collect()
+    },
+    CombBinaryPredicate::ShrS => {
+      let n = shift_amount(y);
+      let sign = x.last().copied().unwrap_or(false);
+      (0..x.len()).map(|i| if i + n < x.len() { x[i + n] } else { sign }).collect()
+    },
+  }))
+}
+
+pub(crate) fn icmp(
+  predicate: &CombICmpPredicate, op0: &Value, op1: &Value,
+) -> Result<Value, String> {
+  use CombICmpPredicate::*;
+  if !matches!(predicate, EQ | NE | CEQ | CNE | WEQ | WNE) {
+    same_width(op0.bits()?, op1)?;
+  }
+  let result = match predicate {
+    EQ | CEQ | WEQ => op0 == op1,
+    NE | CNE | WNE => op0 != op1,
+    ULT => compare(op0.bits()?, op1.bits()?) == Ordering::Less,
+    ULE => compare(op0.bits()?, op1.bits()?) != Ordering::Greater,
+    UGT => compare(op0.bits()?, op1.bits()?) == Ordering::Greater,
+    UGE => compare(op0.bits()?, op1.bits()?) != Ordering::Less,
+    SLT => signed_compare(op0.bits()?, op1.bits()?) == Ordering::Less,
+    SLE => signed_compare(op0.bits()?, op1.bits()?) != Ordering::Greater,
+    SGT => signed_compare(op0.bits()?, op1.bits()?) == Ordering::Greater,
+    SGE => signed_compare(op0.bits()?, op1.bits()?) != Ordering::Less,
+  };
+  Ok(Value::from_bool(result))
+}
+
+/// Value of a `select`: the value of the first holding condition, or the default
+pub(crate) fn select(
+  conds: &[Option<Value>], values: &[Value], default: Option<Value>, onehot: bool,
+) -> Result<Value, String> {
+  let mut holding = vec![];
+  for (cond, value) in conds.iter().zip(values) {
+    match cond {
+      Some(cond) if cond.as_bool()? => holding.push(value),
+      Some(_) => {},
+      None => return Err("select with an unresolved condition".to_string()),
+    }
+  }
+  if onehot && holding.len() > 1 {
+    return Err(format!("{} conditions of a onehot select hold", holding.len()));
+  }
+  match (holding.first(), default) {
+    (Some(value), _) => Ok((*value).to_owned()),
+    (None, Some(default)) => Ok(default),
+    (None, None) => {
+      Err("no condition of a select holds and it has no default".to_string())
+    },
+  }
+}
+
+pub(crate) fn cond_check(
+  conds: &[Value], has_default: bool, onehot: bool,
+) -> Result<(), String> {
+  let mut holding = 0;
+  for cond in conds {
+    holding += cond.as_bool()? as usize;
+  }
+  if onehot && holding > 1 {
+    return Err(format!("{} of the onehot conditions hold", holding));
+  }
+  if !has_default && holding == 0 {
+    return Err("no condition holds and there is no default".to_string());
+  }
+  Ok(())
+}
+
+fn to_index(value: &Value) -> Result<usize, String> {
+  value.to_u32().map(|x| x as usize).ok_or(format!("index {} is too large", value))
+}
+
+fn same_width<'a>(bits: &[bool], value: &'a Value) -> Result<&'a [bool], String> {
+  let other = value.bits()?;
+  if other.len() != bits.len() {
+    return Err(format!("operands of {} and {} bits", bits.len(), other.len()));
+  }
+  Ok(other)
+}
+
+fn shift_amount(bits: &[bool]) -> usize {
+  match Value::Bits(bits.to_vec()).to_u32() {
+    Some(x) => x as usize,
+    None => usize::MAX,
+  }
+}
+
+fn add(x: &[bool], y: &[bool]) -> Vec<bool> {
+  let mut carry = false;
+  x.iter()
+    .zip(y)
+    .map(|(x, y)| {
+      let sum = x ^ y ^ carry;
+      carry = (x & y) | (carry & (x ^ y));
+      sum
+    })
+    .collect()
+}
+
+fn neg(x: &[bool]) -> Vec<bool> {
+  let not = x.iter().map(|x| !x).collect::<Vec<_>>();
+  let mut one = vec![false; x.len()];
+  if let Some(x) = one.first_mut() {
+    *x = true;
+  }
+  add(&not, &one)
+}
+
+fn mul(x: &[bool], y: &[bool]) -> Vec<bool> {
+  let mut result = vec![false; x.len()];
+  for (i, bit) in y.iter().enumerate() {
+    if *bit {
+      let shifted = (0..x.len()).map(|j| j >= i && x[j - i]).collect::<Vec<_>>();
+      result = add(&result, &shifted);
+    }
+  }
+  result
+}
+
+fn compare(x: &[bool], y: &[bool]) -> Ordering { x.iter().rev().cmp(y.iter().rev()) }
+
+fn signed_compare(x: &[bool], y: &[bool]) -> Ordering {
+  match (x.last(), y.last()) {
+    (Some(true), Some(false)) => Ordering::Less,
+    (Some(false), Some(true)) => Ordering::Greater,
+    _ => compare(x, y),
+  }
+}
+
+/// Unsigned quotient and remainder, both zero when dividing by zero
+fn divmod(x: &[bool], y: &[bool]) -> (Vec<bool>, Vec<bool>) {
+  let zero = vec![false; x.len()];
+  if !y.iter().any(|x| *x) {
+    return (zero.to_owned(), zero);
+  }
+  let (mut quotient, mut remainder) = (zero.to_owned(), zero);
+  for i in (0..x.len()).rev() {
+    remainder.rotate_right(1);
+    remainder[0] = x[i];
+    if compare(&remainder, y) != Ordering::Less {
+      remainder = add(&remainder, &neg(y));
+      quotient[i] = true;
+    }
+  }
+  (quotient, remainder)
+}
+
+/// Quotient rounded toward zero, and remainder with the sign of the dividend
+fn signed_divmod(x: &[bool], y: &[bool]) -> (Vec<bool>, Vec<bool>) {
+  let sign = |x: &[bool]| x.last().copied().unwrap_or(false);
+  let abs = |x: &[bool]| if sign(x) { neg(x) } else { x.to_vec() };
+  let (quotient, remainder) = divmod(&abs(x), &abs(y));
+  let quotient = if sign(x) != sign(y) { neg(&quotient) } else { quotient };
+  let remainder = if sign(x) { neg(&remainder) } else { remainder };
+  (quotient, remainder)
+}
+
+// ------ the interpreter ------
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InterpretError {
+  pub op: Option<OpId>,
+  pub message: String,
+}
+
+impl fmt::Display for InterpretError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.message)
+  }
+}
+
+impl std::error::Error for InterpretError {}
+
+type InterpretResult<T> = Result<T, InterpretError>;
+
+/// Values of an instance of a module
+#[derive(Default)]
+struct Frame {
+  inputs: Vec<Option<EntityId>>,
+  outputs: Vec<Option<EntityId>>,
+  values: HashMap<EntityId, Value>,
+  /// Outputs of the registers, by the value they hold in the current cycle
+  registers: HashMap<EntityId, Value>,
+  next: HashMap<EntityId, Value>,
+}
+
+impl InterpreterStateTrait for Frame {
+  type ValueT = Value;
+
+  fn get_value(&self, entity: EntityId) -> Option<Value> {
+    self.values.get(&entity).cloned()
+  }
+
+  fn set_value(&mut self, entity: EntityId, value: Value) {
+    self.values.insert(entity, value);
+  }
+
+  fn set_next_value(&mut self, entity: EntityId, value: Value) {
+    self.next.insert(entity, value);
+  }
+}
+
+/// Values of the results of `op` evaluated on the values of its operands, used to fold
+/// ops with constant operands
+pub fn evaluate<E: Environ<OpT = OpEnum>>(
+  env: &E, op: OpId, operands: HashMap<EntityId, Value>,
+) -> Result<HashMap<EntityId, Value>, String> {
+  let mut frame = Frame { values: operands, ..Default::default() };
+  env.interpret_op(op, &mut frame)?;
+  let defs = env.get_op(op).get_defs().into_iter().flat_map(|(_, x)| x).flatten();
+  Ok(defs.filter_map(|x| Some((x, frame.values.remove(&x)?))).collect())
+}
+
+enum Item {
+  Op(usize, OpId),
+  /// Copy a value across an instance boundary, from and to (frame, entity)
+  Connect((usize, EntityId), (usize, EntityId)),
+}
+
+/// Cycle-by-cycle interpreter of a module and the modules it instantiates
+pub struct Interpreter<'ir> {
+  ir: &'ir CmtIR,
+  /// Frame of every instance, the first being the interpreted module
+  frames: Vec<Frame>,
+  items: Vec<Item>,
+  arg_names: Vec<String>,
+  output_names: Vec<String>,
+  pokes: HashMap<String, Value>,
+  cycle: usize,
+}
+
+impl<'ir> Interpreter<'ir> {
+  pub fn new(ir: &'ir CmtIR, module: OpId) -> InterpretResult<Self> {
+    let OpEnum::HwModule(top) = ir.get_op(module) else {
+      return Err(InterpretError {
+        op: Some(module),
+        message: format!(
+          "can't interpret `{}`, which isn't a module",
+          ir.print_op(module)
+        ),
+      });
+    };
+    let names = |x: &Option<ArrayAttr>| -> Vec<String> {
+      x.iter()
+        .flat_map(|x| x.0.iter())
+        .map(|x| match x {
+          AttributeEnum::StringAttr(StringAttr(x)) => x.to_owned(),
+          x => x.to_string(),
+        })
+        .collect()
+    };
+    let mut interpreter = Interpreter {
+      ir,
+      frames: vec![],
+      items: vec![],
+      arg_names: names(&top.arg_names),
+      output_names: names(&top.output_names),
+      pokes: HashMap::new(),
+      cycle: 0,
+    };
+    interpreter.instantiate(module, &mut vec![])?;
+    Ok(interpreter)
+  }
+
+  /// Drive an input of the module from now on
+  pub fn poke(&mut self, input: &str, value: Value) -> InterpretResult<()> {
+    if !self.arg_names.iter().any(|x| x == input) {
+      return Err(InterpretError {
+        op: None,
+        message: format!("no input `{}`", input),
+      });
+    }
+    self.pokes.insert(input.to_string(), value);
+    Ok(())
+  }
+
+  /// Value of an input or output of the module in the last evaluated cycle
+  pub fn peek(&self, port: &str) -> Option<Value> {
+    let top = self.frames.first()?;
+    let entity = match self.arg_names.iter().position(|x| x == port) {
+      Some(i) => top.inputs.get(i),
+      None => {
+        self.output_names.iter().position(|x| x == port).and_then(|i| top.outputs.get(i))
+      },
+    };
+    top.values.get(&(*entity?)?).cloned()
+  }
+
+  pub fn cycle(&self) -> usize { self.cycle }
+
+  /// Evaluate every value of the current cycle
+  pub fn eval(&mut self) -> InterpretResult<()> {
+    for frame in self.frames.iter_mut() {
+      frame.values = frame.registers.clone();
+      frame.next.clear();
+    }
+    let inputs = self.frames[0].inputs.to_owned();
+    for (name, input) in self.arg_names.iter().zip(inputs) {
+      let Some(input) = input else { continue };
+      let value = match self.pokes.get(name) {
+        Some(value) => value.to_owned(),
+        None => {
+          Value::zero(&dtype(self.ir, Some(input)).map_err(|x| self.error(None, x))?)
+        },
+      };
+      self.frames[0].values.insert(input, value);
+    }
+
+    let mut pending = (0..self.items.len()).collect::<Vec<_>>();
+    while !pending.is_empty() {
+      let mut rest = vec![];
+      for i in pending.iter().copied() {
+        if !self.run(i)? {
+          rest.push(i);
+        }
+      }
+      if rest.len() == pending.len() {
+        let op = rest.iter().find_map(|x| match self.items[*x] {
+          Item::Op(_, op) => Some(op),
+          Item::Connect(..) => None,
+        });
+        let message = match op {
+          Some(op) => {
+            format!("`{}` is in a combinational loop or undriven", self.ir.print_op(op))
+          },
+          None => "an instance is in a combinational loop or undriven".to_string(),
+        };
+        return Err(self.error(op, message));
+      }
+      pending = rest;
+    }
+    Ok(())
+  }
+
+  /// Evaluate the current cycle, then clock every register
+  pub fn step(&mut self) -> InterpretResult<()> {
+    self.eval()?;
+    for frame in self.frames.iter_mut() {
+      for (register, value) in frame.next.drain() {
+        frame.registers.insert(register, value);
+      }
+    }
+    self.cycle += 1;
+    Ok(())
+  }
+
+  /// Allocate the frame of an instance of `module` and the items to evaluate it
+  fn instantiate(
+    &mut self, module: OpId, path: &mut Vec<OpId>,
+  ) -> InterpretResult<usize> {
+    let OpEnum::HwModule(HwModule { name, is_extern, body, .. }) = self.ir.get_op(module)
+    else {
+      return Err(self.error(Some(module), "instance of a non-module".to_string()));
+    };
+    let name = name.as_ref().map_or(String::default(), |x| x.0.to_owned());
+    if is_extern.as_ref().is_some_and(|x| x.0) {
+      return Err(
+        self.error(Some(module), format!("can't interpret extern module @{}", name)),
+      );
+    }
+    if path.contains(&module) {
+      return Err(
+        self.error(Some(module), format!("module @{} instantiates itself", name)),
+      );
+    }
+    path.push(module);
+
+    let frame = self.frames.len();
+    self.frames.push(Frame::default());
+    let body = body.ok_or(self.error(Some(module), format!("@{} has no body", name)))?;
+    for op in self.ir.get_region(body).get_op_children() {
+      match self.ir.get_op(op) {
+        OpEnum::HwInput(input) => self.frames[frame].inputs = input.inputs.to_owned(),
+        OpEnum::HwOutput(output) => {
+          self.frames[frame].outputs = output.outputs.to_owned()
+        },
+        OpEnum::HwInstance(instance) => {
+          let target = instance
+            .target_op_id
+            .as_ref()
+            .ok_or(self.error(Some(op), "instance without module".to_string()))?;
+          let child = self.instantiate(target.0, path)?;
+          let inputs = self.frames[child].inputs.to_owned();
+          for (from, to) in instance.inputs.iter().zip(inputs) {
+            if let (Some(from), Some(to)) = (from, to) {
+              self.items.push(Item::Connect((frame, *from), (child, to)));
+            }
+          }
+          let outputs = self.frames[child].outputs.to_owned();
+          for (from, to) in outputs.iter().zip(instance.outputs.iter()) {
+            if let (Some(from), Some(to)) = (from, to) {
+              self.items.push(Item::Connect((child, *from), (frame, *to)));
+            }
+          }
+        },
+        OpEnum::SeqCompReg(register) => {
+          let output = register.output;
+          let zero =
+            Value::zero(&dtype(self.ir, output).map_err(|x| self.error(Some(op), x))?);
+          self.frames[frame].registers.insert(output.unwrap(), zero);
+          self.items.push(Item::Op(frame, op));
+        },
+        // statements are kept after control synthesis, which lowered them to the
+        // ops interpreted
+        OpEnum::StmtSynth(_)
+        | OpEnum::StmtStep(_)
+        | OpEnum::StmtSeq(_)
+        | OpEnum::StmtIf(_)
+        | OpEnum::StmtFor(_)
+        | OpEnum::StmtWhile(_)
+        | OpEnum::StmtPar(_)
+        | OpEnum::StmtCall(_) => {},
+        _ => self.items.push(Item::Op(frame, op)),
+      }
+    }
+
+    path.pop();
+    Ok(frame)
+  }
+
+  /// Run an item if its operands are evaluated, returning whether it ran
+  fn run(&mut self, item: usize) -> InterpretResult<bool> {
+    match self.items[item] {
+      Item::Op(frame, op) => {
+        let ready = self.ir.get_op(op).get_uses().iter().all(|(_, uses)| {
+          uses.iter().flatten().all(|x| self.frames[frame].values.contains_key(x))
+        });
+        if ready {
+          self.ir.interpret_op(op, &mut self.frames[frame]).map_err(|x| {
+            self.error(Some(op), format!("`{}`: {}", self.ir.print_op(op), x))
+          })?;
+        }
+        Ok(ready)
+      },
+      Item::Connect((from_frame, from), (to_frame, to)) => {
+        let Some(value) = self.frames[from_frame].values.get(&from).cloned() else {
+          return Ok(false);
+        };
+        self.frames[to_frame].values.insert(to, value);
+        Ok(true)
+      },
+    }
+  }
+
+  fn error(&self, op: Option<OpId>, message: String) -> InterpretError {
+    InterpretError {
+      op,
+      message: format!("cycle {}: {}", self.cycle, message),
+    }
+  }
+}
@@ -0,0 +1,65 @@
+/// A JSON value, printed with one object member per line
+pub(crate) enum Json {
+  Str(String),
+  Num(usize),
+  Arr(Vec<Json>),
+  Obj(Vec<(String, Json)>),
+}
+
+impl Json {
+  pub fn obj<K: Into<String>>(members: impl IntoIterator<Item = (K, Json)>) -> Json {
+    Json::Obj(members.into_iter().map(|(k, v)| (k.into(), v)).collect())
+  }
+
+  fn write(&self, out: &mut String, indent: usize) {
+    match self {
+      Json::Str(x) => {
+        out.push('"');
+        for c in x.chars() {
+          match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+          }
+        }
+        out.push('"');
+      },
+      Json::Num(x) => out.push_str(&x.to_string()),
+      Json::Arr(items) => {
+        out.push('[');
+        for (i, item) in items.iter().enumerate() {
+          if i > 0 {
+            out.push_str(", ");
+          }
+          item.write(out, indent);
+        }
+        out.push(']');
+      },
+      Json::Obj(members) if members.is_empty() => out.push_str("{}"),
+      Json::Obj(members) => {
+        out.push_str("{\n");
+        for (i, (key, value)) in members.iter().enumerate() {
+          if i > 0 {
+            out.push_str(",\n");
+          }
+          out.push_str(&" ".repeat(indent + 2));
+          Json::Str(key.to_owned()).write(out, indent + 2);
+          out.push_str(": ");
+          value.write(out, indent + 2);
+        }
+        out.push('\n');
+        out.push_str(&" ".repeat(indent));
+        out.push('}');
+      },
+    }
+  }
+}
+
+impl std::fmt::Display for Json {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let mut out = String::new();
+    self.write(&mut out, 0);
+    write!(f, "{}", out)
+  }
+}
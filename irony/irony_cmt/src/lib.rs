@@ -2,19 +2,33 @@
 #[allow(unused_variables)]
 pub use irony::{self, preclude::*};
 
+mod analyses;
 /// define types and attributes
 mod common;
 mod constraints;
+mod firrtl;
+mod json;
 mod passes;
+mod parser;
+mod sv;
+mod symbol_table;
+mod yosys;
 
+pub use analyses::*;
 pub use common::*;
 pub use constraints::*;
+pub use firrtl::*;
 pub use indexmap;
+pub use parser::*;
 pub use passes::*;
+pub use sv::*;
+pub use symbol_table::*;
+pub use yosys::*;
 
 mod cmt_utils;
 
-// pub use interpret::*;
+mod interpret;
+pub use interpret::*;
 
 irony::entity_def! {
     [data_type = DataTypeEnum, attr = AttributeEnum]
@@ -28,7 +42,7 @@ irony::entity_def! {
 }
 
 irony::op_def! {
-    [data_type = DataTypeEnum, attr = AttributeEnum, constraint = ConstraintEnum]
+    [data_type = DataTypeEnum, attr = AttributeEnum, constraint = ConstraintEnum, value = Value]
 
     OpEnum = {
 
@@ -172,6 +186,20 @@ irony::op_def! {
                 }
             )
         },
+
+        StmtCall: {
+            defs: [lhs],
+            uses: [go, done, callee_go],
+            print: (
+                |env: &E, _, uses: Vec<(String, Vec<Option<EntityId>>)>, defs:Vec<(String, Vec<Option<EntityId>>)>, _| {
+                    let lhs = env.print_entity(defs[0].1[0].unwrap());
+                    let go = env.print_entity(uses[0].1[0].unwrap());
+                    let done = env.print_entity(uses[1].1[0].unwrap());
+                    let callee_go = env.print_entity(uses[2].1[0].unwrap());
+                    format!("{} = stmt.call {} go {} done {}", lhs, callee_go, go, done)
+                }
+            )
+        },
         // ------ END: define the operations in `stmt` dialect -------
 
 
@@ -268,6 +296,15 @@ irony::op_def! {
                     let typ = env.get_entity(defs[0].1[0].unwrap()).get_dtype().unwrap();
                     format!("{} = ILLEGAL.select {} {{\n{}\n{}}} : {}", lhs, mode, candidates, default, typ)
                 }
+            ),
+            interpret: (
+                |_, state: &mut S, attrs: Vec<(String, AttributeEnum)>, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _| -> Result<(), String> {
+                    let AttributeEnum::BoolAttr(BoolAttr(onehot)) = irony::utils::extract_vec(&attrs, "onehot").unwrap() else { panic!("")};
+                    let default = uses[0].1[0].map(|x| interpret::read(state, Some(x))).transpose()?;
+                    let conds = uses[1].1.iter().map(|x| x.map(|x| interpret::read(state, Some(x))).transpose()).collect::<Result<Vec<_>, _>>()?;
+                    let values = interpret::read_all(state, &uses[2].1)?;
+                    interpret::write(state, defs[0].1[0], interpret::select(&conds, &values, default, onehot)?)
+                }
             )
         },
 
@@ -284,6 +321,13 @@ irony::op_def! {
                     let typ = env.get_entity(defs[0].1[0].unwrap()).get_dtype().unwrap();
                     format!("{} = ILLEGAL.{} {} : {}", def, predicate, uses, typ)
                 }
+            ),
+            interpret: (
+                |_, state: &mut S, attrs: Vec<(String, AttributeEnum)>, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _| -> Result<(), String> {
+                    let AttributeEnum::CombUnaryPredicate(predicate) = irony::utils::extract_vec(&attrs, "predicate").unwrap() else { panic!("")};
+                    let op = interpret::read(state, uses[0].1[0])?;
+                    interpret::write(state, defs[0].1[0], interpret::unary(&predicate, &op)?)
+                }
             )
         },
 
@@ -300,6 +344,12 @@ irony::op_def! {
                   let typ = env.get_entity(defs[0].1[0].unwrap()).get_dtype().unwrap();
                   format!("{} = sv.constantX : {}", lhs, typ)
               }
+          ),
+          interpret: (
+              |env: &E, state: &mut S, _, _, defs: Vec<(String, Vec<Option<EntityId>>)>, _| -> Result<(), String> {
+                  let typ = interpret::dtype(env, defs[0].1[0])?;
+                  interpret::write(state, defs[0].1[0], Value::zero(&typ))
+              }
           )
         },
 
@@ -319,6 +369,12 @@ irony::op_def! {
                     let typ = env.get_entity(defs[0].1[0].unwrap()).get_dtype().unwrap();
                     format!("{} = hw.wire {} : {}", lhs, rhs, typ)
                 }
+            ),
+            interpret: (
+                |_, state: &mut S, _, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _| -> Result<(), String> {
+                    let rhs = interpret::read(state, uses[0].1[0])?;
+                    interpret::write(state, defs[0].1[0], rhs)
+                }
             )
         },
 
@@ -439,6 +495,13 @@ irony::op_def! {
 
                     format!("{} = hw.bitcast {}: ({}) -> {}", lhs, rhs, rhs_typ, lhs_typ)
                 }
+            ),
+            interpret: (
+                |env: &E, state: &mut S, _, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _| -> Result<(), String> {
+                    let rhs = interpret::read(state, uses[0].1[0])?;
+                    let typ = interpret::dtype(env, defs[0].1[0])?;
+                    interpret::write(state, defs[0].1[0], interpret::bitcast(&rhs, &typ))
+                }
             )
         },
 
@@ -460,6 +523,13 @@ irony::op_def! {
                     }).collect::<Vec<_>>().join(", ");
                     format!("{} = hw.constant {}: {}", names, value, types)
                 }
+            ),
+            interpret: (
+                |env: &E, state: &mut S, attrs: Vec<(String, AttributeEnum)>, _, defs: Vec<(String, Vec<Option<EntityId>>)>, _| -> Result<(), String> {
+                    let value = irony::utils::extract_vec(&attrs, "value").unwrap();
+                    let typ = interpret::dtype(env, defs[0].1[0])?;
+                    interpret::write(state, defs[0].1[0], interpret::constant(&value, &typ)?)
+                }
             )
         },
 
@@ -476,6 +546,13 @@ irony::op_def! {
                     let values = attrs.print_for_aggregate_constant(env.get_entity(defs[0].1[0].unwrap()).get_dtype().unwrap());
                     format!("{} = hw.aggregate_constant {} : {}", name, values, types)
                 }
+            ),
+            interpret: (
+                |env: &E, state: &mut S, attrs: Vec<(String, AttributeEnum)>, _, defs: Vec<(String, Vec<Option<EntityId>>)>, _| -> Result<(), String> {
+                    let value = irony::utils::extract_vec(&attrs, "attrs").unwrap();
+                    let typ = interpret::dtype(env, defs[0].1[0])?;
+                    interpret::write(state, defs[0].1[0], interpret::constant(&value, &typ)?)
+                }
             )
         },
 
@@ -494,6 +571,12 @@ irony::op_def! {
                     }).collect::<Vec<_>>().join(", ");
                     format!("{} = hw.array_concat {} : {}", rst, operands, sub_typs)
                 }
+            ),
+            interpret: (
+                |_, state: &mut S, _, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _| -> Result<(), String> {
+                    let operands = interpret::read_all(state, &uses[0].1)?;
+                    interpret::write(state, defs[0].1[0], interpret::array_concat(&operands)?)
+                }
             )
         },
 
@@ -510,6 +593,12 @@ irony::op_def! {
                     let sub_typ = env.get_entity(uses[0].1[0].unwrap()).get_dtype().unwrap();
                     format!("{} = hw.array_create {} : {}", rst, operands, sub_typ)
                 }
+            ),
+            interpret: (
+                |_, state: &mut S, _, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _| -> Result<(), String> {
+                    let operands = interpret::read_all(state, &uses[0].1)?;
+                    interpret::write(state, defs[0].1[0], Value::Aggregate(operands))
+                }
             )
         },
 
@@ -526,6 +615,13 @@ irony::op_def! {
                     let index_typ = env.get_entity(uses[1].1[0].unwrap()).get_dtype().unwrap();
                     format!("{} = hw.array_get {}[{}] : {}, {}", rst, array, index, array_typ, index_typ)
                 }
+            ),
+            interpret: (
+                |_, state: &mut S, _, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _| -> Result<(), String> {
+                    let array = interpret::read(state, uses[0].1[0])?;
+                    let index = interpret::read(state, uses[1].1[0])?;
+                    interpret::write(state, defs[0].1[0], interpret::array_get(&array, &index)?)
+                }
             )
         },
 
@@ -542,6 +638,16 @@ irony::op_def! {
                     let new_typ = env.get_entity(defs[0].1[0].unwrap()).get_dtype().unwrap();
                     format!("{} = hw.array_slice {}[{}] : ({}) -> {}", rst, array, index, old_typ, new_typ)
                 }
+            ),
+            interpret: (
+                |env: &E, state: &mut S, _, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _| -> Result<(), String> {
+                    let array = interpret::read(state, uses[0].1[0])?;
+                    let index = interpret::read(state, uses[1].1[0])?;
+                    let DataTypeEnum::Array(ArrayType(_, len)) = interpret::dtype(env, defs[0].1[0])? else {
+                        return Err("array_slice to a non-array type".to_string());
+                    };
+                    interpret::write(state, defs[0].1[0], interpret::array_slice(&array, &index, len)?)
+                }
             )
         },
 
@@ -559,6 +665,12 @@ irony::op_def! {
                     let lhs_ty = env.get_entity(defs[0].1[0].unwrap()).get_dtype().unwrap();
                     format!("{} = hw.struct_create ({}) : {}", lhs, operands, lhs_ty)
                 }
+            ),
+            interpret: (
+                |_, state: &mut S, _, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _| -> Result<(), String> {
+                    let operands = interpret::read_all(state, &uses[0].1)?;
+                    interpret::write(state, defs[0].1[0], Value::Aggregate(operands))
+                }
             )
         },
 
@@ -575,6 +687,14 @@ irony::op_def! {
                     let struct_ty = env.get_entity(uses[0].1[0].unwrap()).get_dtype().unwrap();
                     format!("{} = hw.struct_extract {}[\"{}\"] : {}", lhs, struct_input, field, struct_ty)
                 }
+            ),
+            interpret: (
+                |env: &E, state: &mut S, attrs: Vec<(String, AttributeEnum)>, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _| -> Result<(), String> {
+                    let AttributeEnum::StringAttr(field) = irony::utils::extract_vec(&attrs, "field").unwrap() else { panic!("")};
+                    let index = interpret::field_index(&interpret::dtype(env, uses[0].1[0])?, &field.0)?;
+                    let input = interpret::read(state, uses[0].1[0])?;
+                    interpret::write(state, defs[0].1[0], interpret::struct_get(&input, index)?)
+                }
             )
         },
 
@@ -592,6 +712,15 @@ irony::op_def! {
                     let struct_ty = env.get_entity(uses[0].1[0].unwrap()).get_dtype().unwrap();
                     format!("{} = hw.struct_inject {}[\"{}\"], {} : {}", lhs, struct_input, field, new_value, struct_ty)
                 }
+            ),
+            interpret: (
+                |env: &E, state: &mut S, attrs: Vec<(String, AttributeEnum)>, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _| -> Result<(), String> {
+                    let AttributeEnum::StringAttr(field) = irony::utils::extract_vec(&attrs, "field").unwrap() else { panic!("")};
+                    let index = interpret::field_index(&interpret::dtype(env, uses[0].1[0])?, &field.0)?;
+                    let input = interpret::read(state, uses[0].1[0])?;
+                    let new_value = interpret::read(state, uses[1].1[0])?;
+                    interpret::write(state, defs[0].1[0], interpret::struct_set(&input, index, new_value)?)
+                }
             )
         },
 
@@ -609,6 +738,15 @@ irony::op_def! {
 
                     format!("{} = hw.struct_explode {} : {}", outputs, struct_input, struct_ty)
                 }
+            ),
+            interpret: (
+                |_, state: &mut S, _, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _| -> Result<(), String> {
+                    let input = interpret::read(state, uses[0].1[0])?;
+                    for (index, output) in defs[0].1.iter().enumerate() {
+                        interpret::write(state, *output, interpret::struct_get(&input, index)?)?;
+                    }
+                    Ok(())
+                }
             )
         },
 
@@ -631,6 +769,13 @@ irony::op_def! {
                     let typ = env.get_entity(defs[0].1[0].unwrap()).get_dtype().unwrap();
                     format!("{} = comb.{} {} : {}", def, predicate, uses, typ)
                 }
+            ),
+            interpret: (
+                |_, state: &mut S, attrs: Vec<(String, AttributeEnum)>, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _| -> Result<(), String> {
+                    let AttributeEnum::CombVariadicPredicate(predicate) = irony::utils::extract_vec(&attrs, "predicate").unwrap() else { panic!("")};
+                    let operands = interpret::read_all(state, &uses[0].1)?;
+                    interpret::write(state, defs[0].1[0], interpret::variadic(&predicate, &operands)?)
+                }
             )
         },
         CombBinary: {
@@ -646,6 +791,14 @@ irony::op_def! {
                     let typ = env.get_entity(defs[0].1[0].unwrap()).get_dtype().unwrap();
                     format!("{} = comb.{} {} : {}", def, predicate, uses, typ)
                 }
+            ),
+            interpret: (
+                |_, state: &mut S, attrs: Vec<(String, AttributeEnum)>, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _| -> Result<(), String> {
+                    let AttributeEnum::CombBinaryPredicate(predicate) = irony::utils::extract_vec(&attrs, "predicate").unwrap() else { panic!("")};
+                    let op0 = interpret::read(state, uses[0].1[0])?;
+                    let op1 = interpret::read(state, uses[1].1[0])?;
+                    interpret::write(state, defs[0].1[0], interpret::binary(&predicate, &op0, &op1)?)
+                }
             )
         },
 
@@ -662,6 +815,14 @@ irony::op_def! {
                     let typ = env.get_entity(uses[0].1[0].unwrap()).get_dtype().unwrap();
                     format!("{} = comb.icmp {} {} : {}", def, predicate, inputs, typ)
                 }
+            ),
+            interpret: (
+                |_, state: &mut S, attrs: Vec<(String, AttributeEnum)>, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _| -> Result<(), String> {
+                    let AttributeEnum::CombICmpPredicate(predicate) = irony::utils::extract_vec(&attrs, "predicate").unwrap() else { panic!("")};
+                    let op0 = interpret::read(state, uses[0].1[0])?;
+                    let op1 = interpret::read(state, uses[1].1[0])?;
+                    interpret::write(state, defs[0].1[0], interpret::icmp(&predicate, &op0, &op1)?)
+                }
             )
         },
         // CombParity: {
@@ -688,6 +849,14 @@ irony::op_def! {
 
                     format!("{} = comb.extract {} from {} : ({}) -> {}", lhs, input, low, input_type, lhs_type)
                 }
+            ),
+            interpret: (
+                |env: &E, state: &mut S, attrs: Vec<(String, AttributeEnum)>, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _| -> Result<(), String> {
+                    let AttributeEnum::UIntAttr(UIntAttr(low)) = irony::utils::extract_vec(&attrs, "low").unwrap() else { panic!("")};
+                    let input = interpret::read(state, uses[0].1[0])?;
+                    let width = interpret::dtype(env, defs[0].1[0])?.width();
+                    interpret::write(state, defs[0].1[0], interpret::extract(&input, low as usize, width)?)
+                }
             )
         },
         CombConcat: {
@@ -707,6 +876,12 @@ irony::op_def! {
 
                     format!("{} = comb.concat {} : {}", lhs, operands, op_types)
                 }
+            ),
+            interpret: (
+                |_, state: &mut S, _, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _| -> Result<(), String> {
+                    let operands = interpret::read_all(state, &uses[0].1)?;
+                    interpret::write(state, defs[0].1[0], interpret::concat(&operands)?)
+                }
             )
         },
         // CombReplicate: {
@@ -722,7 +897,7 @@ irony::op_def! {
         CombMux2: {
             defs: [lhs],
             uses: [cond, op0, op1],
-            constraints: [/* TODO: fill this */],
+            constraints: [Mux2Constraint::default().into()],
             print: (
                 |env: &E, _, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _| {
                     let def = env.print_entity(defs[0].1[0].unwrap());
@@ -732,6 +907,13 @@ irony::op_def! {
                     let typ = env.get_entity(defs[0].1[0].unwrap()).get_dtype().unwrap();
                     format!("{} = comb.mux {} : {}", def, uses, typ)
                 }
+            ),
+            interpret: (
+                |_, state: &mut S, _, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _| -> Result<(), String> {
+                    let cond = interpret::read(state, uses[0].1[0])?.as_bool()?;
+                    let value = interpret::read(state, if cond { uses[1].1[0] } else { uses[2].1[0] })?;
+                    interpret::write(state, defs[0].1[0], value)
+                }
             )
         },
         // ------ END: define the operations in `comb` dialect -------
@@ -740,7 +922,7 @@ irony::op_def! {
         SeqCompReg: {
             defs: [output],
             uses: [input, clk,reset,reset_val],
-            constraints: [/* TODO: fill this */],
+            constraints: [CompRegConstraint::default().into()],
             print: (
                 |env: &E, _, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _| {
                     let output_name = env.print_entity(defs[0].1[0].unwrap());
@@ -775,6 +957,17 @@ irony::op_def! {
 
                     format!("{} = seq.compreg {} {} {} {} : {}", output_name, input_name, clk, reset, reset_val, typ)
                 }
+            ),
+            interpret: (
+                |_, state: &mut S, _, uses: Vec<(String, Vec<Option<EntityId>>)>, defs: Vec<(String, Vec<Option<EntityId>>)>, _| -> Result<(), String> {
+                    let reset = uses[2].1[0].map(|x| interpret::read(state, Some(x))).transpose()?;
+                    let next = match reset {
+                        Some(reset) if reset.as_bool()? => interpret::read(state, uses[3].1[0])?,
+                        _ => interpret::read(state, uses[0].1[0])?,
+                    };
+                    state.set_next_value(defs[0].1[0].ok_or("missing output".to_string())?, next);
+                    Ok(())
+                }
             )
         },
 
@@ -831,6 +1024,14 @@ irony::op_def! {
                     let onehot = irony::utils::extract_vec(&attrs, "onehot").unwrap();
                     format!("itprt.cond_check {} {{has_default = {}, onehot = {}}}", conds, has_default, onehot)
                 }
+            ),
+            interpret: (
+                |_, state: &mut S, attrs: Vec<(String, AttributeEnum)>, uses: Vec<(String, Vec<Option<EntityId>>)>, _, _| -> Result<(), String> {
+                    let AttributeEnum::BoolAttr(BoolAttr(has_default)) = irony::utils::extract_vec(&attrs, "has_default").unwrap() else { panic!("")};
+                    let AttributeEnum::BoolAttr(BoolAttr(onehot)) = irony::utils::extract_vec(&attrs, "onehot").unwrap() else { panic!("")};
+                    let conds = interpret::read_all(state, &uses[0].1)?;
+                    interpret::cond_check(&conds, has_default, onehot)
+                }
             )
         },
 
@@ -840,6 +1041,12 @@ irony::op_def! {
     }
 }
 
+impl CombMux2 {
+  /// Operands selected if `cond` is set and if it's not: like `comb.mux` in CIRCT,
+  /// `comb.mux %cond, %op0, %op1` is `cond ? op0 : op1`
+  pub fn branches(&self) -> (Option<EntityId>, Option<EntityId>) { (self.op0, self.op1) }
+}
+
 irony::environ_def! {
     [data_type = DataTypeEnum, attr = AttributeEnum, entity = EntityEnum, op = OpEnum, constraint = ConstraintEnum, pm = PassManager]
     struct CmtIR;
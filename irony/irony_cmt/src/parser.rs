@@ -0,0 +1,1057 @@
+//! Parser of the textual form of `CmtIR`, i.e. what `Environ::print_op` emits for the
+//! modules of a design
+//!
+//! Entities are named by their printed name and scoped by module, so a use may come
+//! before the op defining it. The kind and type of an entity are taken from the op
+//! defining it, or from a typed use if it's never defined. What the printers don't
+//! emit can't be recovered: every entity gets the location of the `parse` call with
+//! `debug` off, the `// %x: location` comments are skipped, clocks are `i1` wires and
+//! every module is `top`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::panic::Location;
+
+use irony::{Entity, Environ, Region};
+
+use crate::*;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+  pub line: usize,
+  pub col: usize,
+  pub message: String,
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}:{}: {}", self.line, self.col, self.message)
+  }
+}
+
+impl std::error::Error for ParseError {}
+
+type ParseResult<T> = Result<T, ParseError>;
+
+impl CmtIR {
+  /// Parse the printed modules of a design
+  #[track_caller]
+  pub fn parse(src: &str) -> ParseResult<CmtIR> {
+    let mut parser = Parser {
+      src,
+      pos: 0,
+      ir: CmtIR::new(),
+      location: Location::caller(),
+      entities: HashMap::new(),
+      modules: HashMap::new(),
+      instances: Vec::new(),
+    };
+    parser.parse_top()?;
+    Ok(parser.ir)
+  }
+}
+
+/// Constant of a `hw.aggregate_constant`, before its type is known
+enum Aggregate {
+  Const(u32),
+  Array(Vec<Aggregate>),
+}
+
+struct Parser<'a> {
+  src: &'a str,
+  pos: usize,
+  ir: CmtIR,
+  location: &'static Location<'static>,
+  /// Entities of the module being parsed, by name
+  entities: HashMap<String, EntityId>,
+  modules: HashMap<String, OpId>,
+  /// Instances with the name of their module, resolved once every module is parsed
+  instances: Vec<(OpId, String, usize)>,
+}
+
+impl<'a> Parser<'a> {
+  fn parse_top(&mut self) -> ParseResult<()> {
+    while !self.at_end() {
+      self.parse_op()?;
+    }
+    for (op, module, pos) in std::mem::take(&mut self.instances) {
+      let Some(target) = self.modules.get(&module).copied() else {
+        self.pos = pos;
+        return Err(self.error(format!("unknown module @{}", module)));
+      };
+      self.ir.get_op_entry(op).and_modify(|op| {
+        if let OpEnum::HwInstance(instance) = op {
+          instance.target_op_id = Some(OpIdAttr(target));
+        }
+      });
+    }
+    Ok(())
+  }
+
+  fn parse_region(&mut self, region: RegionId) -> ParseResult<()> {
+    self.expect("{")?;
+    self.ir.begin_region(Some(region));
+    while !self.peek("}") {
+      if self.at_end() {
+        return Err(self.error("expected `}`".to_string()));
+      }
+      self.parse_op()?;
+    }
+    self.ir.end_region();
+    self.expect("}")
+  }
+
+  fn parse_op(&mut self) -> ParseResult<OpId> {
+    if self.eat("//") {
+      self.expect("hw.input")?;
+      let inputs = self.value_list();
+      self.expect(":")?;
+      let types = self.type_list()?;
+      let inputs = self.define_wires(&inputs, types)?;
+      return Ok(self.ir.add_op(HwInput::new(inputs).into()));
+    }
+
+    let defs = self.value_list();
+    if !defs.is_empty() || self.peek("=") {
+      self.expect("=")?;
+    }
+    self.skip_ws();
+    let start = self.pos;
+    let name = self.op_name()?;
+    let op: OpEnum = match name.as_str() {
+      "hw.module" | "hw.module.extern" => {
+        let is_extern = name == "hw.module.extern";
+        return self.parse_module(is_extern);
+      },
+
+      "stmt.synth" => {
+        let stmt = self.use_value()?;
+        self.expect("into")?;
+        self.expect("protocol")?;
+        self.expect("{")?;
+        self.eat(",");
+        let (mut names, mut events, mut clk) = (Vec::new(), Vec::new(), None);
+        while !self.eat("}") {
+          let name = self.name()?;
+          self.expect(":")?;
+          let value = self.use_value()?;
+          if name == "clk" {
+            clk = value;
+          } else {
+            names.push(StringAttr(name).into());
+            events.push(value);
+          }
+          self.eat(",");
+        }
+        StmtSynth::new(stmt, clk, events, Some(ArrayAttr(names))).into()
+      },
+      "stmt.step" => {
+        let lhs = self.define_stmt(&defs)?;
+        let events = self.use_values();
+        self.expect("{")?;
+        let mut waits = Vec::new();
+        if self.eat("wait-at-exit") {
+          self.expect(":")?;
+          waits = self.use_values();
+        }
+        self.expect("}")?;
+        StmtStep::new(lhs, events, waits).into()
+      },
+      "stmt.seq" => {
+        let lhs = self.define_stmt(&defs)?;
+        StmtSeq::new(lhs, self.use_values()).into()
+      },
+      "stmt.if" => {
+        let lhs = self.define_stmt(&defs)?;
+        let cond = self.use_value()?;
+        self.expect("then")?;
+        self.expect("{")?;
+        let then_stmt = self.use_value()?;
+        self.expect("}")?;
+        let mut else_stmt = None;
+        if self.eat("else") {
+          self.expect("{")?;
+          else_stmt = self.use_value()?;
+          self.expect("}")?;
+        }
+        StmtIf::new(lhs, cond, then_stmt, else_stmt).into()
+      },
+      "stmt.for" => {
+        let lhs = self.define_stmt(&defs)?;
+        self.expect("(")?;
+        let indvar_rd = self.use_value()?;
+        self.expect(",")?;
+        let indvar_wr = self.use_value()?;
+        self.expect(")")?;
+        self.expect("=")?;
+        let (start, const_start) = self.bound()?;
+        let incr = if self.eat("downto") {
+          false
+        } else {
+          self.expect("to")?;
+          true
+        };
+        let (end, const_end) = self.bound()?;
+        self.expect("step")?;
+        let step = self.number()?;
+        self.expect("do")?;
+        let do_stmt = self.use_value()?;
+        StmtFor::new(
+          lhs,
+          indvar_rd,
+          indvar_wr,
+          do_stmt,
+          start,
+          end,
+          Some(incr.into()),
+          const_start,
+          const_end,
+          Some(step.into()),
+        )
+        .into()
+      },
+      "stmt.while" => {
+        let lhs = self.define_stmt(&defs)?;
+        let cond = self.use_value()?;
+        self.expect("do")?;
+        StmtWhile::new(lhs, cond, self.use_value()?).into()
+      },
+      "stmt.par" => {
+        let lhs = self.define_stmt(&defs)?;
+        StmtPar::new(lhs, self.use_values()).into()
+      },
+      "stmt.call" => {
+        let lhs = self.define_stmt(&defs)?;
+        let callee_go = self.use_value()?;
+        self.expect("go")?;
+        let go = self.use_value()?;
+        self.expect("done")?;
+        let done = self.use_value()?;
+        StmtCall::new(lhs, go, done, callee_go).into()
+      },
+
+      "event.signal" => {
+        let event = self.use_value()?;
+        self.expect("===")?;
+        EventSignal::new(event, self.use_value()?).into()
+      },
+      "event.port" => {
+        let event = self.use_value()?;
+        self.expect("===")?;
+        self.expect("[")?;
+        let wires = self.use_values();
+        self.expect("]")?;
+        EventPort::new(event, wires).into()
+      },
+      "event.def" => {
+        let event = self.single_def(&defs)?;
+        EventDef::new(Some(
+          self.define(event, |x| IREvent::new(x.0, x.1, x.2, x.3).into()),
+        ))
+        .into()
+      },
+
+      "ILLEGAL.when" => {
+        let cond = self.use_value()?;
+        let body = self.ir.add_region(Region::new(false));
+        let op = self.ir.add_op(TmpWhen::new(cond, Some(body)).into());
+        self.parse_region(body)?;
+        return Ok(op);
+      },
+      "ILLEGAL.select" => {
+        let lhs = self.single_def(&defs)?;
+        let onehot = if self.eat("onehot") {
+          true
+        } else {
+          self.expect("priority")?;
+          false
+        };
+        self.expect("{")?;
+        let (mut conds, mut values, mut default) = (Vec::new(), Vec::new(), None);
+        while !self.eat("}") {
+          if self.eat("default") {
+            self.expect(":")?;
+            default = self.use_value()?;
+            continue;
+          }
+          let cond = if self.eat("[TBD]") { None } else { self.use_value()? };
+          self.expect(":")?;
+          conds.push(cond);
+          values.push(self.use_value()?);
+          self.eat(",");
+        }
+        self.expect(":")?;
+        let dtype = self.dtype()?;
+        let lhs = self.define_wire(lhs, dtype);
+        TmpSelect::new(Some(lhs), default, conds, values, Some(onehot.into())).into()
+      },
+      "ILLEGAL.not" | "ILLEGAL.neg" => {
+        let lhs = self.single_def(&defs)?;
+        let predicate = match name.as_str() {
+          "ILLEGAL.not" => CombUnaryPredicate::Not,
+          _ => CombUnaryPredicate::Neg,
+        };
+        let op = self.use_value()?;
+        let (lhs, _) = self.typed_result(lhs, &[op])?;
+        TmpUnary::new(lhs, op, Some(predicate)).into()
+      },
+
+      "sv.constantX" => {
+        let lhs = self.single_def(&defs)?;
+        self.expect(":")?;
+        let dtype = self.dtype()?;
+        SvConstantX::new(Some(self.define_wire(lhs, dtype))).into()
+      },
+
+      "hw.wire" => {
+        let lhs = self.single_def(&defs)?;
+        let rhs = self.use_value()?;
+        let (lhs, _) = self.typed_result(lhs, &[rhs])?;
+        Assign::new(lhs, rhs).into()
+      },
+      "hw.instance" => {
+        let instance = self.string()?;
+        self.expect("@")?;
+        let module = self.name()?;
+        self.expect("(")?;
+        let mut inputs = Vec::new();
+        while !self.eat(")") {
+          self.name()?;
+          self.expect(":")?;
+          let input = self.use_value()?;
+          self.expect(":")?;
+          let dtype = self.dtype()?;
+          self.set_type(input, dtype);
+          inputs.push(input);
+          self.eat(",");
+        }
+        self.expect("->")?;
+        let (_, types) = self.ports()?;
+        let outputs = self.define_wires(&defs, types)?;
+        let op = self.ir.add_op(
+          HwInstance::new(
+            outputs,
+            inputs,
+            Some(OpIdAttr(OpId(0))),
+            Some(instance.into()),
+          )
+          .into(),
+        );
+        self.instances.push((op, module, start));
+        return Ok(op);
+      },
+      "hw.output" => {
+        let outputs = self.use_values();
+        self.expect(":")?;
+        let types = self.type_list()?;
+        for (output, dtype) in outputs.iter().zip(types) {
+          self.set_type(*output, dtype);
+        }
+        HwOutput::new(outputs).into()
+      },
+      "hw.bitcast" => {
+        let lhs = self.single_def(&defs)?;
+        let rhs = self.use_value()?;
+        self.expect(":")?;
+        self.expect("(")?;
+        let dtype = self.dtype()?;
+        self.set_type(rhs, dtype);
+        self.expect(")")?;
+        self.expect("->")?;
+        let dtype = self.dtype()?;
+        HwBitCast::new(Some(self.define_wire(lhs, dtype)), rhs).into()
+      },
+      "hw.constant" => {
+        let lhs = self.single_def(&defs)?;
+        let value = self.number()?;
+        self.expect(":")?;
+        let dtype = self.dtype()?;
+        let value = ConstantAttr(bits(value, dtype.width()));
+        HwConstant::new(Some(self.define_wire(lhs, dtype)), Some(value)).into()
+      },
+      "hw.aggregate_constant" => {
+        let lhs = self.single_def(&defs)?;
+        let value = self.aggregate()?;
+        self.expect(":")?;
+        let dtype = self.dtype()?;
+        let AttributeEnum::ArrayAttr(value) = self.aggregate_attr(value, &dtype)? else {
+          return Err(
+            self.error("aggregate constant of a non-aggregate type".to_string()),
+          );
+        };
+        HwAggregateConstant::new(Some(self.define_wire(lhs, dtype)), Some(value)).into()
+      },
+      "hw.array_concat" => {
+        let lhs = self.single_def(&defs)?;
+        let operands = self.use_values();
+        self.expect(":")?;
+        let types = self.type_list()?;
+        let mut len = 0;
+        let mut element = None;
+        for (operand, dtype) in operands.iter().zip(types) {
+          let DataTypeEnum::Array(ArrayType(x, n)) = dtype.to_owned() else {
+            return Err(self.error(format!("array_concat of non-array type {}", dtype)));
+          };
+          len += n;
+          element = Some(x);
+          self.set_type(*operand, dtype);
+        }
+        let dtype = match element {
+          Some(element) => DataTypeEnum::Array(ArrayType(element, len)),
+          None => return Err(self.error("array_concat of no operand".to_string())),
+        };
+        HwArrayConcat::new(Some(self.define_wire(lhs, dtype)), operands).into()
+      },
+      "hw.array_create" => {
+        let lhs = self.single_def(&defs)?;
+        let operands = self.use_values();
+        self.expect(":")?;
+        let element = self.dtype()?;
+        for operand in operands.iter() {
+          self.set_type(*operand, element.to_owned());
+        }
+        let dtype = DataTypeEnum::Array(ArrayType(Box::new(element), operands.len()));
+        HwArrayCreate::new(Some(self.define_wire(lhs, dtype)), operands).into()
+      },
+      "hw.array_get" => {
+        let lhs = self.single_def(&defs)?;
+        let (array, index) = self.indexed()?;
+        self.expect(":")?;
+        let array_type = self.dtype()?;
+        self.expect(",")?;
+        let dtype = self.dtype()?;
+        self.set_type(index, dtype);
+        let DataTypeEnum::Array(ArrayType(element, _)) = array_type.to_owned() else {
+          return Err(self.error(format!("array_get of non-array type {}", array_type)));
+        };
+        self.set_type(array, array_type);
+        HwArrayGet::new(Some(self.define_wire(lhs, *element)), array, index).into()
+      },
+      "hw.array_slice" => {
+        let lhs = self.single_def(&defs)?;
+        let (array, index) = self.indexed()?;
+        self.expect(":")?;
+        self.expect("(")?;
+        let dtype = self.dtype()?;
+        self.set_type(array, dtype);
+        self.expect(")")?;
+        self.expect("->")?;
+        let dtype = self.dtype()?;
+        HwArraySlice::new(Some(self.define_wire(lhs, dtype)), array, index).into()
+      },
+      "hw.struct_create" => {
+        let lhs = self.single_def(&defs)?;
+        self.expect("(")?;
+        let operands = self.use_values();
+        self.expect(")")?;
+        self.expect(":")?;
+        let dtype = self.dtype()?;
+        HwStructCreate::new(Some(self.define_wire(lhs, dtype)), operands).into()
+      },
+      "hw.struct_extract" => {
+        let lhs = self.single_def(&defs)?;
+        let input = self.use_value()?;
+        let field = self.field()?;
+        self.expect(":")?;
+        let dtype = self.dtype()?;
+        let field_type = self.field_type(&dtype, &field)?;
+        self.set_type(input, dtype);
+        HwStructExtract::new(
+          Some(self.define_wire(lhs, field_type)),
+          input,
+          Some(field.into()),
+        )
+        .into()
+      },
+      "hw.struct_inject" => {
+        let lhs = self.single_def(&defs)?;
+        let input = self.use_value()?;
+        let field = self.field()?;
+        self.expect(",")?;
+        let new_value = self.use_value()?;
+        self.expect(":")?;
+        let dtype = self.dtype()?;
+        self.set_type(new_value, self.field_type(&dtype, &field)?);
+        self.set_type(input, dtype.to_owned());
+        HwStructInject::new(
+          Some(self.define_wire(lhs, dtype)),
+          input,
+          new_value,
+          Some(field.into()),
+        )
+        .into()
+      },
+      "hw.struct_explode" => {
+        let input = self.use_value()?;
+        self.expect(":")?;
+        let dtype = self.dtype()?;
+        let DataTypeEnum::Struct(StructType(fields)) = dtype.to_owned() else {
+          return Err(self.error(format!("struct_explode of non-struct type {}", dtype)));
+        };
+        self.set_type(input, dtype);
+        let types = fields.into_iter().map(|(_, x)| *x).collect();
+        HwStructExplode::new(self.define_wires(&defs, types)?, input).into()
+      },
+
+      "comb.icmp" => {
+        let lhs = self.single_def(&defs)?;
+        let predicate = self.name()?;
+        let Some(predicate) = icmp_predicate(&predicate) else {
+          return Err(self.error(format!("unknown icmp predicate `{}`", predicate)));
+        };
+        let op0 = self.use_value()?;
+        self.expect(",")?;
+        let op1 = self.use_value()?;
+        self.expect(":")?;
+        let dtype = self.dtype()?;
+        self.set_type(op0, dtype.to_owned());
+        self.set_type(op1, dtype);
+        let lhs = self.define_wire(lhs, DataTypeEnum::UInt(1.into()));
+        CombICmp::new(Some(lhs), op0, op1, Some(predicate)).into()
+      },
+      "comb.extract" => {
+        let lhs = self.single_def(&defs)?;
+        let input = self.use_value()?;
+        self.expect("from")?;
+        let low = self.number()?;
+        self.expect(":")?;
+        self.expect("(")?;
+        let dtype = self.dtype()?;
+        self.set_type(input, dtype);
+        self.expect(")")?;
+        self.expect("->")?;
+        let dtype = self.dtype()?;
+        let lhs = self.define_wire(lhs, dtype);
+        CombExtract::new(Some(lhs), input, Some(low.into())).into()
+      },
+      "comb.concat" => {
+        let lhs = self.single_def(&defs)?;
+        let operands = self.use_values();
+        self.expect(":")?;
+        let types = self.type_list()?;
+        let width = types.iter().map(|x| x.width()).sum::<usize>();
+        for (operand, dtype) in operands.iter().zip(types) {
+          self.set_type(*operand, dtype);
+        }
+        let lhs = self.define_wire(lhs, DataTypeEnum::UInt(width.into()));
+        CombConcat::new(Some(lhs), operands).into()
+      },
+      "comb.mux" => {
+        let lhs = self.single_def(&defs)?;
+        let operands = self.use_values();
+        let [cond, op0, op1] = operands[..] else {
+          return Err(self.error("comb.mux takes 3 operands".to_string()));
+        };
+        let (lhs, _) = self.typed_result(lhs, &[op0, op1])?;
+        self.set_type(cond, DataTypeEnum::UInt(1.into()));
+        CombMux2::new(lhs, cond, op0, op1).into()
+      },
+      x if x.starts_with("comb.") => {
+        let lhs = self.single_def(&defs)?;
+        let predicate = &x["comb.".len()..];
+        let operands = self.use_values();
+        let (lhs, _) = self.typed_result(lhs, &operands)?;
+        if let Some(predicate) = variadic_predicate(predicate) {
+          CombVariadic::new(lhs, operands, Some(predicate)).into()
+        } else if let Some(predicate) = binary_predicate(predicate) {
+          let [op0, op1] = operands[..] else {
+            return Err(self.error(format!("comb.{} takes 2 operands", predicate)));
+          };
+          CombBinary::new(lhs, op0, op1, Some(predicate)).into()
+        } else {
+          self.pos = start;
+          return Err(self.error(format!("unknown op `{}`", x)));
+        }
+      },
+
+      "seq.compreg" => {
+        let output = self.single_def(&defs)?;
+        let input = self.use_value()?;
+        let mut uses = Vec::new();
+        while self.eat(",") {
+          uses.push(self.use_value()?);
+        }
+        let (output, _) = self.typed_result(output, &[input])?;
+        let mut uses = uses.into_iter();
+        let clk = uses.next().flatten();
+        if clk.is_none() {
+          return Err(self.error("seq.compreg without clock".to_string()));
+        }
+        let (reset, reset_val) = (uses.next().flatten(), uses.next().flatten());
+        SeqCompReg::new(output, input, clk, reset, reset_val).into()
+      },
+
+      "itprt.cond_check" => {
+        let conds = self.use_values();
+        self.expect("{")?;
+        self.expect("has_default")?;
+        self.expect("=")?;
+        let has_default = self.number()? != 0;
+        self.expect(",")?;
+        self.expect("onehot")?;
+        self.expect("=")?;
+        let onehot = self.number()? != 0;
+        self.expect("}")?;
+        ItprtCondCheck::new(conds, Some(has_default.into()), Some(onehot.into())).into()
+      },
+
+      x => {
+        self.pos = start;
+        return Err(self.error(format!("unknown op `{}`", x)));
+      },
+    };
+    Ok(self.ir.add_op(op))
+  }
+
+  fn parse_module(&mut self, is_extern: bool) -> ParseResult<OpId> {
+    self.expect("@")?;
+    let name = self.name()?;
+    if self.modules.contains_key(&name) {
+      return Err(self.error(format!("redefinition of module @{}", name)));
+    }
+    self.expect("(")?;
+    let (mut arg_names, mut arg_types) = (Vec::new(), Vec::new());
+    while !self.eat(")") {
+      arg_names.push(StringAttr(self.value()?).into());
+      self.expect(":")?;
+      arg_types.push(TypeAttr(self.dtype()?).into());
+      self.eat(",");
+    }
+    self.expect("->")?;
+    let (output_names, output_types) = self.ports()?;
+
+    let body = self.ir.add_region(Region::new(true));
+    let op = self.ir.add_op(
+      HwModule::new(
+        Some(name.to_owned().into()),
+        Some(is_extern.into()),
+        Some(true.into()),
+        Some(ArrayAttr(arg_names)),
+        Some(ArrayAttr(arg_types)),
+        Some(ArrayAttr(output_names.into_iter().map(|x| StringAttr(x).into()).collect())),
+        Some(ArrayAttr(output_types.into_iter().map(|x| TypeAttr(x).into()).collect())),
+        Some(body),
+      )
+      .into(),
+    );
+    self.modules.insert(name, op);
+    if !is_extern {
+      self.entities.clear();
+      self.parse_region(body)?;
+    }
+    Ok(op)
+  }
+
+  // ------ entities ------
+
+  /// Entity of a used name, which is a wire without type until it's defined
+  fn entity(&mut self, name: &str) -> EntityId {
+    if let Some(id) = self.entities.get(name) {
+      return *id;
+    }
+    let id = self.ir.add_entity(
+      IRWire::new(
+        None,
+        Some(name.into()),
+        Some(false.into()),
+        Some(self.location.into()),
+      )
+      .into(),
+    );
+    self.entities.insert(name.to_string(), id);
+    id
+  }
+
+  /// (Re)define the entity of `name` in the current region, with `f` building it
+  /// from its type, name, debug and location attributes
+  fn define<F>(&mut self, name: &str, f: F) -> EntityId
+  where F: FnOnce(
+      (Option<DataTypeEnum>, Option<StringAttr>, Option<BoolAttr>, Option<LocationAttr>),
+    ) -> EntityEnum {
+    let id = self.entity(name);
+    let entity = f((
+      None,
+      Some(name.into()),
+      Some(false.into()),
+      Some(self.location.into()),
+    ));
+    if let Some(parent) = self.ir.get_entity(id).get_parent() {
+      self.ir.get_region_entry(parent).and_modify(|x| x.delete_entity_child(id));
+    }
+    self.ir.get_entity_entry(id).and_modify(|x| *x = entity);
+    self.ir.set_entity_parent(id);
+    id
+  }
+
+  fn define_wire(&mut self, name: &str, dtype: DataTypeEnum) -> EntityId {
+    self.define(name, |x| IRWire::new(Some(dtype), x.1, x.2, x.3).into())
+  }
+
+  fn define_wires(
+    &mut self, names: &[String], types: Vec<DataTypeEnum>,
+  ) -> ParseResult<Vec<Option<EntityId>>> {
+    if names.len() != types.len() {
+      return Err(self.error(format!(
+        "{} values but {} types",
+        names.len(),
+        types.len()
+      )));
+    }
+    Ok(
+      names
+        .iter()
+        .zip(types)
+        .map(|(x, dtype)| Some(self.define_wire(x, dtype)))
+        .collect(),
+    )
+  }
+
+  fn define_stmt(&mut self, defs: &[String]) -> ParseResult<Option<EntityId>> {
+    let lhs = self.single_def(defs)?;
+    Ok(Some(
+      self.define(lhs, |x| IRStmt::new(Some(DataTypeEnum::Void), x.1, x.2, x.3).into()),
+    ))
+  }
+
+  /// Define the result of an op whose type is printed last, and is also the type of
+  /// its `operands`
+  fn typed_result(
+    &mut self, lhs: &str, operands: &[Option<EntityId>],
+  ) -> ParseResult<(Option<EntityId>, DataTypeEnum)> {
+    self.expect(":")?;
+    let dtype = self.dtype()?;
+    for operand in operands {
+      self.set_type(*operand, dtype.to_owned());
+    }
+    Ok((Some(self.define_wire(lhs, dtype.to_owned())), dtype))
+  }
+
+  /// Give a type to a used wire which doesn't have one yet
+  fn set_type(&mut self, id: Option<EntityId>, dtype: DataTypeEnum) {
+    let Some(id) = id else {
+      return;
+    };
+    self.ir.get_entity_entry(id).and_modify(|x| {
+      if let EntityEnum::IRWire(wire) = x {
+        if wire.get_dtype().is_none() {
+          let parent = wire.get_parent();
+          let mut typed = IRWire::new(
+            Some(dtype),
+            wire.name.to_owned(),
+            wire.debug.to_owned(),
+            wire.location.to_owned(),
+          );
+          typed.set_parent(parent);
+          *wire = typed;
+        }
+      }
+    });
+  }
+
+  fn single_def<'b>(&self, defs: &'b [String]) -> ParseResult<&'b str> {
+    match defs {
+      [x] => Ok(x),
+      _ => Err(self.error(format!("expected one result, got {}", defs.len()))),
+    }
+  }
+
+  fn use_value(&mut self) -> ParseResult<Option<EntityId>> {
+    let name = self.value()?;
+    Ok(Some(self.entity(&name)))
+  }
+
+  fn use_values(&mut self) -> Vec<Option<EntityId>> {
+    self.value_list().iter().map(|x| Some(self.entity(x))).collect()
+  }
+
+  // ------ attributes and types ------
+
+  /// Constant or entity bound of a `stmt.for`
+  fn bound(&mut self) -> ParseResult<(Option<EntityId>, Option<UIntAttr>)> {
+    if self.peek("%") {
+      Ok((self.use_value()?, None))
+    } else {
+      Ok((None, Some(self.number()?.into())))
+    }
+  }
+
+  /// `%array[%index]`
+  fn indexed(&mut self) -> ParseResult<(Option<EntityId>, Option<EntityId>)> {
+    let array = self.use_value()?;
+    self.expect("[")?;
+    let index = self.use_value()?;
+    self.expect("]")?;
+    Ok((array, index))
+  }
+
+  /// `["field"]`
+  fn field(&mut self) -> ParseResult<String> {
+    self.expect("[")?;
+    let field = self.string()?;
+    self.expect("]")?;
+    Ok(field)
+  }
+
+  fn field_type(&self, dtype: &DataTypeEnum, field: &str) -> ParseResult<DataTypeEnum> {
+    match dtype {
+      DataTypeEnum::Struct(StructType(fields)) => {
+        match fields.iter().find(|x| x.0 == field) {
+          Some((_, x)) => Ok(*x.to_owned()),
+          None => Err(self.error(format!("no field `{}` in {}", field, dtype))),
+        }
+      },
+      _ => Err(self.error(format!("{} is not a struct type", dtype))),
+    }
+  }
+
+  /// `(name: type, ...)` of module outputs
+  fn ports(&mut self) -> ParseResult<(Vec<String>, Vec<DataTypeEnum>)> {
+    self.expect("(")?;
+    let (mut names, mut types) = (Vec::new(), Vec::new());
+    while !self.eat(")") {
+      names.push(self.name()?);
+      self.expect(":")?;
+      types.push(self.dtype()?);
+      self.eat(",");
+    }
+    Ok((names, types))
+  }
+
+  fn aggregate(&mut self) -> ParseResult<Aggregate> {
+    if !self.eat("[") {
+      let value = self.number()?;
+      self.expect(":")?;
+      self.dtype()?;
+      return Ok(Aggregate::Const(value));
+    }
+    let mut items = Vec::new();
+    while !self.eat("]") {
+      items.push(self.aggregate()?);
+      self.eat(",");
+    }
+    Ok(Aggregate::Array(items))
+  }
+
+  fn aggregate_attr(
+    &self, value: Aggregate, dtype: &DataTypeEnum,
+  ) -> ParseResult<AttributeEnum> {
+    match (value, dtype) {
+      (Aggregate::Const(x), DataTypeEnum::UInt(UIntType(width))) => {
+        Ok(ConstantAttr(bits(x, *width)).into())
+      },
+      (Aggregate::Array(items), DataTypeEnum::Array(ArrayType(element, len)))
+        if items.len() == *len =>
+      {
+        let items = items.into_iter().map(|x| self.aggregate_attr(x, element));
+        Ok(ArrayAttr(items.collect::<ParseResult<_>>()?).into())
+      },
+      (Aggregate::Array(items), DataTypeEnum::Struct(StructType(fields)))
+        if items.len() == fields.len() =>
+      {
+        let items =
+          items.into_iter().zip(fields).map(|(x, (_, y))| self.aggregate_attr(x, y));
+        Ok(ArrayAttr(items.collect::<ParseResult<_>>()?).into())
+      },
+      _ => Err(self.error(format!("constant doesn't match type {}", dtype))),
+    }
+  }
+
+  fn dtype(&mut self) -> ParseResult<DataTypeEnum> {
+    self.skip_ws();
+    if self.eat("!hw.struct") {
+      self.expect("<")?;
+      let mut fields = Vec::new();
+      while !self.eat(">") {
+        let name = self.name()?;
+        self.expect(":")?;
+        fields.push((name, Box::new(self.dtype()?)));
+        self.eat(",");
+      }
+      Ok(DataTypeEnum::Struct(StructType(fields)))
+    } else if self.eat("!hw.array") {
+      self.expect("<")?;
+      let len = self.number()? as usize;
+      // the element type follows the `x` immediately, e.g. `4xi8`
+      if !self.rest().starts_with('x') {
+        return Err(self.error("expected `x`".to_string()));
+      }
+      self.pos += 1;
+      let element = self.dtype()?;
+      self.expect(">")?;
+      Ok(DataTypeEnum::Array(ArrayType(Box::new(element), len)))
+    } else if self.eat("void") {
+      Ok(DataTypeEnum::Void)
+    } else if self.at_uint_type() {
+      self.pos += 1;
+      Ok(DataTypeEnum::UInt((self.number()? as usize).into()))
+    } else {
+      Err(self.error("expected a type".to_string()))
+    }
+  }
+
+  fn type_list(&mut self) -> ParseResult<Vec<DataTypeEnum>> {
+    let mut types = Vec::new();
+    self.skip_ws();
+    while self.at_uint_type() || self.peek("!hw.") || self.peek("void") {
+      types.push(self.dtype()?);
+      if !self.eat(",") {
+        break;
+      }
+      self.skip_ws();
+    }
+    Ok(types)
+  }
+
+  fn at_uint_type(&self) -> bool {
+    let mut chars = self.rest().chars();
+    chars.next() == Some('i') && chars.next().is_some_and(|x| x.is_ascii_digit())
+  }
+
+  // ------ lexing ------
+
+  fn rest(&self) -> &'a str { &self.src[self.pos..] }
+
+  /// Skip whitespace, location comments and MLIR locations, but not `// hw.input`
+  fn skip_ws(&mut self) {
+    loop {
+      let rest = self.rest();
+      let trimmed = rest.trim_start();
+      self.pos += rest.len() - trimmed.len();
+      if trimmed.starts_with("//") && trimmed[2..].trim_start().starts_with('%') {
+        self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+      } else if let Some(location) = trimmed.strip_prefix("loc(\"") {
+        // the file is followed by `":line:col)`
+        let end = location
+          .find('"')
+          .and_then(|x| location[x..].find(')').map(|y| x + y + 1))
+          .unwrap_or(location.len());
+        self.pos += "loc(\"".len() + end;
+      } else {
+        break;
+      }
+    }
+  }
+
+  fn at_end(&mut self) -> bool {
+    self.skip_ws();
+    self.rest().is_empty()
+  }
+
+  fn peek(&mut self, s: &str) -> bool {
+    self.skip_ws();
+    self.rest().starts_with(s)
+  }
+
+  fn eat(&mut self, s: &str) -> bool {
+    let found = self.peek(s);
+    if found {
+      self.pos += s.len();
+    }
+    found
+  }
+
+  fn expect(&mut self, s: &str) -> ParseResult<()> {
+    if self.eat(s) {
+      Ok(())
+    } else {
+      Err(self.error(format!("expected `{}`", s)))
+    }
+  }
+
+  fn take_while(&mut self, f: impl Fn(char) -> bool) -> &'a str {
+    self.skip_ws();
+    let rest = self.rest();
+    let len = rest.find(|x| !f(x)).unwrap_or(rest.len());
+    self.pos += len;
+    &rest[..len]
+  }
+
+  fn name(&mut self) -> ParseResult<String> {
+    let name = self.take_while(|x| x.is_alphanumeric() || "_.$".contains(x));
+    if name.is_empty() {
+      return Err(self.error("expected a name".to_string()));
+    }
+    Ok(name.to_string())
+  }
+
+  fn op_name(&mut self) -> ParseResult<String> {
+    let name = self.take_while(|x| x.is_alphanumeric() || "_.".contains(x));
+    if name.is_empty() {
+      return Err(self.error("expected an op".to_string()));
+    }
+    Ok(name.to_string())
+  }
+
+  /// `%name`, without the `%`
+  fn value(&mut self) -> ParseResult<String> {
+    self.expect("%")?;
+    self.name()
+  }
+
+  /// Comma-separated values, possibly none
+  fn value_list(&mut self) -> Vec<String> {
+    let mut values = Vec::new();
+    while self.peek("%") {
+      let start = self.pos;
+      match self.value() {
+        Ok(x) => values.push(x),
+        Err(_) => {
+          self.pos = start;
+          break;
+        },
+      }
+      if !self.eat(",") {
+        break;
+      }
+    }
+    values
+  }
+
+  fn number(&mut self) -> ParseResult<u32> {
+    let digits = self.take_while(|x| x.is_ascii_digit());
+    digits.parse().map_err(|_| self.error("expected a number".to_string()))
+  }
+
+  fn string(&mut self) -> ParseResult<String> {
+    self.expect("\"")?;
+    let s = self.rest();
+    let Some(end) = s.find('"') else {
+      return Err(self.error("unterminated string".to_string()));
+    };
+    self.pos += end + 1;
+    Ok(s[..end].to_string())
+  }
+
+  fn error(&self, message: String) -> ParseError {
+    let before = &self.src[..self.pos];
+    let line = before.matches('\n').count() + 1;
+    let col = before.len() - before.rfind('\n').map_or(0, |x| x + 1) + 1;
+    ParseError { line, col, message }
+  }
+}
+
+/// Little-endian bits of `value`, padded to `width`
+fn bits(value: u32, width: usize) -> Vec<bool> {
+  let mut bits = irony::utils::arith::from_u32_to_bits(value);
+  if bits.len() < width {
+    bits.resize(width, false);
+  }
+  bits
+}
+
+fn variadic_predicate(s: &str) -> Option<CombVariadicPredicate> {
+  use CombVariadicPredicate::*;
+  [Add, Mul, And, Or, Xor].into_iter().find(|x| x.get_str() == s)
+}
+
+fn binary_predicate(s: &str) -> Option<CombBinaryPredicate> {
+  use CombBinaryPredicate::*;
+  [DivU, DivS, ModU, ModS, Shl, ShrU, ShrS, Sub].into_iter().find(|x| x.get_str() == s)
+}
+
+fn icmp_predicate(s: &str) -> Option<CombICmpPredicate> {
+  use CombICmpPredicate::*;
+  [EQ, NE, SLT, SLE, SGT, SGE, ULT, ULE, UGT, UGE, CEQ, CNE, WEQ, WNE]
+    .into_iter()
+    .find(|x| x.get_str() == s)
+}
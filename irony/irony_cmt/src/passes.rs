@@ -1,14 +1,90 @@
-use core::panic;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::Hasher;
+use std::path::PathBuf;
+use std::time::Instant;
 
-use irony::{Entity, Environ, Op, OpId, PassManagerTrait, PassTrait};
+use irony::indexmap::map::Entry;
+use irony::{
+  AnalysisManager, DefUse, Entity, EntityId, Environ, FxHasher, Id, IronyError,
+  IronyResult, Op, OpId, PassManagerTrait, PassPipeline, PassRegistryTrait,
+  PassStatistics, PassTrait, PreservedAnalyses, ReducerTrait, RegionId,
+};
 
+use crate::interpret::{constant, evaluate};
 use crate::{
-  Assign, AttributeEnum, CombBinaryPredicate, CombMux2, CombVariadicPredicate,
-  ConstantAttr, EntityEnum, EventSignal, HwConstant, IRWire, OpEnum, StringAttr,
-  SvConstantX, TmpSelect, TmpUnary, TmpWhen,
+  Assign, AttributeEnum, BoolAttr, CombBinary, CombBinaryPredicate, CombConcat,
+  CombDependencies, CombExtract, CombICmp, CombICmpPredicate, CombMux2, CombVariadic,
+  CombVariadicPredicate, ConstantAttr, DataTypeEnum, EntityEnum, EventSignal, HwConstant,
+  HwInstance, HwModule, IRWire, InstanceHierarchy, LocationAttr, OpEnum, OpIdAttr,
+  StringAttr, SvConstantX, SymbolTable, TmpSelect, TmpUnary, TmpWhen, TopoOrder,
+  UIntAttr, UIntType, Value,
 };
 
+fn module_region<E: Environ>(env: &E, op: OpId) -> IronyResult<RegionId> {
+  env.get_op(op).get_regions()[0].1[0]
+    .ok_or_else(|| IronyError::new("module must have a body").with_op(op))
+}
+
+fn required(entity: Option<EntityId>, op: OpId, what: &str) -> IronyResult<EntityId> {
+  entity.ok_or_else(|| IronyError::new(format!("missing {}", what)).with_op(op))
+}
+
+/// Replace the children of `region`, including the ops and entities added by a pass,
+/// which `Environ::add_op` and `Environ::add_entity` attach to the innermost open
+/// region instead
+fn set_region_children<E: Environ>(
+  env: &mut E, region: RegionId, ops: Vec<OpId>, entities: Vec<EntityId>,
+) {
+  for op in ops.iter() {
+    env.get_op_entry(*op).and_modify(|op| op.set_parent(Some(region)));
+  }
+  for entity in entities.iter() {
+    env.get_entity_entry(*entity).and_modify(|entity| entity.set_parent(Some(region)));
+  }
+  env.get_region_entry(region).and_modify(|region| {
+    region.op_children = ops;
+    region.entity_children = entities;
+  });
+}
+
+/// Name, debug flag and location of `wire`, inherited by the wires derived from it
+fn wire_attrs<E>(
+  env: &E, wire: EntityId, op: OpId,
+) -> IronyResult<(String, BoolAttr, LocationAttr)>
+where E: Environ<EntityT = EntityEnum> {
+  let entity = env.get_entity(wire);
+  match (entity.get_attr("name"), entity.get_attr("debug"), entity.get_attr("location")) {
+    (
+      Some(AttributeEnum::StringAttr(StringAttr(name))),
+      Some(AttributeEnum::BoolAttr(debug)),
+      Some(AttributeEnum::LocationAttr(location)),
+    ) => Ok((name, debug, location)),
+    _ => Err(
+      IronyError::new("must be a wire with a name, a debug flag and a location")
+        .with_op(op)
+        .with_entity(env, wire),
+    ),
+  }
+}
+
+fn is_debug<E: Environ<EntityT = EntityEnum>>(env: &E, entity: EntityId) -> bool {
+  matches!(
+    env.get_entity(entity),
+    EntityEnum::IRWire(IRWire { debug: Some(BoolAttr(true)), .. })
+  )
+}
+
+/// Replace an op by `new`, which takes its id and its place in the region
+fn replace_op<E: Environ<OpT = OpEnum>>(env: &mut E, op_id: OpId, new: OpEnum) {
+  env.get_op_entry(op_id).and_modify(|op| {
+    let parent = op.get_parent();
+    *op = new;
+    op.set_id(op_id.id());
+    op.set_parent(parent);
+  });
+}
+
 #[derive(Debug, Clone)]
 pub struct ReorderPass;
 
@@ -16,10 +92,23 @@ impl Into<PassEnum> for ReorderPass {
   fn into(self) -> PassEnum { PassEnum::ReorderPass(self) }
 }
 
-impl PassTrait<(), ()> for ReorderPass {
+impl PassTrait<(), IronyError> for ReorderPass {
   type EntityT = EntityEnum;
   type OpT = OpEnum;
 
+  fn name(&self) -> &'static str { "reorder" }
+
+  fn description(&self) -> &'static str {
+    "Move inputs and events to the front of modules, and outputs to the back"
+  }
+
+  fn preserved_analyses(&self) -> PreservedAnalyses {
+    PreservedAnalyses::none()
+      .preserve::<DefUse>()
+      .preserve::<InstanceHierarchy>()
+      .preserve::<CombDependencies>()
+  }
+
   fn check_op<E>(&self, env: &E, op: OpId) -> bool
   where E: Environ<EntityT = Self::EntityT, OpT = Self::OpT> {
     match env.get_op(op) {
@@ -28,9 +117,11 @@ impl PassTrait<(), ()> for ReorderPass {
     }
   }
 
-  fn run_raw<E>(&self, env: &mut E, op: OpId) -> Result<(), ()>
+  fn run_raw<E>(
+    &self, env: &mut E, _analyses: &mut AnalysisManager, op: OpId,
+  ) -> IronyResult<()>
   where E: Environ<EntityT = Self::EntityT, OpT = Self::OpT> {
-    let region = env.get_op(op).get_regions()[0].1[0].expect("must have region");
+    let region = module_region(env, op)?;
 
     let included = env.get_region(region).op_children.to_owned();
     let mut head = Vec::new();
@@ -69,10 +160,20 @@ impl Into<PassEnum> for RemoveEventPass {
   fn into(self) -> PassEnum { PassEnum::RemoveEventPass(self) }
 }
 
-impl PassTrait<(), ()> for RemoveEventPass {
+impl PassTrait<(), IronyError> for RemoveEventPass {
   type EntityT = EntityEnum;
   type OpT = OpEnum;
 
+  fn name(&self) -> &'static str { "remove-event" }
+
+  fn description(&self) -> &'static str {
+    "Replace events with their source signals, and move the bodies of whens into modules"
+  }
+
+  fn preserved_analyses(&self) -> PreservedAnalyses {
+    PreservedAnalyses::none().preserve::<InstanceHierarchy>()
+  }
+
   fn check_op<E>(&self, env: &E, op: OpId) -> bool
   where E: Environ<EntityT = Self::EntityT, OpT = Self::OpT> {
     match env.get_op(op) {
@@ -81,13 +182,14 @@ impl PassTrait<(), ()> for RemoveEventPass {
     }
   }
 
-  fn run_raw<E>(&self, env: &mut E, op: OpId) -> Result<(), ()>
+  fn run_raw<E>(
+    &self, env: &mut E, _analyses: &mut AnalysisManager, op: OpId,
+  ) -> IronyResult<()>
   where E: Environ<EntityT = Self::EntityT, OpT = Self::OpT> {
     let mut event_signal_mapping: HashMap<irony::EntityId, Vec<irony::EntityId>> =
       HashMap::new();
     let mut wire_guarded_table = HashMap::new();
-    let mut wire_to_be_selected_table = HashMap::new();
-    let region = env.get_op(op).get_regions()[0].1[0].expect("must have region");
+    let region = module_region(env, op)?;
     let included = env.get_region(region).op_children.to_owned();
     let mut new_included = Vec::new();
 
@@ -104,14 +206,6 @@ impl PassTrait<(), ()> for RemoveEventPass {
             .or_insert(Vec::new())
             .push(signal.to_owned());
         },
-        OpEnum::TmpSelect(TmpSelect { conds, values, .. }) => {
-          for (cond, value) in conds.iter().zip(values.iter()) {
-            let value_id = value.unwrap().to_owned();
-            if let None = cond.to_owned() {
-              *wire_to_be_selected_table.entry(value_id).or_insert(0) += 1;
-            }
-          }
-        },
         _ => {},
       }
     }
@@ -125,13 +219,14 @@ impl PassTrait<(), ()> for RemoveEventPass {
           !defs.is_empty()
         })
         .collect();
-      assert!(
-        source_signals.len() != 0,
-        "{:?}({:?}) doesn't have source signals, which control synthesis should provide",
-        event,
-        env.get_entity(event).get_attr("name").unwrap()
-      );
-      assert!(source_signals.len() == 1, "event must have at most one source signal");
+      if source_signals.len() != 1 {
+        let message = if source_signals.is_empty() {
+          "event doesn't have source signals, which control synthesis should provide"
+        } else {
+          "event must have at most one source signal"
+        };
+        return Err(IronyError::new(message).with_op(op).with_entity(env, event));
+      }
       let source_signal = source_signals[0].to_owned();
       let event_uses = env.get_uses(event.to_owned());
       for event_use in event_uses {
@@ -160,44 +255,18 @@ impl PassTrait<(), ()> for RemoveEventPass {
         },
         OpEnum::TmpWhen(TmpWhen { cond: Some(cond), body: Some(body), .. }) => {
           let body = env.get_region(body.to_owned()).op_children.to_owned();
+          // the guarded ops are hoisted, and the wires they define are selected with
+          // `cond` by the selects which don't give their conditions
           for op_id in body {
-            let defs = env
-              .get_op(op_id)
-              .get_defs()
-              .into_iter()
-              .flat_map(|(_, v)| v.into_iter().filter_map(|x| x.map(|x| x.to_owned())))
-              .collect::<Vec<_>>();
-
-            let mut only_to_be_selected = true;
-            for def in defs {
-              wire_guarded_table.insert(def.to_owned(), cond.to_owned());
-              // let times_be_used = env.get_uses(def.to_owned()).len();
-
-              let times_be_used_outside = 0;
-              // for use_id in env.get_uses(def.to_owned()) {
-              //   let use_op = env.get_op(use_id);
-              //   if use_op.get_regions().len() == 0 {
-              //     times_be_used_outside += 1;
-              //   }
-              // }
-
-              let times_to_be_selected = wire_to_be_selected_table
-                .get(&def)
-                .to_owned()
-                .map(|x| x.to_owned())
-                .unwrap_or(0);
-
-              only_to_be_selected &= times_be_used_outside == times_to_be_selected;
-            }
-
-            if only_to_be_selected {
-              new_included.push(op_id.to_owned());
-              env.get_op_entry(op_id).and_modify(|op| {
-                op.set_parent(Some(region));
-              });
-            } else {
-              // panic!("defs in when body must be only used by select or operations in the same when")
+            for (_, defs) in env.get_op(op_id).get_defs() {
+              for def in defs.into_iter().flatten() {
+                wire_guarded_table.insert(def, cond.to_owned());
+              }
             }
+            new_included.push(op_id.to_owned());
+            env.get_op_entry(op_id).and_modify(|op| {
+              op.set_parent(Some(region));
+            });
           }
 
           env.delete_op(op_id.to_owned());
@@ -214,17 +283,21 @@ impl PassTrait<(), ()> for RemoveEventPass {
         OpEnum::TmpSelect(TmpSelect { conds, values, .. }) => {
           let mut new_conds = Vec::new();
           for (old_cond, value) in conds.iter().zip(values.iter()) {
-            let value_id = value.unwrap().to_owned();
+            let value_id = required(*value, *op_id, "value of select")?;
             if let None = old_cond.to_owned() {
-              let cond_id = wire_guarded_table
-                .get(&value_id)
-                .expect("value to be selected must be guarded")
-                .to_owned();
-              let cond = env.get_entity(cond_id).to_owned();
-              let signal_id = match cond {
-                EntityEnum::IRWire(_) => cond_id,
+              let cond_id = wire_guarded_table.get(&value_id).to_owned().ok_or_else(|| {
+                IronyError::new("value to be selected must be guarded")
+                  .with_op(*op_id)
+                  .with_entity(env, value_id)
+              })?;
+              let signal_id = match env.get_entity(*cond_id) {
+                EntityEnum::IRWire(_) => *cond_id,
                 _ => {
-                  panic!()
+                  return Err(
+                    IronyError::new("guard of a selected value must be a wire")
+                      .with_op(*op_id)
+                      .with_entity(env, *cond_id),
+                  );
                 },
               };
               new_conds.push(Some(signal_id));
@@ -260,10 +333,20 @@ impl Into<PassEnum> for RemoveSelectPass {
   fn into(self) -> PassEnum { PassEnum::RemoveSelectPass(self) }
 }
 
-impl PassTrait<(), ()> for RemoveSelectPass {
+impl PassTrait<(), IronyError> for RemoveSelectPass {
   type EntityT = EntityEnum;
   type OpT = OpEnum;
 
+  fn name(&self) -> &'static str { "remove-select" }
+
+  fn description(&self) -> &'static str {
+    "Lower selects to chains of muxes"
+  }
+
+  fn preserved_analyses(&self) -> PreservedAnalyses {
+    PreservedAnalyses::none().preserve::<InstanceHierarchy>()
+  }
+
   fn check_op<E>(&self, env: &E, op: OpId) -> bool
   where E: Environ<EntityT = Self::EntityT, OpT = Self::OpT> {
     match env.get_op(op) {
@@ -272,9 +355,11 @@ impl PassTrait<(), ()> for RemoveSelectPass {
     }
   }
 
-  fn run_raw<E>(&self, env: &mut E, op: OpId) -> Result<(), ()>
+  fn run_raw<E>(
+    &self, env: &mut E, _analyses: &mut AnalysisManager, op: OpId,
+  ) -> IronyResult<()>
   where E: Environ<EntityT = Self::EntityT, OpT = Self::OpT> {
-    let region = env.get_op(op).get_regions()[0].1[0].expect("must have region");
+    let region = module_region(env, op)?;
     let included = env.get_region(region).op_children.to_owned();
     let mut included_entity = env.get_region(region).entity_children.to_owned();
     let mut new_included_op = Vec::new();
@@ -283,6 +368,7 @@ impl PassTrait<(), ()> for RemoveSelectPass {
       let op = env.get_op(op_id.to_owned()).to_owned();
       match op {
         OpEnum::TmpSelect(TmpSelect { lhs, conds, values, default, onehot, .. }) => {
+          let lhs = required(lhs, *op_id, "result of select")?;
           let onehot = onehot.unwrap_or(false.into()).0;
           if onehot {
             println!("[TODO] support onehot select");
@@ -295,36 +381,11 @@ impl PassTrait<(), ()> for RemoveSelectPass {
             let default = match default {
               Some(default) => default,
               None => {
-                let data_type = env
-                  .get_entity(
-                    values
-                      .first()
-                      .expect("must have at least one value")
-                      .expect("must be Some")
-                      .to_owned(),
-                  )
-                  .get_dtype();
-                let AttributeEnum::StringAttr(StringAttr(name)) = env
-                  .get_entity(lhs.expect("must be Some").to_owned())
-                  .get_attr("name")
-                  .unwrap()
-                else {
-                  panic!()
-                };
-                let AttributeEnum::LocationAttr(location) = env
-                  .get_entity(lhs.expect("must be Some").to_owned())
-                  .get_attr("location")
-                  .unwrap()
-                else {
-                  panic!()
-                };
-                let AttributeEnum::BoolAttr(debug) = env
-                  .get_entity(lhs.expect("must be Some").to_owned())
-                  .get_attr("debug")
-                  .unwrap()
-                else {
-                  panic!()
-                };
+                let first = values.first().copied().flatten().ok_or_else(|| {
+                  IronyError::new("select must have at least one value").with_op(*op_id)
+                })?;
+                let data_type = env.get_entity(first).get_dtype();
+                let (name, debug, location) = wire_attrs(env, lhs, *op_id)?;
 
                 // unimplemented!()
                 let default = env.add_entity(
@@ -360,30 +421,10 @@ impl PassTrait<(), ()> for RemoveSelectPass {
             let mut last = default;
             let mut mux_i = 0;
             for (cond, value) in conds.iter().zip(values.iter()).rev() {
-              let cond = cond.to_owned().expect("must be Some");
-              let value = value.to_owned().expect("must be Some");
+              let cond = required(*cond, *op_id, "condition of select, set by RemoveEventPass")?;
+              let value = required(*value, *op_id, "value of select")?;
               let data_type = env.get_entity(value).get_dtype();
-              let AttributeEnum::StringAttr(StringAttr(name)) = env
-                .get_entity(lhs.expect("must be Some").to_owned())
-                .get_attr("name")
-                .unwrap()
-              else {
-                panic!()
-              };
-              let AttributeEnum::LocationAttr(location) = env
-                .get_entity(lhs.expect("must be Some").to_owned())
-                .get_attr("location")
-                .unwrap()
-              else {
-                panic!()
-              };
-              let AttributeEnum::BoolAttr(debug) = env
-                .get_entity(lhs.expect("must be Some").to_owned())
-                .get_attr("debug")
-                .unwrap()
-              else {
-                panic!()
-              };
+              let (name, debug, location) = wire_attrs(env, lhs, *op_id)?;
 
               let mux_wire = env.add_entity(
                 {
@@ -424,7 +465,7 @@ impl PassTrait<(), ()> for RemoveSelectPass {
             let assign = env.add_op(
               {
                 let mut assign =
-                  Assign::new(Some(lhs.expect("must be Some")), Some(last));
+                  Assign::new(Some(lhs), Some(last));
                 assign.parent = Some(region.to_owned());
                 assign
               }
@@ -441,10 +482,7 @@ impl PassTrait<(), ()> for RemoveSelectPass {
       }
     }
 
-    env.get_region_entry(region).and_modify(|region| {
-      region.op_children = new_included_op;
-      region.entity_children = included_entity;
-    });
+    set_region_children(env, region, new_included_op, included_entity);
 
     Ok(())
   }
@@ -455,10 +493,20 @@ pub struct RemoveUnaryPass;
 impl Into<PassEnum> for RemoveUnaryPass {
   fn into(self) -> PassEnum { PassEnum::RemoveUnaryPass(self) }
 }
-impl PassTrait<(), ()> for RemoveUnaryPass {
+impl PassTrait<(), IronyError> for RemoveUnaryPass {
   type EntityT = EntityEnum;
   type OpT = OpEnum;
 
+  fn name(&self) -> &'static str { "remove-unary" }
+
+  fn description(&self) -> &'static str {
+    "Lower not and neg to xor and sub"
+  }
+
+  fn preserved_analyses(&self) -> PreservedAnalyses {
+    PreservedAnalyses::none().preserve::<InstanceHierarchy>()
+  }
+
   fn check_op<E>(&self, env: &E, op: OpId) -> bool
   where E: Environ<EntityT = Self::EntityT, OpT = Self::OpT> {
     match env.get_op(op) {
@@ -467,9 +515,11 @@ impl PassTrait<(), ()> for RemoveUnaryPass {
     }
   }
 
-  fn run_raw<E>(&self, env: &mut E, op: OpId) -> Result<(), ()>
+  fn run_raw<E>(
+    &self, env: &mut E, _analyses: &mut AnalysisManager, op: OpId,
+  ) -> IronyResult<()>
   where E: Environ<EntityT = Self::EntityT, OpT = Self::OpT> {
-    let region = env.get_op(op).get_regions()[0].1[0].expect("must have region");
+    let region = module_region(env, op)?;
     let included = env.get_region(region).op_children.to_owned();
     let mut new_included_op = Vec::new();
     let mut included_entity = env.get_region(region).entity_children.to_owned();
@@ -478,31 +528,23 @@ impl PassTrait<(), ()> for RemoveUnaryPass {
       let op = env.get_op(op_id.to_owned()).to_owned();
       match op {
         OpEnum::TmpUnary(TmpUnary { lhs, op, predicate, .. }) => {
-          match predicate.expect("unary predicate must be some") {
+          let lhs = required(lhs, *op_id, "result of unary")?;
+          let op = required(op, *op_id, "operand of unary")?;
+          let predicate = predicate.ok_or_else(|| {
+            IronyError::new("missing predicate of unary").with_op(*op_id)
+          })?;
+          let width = env
+            .get_entity(lhs)
+            .get_dtype()
+            .ok_or_else(|| {
+              IronyError::new("unary must be typed").with_op(*op_id).with_entity(env, lhs)
+            })?
+            .width();
+          match predicate {
             crate::CombUnaryPredicate::Not => {
-              let AttributeEnum::StringAttr(StringAttr(name)) = env
-                .get_entity(lhs.expect("must be Some").to_owned())
-                .get_attr("name")
-                .unwrap()
-              else {
-                panic!()
-              };
-              let AttributeEnum::LocationAttr(location) = env
-                .get_entity(lhs.expect("must be Some").to_owned())
-                .get_attr("location")
-                .unwrap()
-              else {
-                panic!()
-              };
-              let AttributeEnum::BoolAttr(debug) = env
-                .get_entity(lhs.expect("must be Some").to_owned())
-                .get_attr("debug")
-                .unwrap()
-              else {
-                panic!()
-              };
+              let (name, debug, location) = wire_attrs(env, lhs, *op_id)?;
               let data_type =
-                env.get_entity(lhs.expect("must be Some").to_owned()).get_dtype();
+                env.get_entity(lhs.to_owned()).get_dtype();
               let one = env.add_entity(
                 {
                   let mut wire = IRWire::new(
@@ -520,7 +562,7 @@ impl PassTrait<(), ()> for RemoveUnaryPass {
               let constant_op = env.add_op(
                 {
                   let mut op =
-                    HwConstant::new(Some(one.to_owned()), Some(ConstantAttr(vec![true])));
+                    HwConstant::new(Some(one.to_owned()), Some(ConstantAttr(vec![true; width])));
                   op.parent = Some(region.to_owned());
                   op
                 }
@@ -530,9 +572,9 @@ impl PassTrait<(), ()> for RemoveUnaryPass {
               let xor_op = env.add_op(
                 {
                   let mut op = crate::CombVariadic::new(
-                    Some(lhs.expect("must be Some").to_owned()),
+                    Some(lhs.to_owned()),
                     vec![
-                      Some(op.expect("must be Some").to_owned()),
+                      Some(op.to_owned()),
                       Some(one.to_owned()),
                     ],
                     Some(CombVariadicPredicate::Xor),
@@ -546,29 +588,9 @@ impl PassTrait<(), ()> for RemoveUnaryPass {
               env.delete_op(op_id.to_owned());
             },
             crate::CombUnaryPredicate::Neg => {
-              let AttributeEnum::StringAttr(StringAttr(name)) = env
-                .get_entity(lhs.expect("must be Some").to_owned())
-                .get_attr("name")
-                .unwrap()
-              else {
-                panic!()
-              };
-              let AttributeEnum::LocationAttr(location) = env
-                .get_entity(lhs.expect("must be Some").to_owned())
-                .get_attr("location")
-                .unwrap()
-              else {
-                panic!()
-              };
-              let AttributeEnum::BoolAttr(debug) = env
-                .get_entity(lhs.expect("must be Some").to_owned())
-                .get_attr("debug")
-                .unwrap()
-              else {
-                panic!()
-              };
+              let (name, debug, location) = wire_attrs(env, lhs, *op_id)?;
               let data_type =
-                env.get_entity(lhs.expect("must be Some").to_owned()).get_dtype();
+                env.get_entity(lhs.to_owned()).get_dtype();
               let zero = env.add_entity(
                 {
                   let mut wire = IRWire::new(
@@ -587,7 +609,7 @@ impl PassTrait<(), ()> for RemoveUnaryPass {
                 {
                   let mut op = HwConstant::new(
                     Some(zero.to_owned()),
-                    Some(ConstantAttr(vec![false])),
+                    Some(ConstantAttr(vec![false; width])),
                   );
                   op.parent = Some(region.to_owned());
                   op
@@ -598,9 +620,9 @@ impl PassTrait<(), ()> for RemoveUnaryPass {
               let sub_op = env.add_op(
                 {
                   let mut op = crate::CombBinary::new(
-                    Some(lhs.expect("must be Some").to_owned()),
+                    Some(lhs.to_owned()),
                     Some(zero.to_owned()),
-                    Some(lhs.expect("must be Some").to_owned()),
+                    Some(op.to_owned()),
                     Some(CombBinaryPredicate::Sub),
                   );
                   op.parent = Some(region.to_owned());
@@ -619,27 +641,829 @@ impl PassTrait<(), ()> for RemoveUnaryPass {
       }
     }
 
-    env.get_region_entry(region).and_modify(|region| {
-      region.op_children = new_included_op;
-      region.entity_children = included_entity;
-    });
+    set_region_children(env, region, new_included_op, included_entity);
 
     Ok(())
   }
 }
 
+#[derive(Debug, Clone)]
+pub struct CanonicalizePass;
+
+impl Into<PassEnum> for CanonicalizePass {
+  fn into(self) -> PassEnum { PassEnum::CanonicalizePass(self) }
+}
+
+/// Bit `i` of a constant of any width, like `|i| i == 0` for one
+type BitPattern = fn(usize) -> bool;
+
+/// Whether `entity` is a constant whose bit `i` is `bit(i)` for every `i`
+fn is_constant(
+  constants: &HashMap<EntityId, Value>, entity: EntityId, bit: BitPattern,
+) -> bool {
+  match constants.get(&entity).map(Value::bits) {
+    Some(Ok(bits)) => bits.iter().enumerate().all(|(i, x)| *x == bit(i)),
+    _ => false,
+  }
+}
+
+impl CanonicalizePass {
+  /// An op computing the same result as a comb op, with its operands folded if they are
+  /// all constants, or with identities simplified
+  fn simplify<E>(
+    env: &E, op_id: OpId, constants: &HashMap<EntityId, Value>,
+  ) -> IronyResult<Option<OpEnum>>
+  where E: Environ<EntityT = EntityEnum, OpT = OpEnum> {
+    let op = env.get_op(op_id);
+    if !matches!(
+      op,
+      OpEnum::CombVariadic(_)
+        | OpEnum::CombBinary(_)
+        | OpEnum::CombICmp(_)
+        | OpEnum::CombMux2(_)
+        | OpEnum::CombExtract(_)
+        | OpEnum::CombConcat(_)
+    ) {
+      return Ok(None);
+    }
+    let lhs = required(op.get_defs()[0].1[0], op_id, "result")?;
+    let dtype = env.get_entity(lhs).get_dtype();
+    let Some(DataTypeEnum::UInt(UIntType(width))) = dtype else {
+      return Ok(None);
+    };
+    let wire = |x: EntityId| Some(Assign::new(Some(lhs), Some(x)).into());
+    let constant =
+      |bits: Vec<bool>| Some(HwConstant::new(Some(lhs), Some(ConstantAttr(bits))).into());
+
+    let uses =
+      op.get_uses().into_iter().flat_map(|(_, x)| x).flatten().collect::<Vec<_>>();
+    if uses.iter().all(|x| constants.contains_key(x)) {
+      let operands = uses.iter().map(|x| (*x, constants[x].to_owned())).collect();
+      let mut results =
+        evaluate(env, op_id, operands).map_err(|x| IronyError::new(x).with_op(op_id))?;
+      if let Some(Value::Bits(bits)) = results.remove(&lhs) {
+        return Ok(constant(bits));
+      }
+    }
+
+    Ok(match op {
+      OpEnum::CombVariadic(CombVariadic {
+        operands, predicate: Some(predicate), ..
+      }) => {
+        // bits of the identity and of the absorbing element
+        let (identity, absorbing): (BitPattern, Option<BitPattern>) = match predicate {
+          CombVariadicPredicate::Add | CombVariadicPredicate::Xor => (|_| false, None),
+          CombVariadicPredicate::Or => (|_| false, Some(|_| true)),
+          CombVariadicPredicate::And => (|_| true, Some(|_| false)),
+          CombVariadicPredicate::Mul => (|i| i == 0, Some(|_| false)),
+        };
+        let operands = operands.iter().flatten().copied().collect::<Vec<_>>();
+        if let Some(absorbing) = absorbing {
+          if operands.iter().any(|x| is_constant(constants, *x, absorbing)) {
+            return Ok(constant((0..width).map(absorbing).collect()));
+          }
+        }
+        let mut kept = vec![];
+        for operand in operands.iter() {
+          let idempotent =
+            matches!(predicate, CombVariadicPredicate::And | CombVariadicPredicate::Or);
+          if !is_constant(constants, *operand, identity)
+            && !(idempotent && kept.contains(operand))
+          {
+            kept.push(*operand);
+          }
+        }
+        match kept.len() {
+          _ if kept.len() == operands.len() => None,
+          0 => constant((0..width).map(identity).collect()),
+          1 => wire(kept[0]),
+          _ => Some(
+            CombVariadic::new(
+              Some(lhs),
+              kept.into_iter().map(Some).collect(),
+              Some(predicate.to_owned()),
+            )
+            .into(),
+          ),
+        }
+      },
+      OpEnum::CombBinary(CombBinary {
+        op0: Some(op0),
+        op1: Some(op1),
+        predicate: Some(predicate),
+        ..
+      }) => match predicate {
+        CombBinaryPredicate::Sub if op0 == op1 => constant(vec![false; width]),
+        CombBinaryPredicate::Sub
+        | CombBinaryPredicate::Shl
+        | CombBinaryPredicate::ShrU
+        | CombBinaryPredicate::ShrS
+          if is_constant(constants, *op1, |_| false) =>
+        {
+          wire(*op0)
+        },
+        CombBinaryPredicate::DivU | CombBinaryPredicate::DivS
+          if is_constant(constants, *op1, |i| i == 0) =>
+        {
+          wire(*op0)
+        },
+        _ => None,
+      },
+      OpEnum::CombICmp(CombICmp {
+        op0: Some(op0),
+        op1: Some(op1),
+        predicate: Some(predicate),
+        ..
+      }) if op0 == op1 => match predicate {
+        CombICmpPredicate::EQ
+        | CombICmpPredicate::SLE
+        | CombICmpPredicate::SGE
+        | CombICmpPredicate::ULE
+        | CombICmpPredicate::UGE
+        | CombICmpPredicate::CEQ
+        | CombICmpPredicate::WEQ => constant(vec![true]),
+        _ => constant(vec![false]),
+      },
+      OpEnum::CombMux2(mux @ CombMux2 { cond: Some(cond), .. }) => match mux.branches() {
+        (Some(set), Some(clear))
+          if set == clear || is_constant(constants, *cond, |_| true) =>
+        {
+          wire(set)
+        },
+        (Some(_), Some(clear)) if is_constant(constants, *cond, |_| false) => wire(clear),
+        _ => None,
+      },
+      OpEnum::CombExtract(CombExtract {
+        input: Some(input),
+        low: Some(UIntAttr(0)),
+        ..
+      }) if env.get_entity(*input).get_dtype() == dtype => wire(*input),
+      OpEnum::CombConcat(CombConcat { operands, .. }) if operands.len() == 1 => {
+        operands[0].and_then(wire)
+      },
+      _ => None,
+    })
+  }
+
+  /// Collapse `%b = hw.wire %a` by using `%a` in place of `%b`. `%a` takes the name of
+  /// `%b` if it's only used by the assign, so that named wires survive.
+  fn collapse_assigns<E>(
+    env: &mut E, analyses: &mut AnalysisManager, op: OpId, region: RegionId,
+  ) -> IronyResult<bool>
+  where E: Environ<EntityT = EntityEnum, OpT = OpEnum> {
+    let ops = env.get_region(region).op_children.to_owned();
+    let def_use = analyses.get::<DefUse, _>(env, op)?;
+
+    // entities whose def-use chains are changed in this round
+    let mut touched = HashSet::new();
+    for op_id in ops {
+      let OpEnum::Assign(Assign { lhs: Some(b), rhs: Some(a), .. }) = env.get_op(op_id)
+      else {
+        continue;
+      };
+      let (a, b) = (*a, *b);
+      if a == b
+        || touched.contains(&a)
+        || touched.contains(&b)
+        || def_use.defs(b).len() != 1
+        || is_debug(env, b)
+      {
+        continue;
+      }
+      touched.extend([a, b]);
+
+      let renamed = def_use.defs(a).len() == 1
+        && !matches!(env.get_op(def_use.defs(a)[0]), OpEnum::HwInput(_))
+        && def_use.uses(a) == [op_id]
+        && !is_debug(env, a);
+      // `a` keeps its own location, where its value is computed
+      if let (true, EntityEnum::IRWire(IRWire { name, .. })) =
+        (renamed, env.get_entity(b).to_owned())
+      {
+        env.get_entity_entry(a).and_modify(|wire| {
+          if let EntityEnum::IRWire(wire) = wire {
+            wire.name = name;
+          }
+        });
+      }
+      for user in def_use.uses(b) {
+        env.get_op_entry(*user).and_modify(|op| op.replace_use(b, a));
+      }
+      env.delete_op(op_id);
+      env.delete_entity(b);
+      env.get_region_entry(region).and_modify(|region| {
+        region.entity_children.retain(|x| *x != b);
+      });
+    }
+    Ok(!touched.is_empty())
+  }
+}
+
+impl PassTrait<(), IronyError> for CanonicalizePass {
+  type EntityT = EntityEnum;
+  type OpT = OpEnum;
+
+  fn name(&self) -> &'static str { "canonicalize" }
+
+  fn description(&self) -> &'static str {
+    "Fold constants, simplify identities of comb ops and collapse chains of assigns"
+  }
+
+  fn preserved_analyses(&self) -> PreservedAnalyses {
+    PreservedAnalyses::none().preserve::<InstanceHierarchy>()
+  }
+
+  fn check_op<E>(&self, env: &E, op: OpId) -> bool
+  where E: Environ<EntityT = Self::EntityT, OpT = Self::OpT> {
+    matches!(env.get_op(op), OpEnum::HwModule(_))
+  }
+
+  fn run_raw<E>(
+    &self, env: &mut E, analyses: &mut AnalysisManager, op: OpId,
+  ) -> IronyResult<()>
+  where E: Environ<EntityT = Self::EntityT, OpT = Self::OpT> {
+    let OpEnum::HwModule(HwModule { body: Some(region), .. }) = env.get_op(op) else {
+      return Ok(());
+    };
+    let region = *region;
+
+    loop {
+      // operands are folded before their users, unless they are in a loop
+      let ops = match analyses.get::<TopoOrder, _>(env, op) {
+        Ok(order) => order.0.to_owned(),
+        Err(_) => env.get_region(region).op_children.to_owned(),
+      };
+      let def_use = analyses.get::<DefUse, _>(env, op)?;
+      let mut constants = HashMap::new();
+      for op_id in ops.iter() {
+        if let OpEnum::HwConstant(HwConstant {
+          lhs: Some(lhs), value: Some(value), ..
+        }) = env.get_op(*op_id)
+        {
+          if let (1, Some(DataTypeEnum::UInt(dtype))) =
+            (def_use.defs(*lhs).len(), env.get_entity(*lhs).get_dtype())
+          {
+            let value = AttributeEnum::ConstantAttr(value.to_owned());
+            if let Ok(value) = constant(&value, &DataTypeEnum::UInt(dtype)) {
+              constants.insert(*lhs, value);
+            }
+          }
+        }
+      }
+
+      let mut changed = false;
+      for op_id in ops {
+        let Some(new) = Self::simplify(env, op_id, &constants)? else {
+          continue;
+        };
+        if let OpEnum::HwConstant(HwConstant {
+          lhs: Some(lhs), value: Some(value), ..
+        }) = &new
+        {
+          constants.insert(*lhs, Value::Bits(value.0.to_owned()));
+        }
+        replace_op(env, op_id, new);
+        changed = true;
+      }
+      if changed {
+        analyses.invalidate(env, op, &self.preserved_analyses());
+      }
+      if Self::collapse_assigns(env, analyses, op, region)? {
+        analyses.invalidate(env, op, &self.preserved_analyses());
+        changed = true;
+      }
+      if !changed {
+        return Ok(());
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct CsePass;
+
+impl Into<PassEnum> for CsePass {
+  fn into(self) -> PassEnum { PassEnum::CsePass(self) }
+}
+
+/// Keeps the entities used by an op and hides the ones it defines, so that ops
+/// computing the same value from the same operands hash the same
+struct CseReducer(Vec<EntityId>);
+
+impl ReducerTrait for CseReducer {
+  fn reduce_entity(&mut self, id: EntityId) -> usize {
+    if self.0.contains(&id) {
+      usize::MAX
+    } else {
+      id.0
+    }
+  }
+
+  fn reduce_op(&mut self, id: OpId) -> usize { id.0 }
+}
+
+impl CsePass {
+  /// Ops whose results only depend on their operands and attributes
+  fn is_pure(op: &OpEnum) -> bool {
+    matches!(
+      op,
+      OpEnum::HwBitCast(_)
+        | OpEnum::HwConstant(_)
+        | OpEnum::HwAggregateConstant(_)
+        | OpEnum::HwArrayConcat(_)
+        | OpEnum::HwArrayCreate(_)
+        | OpEnum::HwArrayGet(_)
+        | OpEnum::HwArraySlice(_)
+        | OpEnum::HwStructCreate(_)
+        | OpEnum::HwStructExtract(_)
+        | OpEnum::HwStructInject(_)
+        | OpEnum::HwStructExplode(_)
+        | OpEnum::CombVariadic(_)
+        | OpEnum::CombBinary(_)
+        | OpEnum::CombICmp(_)
+        | OpEnum::CombExtract(_)
+        | OpEnum::CombConcat(_)
+        | OpEnum::CombMux2(_)
+    )
+  }
+
+  fn hash<E>(env: &E, op_id: OpId, defs: &[EntityId]) -> u64
+  where E: Environ<EntityT = EntityEnum, OpT = OpEnum> {
+    *env.get_hasher() = FxHasher::default();
+    env.get_op(op_id).hash_with_reducer(env, &mut CseReducer(defs.to_owned()));
+    env.get_hasher().finish()
+  }
+
+  /// Not every attribute is hashed, so ops with the same hash are compared in full
+  fn same<E>(env: &E, a: OpId, b: OpId) -> bool
+  where E: Environ<EntityT = EntityEnum, OpT = OpEnum> {
+    let (a, b) = (env.get_op(a), env.get_op(b));
+    let dtypes = |op: &OpEnum| {
+      defs_of(op).into_iter().map(|x| env.get_entity(x).get_dtype()).collect::<Vec<_>>()
+    };
+    a.get_op_name() == b.get_op_name()
+      && a.get_uses() == b.get_uses()
+      && a.get_attrs() == b.get_attrs()
+      && dtypes(a) == dtypes(b)
+  }
+}
+
+fn defs_of(op: &OpEnum) -> Vec<EntityId> {
+  op.get_defs().into_iter().flat_map(|(_, x)| x).flatten().collect()
+}
+
+impl PassTrait<(), IronyError> for CsePass {
+  type EntityT = EntityEnum;
+  type OpT = OpEnum;
+
+  fn name(&self) -> &'static str { "cse" }
+
+  fn description(&self) -> &'static str {
+    "Merge the pure ops computing the same value from the same operands"
+  }
+
+  fn preserved_analyses(&self) -> PreservedAnalyses {
+    PreservedAnalyses::none().preserve::<InstanceHierarchy>()
+  }
+
+  fn check_op<E>(&self, env: &E, op: OpId) -> bool
+  where E: Environ<EntityT = Self::EntityT, OpT = Self::OpT> {
+    matches!(env.get_op(op), OpEnum::HwModule(_))
+  }
+
+  fn run_raw<E>(
+    &self, env: &mut E, analyses: &mut AnalysisManager, op: OpId,
+  ) -> IronyResult<()>
+  where E: Environ<EntityT = Self::EntityT, OpT = Self::OpT> {
+    let OpEnum::HwModule(HwModule { body: Some(region), .. }) = env.get_op(op) else {
+      return Ok(());
+    };
+    let region = *region;
+
+    loop {
+      let ops = env.get_region(region).op_children.to_owned();
+      let def_use = analyses.get::<DefUse, _>(env, op)?;
+
+      // ops by hash, the first op of a bucket being kept
+      let mut table = HashMap::<u64, Vec<OpId>>::new();
+      let mut replaced = HashMap::new();
+      for op_id in ops.iter().copied() {
+        let op = env.get_op(op_id);
+        let defs = defs_of(op);
+        if !Self::is_pure(op)
+          || defs.iter().any(|x| def_use.defs(*x).len() != 1 || is_debug(env, *x))
+        {
+          continue;
+        }
+        let bucket = table.entry(Self::hash(env, op_id, &defs)).or_default();
+        match bucket.iter().find(|x| Self::same(env, **x, op_id)) {
+          Some(kept) => {
+            replaced.extend(defs.into_iter().zip(defs_of(env.get_op(*kept))));
+            env.delete_op(op_id);
+          },
+          None => bucket.push(op_id),
+        }
+      }
+      if replaced.is_empty() {
+        return Ok(());
+      }
+
+      for op_id in env.walk_ops_from(env.get_region(region).op_children.to_owned()) {
+        env.get_op_entry(op_id).and_modify(|op| {
+          for (old, new) in replaced.iter() {
+            if op.uses(*old) {
+              op.replace_use(*old, *new);
+            }
+          }
+        });
+      }
+      for old in replaced.keys() {
+        env.delete_entity(*old);
+      }
+      env.get_region_entry(region).and_modify(|region| {
+        region.entity_children.retain(|x| !replaced.contains_key(x));
+      });
+      analyses.invalidate(env, op, &self.preserved_analyses());
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct DcePass;
+
+impl Into<PassEnum> for DcePass {
+  fn into(self) -> PassEnum { PassEnum::DcePass(self) }
+}
+
+impl DcePass {
+  /// Ops kept regardless of their results: module ports, instances, debug wires and
+  /// ops without results or with regions
+  fn is_root<E>(env: &E, op: &OpEnum) -> bool
+  where E: Environ<EntityT = EntityEnum, OpT = OpEnum> {
+    let defs =
+      op.get_defs().into_iter().flat_map(|(_, x)| x).flatten().collect::<Vec<_>>();
+    match op {
+      OpEnum::HwInput(_) | OpEnum::HwOutput(_) | OpEnum::HwInstance(_) => true,
+      _ if defs.is_empty() || !op.get_regions().is_empty() => true,
+      _ => defs.into_iter().any(|def| is_debug(env, def)),
+    }
+  }
+
+  /// Delete the non-top modules not instantiated by any top module
+  fn remove_unused_modules<E>(
+    env: &mut E, analyses: &mut AnalysisManager,
+  ) -> IronyResult<()>
+  where E: Environ<EntityT = EntityEnum, OpT = OpEnum> {
+    let modules = env
+      .get_ops_with_parent(None)
+      .into_iter()
+      .filter_map(|op_id| match env.get_op(op_id) {
+        OpEnum::HwModule(module) => Some((op_id, module.top == Some(BoolAttr(true)))),
+        _ => None,
+      })
+      .collect::<Vec<_>>();
+    let mut used = HashSet::new();
+    for (op_id, _) in modules.iter().filter(|(_, top)| *top) {
+      used.extend(analyses.get::<InstanceHierarchy, _>(env, *op_id)?.modules.to_owned());
+    }
+    for (op_id, _) in modules {
+      if !used.contains(&op_id) {
+        env.delete_op_and_all(op_id);
+      }
+    }
+    Ok(())
+  }
+}
+
+impl PassTrait<(), IronyError> for DcePass {
+  type EntityT = EntityEnum;
+  type OpT = OpEnum;
+
+  fn name(&self) -> &'static str { "dce" }
+
+  fn description(&self) -> &'static str {
+    "Remove ops and entities whose results are never used, and modules never instantiated"
+  }
+
+  fn preserved_analyses(&self) -> PreservedAnalyses {
+    // the modules left are the ones instantiated by top modules
+    PreservedAnalyses::none().preserve::<InstanceHierarchy>()
+  }
+
+  fn check_op<E>(&self, env: &E, op: OpId) -> bool
+  where E: Environ<EntityT = Self::EntityT, OpT = Self::OpT> {
+    matches!(env.get_op(op), OpEnum::HwModule(_))
+  }
+
+  fn run_raw<E>(
+    &self, env: &mut E, analyses: &mut AnalysisManager, op: OpId,
+  ) -> IronyResult<()>
+  where E: Environ<EntityT = Self::EntityT, OpT = Self::OpT> {
+    let OpEnum::HwModule(module) = env.get_op(op) else { unreachable!() };
+    let (top, body) = (module.top == Some(BoolAttr(true)), module.body);
+    if top {
+      Self::remove_unused_modules(env, analyses)?;
+    }
+    let Some(region) = body else {
+      return Ok(());
+    };
+
+    let ops = env.get_region(region).op_children.to_owned();
+    let def_use = analyses.get::<DefUse, _>(env, op)?;
+
+    // an op is live if it's a root or defines an entity used by a live op, or by an
+    // op in the regions of a live op
+    let mut live_ops = HashSet::new();
+    let mut used = HashSet::new();
+    let mut stack = ops
+      .iter()
+      .copied()
+      .filter(|x| Self::is_root(env, env.get_op(*x)))
+      .collect::<Vec<_>>();
+    while let Some(op_id) = stack.pop() {
+      if !live_ops.insert(op_id) {
+        continue;
+      }
+      for nested in env.walk_ops_from(vec![op_id]) {
+        for (_, uses) in env.get_op(nested).get_uses() {
+          for entity in uses.into_iter().flatten() {
+            if used.insert(entity) {
+              stack.extend(def_use.defs(entity));
+            }
+          }
+        }
+      }
+    }
+    let mut live_entities = used;
+    for op_id in live_ops.iter() {
+      for (_, defs) in env.get_op(*op_id).get_defs() {
+        live_entities.extend(defs.into_iter().flatten());
+      }
+    }
+
+    let (live, dead): (Vec<_>, Vec<_>) =
+      ops.into_iter().partition(|x| live_ops.contains(x));
+    for op_id in dead {
+      env.delete_op(op_id);
+    }
+    let (entities, dead): (Vec<_>, Vec<_>) = env
+      .get_region(region)
+      .entity_children
+      .iter()
+      .partition(|x| live_entities.contains(x));
+    for entity in dead {
+      env.delete_entity(entity);
+    }
+    set_region_children(env, region, live, entities);
+    Ok(())
+  }
+}
+
+/// Inline the instances of modules into the module instantiating them, leaving the
+/// instances of extern modules
+#[derive(Debug, Clone, Default)]
+pub struct FlattenPass {
+  /// Inline these modules, like modules marked `inline`
+  pub modules: Option<Vec<OpId>>,
+  /// Inline the modules with at most this many ops
+  pub max_ops: Option<usize>,
+}
+
+impl Into<PassEnum> for FlattenPass {
+  fn into(self) -> PassEnum { PassEnum::FlattenPass(self) }
+}
+
+/// Maps the entities of an inlined module to the entities of its parent
+struct InlineReducer(HashMap<EntityId, EntityId>);
+
+impl ReducerTrait for InlineReducer {
+  fn reduce_entity(&mut self, id: EntityId) -> usize {
+    self.0.get(&id).copied().unwrap_or(id).0
+  }
+
+  fn reduce_op(&mut self, id: OpId) -> usize { id.0 }
+}
+
+fn entity_name<E: Environ<EntityT = EntityEnum>>(env: &E, entity: EntityId) -> String {
+  match env.get_entity(entity).get_attr("name") {
+    Some(AttributeEnum::StringAttr(StringAttr(name))) => name,
+    _ => entity.0.to_string(),
+  }
+}
+
+impl FlattenPass {
+  /// Every module but extern ones if neither `modules` nor `max_ops` is set
+  fn should_inline<E>(&self, env: &E, module: OpId) -> bool
+  where E: Environ<EntityT = EntityEnum, OpT = OpEnum> {
+    let OpEnum::HwModule(HwModule { is_extern, body: Some(body), .. }) =
+      env.get_op(module)
+    else {
+      return false;
+    };
+    if *is_extern == Some(BoolAttr(true)) {
+      return false;
+    }
+    match (&self.modules, self.max_ops) {
+      (None, None) => true,
+      (modules, max_ops) => {
+        modules.as_ref().is_some_and(|x| x.contains(&module))
+          || max_ops.is_some_and(|x| env.get_region(*body).op_children.len() <= x)
+      },
+    }
+  }
+
+  /// Copy the body of the module instantiated by `instance` into `region`, prefixing
+  /// the names of its entities with the instance name. Returns the new ops, which
+  /// replace the instance, and the new entities.
+  fn inline<E>(
+    env: &mut E, instance: OpId, region: RegionId, names: &mut SymbolTable,
+  ) -> IronyResult<(Vec<OpId>, Vec<EntityId>)>
+  where E: Environ<EntityT = EntityEnum, OpT = OpEnum> {
+    let OpEnum::HwInstance(HwInstance {
+      inputs,
+      outputs,
+      target_op_id: Some(OpIdAttr(target)),
+      name: Some(StringAttr(prefix)),
+      ..
+    }) = env.get_op(instance).to_owned()
+    else {
+      return Err(IronyError::new("must be an instance with a target").with_op(instance));
+    };
+    let body = env.get_region(module_region(env, target)?).op_children.to_owned();
+
+    let mut reducer = InlineReducer(HashMap::new());
+    let mut module_outputs = vec![];
+    let mut entities = vec![];
+    for op_id in body.iter() {
+      let op = env.get_op(*op_id);
+      match op {
+        OpEnum::HwInput(input) => {
+          for (arg, operand) in input.inputs.iter().zip(inputs.iter()) {
+            let arg = required(*arg, *op_id, "input")?;
+            reducer.0.insert(arg, required(*operand, instance, "input")?);
+          }
+        },
+        OpEnum::HwOutput(output) => module_outputs = output.outputs.to_owned(),
+        _ if !op.get_regions().is_empty() => {
+          return Err(
+            IronyError::new("can't inline a module with nested regions").with_op(*op_id),
+          );
+        },
+        _ => {},
+      }
+      for (_, x) in op.get_defs().into_iter().chain(op.get_uses()) {
+        entities.extend(x.into_iter().flatten());
+      }
+    }
+
+    let mut new_entities = vec![];
+    for entity in entities {
+      if reducer.0.contains_key(&entity) {
+        continue;
+      }
+      let name = format!("{}_{}", prefix, entity_name(env, entity));
+      let name = names.get_legal_name_in(Some(region), &name);
+      let mut new = env.get_entity(entity).to_owned();
+      new.set_attrs(vec![("name".into(), StringAttr(name).into())]);
+      let new = env.add_entity(new);
+      reducer.0.insert(entity, new);
+      new_entities.push(new);
+    }
+
+    let mut new_ops = vec![];
+    for op_id in body {
+      let op = match env.get_op(op_id) {
+        OpEnum::HwInput(_) | OpEnum::HwOutput(_) => continue,
+        OpEnum::HwInstance(inner) => {
+          let name = format!("{}_{}", prefix, inner.name.as_ref().unwrap().0);
+          let mut op = env.get_op(op_id).to_owned();
+          op.set_attrs(vec![("name".into(), StringAttr(name).into())]);
+          op
+        },
+        op => op.to_owned(),
+      };
+      new_ops.push(env.add_op(op.reduce_def_use(&mut reducer)));
+    }
+    for (output, value) in outputs.iter().zip(module_outputs) {
+      let output = required(*output, instance, "output")?;
+      let value = reducer.0[&required(value, target, "output")?];
+      new_ops.push(env.add_op(Assign::new(Some(output), Some(value)).into()));
+    }
+    Ok((new_ops, new_entities))
+  }
+}
+
+impl PassTrait<(), IronyError> for FlattenPass {
+  type EntityT = EntityEnum;
+  type OpT = OpEnum;
+
+  fn name(&self) -> &'static str { "flatten" }
+
+  fn description(&self) -> &'static str {
+    "Inline the instances of non-extern modules, prefixing the names of the inlined \
+     entities with the instance path"
+  }
+
+  fn preserved_analyses(&self) -> PreservedAnalyses { PreservedAnalyses::none() }
+
+  fn check_op<E>(&self, env: &E, op: OpId) -> bool
+  where E: Environ<EntityT = Self::EntityT, OpT = Self::OpT> {
+    matches!(env.get_op(op), OpEnum::HwModule(_))
+  }
+
+  fn run_raw<E>(
+    &self, env: &mut E, analyses: &mut AnalysisManager, op: OpId,
+  ) -> IronyResult<()>
+  where E: Environ<EntityT = Self::EntityT, OpT = Self::OpT> {
+    let OpEnum::HwModule(HwModule { body: Some(region), .. }) = env.get_op(op) else {
+      return Ok(());
+    };
+    let region = *region;
+    // inlining a recursive module would never end
+    analyses.get::<InstanceHierarchy, _>(env, op)?;
+
+    let mut names = SymbolTable::default();
+    for entity in env.get_region(region).entity_children.iter() {
+      names.insert(Some(region), entity_name(env, *entity));
+    }
+    loop {
+      let ops = env.get_region(region).op_children.to_owned();
+      let Some(pos) = ops.iter().position(|op_id| match env.get_op(*op_id) {
+        OpEnum::HwInstance(HwInstance {
+          target_op_id: Some(OpIdAttr(target)), ..
+        }) => self.should_inline(env, *target),
+        _ => false,
+      }) else {
+        return Ok(());
+      };
+      let instance = ops[pos];
+      let (new_ops, new_entities) = Self::inline(env, instance, region, &mut names)?;
+      env.delete_op(instance);
+
+      let mut ops = ops;
+      ops.splice(pos..pos + 1, new_ops);
+      let mut entities = env.get_region(region).entity_children.to_owned();
+      entities.extend(new_entities);
+      set_region_children(env, region, ops, entities);
+    }
+  }
+}
+
 #[derive(Debug, Clone)]
 pub enum PassEnum {
   ReorderPass(ReorderPass),
   RemoveEventPass(RemoveEventPass),
   RemoveSelectPass(RemoveSelectPass),
   RemoveUnaryPass(RemoveUnaryPass),
+  CanonicalizePass(CanonicalizePass),
+  CsePass(CsePass),
+  DcePass(DcePass),
+  FlattenPass(FlattenPass),
 }
 
-impl PassTrait<(), ()> for PassEnum {
+impl PassTrait<(), IronyError> for PassEnum {
   type EntityT = EntityEnum;
   type OpT = OpEnum;
 
+  fn name(&self) -> &'static str {
+    match self {
+      PassEnum::ReorderPass(pass) => pass.name(),
+      PassEnum::RemoveEventPass(pass) => pass.name(),
+      PassEnum::RemoveSelectPass(pass) => pass.name(),
+      PassEnum::RemoveUnaryPass(pass) => pass.name(),
+      PassEnum::CanonicalizePass(pass) => pass.name(),
+      PassEnum::CsePass(pass) => pass.name(),
+      PassEnum::DcePass(pass) => pass.name(),
+      PassEnum::FlattenPass(pass) => pass.name(),
+    }
+  }
+
+  fn description(&self) -> &'static str {
+    match self {
+      PassEnum::ReorderPass(pass) => pass.description(),
+      PassEnum::RemoveEventPass(pass) => pass.description(),
+      PassEnum::RemoveSelectPass(pass) => pass.description(),
+      PassEnum::RemoveUnaryPass(pass) => pass.description(),
+      PassEnum::CanonicalizePass(pass) => pass.description(),
+      PassEnum::CsePass(pass) => pass.description(),
+      PassEnum::DcePass(pass) => pass.description(),
+      PassEnum::FlattenPass(pass) => pass.description(),
+    }
+  }
+
+  fn preserved_analyses(&self) -> PreservedAnalyses {
+    match self {
+      PassEnum::ReorderPass(pass) => pass.preserved_analyses(),
+      PassEnum::RemoveEventPass(pass) => pass.preserved_analyses(),
+      PassEnum::RemoveSelectPass(pass) => pass.preserved_analyses(),
+      PassEnum::RemoveUnaryPass(pass) => pass.preserved_analyses(),
+      PassEnum::CanonicalizePass(pass) => pass.preserved_analyses(),
+      PassEnum::CsePass(pass) => pass.preserved_analyses(),
+      PassEnum::DcePass(pass) => pass.preserved_analyses(),
+      PassEnum::FlattenPass(pass) => pass.preserved_analyses(),
+    }
+  }
+
   fn check_op<E>(&self, env: &E, op_id: irony::OpId) -> bool
   where E: Environ<EntityT = Self::EntityT, OpT = Self::OpT> {
     match self {
@@ -647,43 +1471,142 @@ impl PassTrait<(), ()> for PassEnum {
       PassEnum::RemoveEventPass(pass) => pass.check_op(env, op_id),
       PassEnum::RemoveSelectPass(pass) => pass.check_op(env, op_id),
       PassEnum::RemoveUnaryPass(pass) => pass.check_op(env, op_id),
+      PassEnum::CanonicalizePass(pass) => pass.check_op(env, op_id),
+      PassEnum::CsePass(pass) => pass.check_op(env, op_id),
+      PassEnum::DcePass(pass) => pass.check_op(env, op_id),
+      PassEnum::FlattenPass(pass) => pass.check_op(env, op_id),
     }
   }
 
-  fn run_raw<E>(&self, env: &mut E, op_id: irony::OpId) -> Result<(), ()>
+  fn run_raw<E>(
+    &self, env: &mut E, analyses: &mut AnalysisManager, op_id: irony::OpId,
+  ) -> IronyResult<()>
   where E: Environ<EntityT = Self::EntityT, OpT = Self::OpT> {
     match self {
-      PassEnum::ReorderPass(pass) => pass.run_raw(env, op_id),
-      PassEnum::RemoveEventPass(pass) => pass.run_raw(env, op_id),
-      PassEnum::RemoveSelectPass(pass) => pass.run_raw(env, op_id),
-      PassEnum::RemoveUnaryPass(pass) => pass.run_raw(env, op_id),
+      PassEnum::ReorderPass(pass) => pass.run_raw(env, analyses, op_id),
+      PassEnum::RemoveEventPass(pass) => pass.run_raw(env, analyses, op_id),
+      PassEnum::RemoveSelectPass(pass) => pass.run_raw(env, analyses, op_id),
+      PassEnum::RemoveUnaryPass(pass) => pass.run_raw(env, analyses, op_id),
+      PassEnum::CanonicalizePass(pass) => pass.run_raw(env, analyses, op_id),
+      PassEnum::CsePass(pass) => pass.run_raw(env, analyses, op_id),
+      PassEnum::DcePass(pass) => pass.run_raw(env, analyses, op_id),
+      PassEnum::FlattenPass(pass) => pass.run_raw(env, analyses, op_id),
     }
   }
 }
 
+impl PassEnum {
+  /// Every pass, in the order they can be applied
+  pub const ALL: [PassEnum; 8] = [
+    PassEnum::ReorderPass(ReorderPass),
+    PassEnum::RemoveEventPass(RemoveEventPass),
+    PassEnum::RemoveSelectPass(RemoveSelectPass),
+    PassEnum::RemoveUnaryPass(RemoveUnaryPass),
+    PassEnum::CanonicalizePass(CanonicalizePass),
+    PassEnum::CsePass(CsePass),
+    PassEnum::DcePass(DcePass),
+    PassEnum::FlattenPass(FlattenPass { modules: None, max_ops: None }),
+  ];
+}
+
+impl PassRegistryTrait<(), IronyError> for PassEnum {
+  fn registered() -> Vec<Self> { PassEnum::ALL.to_vec() }
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct PassManager {
   passes: Vec<PassEnum>,
-  start_ops: Vec<Vec<OpId>>,
+  /// Ops each pass starts from, or all the top-level ops if `None`
+  start_ops: Vec<Option<Vec<OpId>>>,
+  /// Dump the IR to this directory before the first pass and after every pass
+  pub dump_dir: Option<PathBuf>,
+  statistics: Vec<PassStatistics>,
+  /// Analyses cached across passes, invalidated by the passes not preserving them
+  pub analyses: AnalysisManager,
+}
+
+impl PassManager {
+  /// Add passes which start from all the top-level ops at the time they run
+  pub fn add_pipeline(&mut self, pipeline: PassPipeline<PassEnum>) {
+    self.start_ops.extend(pipeline.0.iter().map(|_| None));
+    self.passes.extend(pipeline.0);
+  }
+
+  /// Statistics of the passes run so far, in order
+  pub fn statistics(&self) -> &[PassStatistics] { &self.statistics }
+
+  fn dump<E>(&self, env: &E, n: usize, pass: &str) -> IronyResult<()>
+  where E: Environ<EntityT = EntityEnum, OpT = OpEnum> {
+    let Some(dir) = &self.dump_dir else {
+      return Ok(());
+    };
+    let ir = env
+      .get_ops_with_parent(None)
+      .into_iter()
+      .map(|op_id| env.print_op(op_id))
+      .collect::<Vec<_>>()
+      .join("\n");
+    fs::create_dir_all(dir)
+      .and_then(|_| fs::write(dir.join(format!("{:02}_{}.mlir", n, pass)), ir))
+      .map_err(|err| {
+        IronyError::new(format!("failed to dump the IR: {}", err)).with_pass(pass)
+      })
+  }
 }
 
-impl PassManagerTrait<(), ()> for PassManager {
+impl PassManagerTrait<(), IronyError> for PassManager {
   type EntityT = EntityEnum;
   type OpT = OpEnum;
   type PassT = PassEnum;
 
-  fn add_passes(&mut self, mut passes: Vec<Self::PassT>, mut start_ops: Vec<Vec<OpId>>) {
+  fn add_passes(&mut self, mut passes: Vec<Self::PassT>, start_ops: Vec<Vec<OpId>>) {
     assert_eq!(passes.len(), start_ops.len());
     self.passes.append(&mut passes);
-    self.start_ops.append(&mut start_ops);
+    self.start_ops.extend(start_ops.into_iter().map(Some));
   }
 
-  fn run_passes<E>(&self, env: &mut E) -> Result<(), ()>
+  fn run_passes<E>(&mut self, env: &mut E) -> IronyResult<()>
   where E: Environ<EntityT = Self::EntityT, OpT = Self::OpT> {
-    for (pass, op) in self.passes.iter().zip(self.start_ops.iter()) {
-      for op in op.iter() {
-        pass.run_on(env, *op)?;
+    self.dump(env, 0, "input")?;
+    for (i, (pass, start_ops)) in
+      self.passes.iter().zip(self.start_ops.iter()).enumerate()
+    {
+      let start_ops =
+        start_ops.to_owned().unwrap_or_else(|| env.get_ops_with_parent(None));
+      let ops_before = env.walk_ops().len();
+      let start = Instant::now();
+      let mut runs = 0;
+      for op in start_ops {
+        // top-level ops may be deleted by an earlier run of the pass
+        if let Entry::Vacant(_) = env.get_op_entry(op) {
+          continue;
+        }
+        if !pass.check_op(env, op) {
+          continue;
+        }
+        runs += 1;
+        pass.run_raw(env, &mut self.analyses, op).map_err(|err| {
+          let err = err.with_pass(pass.name());
+          if err.op.is_none() {
+            err.with_op(op)
+          } else {
+            err
+          }
+        })?;
+        let preserved = pass.preserved_analyses();
+        match env.get_op_entry(op) {
+          Entry::Vacant(_) => self.analyses.invalidate_all(&preserved),
+          Entry::Occupied(_) => self.analyses.invalidate(env, op, &preserved),
+        }
       }
+      self.statistics.push(PassStatistics {
+        name: pass.name(),
+        time: start.elapsed(),
+        ops_before,
+        ops_after: env.walk_ops().len(),
+        runs,
+      });
+      self.dump(env, i + 1, pass.name())?;
     }
     Ok(())
   }
@@ -0,0 +1,619 @@
+use std::collections::{HashMap, HashSet};
+
+use irony::{Entity, EntityId, Environ, IronyError, IronyResult, Op, OpId};
+
+use crate::interpret::{bitcast, constant};
+use crate::json::Json;
+use crate::{
+  ArrayAttr, ArrayType, AttributeEnum, BoolAttr, CombBinary, CombBinaryPredicate,
+  CombConcat, CombExtract, CombICmp, CombICmpPredicate, CombMux2, CombUnaryPredicate,
+  CombVariadic, CombVariadicPredicate, DataTypeEnum, EntityEnum, HwAggregateConstant,
+  HwArrayConcat, HwArrayCreate, HwArrayGet, HwArraySlice, HwBitCast, HwConstant, HwInput,
+  HwInstance, HwModule, HwOutput, HwStructCreate, HwStructExplode, HwStructExtract,
+  HwStructInject, ItprtCondCheck, OpEnum, OpIdAttr, SeqCompReg, StringAttr, StructType,
+  SymbolTable, TmpUnary, UIntAttr, UIntType, Value,
+};
+
+/// Keywords of SystemVerilog, which can't be used as identifiers
+const KEYWORDS: &str = "\
+  alias always always_comb always_ff always_latch and assert assign assume automatic \
+  before begin bind bit break buf byte case casex casez cell class config const \
+  constraint context continue cover default defparam design disable do edge else end \
+  endcase endfunction endgenerate endmodule endtask enum event export extends extern \
+  final for force foreach forever fork function generate genvar if iff import initial \
+  inout input inside instance int integer interface join let library local localparam \
+  logic longint module nand negedge new nor not null or output package packed \
+  parameter posedge priority program property real reg release repeat return sequence \
+  shortint signed static string struct super supply0 supply1 table task this time tri \
+  type typedef union unique unsigned use var virtual void wait wand while wire with \
+  wor xnor xor";
+
+/// Make `name` a legal identifier, which is not one of the space-separated `keywords`
+pub(crate) fn identifier(name: &str, keywords: &str) -> String {
+  let mut name = name
+    .chars()
+    .map(|x| if x.is_ascii_alphanumeric() || x == '_' { x } else { '_' })
+    .collect::<String>();
+  if name.is_empty() || name.starts_with(|x: char| x.is_ascii_digit()) {
+    name.insert(0, '_');
+  }
+  if keywords.split_whitespace().any(|x| x == name) {
+    name.push('_');
+  }
+  name
+}
+
+fn sanitize(name: &str) -> String { identifier(name, KEYWORDS) }
+
+pub(crate) fn string_of(attr: &AttributeEnum) -> String {
+  match attr {
+    AttributeEnum::StringAttr(StringAttr(x)) => x.to_owned(),
+    attr => attr.to_string(),
+  }
+}
+
+/// The packed type of `dtype`, split into a base type and packed dimensions so that
+/// arrays can put their dimension in front of the dimensions of their elements
+fn packed_type(dtype: &DataTypeEnum) -> Result<(String, Vec<String>), String> {
+  match dtype {
+    DataTypeEnum::Clk(_) | DataTypeEnum::UInt(UIntType(1)) => {
+      Ok(("logic".into(), vec![]))
+    },
+    DataTypeEnum::UInt(UIntType(width)) => {
+      Ok(("logic".into(), vec![format!("[{}:0]", width - 1)]))
+    },
+    DataTypeEnum::Array(ArrayType(element, len)) => {
+      let (base, mut dims) = packed_type(element)?;
+      dims.insert(0, format!("[{}:0]", len - 1));
+      Ok((base, dims))
+    },
+    // the first field is the least significant one, as in the interpreter, so that
+    // bitcasts keep their meaning
+    DataTypeEnum::Struct(StructType(fields)) => {
+      let fields = fields
+        .iter()
+        .rev()
+        .map(|(name, dtype)| Ok(format!("{} {};", sv_type(dtype)?, sanitize(name))))
+        .collect::<Result<Vec<_>, String>>()?;
+      Ok((format!("struct packed {{ {} }}", fields.join(" ")), vec![]))
+    },
+    dtype => Err(format!("type {:?} can't be emitted to SystemVerilog", dtype)),
+  }
+}
+
+fn sv_type(dtype: &DataTypeEnum) -> Result<String, String> {
+  let (base, dims) = packed_type(dtype)?;
+  Ok(if dims.is_empty() { base } else { format!("{} {}", base, dims.concat()) })
+}
+
+/// Hexadecimal digits of `bits`, least significant bit first
+pub(crate) fn hex_digits(bits: &[bool]) -> String {
+  if bits.is_empty() {
+    return "0".into();
+  }
+  bits
+    .chunks(4)
+    .rev()
+    .map(|x| {
+      let digit = x.iter().enumerate().map(|(i, x)| (*x as u32) << i).sum();
+      char::from_digit(digit, 16).unwrap()
+    })
+    .collect()
+}
+
+fn literal(bits: &[bool]) -> String { format!("{}'h{}", bits.len(), hex_digits(bits)) }
+
+/// Names of the inputs and outputs of a module, made legal by `legal`, which its
+/// instances are connected by, and the table of the names taken by them
+pub(crate) fn port_names<E>(
+  env: &E, module: OpId, legal: fn(&str) -> String,
+) -> IronyResult<(Vec<String>, Vec<String>, SymbolTable)>
+where E: Environ<EntityT = EntityEnum, OpT = OpEnum> {
+  let OpEnum::HwModule(HwModule {
+    arg_names: Some(ArrayAttr(args)),
+    output_names: Some(ArrayAttr(outputs)),
+    ..
+  }) = env.get_op(module)
+  else {
+    return Err(IronyError::new("must be a module with named ports").with_op(module));
+  };
+  let mut table = SymbolTable::default();
+  let mut names = |names: &[AttributeEnum]| -> Vec<String> {
+    names.iter().map(|x| table.get_legal_name_in(None, &legal(&string_of(x)))).collect()
+  };
+  let (args, outputs) = (names(args), names(outputs));
+  Ok((args, outputs, table))
+}
+
+pub(crate) fn module_name<E>(
+  env: &E, module: OpId, legal: fn(&str) -> String,
+) -> IronyResult<String>
+where E: Environ<EntityT = EntityEnum, OpT = OpEnum> {
+  match env.get_op(module) {
+    OpEnum::HwModule(HwModule { name: Some(StringAttr(name)), .. }) => Ok(legal(name)),
+    _ => Err(IronyError::new("must be a module with a name").with_op(module)),
+  }
+}
+
+struct ModuleEmitter<'a, E> {
+  env: &'a E,
+  /// SystemVerilog names of the entities
+  names: HashMap<EntityId, String>,
+  table: SymbolTable,
+  decls: Vec<String>,
+  body: Vec<String>,
+}
+
+impl<'a, E> ModuleEmitter<'a, E>
+where E: Environ<EntityT = EntityEnum, OpT = OpEnum>
+{
+  fn dtype(&self, entity: EntityId) -> IronyResult<DataTypeEnum> {
+    self.env.get_entity(entity).get_dtype().ok_or_else(|| {
+      IronyError::new("entity must have a type").with_entity(self.env, entity)
+    })
+  }
+
+  fn sv_type(&self, entity: EntityId) -> IronyResult<String> {
+    sv_type(&self.dtype(entity)?)
+      .map_err(|err| IronyError::new(err).with_entity(self.env, entity))
+  }
+
+  fn name(&self, entity: &Option<EntityId>, op: OpId) -> IronyResult<String> {
+    entity
+      .and_then(|x| self.names.get(&x).cloned())
+      .ok_or_else(|| IronyError::new("missing operand").with_op(op))
+  }
+
+  fn names(&self, entities: &[Option<EntityId>], op: OpId) -> IronyResult<Vec<String>> {
+    entities.iter().map(|x| self.name(x, op)).collect()
+  }
+
+  /// Declare `entity` as a variable named after it
+  fn declare(&mut self, entity: EntityId) -> IronyResult<()> {
+    if self.names.contains_key(&entity) {
+      return Ok(());
+    }
+    let name = match self.env.get_entity(entity).get_attr("name") {
+      Some(attr) => sanitize(&string_of(&attr)),
+      None => format!("_{}", entity.0),
+    };
+    let name = self.table.get_legal_name_in(None, &name);
+    self.decls.push(format!("  {} {};", self.sv_type(entity)?, name));
+    self.names.insert(entity, name);
+    Ok(())
+  }
+
+  fn assign(&mut self, lhs: &Option<EntityId>, rhs: String, op: OpId) -> IronyResult<()> {
+    let lhs = self.name(lhs, op)?;
+    self.body.push(format!("  assign {} = {};", lhs, rhs));
+    Ok(())
+  }
+
+  fn constant(
+    &mut self, lhs: &Option<EntityId>, value: AttributeEnum, op: OpId,
+  ) -> IronyResult<()> {
+    let entity = lhs.ok_or_else(|| IronyError::new("missing result").with_op(op))?;
+    let dtype = self.dtype(entity)?;
+    let flat = DataTypeEnum::UInt(UIntType(dtype.width()));
+    let bits = constant(&value, &dtype)
+      .map(|x| bitcast(&x, &flat))
+      .map_err(|err| IronyError::new(err).with_op(op))?;
+    let Value::Bits(bits) = bits else { unreachable!() };
+    self.assign(lhs, literal(&bits), op)
+  }
+
+  fn op(&mut self, op_id: OpId) -> IronyResult<()> {
+    let env = self.env;
+    let signed = |x: String| format!("$signed({})", x);
+    match env.get_op(op_id) {
+      OpEnum::HwInput(_) | OpEnum::HwOutput(_) => {},
+      OpEnum::Assign(op) => {
+        let rhs = self.name(&op.rhs, op_id)?;
+        self.assign(&op.lhs, rhs, op_id)?;
+      },
+      OpEnum::HwBitCast(HwBitCast { lhs, rhs, .. }) => {
+        let rhs = self.name(rhs, op_id)?;
+        self.assign(lhs, rhs, op_id)?;
+      },
+      OpEnum::HwConstant(HwConstant { lhs, value: Some(value), .. }) => {
+        self.constant(lhs, AttributeEnum::ConstantAttr(value.to_owned()), op_id)?;
+      },
+      OpEnum::HwAggregateConstant(HwAggregateConstant {
+        lhs,
+        attrs: Some(value),
+        ..
+      }) => {
+        self.constant(lhs, AttributeEnum::ArrayAttr(value.to_owned()), op_id)?;
+      },
+      OpEnum::SvConstantX(op) => self.assign(&op.lhs, "'x".into(), op_id)?,
+      // element 0 is the least significant one, as in the interpreter
+      OpEnum::HwArrayCreate(HwArrayCreate { lhs, operands, .. })
+      | OpEnum::HwArrayConcat(HwArrayConcat { lhs, operands, .. }) => {
+        let mut operands = self.names(operands, op_id)?;
+        operands.reverse();
+        self.assign(lhs, format!("{{{}}}", operands.join(", ")), op_id)?;
+      },
+      OpEnum::HwArrayGet(HwArrayGet { lhs, array, index, .. }) => {
+        let rhs = format!("{}[{}]", self.name(array, op_id)?, self.name(index, op_id)?);
+        self.assign(lhs, rhs, op_id)?;
+      },
+      OpEnum::HwArraySlice(HwArraySlice { lhs, array, index, .. }) => {
+        let Some(DataTypeEnum::Array(ArrayType(_, len))) =
+          lhs.and_then(|x| env.get_entity(x).get_dtype())
+        else {
+          return Err(IronyError::new("must slice an array").with_op(op_id));
+        };
+        let array = self.name(array, op_id)?;
+        let rhs = format!("{}[{} +: {}]", array, self.name(index, op_id)?, len);
+        self.assign(lhs, rhs, op_id)?;
+      },
+      OpEnum::HwStructCreate(HwStructCreate { lhs, operands, .. }) => {
+        let Some(DataTypeEnum::Struct(StructType(fields))) =
+          lhs.and_then(|x| env.get_entity(x).get_dtype())
+        else {
+          return Err(IronyError::new("must create a struct").with_op(op_id));
+        };
+        let fields = fields
+          .iter()
+          .zip(self.names(operands, op_id)?)
+          .map(|((field, _), value)| format!("{}: {}", sanitize(field), value))
+          .collect::<Vec<_>>();
+        self.assign(lhs, format!("'{{{}}}", fields.join(", ")), op_id)?;
+      },
+      OpEnum::HwStructExtract(HwStructExtract {
+        lhs,
+        struct_input,
+        field: Some(StringAttr(field)),
+        ..
+      }) => {
+        let rhs = format!("{}.{}", self.name(struct_input, op_id)?, sanitize(field));
+        self.assign(lhs, rhs, op_id)?;
+      },
+      OpEnum::HwStructInject(HwStructInject {
+        lhs,
+        struct_input,
+        new_value,
+        field: Some(StringAttr(field)),
+        ..
+      }) => {
+        let lhs = self.name(lhs, op_id)?;
+        self.body.extend([
+          "  always_comb begin".to_string(),
+          format!("    {} = {};", lhs, self.name(struct_input, op_id)?),
+          format!("    {}.{} = {};", lhs, sanitize(field), self.name(new_value, op_id)?),
+          "  end".to_string(),
+        ]);
+      },
+      OpEnum::HwStructExplode(HwStructExplode { outputs, struct_input, .. }) => {
+        let Some(DataTypeEnum::Struct(StructType(fields))) =
+          struct_input.and_then(|x| env.get_entity(x).get_dtype())
+        else {
+          return Err(IronyError::new("must explode a struct").with_op(op_id));
+        };
+        let input = self.name(struct_input, op_id)?;
+        for (output, (field, _)) in outputs.iter().zip(fields) {
+          self.assign(output, format!("{}.{}", input, sanitize(&field)), op_id)?;
+        }
+      },
+      OpEnum::CombVariadic(CombVariadic {
+        lhs,
+        operands,
+        predicate: Some(predicate),
+        ..
+      }) => {
+        let operator = match predicate {
+          CombVariadicPredicate::Add => " + ",
+          CombVariadicPredicate::Mul => " * ",
+          CombVariadicPredicate::And => " & ",
+          CombVariadicPredicate::Or => " | ",
+          CombVariadicPredicate::Xor => " ^ ",
+        };
+        let rhs = self.names(operands, op_id)?.join(operator);
+        self.assign(lhs, rhs, op_id)?;
+      },
+      OpEnum::CombBinary(CombBinary {
+        lhs,
+        op0,
+        op1,
+        predicate: Some(predicate),
+        ..
+      }) => {
+        let (x, y) = (self.name(op0, op_id)?, self.name(op1, op_id)?);
+        let rhs = match predicate {
+          CombBinaryPredicate::Sub => format!("{} - {}", x, y),
+          CombBinaryPredicate::DivU => format!("{} / {}", x, y),
+          CombBinaryPredicate::ModU => format!("{} % {}", x, y),
+          CombBinaryPredicate::DivS => format!("{} / {}", signed(x), signed(y)),
+          CombBinaryPredicate::ModS => format!("{} % {}", signed(x), signed(y)),
+          CombBinaryPredicate::Shl => format!("{} << {}", x, y),
+          CombBinaryPredicate::ShrU => format!("{} >> {}", x, y),
+          CombBinaryPredicate::ShrS => format!("{} >>> {}", signed(x), y),
+        };
+        self.assign(lhs, rhs, op_id)?;
+      },
+      OpEnum::CombICmp(CombICmp {
+        lhs,
+        op0,
+        op1,
+        predicate: Some(predicate),
+        ..
+      }) => {
+        let (x, y) = (self.name(op0, op_id)?, self.name(op1, op_id)?);
+        let (x, operator, y) = match predicate {
+          CombICmpPredicate::EQ => (x, "==", y),
+          CombICmpPredicate::NE => (x, "!=", y),
+          CombICmpPredicate::SLT => (signed(x), "<", signed(y)),
+          CombICmpPredicate::SLE => (signed(x), "<=", signed(y)),
+          CombICmpPredicate::SGT => (signed(x), ">", signed(y)),
+          CombICmpPredicate::SGE => (signed(x), ">=", signed(y)),
+          CombICmpPredicate::ULT => (x, "<", y),
+          CombICmpPredicate::ULE => (x, "<=", y),
+          CombICmpPredicate::UGT => (x, ">", y),
+          CombICmpPredicate::UGE => (x, ">=", y),
+          CombICmpPredicate::CEQ => (x, "===", y),
+          CombICmpPredicate::CNE => (x, "!==", y),
+          CombICmpPredicate::WEQ => (x, "==?", y),
+          CombICmpPredicate::WNE => (x, "!=?", y),
+        };
+        self.assign(lhs, format!("{} {} {}", x, operator, y), op_id)?;
+      },
+      OpEnum::TmpUnary(TmpUnary { lhs, op, predicate: Some(predicate), .. }) => {
+        let operator = match predicate {
+          CombUnaryPredicate::Not => "~",
+          CombUnaryPredicate::Neg => "-",
+        };
+        let rhs = format!("{}{}", operator, self.name(op, op_id)?);
+        self.assign(lhs, rhs, op_id)?;
+      },
+      OpEnum::CombExtract(CombExtract {
+        lhs, input, low: Some(UIntAttr(low)), ..
+      }) => {
+        let width = |x: &Option<EntityId>| {
+          x.and_then(|x| env.get_entity(x).get_dtype()).map(|x| x.width())
+        };
+        let name = self.name(input, op_id)?;
+        let rhs = match (width(lhs), width(input)) {
+          (Some(width), Some(input)) if width == input => name,
+          (Some(1), _) => format!("{}[{}]", name, low),
+          (Some(width), _) => format!("{}[{}:{}]", name, *low as usize + width - 1, low),
+          _ => return Err(IronyError::new("missing result").with_op(op_id)),
+        };
+        self.assign(lhs, rhs, op_id)?;
+      },
+      OpEnum::CombConcat(CombConcat { lhs, operands, .. }) => {
+        let rhs = format!("{{{}}}", self.names(operands, op_id)?.join(", "));
+        self.assign(lhs, rhs, op_id)?;
+      },
+      OpEnum::CombMux2(mux @ CombMux2 { lhs, cond, .. }) => {
+        let (set, clear) = mux.branches();
+        let rhs = format!(
+          "{} ? {} : {}",
+          self.name(cond, op_id)?,
+          self.name(&set, op_id)?,
+          self.name(&clear, op_id)?
+        );
+        self.assign(lhs, rhs, op_id)?;
+      },
+      OpEnum::SeqCompReg(SeqCompReg { output, input, clk, reset, reset_val, .. }) => {
+        let output = self.name(output, op_id)?;
+        let (input, clk) = (self.name(input, op_id)?, self.name(clk, op_id)?);
+        self.body.push(format!("  always_ff @(posedge {}) begin", clk));
+        match reset {
+          Some(_) => {
+            let (reset, reset_val) =
+              (self.name(reset, op_id)?, self.name(reset_val, op_id)?);
+            self.body.extend([
+              format!("    if ({}) {} <= {};", reset, output, reset_val),
+              format!("    else {} <= {};", output, input),
+            ]);
+          },
+          None => self.body.push(format!("    {} <= {};", output, input)),
+        }
+        self.body.push("  end".into());
+      },
+      OpEnum::HwInstance(HwInstance {
+        outputs,
+        inputs,
+        target_op_id: Some(OpIdAttr(target)),
+        name: Some(StringAttr(name)),
+        ..
+      }) => {
+        let (args, results, _) = port_names(env, *target, sanitize)?;
+        let connections = args
+          .iter()
+          .zip(self.names(inputs, op_id)?)
+          .chain(results.iter().zip(self.names(outputs, op_id)?))
+          .map(|(port, value)| format!("    .{}({})", port, value))
+          .collect::<Vec<_>>();
+        let name = self.table.get_legal_name_in(None, &sanitize(name));
+        self.body.extend([
+          format!("  {} {} (", module_name(env, *target, sanitize)?, name),
+          connections.join(",\n"),
+          "  );".into(),
+        ]);
+      },
+      OpEnum::ItprtCondCheck(ItprtCondCheck {
+        conds,
+        has_default: Some(BoolAttr(has_default)),
+        onehot: Some(BoolAttr(onehot)),
+        ..
+      }) => {
+        let conds = format!("{{{}}}", self.names(conds, op_id)?.join(", "));
+        let mut checks = vec![];
+        if *onehot {
+          checks.push(format!("$onehot0({})", conds));
+        }
+        if !has_default {
+          checks.push(format!("|{}", conds));
+        }
+        if !checks.is_empty() {
+          self.body.push(format!(
+            "  always_comb assert ({}) else $error(\"condition check failed\");",
+            checks.join(" && ")
+          ));
+        }
+      },
+      op => {
+        return Err(
+          IronyError::new(format!(
+            "`{}` can't be emitted to SystemVerilog, it must be lowered first",
+            op.get_op_name()
+          ))
+          .with_op(op_id),
+        );
+      },
+    }
+    Ok(())
+  }
+}
+
+/// A module emitted as SystemVerilog
+#[derive(Clone, Debug, PartialEq)]
+pub struct SvModule {
+  pub name: String,
+  pub text: String,
+  /// Source locations of the statements of `text`, by line number from 1
+  pub source_map: Vec<(usize, String)>,
+}
+
+/// Emit a module in the `hw`, `comb` and `seq` dialects as a SystemVerilog module
+pub fn emit_sv<E>(env: &E, module: OpId) -> IronyResult<SvModule>
+where E: Environ<EntityT = EntityEnum, OpT = OpEnum> {
+  let OpEnum::HwModule(HwModule { is_extern, body: Some(body), .. }) = env.get_op(module)
+  else {
+    return Err(IronyError::new("must be a module with a body").with_op(module));
+  };
+  if *is_extern == Some(BoolAttr(true)) {
+    return Err(IronyError::new("extern modules have no body to emit").with_op(module));
+  }
+  let ops = env.get_region(*body).op_children.to_owned();
+  let (args, results, table) = port_names(env, module, sanitize)?;
+  let mut emitter = ModuleEmitter {
+    env,
+    names: HashMap::new(),
+    table,
+    decls: vec![],
+    body: vec![],
+  };
+
+  let find = |f: fn(&OpEnum) -> Option<Vec<Option<EntityId>>>| {
+    ops.iter().find_map(|x| f(env.get_op(*x))).unwrap_or_default()
+  };
+  let inputs = find(|x| match x {
+    OpEnum::HwInput(HwInput { inputs, .. }) => Some(inputs.to_owned()),
+    _ => None,
+  });
+  let outputs = find(|x| match x {
+    OpEnum::HwOutput(HwOutput { outputs, .. }) => Some(outputs.to_owned()),
+    _ => None,
+  });
+  let mut defined = HashSet::new();
+  for op_id in ops.iter() {
+    if !matches!(env.get_op(*op_id), OpEnum::HwInput(_)) {
+      for (_, x) in env.get_op(*op_id).get_defs() {
+        defined.extend(x.into_iter().flatten());
+      }
+    }
+  }
+
+  let mut ports = vec![];
+  for (entity, name) in inputs.iter().zip(args) {
+    let entity =
+      entity.ok_or_else(|| IronyError::new("missing input").with_op(module))?;
+    ports.push(format!("  input  {} {}", emitter.sv_type(entity)?, name));
+    emitter.names.insert(entity, name);
+  }
+  // outputs are driven by the ops defining them, unless they are driven by inputs or
+  // other outputs
+  let mut driven = vec![];
+  for (entity, name) in outputs.iter().zip(results) {
+    let entity =
+      entity.ok_or_else(|| IronyError::new("missing output").with_op(module))?;
+    ports.push(format!("  output {} {}", emitter.sv_type(entity)?, name));
+    if emitter.names.contains_key(&entity) || !defined.contains(&entity) {
+      driven.push((name, entity));
+    } else {
+      emitter.names.insert(entity, name);
+    }
+  }
+
+  for op_id in ops.iter() {
+    let op = env.get_op(*op_id);
+    for (_, x) in op.get_defs().into_iter().chain(op.get_uses()) {
+      for entity in x.into_iter().flatten() {
+        emitter.declare(entity)?;
+      }
+    }
+  }
+  // the first statement emitted for an op is annotated with its location
+  let mut locations = HashMap::new();
+  for op_id in ops {
+    let start = emitter.body.len();
+    emitter.op(op_id)?;
+    if let (Some(location), Some(line)) =
+      (op_location(env, op_id), emitter.body.get_mut(start))
+    {
+      let (file_line, _) = location.rsplit_once(':').unwrap_or((&location, ""));
+      let end = line.find('\n').unwrap_or(line.len());
+      line.insert_str(end, &format!("  // {}", file_line));
+      locations.insert(start, location);
+    }
+  }
+  for (name, entity) in driven {
+    let value = emitter.name(&Some(entity), module)?;
+    emitter.body.push(format!("  assign {} = {};", name, value));
+  }
+
+  let name = module_name(env, module, sanitize)?;
+  let mut text = format!("module {} (\n{}\n);\n", name, ports.join(",\n"));
+  text += &emitter.decls.iter().map(|x| format!("{}\n", x)).collect::<String>();
+  let mut source_map = vec![];
+  for (i, statement) in emitter.body.iter().enumerate() {
+    if let Some(location) = locations.remove(&i) {
+      source_map.push((text.matches('\n').count() + 1, location));
+    }
+    text += &format!("{}\n", statement);
+  }
+  text += "endmodule\n";
+  Ok(SvModule { name, text, source_map })
+}
+
+/// Location of an op, which is where the first of its results is defined
+fn op_location<E>(env: &E, op_id: OpId) -> Option<String>
+where E: Environ<EntityT = EntityEnum, OpT = OpEnum> {
+  env
+    .get_op(op_id)
+    .get_defs()
+    .into_iter()
+    .flat_map(|(_, x)| x.into_iter().flatten())
+    .find_map(|x| env.get_entity(x).get_attr("location"))
+    .map(|x| x.to_string())
+}
+
+/// Emit every top-level module but extern ones, which are provided by IPs, as a
+/// SystemVerilog module, named after the module
+pub fn emit_sv_modules<E>(env: &E) -> IronyResult<Vec<SvModule>>
+where E: Environ<EntityT = EntityEnum, OpT = OpEnum> {
+  let mut modules = vec![];
+  for op_id in env.get_ops_with_parent(None) {
+    if let OpEnum::HwModule(HwModule { is_extern, .. }) = env.get_op(op_id) {
+      if *is_extern != Some(BoolAttr(true)) {
+        modules.push(emit_sv(env, op_id)?);
+      }
+    }
+  }
+  Ok(modules)
+}
+
+/// JSON source map of the files of `modules`, from their lines to the source
+/// locations of the statements there, to trace reports of synthesis tools back
+pub fn sv_source_map(modules: &[SvModule]) -> String {
+  let files = modules.iter().map(|module| {
+    let lines = module
+      .source_map
+      .iter()
+      .map(|(line, location)| (line.to_string(), Json::Str(location.to_owned())));
+    (format!("{}.sv", module.name), Json::obj(lines))
+  });
+  format!("{}\n", Json::obj(files))
+}
@@ -1,4 +1,6 @@
-use irony_cmt::{CmtIR, Environ, FxHashSet, FxIndexMap, Id, RegionId};
+use irony::{Environ, FxHashSet, FxIndexMap, Id, RegionId};
+
+use crate::CmtIR;
 
 #[derive(Debug, Default)]
 pub struct SymbolTable {
@@ -8,7 +10,7 @@ pub struct SymbolTable {
 impl SymbolTable {
   pub fn get_legal_name_in_region(&mut self, ir: &CmtIR, raw_name: &str) -> String {
     // let region_code = self.env.parent_stack.last().unwrap().encode();
-    let region_code = ir
+    let region = ir
       .parent_stack
       .iter()
       .rev()
@@ -22,8 +24,16 @@ impl SymbolTable {
         },
         None => Some(None),
       })
-      .unwrap()
-      .encode();
+      .unwrap();
+    self.get_legal_name_in(region, raw_name)
+  }
+
+  /// Get a name unique among the names taken in `region`, the region of a module
+  /// body or `None` for the top level
+  pub fn get_legal_name_in(
+    &mut self, region: Option<RegionId>, raw_name: &str,
+  ) -> String {
+    let region_code = region.encode();
 
     let raw_name = if raw_name.len() > 50 {
       let mut total_length = 0;
@@ -68,6 +78,11 @@ impl SymbolTable {
 
     retval
   }
+
+  /// Mark `name` as taken in `region`, for names not given by the table
+  pub fn insert(&mut self, region: Option<RegionId>, name: String) {
+    self.table.entry(region.encode()).or_default().insert(name);
+  }
 }
 
 trait Encode {
@@ -30,7 +30,7 @@ mod hw_test {
           Some(DataTypeEnum::UInt(8.into())),
           Some("a".into()),
           Some(true.into()),
-          Some(LocationAttr(Location::caller().to_owned())),
+          Some(Location::caller().into()),
         )
         .into(),
       );
@@ -39,7 +39,7 @@ mod hw_test {
       cmt.add_op(HwOutput::new(vec![Some(a)]).into());
     });
 
-    assert!(cmt.verify_op(module_pass_def));
+    assert!(cmt.verify_op(module_pass_def).is_ok());
 
     let module_body = cmt.add_region(Region::new(true));
     let module_def = cmt.add_op(
@@ -62,7 +62,7 @@ mod hw_test {
           Some(DataTypeEnum::UInt(1.into())),
           Some("clk".into()),
           Some(true.into()),
-          Some(LocationAttr(Location::caller().to_owned())),
+          Some(Location::caller().into()),
         )
         .into(),
       );
@@ -71,7 +71,7 @@ mod hw_test {
           Some(DataTypeEnum::UInt(8.into())),
           Some("a".into()),
           Some(true.into()),
-          Some(LocationAttr(Location::caller().to_owned())),
+          Some(Location::caller().into()),
         )
         .into(),
       );
@@ -80,7 +80,7 @@ mod hw_test {
           Some(DataTypeEnum::UInt(8.into())),
           Some("b".into()),
           Some(true.into()),
-          Some(LocationAttr(Location::caller().to_owned())),
+          Some(Location::caller().into()),
         )
         .into(),
       );
@@ -89,7 +89,7 @@ mod hw_test {
           Some(DataTypeEnum::UInt(8.into())),
           Some("c".into()),
           Some(true.into()),
-          Some(LocationAttr(Location::caller().to_owned())),
+          Some(Location::caller().into()),
         )
         .into(),
       );
@@ -98,7 +98,7 @@ mod hw_test {
           Some(DataTypeEnum::UInt(8.into())),
           Some("d".into()),
           Some(true.into()),
-          Some(LocationAttr(Location::caller().to_owned())),
+          Some(Location::caller().into()),
         )
         .into(),
       );
@@ -107,7 +107,7 @@ mod hw_test {
           Some(DataTypeEnum::UInt(8.into())),
           Some("e".into()),
           Some(true.into()),
-          Some(LocationAttr(Location::caller().to_owned())),
+          Some(Location::caller().into()),
         )
         .into(),
       );
@@ -116,7 +116,7 @@ mod hw_test {
           Some(DataTypeEnum::UInt(1.into())),
           Some("cond".into()),
           Some(true.into()),
-          Some(LocationAttr(Location::caller().to_owned())),
+          Some(Location::caller().into()),
         )
         .into(),
       );
@@ -125,7 +125,7 @@ mod hw_test {
           Some(DataTypeEnum::UInt(8.into())),
           Some("h".into()),
           Some(true.into()),
-          Some(LocationAttr(Location::caller().to_owned())),
+          Some(Location::caller().into()),
         )
         .into(),
       );
@@ -134,7 +134,7 @@ mod hw_test {
           Some(DataTypeEnum::UInt(8.into())),
           Some("h_reg".into()),
           Some(true.into()),
-          Some(LocationAttr(Location::caller().to_owned())),
+          Some(Location::caller().into()),
         )
         .into(),
       );
@@ -151,7 +151,7 @@ mod hw_test {
         .into(),
       );
 
-      assert!(cmt.verify_op(instance));
+      assert!(cmt.verify_op(instance).is_ok());
 
       cmt.add_op(HwConstant::new(Some(c), Some([1, 0, 0, 0].into())).into());
       cmt.add_op(
@@ -225,7 +225,7 @@ mod hw_test {
           Some(DataTypeEnum::UInt(8.into())),
           Some("a".into()),
           Some(true.into()),
-          Some(LocationAttr(Location::caller().to_owned())),
+          Some(Location::caller().into()),
         )
         .into(),
       );
@@ -234,7 +234,7 @@ mod hw_test {
           Some(DataTypeEnum::UInt(8.into())),
           Some("b".into()),
           Some(true.into()),
-          Some(LocationAttr(Location::caller().to_owned())),
+          Some(Location::caller().into()),
         )
         .into(),
       );
@@ -243,7 +243,7 @@ mod hw_test {
       circt.add_op(HwOutput::new(vec![Some(a)]).into());
     });
 
-    assert!(circt.verify_op(module_def))
+    assert!(circt.verify_op(module_def).is_ok())
   }
 
   #[test]
@@ -271,7 +271,7 @@ mod hw_test {
           Some(DataTypeEnum::UInt(8.into())),
           Some("a".into()),
           Some(true.into()),
-          Some(LocationAttr(Location::caller().to_owned())),
+          Some(Location::caller().into()),
         )
         .into(),
       );
@@ -280,7 +280,7 @@ mod hw_test {
       circt.add_op(HwOutput::new(vec![Some(a)]).into());
     });
 
-    assert!(circt.verify_op(module_pass_def));
+    assert!(circt.verify_op(module_pass_def).is_ok());
 
     let module_body = circt.add_region(Region::new(true));
     circt.add_op(
@@ -303,7 +303,7 @@ mod hw_test {
           Some(DataTypeEnum::UInt(8.into())),
           Some("a".into()),
           Some(true.into()),
-          Some(LocationAttr(Location::caller().to_owned())),
+          Some(Location::caller().into()),
         )
         .into(),
       );
@@ -312,7 +312,7 @@ mod hw_test {
           Some(DataTypeEnum::UInt(8.into())),
           Some("b".into()),
           Some(true.into()),
-          Some(LocationAttr(Location::caller().to_owned())),
+          Some(Location::caller().into()),
         )
         .into(),
       );
@@ -330,7 +330,1114 @@ mod hw_test {
         .into(),
       );
 
-      assert!(circt.verify_op(instance))
+      assert!(circt.verify_op(instance).is_ok())
     });
   }
 }
+
+mod parse_test {
+  use irony::Environ;
+
+  use crate::*;
+
+  fn print_all(cmt: &CmtIR) -> String {
+    cmt
+      .op_table
+      .iter()
+      .filter(|(_, op)| op.get_parent().is_none())
+      .map(|(id, _)| cmt.print_op(OpId(*id)))
+      .collect::<Vec<_>>()
+      .join("\n")
+  }
+
+  /// Printing drops nothing but the locations, which become the location of the
+  /// parser, and the location comments of debug entities
+  fn strip_locations(src: &str) -> String {
+    src
+      .lines()
+      .filter(|x| !x.trim_start().starts_with("// %"))
+      .map(|x| x.find(" loc(\"").map_or(x, |i| &x[..i]))
+      .collect::<Vec<_>>()
+      .join("\n")
+  }
+
+  #[test]
+  pub fn round_trip_test() {
+    let (cmt, ..) = super::hw_test::create();
+    let printed = print_all(&cmt);
+    let parsed = CmtIR::parse(&printed).unwrap();
+    assert!(printed.contains("%d = comb.add %b, %c : i8 loc(\"irony_cmt/src/tests.rs\":"));
+    assert_eq!(strip_locations(&print_all(&parsed)), strip_locations(&printed));
+  }
+
+  #[test]
+  pub fn stmt_round_trip_test() {
+    let src = r#"hw.module @counter(%clk: i1, %go: i1) -> (done: i1, count: i8) {
+	// hw.input %clk, %go : i1, i1
+	%zero = hw.constant 0: i8
+	%one = hw.constant 1: i8
+	%count_next = comb.add %count, %one : i8
+	%count = seq.compreg %count_next ,%clk   : i8
+	%full = comb.icmp eq %count, %one : i8
+	%pair = hw.array_create %zero, %one : i8
+	%first = hw.array_get %pair[%full] : !hw.array<2xi8>, i1
+	%table = hw.aggregate_constant [1 : i8, 2 : i8] : !hw.array<2xi8>
+	%bits = comb.concat %full, %first : i1, i8
+	%low = comb.extract %bits from 1 : (i9) -> i2
+	%ev_go = event.def
+	event.signal %ev_go === %go
+	%ev_done = event.def
+	event.port %ev_done === [%full]
+	%s0 = stmt.step %ev_go {}
+	%s1 = stmt.step %ev_done {wait-at-exit: %ev_go}
+	%i = hw.wire %count : i8
+	%body = stmt.seq %s0, %s1
+	%loop = stmt.for (%i, %count_next) = 0 to 4 step 1 do %body
+	%top = stmt.if %full then {%loop} else {%s0}
+	%sel = ILLEGAL.select onehot {
+		%full : %zero, 
+		[TBD] : %one
+		default : %first
+	} : i8
+	stmt.synth %top into protocol {go: %ev_go, done: %ev_done, clk: %clk}
+	hw.output %full, %sel : i1, i8
+
+}
+hw.module @wrapper(%clk: i1, %go: i1) -> (done: i1) {
+	// hw.input %clk, %go : i1, i1
+	%done, %count = hw.instance "inner" @counter(clk : %clk : i1, go : %go : i1) -> (done: i1, count: i8)
+	hw.output %done : i1
+
+}"#;
+    let parsed = CmtIR::parse(src).unwrap();
+    assert_eq!(strip_locations(&print_all(&parsed)), src);
+  }
+
+  #[test]
+  pub fn parse_error_test() {
+    let Err(err) = CmtIR::parse("hw.module @m() -> () {\n\t%a = comb.foo %b : i1\n}") else {
+      panic!("unknown op parsed");
+    };
+    assert_eq!((err.line, err.col), (2, 7));
+  }
+}
+
+mod interpret_test {
+  use crate::*;
+
+  fn interpreter<'ir>(ir: &'ir CmtIR, name: &str) -> Interpreter<'ir> {
+    let module = ir
+      .op_table
+      .iter()
+      .find_map(|(id, op)| match op {
+        OpEnum::HwModule(module) if module.name.as_ref().unwrap().0 == name => Some(OpId(*id)),
+        _ => None,
+      })
+      .unwrap();
+    Interpreter::new(ir, module).unwrap()
+  }
+
+  #[test]
+  pub fn counter_test() {
+    let ir = CmtIR::parse(
+      r#"hw.module @inc(%x: i8) -> (y: i8) {
+	// hw.input %x : i8
+	%one = hw.constant 1: i8
+	%y = comb.add %x, %one : i8
+	hw.output %y : i8
+}
+hw.module @counter(%clk: i1, %en: i1) -> (count: i8) {
+	// hw.input %clk, %en : i1, i1
+	%next, = hw.instance "inc" @inc(x : %count : i8) -> (y: i8)
+	%d = comb.mux %en, %next, %count : i8
+	%count = seq.compreg %d ,%clk   : i8
+	hw.output %count : i8
+}"#,
+    )
+    .unwrap();
+    let mut interpreter = interpreter(&ir, "counter");
+    interpreter.poke("en", Value::from_bool(true)).unwrap();
+    for _ in 0..3 {
+      interpreter.step().unwrap();
+    }
+    interpreter.poke("en", Value::from_bool(false)).unwrap();
+    interpreter.step().unwrap();
+    interpreter.eval().unwrap();
+    assert_eq!(interpreter.cycle(), 4);
+    assert_eq!(interpreter.peek("count"), Some(Value::from_u32(3, 8)));
+  }
+
+  #[test]
+  pub fn comb_test() {
+    let ir = CmtIR::parse(
+      r#"hw.module @comb(%a: i8, %b: i8) -> (div: i8, mod: i8, shr: i8, lt: i1, cat: i4, lo_pair: i8, hi_pair: i8) {
+	// hw.input %a, %b : i8, i8
+	%div = comb.divs %a, %b : i8
+	%mod = comb.mods %a, %b : i8
+	%two = hw.constant 2: i8
+	%shr = comb.shrs %a, %two : i8
+	%lt = comb.icmp slt %a, %b : i8
+	%hi = comb.extract %a from 6 : (i8) -> i2
+	%lo = comb.extract %b from 0 : (i8) -> i2
+	%cat = comb.concat %hi, %lo : i2, i2
+	%s = hw.struct_create (%a, %b) : !hw.struct<x: i8, y: i8>
+	%five = hw.constant 5: i8
+	%t = hw.struct_inject %s["x"], %five : !hw.struct<x: i8, y: i8>
+	%arr = hw.bitcast %t: (!hw.struct<x: i8, y: i8>) -> !hw.array<2xi8>
+	%zero = hw.constant 0: i1
+	%one = hw.constant 1: i1
+	%lo_pair = hw.array_get %arr[%zero] : !hw.array<2xi8>, i1
+	%hi_pair = hw.array_get %arr[%one] : !hw.array<2xi8>, i1
+	hw.output %div, %mod, %shr, %lt, %cat, %lo_pair, %hi_pair : i8, i8, i8, i1, i4, i8, i8
+}"#,
+    )
+    .unwrap();
+    let mut interpreter = interpreter(&ir, "comb");
+    // -7 and 2
+    interpreter.poke("a", Value::from_u32(0xf9, 8)).unwrap();
+    interpreter.poke("b", Value::from_u32(2, 8)).unwrap();
+    interpreter.eval().unwrap();
+    assert_eq!(interpreter.peek("div"), Some(Value::from_u32(0xfd, 8)));
+    assert_eq!(interpreter.peek("mod"), Some(Value::from_u32(0xff, 8)));
+    assert_eq!(interpreter.peek("shr"), Some(Value::from_u32(0xfe, 8)));
+    assert_eq!(interpreter.peek("lt"), Some(Value::from_bool(true)));
+    assert_eq!(interpreter.peek("cat"), Some(Value::from_u32(0b1110, 4)));
+    // element and field 0 are the least significant ones
+    assert_eq!(interpreter.peek("lo_pair"), Some(Value::from_u32(5, 8)));
+    assert_eq!(interpreter.peek("hi_pair"), Some(Value::from_u32(2, 8)));
+  }
+
+  #[test]
+  pub fn error_test() {
+    let ir = CmtIR::parse(
+      r#"hw.module @loop(%a: i1) -> (b: i1) {
+	// hw.input %a : i1
+	%b = comb.and %a, %c : i1
+	%c = hw.wire %b : i1
+	hw.output %b : i1
+}
+hw.module @check(%a: i1, %b: i1) -> () {
+	// hw.input %a, %b : i1, i1
+	itprt.cond_check %a, %b {has_default = 1, onehot = 1}
+	hw.output  : 
+}"#,
+    )
+    .unwrap();
+    let mut comb_loop = interpreter(&ir, "loop");
+    assert!(comb_loop.eval().unwrap_err().message.contains("combinational loop"));
+
+    let mut check = interpreter(&ir, "check");
+    check.poke("a", Value::from_bool(true)).unwrap();
+    check.eval().unwrap();
+    check.poke("b", Value::from_bool(true)).unwrap();
+    let err = check.eval().unwrap_err();
+    assert!(err.op.is_some() && err.message.contains("onehot"));
+  }
+}
+
+mod verify_test {
+  use irony::Environ;
+
+  use crate::*;
+
+  #[test]
+  pub fn verify_ok_test() {
+    let ir = CmtIR::parse(
+      r#"hw.module @pair(%clk: i1, %a: i8) -> (o: !hw.struct<x: i8, y: i8>) {
+	// hw.input %clk, %a : i1, i8
+	%zero = hw.constant 0: i8
+	%r = seq.compreg %a ,%clk   : i8
+	%o = hw.struct_create (%r, %zero) : !hw.struct<x: i8, y: i8>
+	hw.output %o : !hw.struct<x: i8, y: i8>
+}"#,
+    )
+    .unwrap();
+    assert!(ir.verify().is_ok());
+  }
+
+  #[test]
+  pub fn verify_err_test() {
+    let ir = CmtIR::parse(
+      r#"hw.module @pair(%clk: i1, %a: i4) -> (o: i8) {
+	// hw.input %clk, %a : i1, i4
+	%o = seq.compreg %a ,%clk   : i8
+	%s = hw.struct_create (%a) : !hw.struct<x: i8>
+	hw.output %o : i8
+}"#,
+    )
+    .unwrap();
+    let diagnostics = ir.verify().unwrap_err();
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0].op_name, "SeqCompReg");
+    assert_eq!(diagnostics[0].message, "registers i4 as i8");
+    assert!(diagnostics[0].op_text.starts_with("%o = seq.compreg %a"));
+    assert_eq!(diagnostics[0].locations.len(), 3);
+    assert!(diagnostics[0].to_string().contains("%a at "));
+    assert_eq!(diagnostics[1].op_name, "HwStructCreate");
+    assert_eq!(diagnostics[1].message, "creates the field `x` of !hw.struct<x: i8> from i4");
+  }
+}
+
+mod pass_test {
+  use std::panic::Location;
+
+  use irony::{Environ, PassManagerTrait, PassPipeline};
+
+  use crate::*;
+
+  fn run(ir: &mut CmtIR, pipeline: &str) -> irony::IronyResult<PassManager> {
+    let mut pass_manager = PassManager::default();
+    pass_manager.add_pipeline(PassPipeline::parse(pipeline)?);
+    pass_manager.run_passes(ir)?;
+    Ok(pass_manager)
+  }
+
+  #[test]
+  pub fn unguarded_select_test() {
+    let mut ir = CmtIR::parse(
+      r#"hw.module @sel(%a: i1) -> (o: i1) {
+	// hw.input %a : i1
+	%o = ILLEGAL.select priority {
+		[TBD] : %a
+	} : i1
+	hw.output %o : i1
+}"#,
+    )
+    .unwrap();
+    let Err(err) = run(&mut ir, "remove-event") else { panic!("must fail") };
+    assert_eq!(err.pass.as_deref(), Some("remove-event"));
+    assert!(matches!(ir.get_op(err.op.unwrap()), OpEnum::TmpSelect(_)));
+    assert!(err.entity.is_some() && err.location.is_some());
+    let message = "[remove-event] %a: value to be selected must be guarded at ";
+    assert!(err.to_string().starts_with(message));
+  }
+
+  #[test]
+  pub fn remove_event_when_test() {
+    let mut ir = CmtIR::parse(
+      r#"hw.module @guarded(%clk: i1, %go: i1) -> (o: i8) {
+	// hw.input %clk, %go : i1, i1
+	%ev = event.def
+	event.signal %ev === %go
+	%one = hw.constant 1: i8
+	%reg = seq.compreg %o.w_port ,%clk   : i8
+	%o = hw.wire %reg : i8
+	%select = ILLEGAL.select priority {
+		[TBD] : %wr
+		default : %o
+	} : i8
+	%o.w_port = hw.wire %select : i8
+	ILLEGAL.when %ev {
+		%add = comb.add %o, %one : i8
+		%wr = hw.wire %add : i8
+	}
+	hw.output %o : i8
+}"#,
+    )
+    .unwrap();
+    run(&mut ir, "remove-event,remove-select").unwrap();
+    // `%wr` is only used by the select. Ops like it used to be dropped from the body,
+    // leaving the register write undriven, which showed up when the design of
+    // `dce_when_test` was interpreted after `dce` and `remove-event`
+    let module = ir.get_ops_with_parent(None)[0];
+    assert!(ir.print_op(module).contains("%wr = hw.wire %add"));
+    let mut interpreter = Interpreter::new(&ir, module).unwrap();
+    interpreter.poke("go", Value::from_bool(true)).unwrap();
+    interpreter.step().unwrap();
+    interpreter.step().unwrap();
+    interpreter.eval().unwrap();
+    assert_eq!(interpreter.peek("o"), Some(Value::from_u32(2, 8)));
+  }
+
+  #[test]
+  pub fn remove_unary_test() {
+    let mut ir = CmtIR::parse(
+      r#"hw.module @unary(%a: i8) -> (not: i8, neg: i8) {
+	// hw.input %a : i8
+	%not = ILLEGAL.not %a : i8
+	%neg = ILLEGAL.neg %a : i8
+	hw.output %not, %neg : i8, i8
+}"#,
+    )
+    .unwrap();
+    let pass_manager = run(&mut ir, "remove-unary").unwrap();
+    let statistics = pass_manager.statistics();
+    assert_eq!(statistics.len(), 1);
+    assert_eq!(statistics[0].runs, 1);
+    // two unary ops are replaced by two constants and two binary ops
+    assert_eq!(statistics[0].ops_after, statistics[0].ops_before + 2);
+    assert!(ir.verify().is_ok());
+    let module = ir.get_ops_with_parent(None)[0];
+    let mut interpreter = Interpreter::new(&ir, module).unwrap();
+    interpreter.poke("a", Value::from_u32(3, 8)).unwrap();
+    interpreter.eval().unwrap();
+    assert_eq!(interpreter.peek("not"), Some(Value::from_u32(0xfc, 8)));
+    assert_eq!(interpreter.peek("neg"), Some(Value::from_u32(0xfd, 8)));
+  }
+
+  #[test]
+  pub fn pipeline_test() {
+    let pipeline = PassPipeline::<PassEnum>::parse(" reorder, remove-event,,remove-select ");
+    let names = pipeline.unwrap().0.iter().map(|x| x.name()).collect::<Vec<_>>();
+    assert_eq!(names, ["reorder", "remove-event", "remove-select"]);
+
+    let Err(err) = PassPipeline::<PassEnum>::parse("reorder,remove-events") else {
+      panic!("must fail")
+    };
+    assert_eq!(
+      err.message,
+      "unknown pass `remove-events`, expected one of: \
+       reorder, remove-event, remove-select, remove-unary, canonicalize, cse, dce, flatten"
+    );
+  }
+
+  #[test]
+  pub fn canonicalize_test() {
+    let src = r#"hw.module @canon(%a: i8) -> (o: i8, p: i8, q: i1, r: i8, s: i8) {
+	// hw.input %a : i8
+	%zero = hw.constant 0: i8
+	%ones = hw.constant 255: i8
+	%two = hw.constant 2: i8
+	%three = hw.constant 3: i8
+	%five = comb.add %two, %three : i8
+	%x = comb.and %a, %ones : i8
+	%y = comb.or %x, %zero, %x : i8
+	%t = hw.constant 1: i1
+	%m = comb.mux %t, %five, %y : i8
+	%w = hw.wire %m : i8
+	%o = hw.wire %w : i8
+	%p = comb.mul %five, %two : i8
+	%q = comb.icmp eq %a, %a : i8
+	%r = comb.sub %a, %zero : i8
+	%f = hw.constant 0: i1
+	%s = comb.mux %f, %five, %y : i8
+	hw.output %o, %p, %q, %r, %s : i8, i8, i1, i8, i8
+}"#;
+    let eval = |ir: &CmtIR| {
+      let mut interpreter = Interpreter::new(ir, find_module(ir, "canon").unwrap()).unwrap();
+      interpreter.poke("a", Value::from_u32(0x5a, 8)).unwrap();
+      interpreter.eval().unwrap();
+      ["o", "p", "q", "r", "s"].map(|x| interpreter.peek(x).unwrap())
+    };
+    let mut ir = CmtIR::parse(src).unwrap();
+    let expected = eval(&ir);
+    run(&mut ir, "canonicalize,dce").unwrap();
+    assert_eq!(eval(&ir), expected);
+    assert!(ir.verify().is_ok());
+
+    let module = ir.print_op(find_module(&ir, "canon").unwrap());
+    assert!(!module.contains("comb.") && !module.contains("hw.wire"));
+    assert!(module.contains("%o = hw.constant 5: i8"));
+    assert!(module.contains("%p = hw.constant 10: i8"));
+    assert!(module.contains("%q = hw.constant 1: i1"));
+    assert!(module.contains("hw.output %o, %p, %q, %a, %a"));
+  }
+
+  #[test]
+  pub fn canonicalize_names_test() {
+    let mut ir = CmtIR::parse(
+      r#"hw.module @names(%a: i8, %b: i8) -> (o: i8) {
+	// hw.input %a, %b : i8, i8
+	%t0 = comb.add %a, %b : i8
+	%sum = hw.wire %t0 : i8
+	hw.output %sum : i8
+}"#,
+    )
+    .unwrap();
+    let named = |ir: &CmtIR, name: &str| {
+      ir.entity_table.iter().find_map(|(id, x)| match x {
+        EntityEnum::IRWire(wire) if wire.name.as_ref().unwrap().0 == name => {
+          Some(EntityId(*id))
+        },
+        _ => None,
+      })
+    };
+    let t0 = named(&ir, "t0").unwrap();
+    let location: LocationAttr = Location::caller().into();
+    ir.get_entity_entry(t0).and_modify(|x| {
+      if let EntityEnum::IRWire(wire) = x {
+        wire.location = Some(location.to_owned());
+      }
+    });
+    run(&mut ir, "canonicalize").unwrap();
+    let module = ir.print_op(find_module(&ir, "names").unwrap());
+    assert!(module.contains("%sum = comb.add %a, %b : i8"));
+    assert!(!module.contains("%t0") && !module.contains("hw.wire"));
+    // `%sum` is computed where `%t0` was
+    let EntityEnum::IRWire(sum) = ir.get_entity(named(&ir, "sum").unwrap()) else {
+      unreachable!()
+    };
+    assert_eq!(sum.location, Some(location));
+  }
+
+  #[test]
+  pub fn cse_test() {
+    let src = r#"hw.module @cse(%a: i8, %b: i8) -> (o: i1, p: i8, q: i4) {
+	// hw.input %a, %b : i8, i8
+	%x0 = comb.extract %a from 0 : (i8) -> i1
+	%x1 = comb.extract %a from 0 : (i8) -> i1
+	%x2 = comb.extract %a from 1 : (i8) -> i1
+	%e = comb.xor %x0, %x2 : i1
+	%f = comb.xor %x1, %x2 : i1
+	%o = comb.and %e, %f : i1
+	%s0 = comb.add %a, %b : i8
+	%s1 = comb.add %a, %b : i8
+	%d = comb.sub %a, %b : i8
+	%p = comb.mul %s0, %s1, %d : i8
+	%l0 = comb.extract %a from 0 : (i8) -> i4
+	%q = comb.xor %l0, %l0 : i4
+	hw.output %o, %p, %q : i1, i8, i4
+}"#;
+    let eval = |ir: &CmtIR| {
+      let mut interpreter = Interpreter::new(ir, find_module(ir, "cse").unwrap()).unwrap();
+      interpreter.poke("a", Value::from_u32(0x5a, 8)).unwrap();
+      interpreter.poke("b", Value::from_u32(0x17, 8)).unwrap();
+      interpreter.eval().unwrap();
+      ["o", "p", "q"].map(|x| interpreter.peek(x).unwrap())
+    };
+    let mut ir = CmtIR::parse(src).unwrap();
+    let expected = eval(&ir);
+    run(&mut ir, "cse").unwrap();
+    assert_eq!(eval(&ir), expected);
+    assert!(ir.verify().is_ok());
+
+    let module = ir.print_op(find_module(&ir, "cse").unwrap());
+    for merged in ["%x1", "%f", "%s1"] {
+      assert!(!module.contains(merged));
+    }
+    // same operand and offset but a different width
+    assert!(module.contains("%l0 = comb.extract %a from 0 : (i8) -> i4"));
+    assert!(module.contains("%o = comb.and %e, %e : i1"));
+    assert!(module.contains("%p = comb.mul %s0, %s0, %d : i8"));
+  }
+
+  const HIERARCHY: &str = r#"hw.module @inc(%x: i8) -> (y: i8) {
+	// hw.input %x : i8
+	%one = hw.constant 1: i8
+	%y = comb.add %x, %one : i8
+	hw.output %y : i8
+}
+hw.module @twice(%x: i8) -> (y: i8) {
+	// hw.input %x : i8
+	%m, = hw.instance "a" @inc(x : %x : i8) -> (y: i8)
+	%y, = hw.instance "b" @inc(x : %m : i8) -> (y: i8)
+	hw.output %y : i8
+}
+hw.module.extern @ext(%x: i8) -> (y: i8)
+"#;
+
+  #[test]
+  pub fn flatten_test() {
+    let src = HIERARCHY.to_string()
+      + r#"hw.module @top(%a: i8) -> (o: i8) {
+	// hw.input %a : i8
+	%t_m = comb.add %a, %a : i8
+	%b, = hw.instance "t" @twice(x : %t_m : i8) -> (y: i8)
+	%o = comb.xor %a, %b : i8
+	hw.output %o : i8
+}"#;
+    let eval = |ir: &CmtIR| {
+      let mut interpreter = Interpreter::new(ir, find_module(ir, "top").unwrap()).unwrap();
+      interpreter.poke("a", Value::from_u32(0x2c, 8)).unwrap();
+      interpreter.eval().unwrap();
+      interpreter.peek("o").unwrap()
+    };
+    let mut ir = CmtIR::parse(&src).unwrap();
+    let expected = eval(&ir);
+    run(&mut ir, "flatten").unwrap();
+    assert_eq!(eval(&ir), expected);
+    assert!(ir.verify().is_ok());
+
+    let module = ir.print_op(find_module(&ir, "top").unwrap());
+    assert!(!module.contains("hw.instance"));
+    assert!(module.contains("%t_a_one = hw.constant 1: i8"));
+    assert!(module.contains("%t_a_y = comb.add %t_m, %t_a_one : i8"));
+    // the name `t_m` is taken by a wire of the parent
+    assert!(module.contains("%t_m_1 = hw.wire %t_a_y : i8"));
+    assert!(module.contains("%b = hw.wire %t_y : i8"));
+  }
+
+  #[test]
+  pub fn flatten_selected_test() {
+    let src = HIERARCHY.to_string()
+      + r#"hw.module @top(%a: i8) -> (o: i8) {
+	// hw.input %a : i8
+	%i, = hw.instance "i" @inc(x : %a : i8) -> (y: i8)
+	%t, = hw.instance "t" @twice(x : %i : i8) -> (y: i8)
+	%o, = hw.instance "e" @ext(x : %t : i8) -> (y: i8)
+	hw.output %o : i8
+}"#;
+    let mut ir = CmtIR::parse(&src).unwrap();
+    let top = find_module(&ir, "top").unwrap();
+    let flatten = FlattenPass {
+      modules: Some(vec![find_module(&ir, "inc").unwrap()]),
+      max_ops: None,
+    };
+    let mut pass_manager = PassManager::default();
+    pass_manager.add_passes(vec![flatten.into()], vec![vec![top]]);
+    pass_manager.run_passes(&mut ir).unwrap();
+
+    let module = ir.print_op(top);
+    assert!(!module.contains("@inc"));
+    assert!(module.contains("%t = hw.instance \"t\" @twice"));
+    assert!(module.contains("%o = hw.instance \"e\" @ext"));
+
+    // without a selection, only the extern module is left
+    run(&mut ir, "flatten").unwrap();
+    let module = ir.print_op(top);
+    assert_eq!(module.matches("hw.instance").count(), 1);
+    assert!(module.contains("%o = hw.instance \"e\" @ext"));
+  }
+
+  fn find_module(ir: &CmtIR, name: &str) -> Option<OpId> {
+    ir.get_ops_with_parent(None).into_iter().find(|id| match ir.get_op(*id) {
+      OpEnum::HwModule(module) => module.name.as_ref().unwrap().0 == name,
+      _ => false,
+    })
+  }
+
+  #[test]
+  pub fn dce_test() {
+    let mut ir = CmtIR::parse(
+      r#"hw.module @dead(%a: i8, %b: i8) -> (o: i8) {
+	// hw.input %a, %b : i8, i8
+	%one = hw.constant 1: i8
+	%t = comb.add %a, %one : i8
+	%u = comb.add %t, %b : i8
+	%dbg = comb.xor %a, %b : i8
+	%s = comb.add %a, %b : i8
+	%o = hw.wire %s : i8
+	hw.output %o : i8
+}"#,
+    )
+    .unwrap();
+    let name = AttributeEnum::StringAttr(StringAttr("dbg".into()));
+    let dbg = ir.entity_table.iter().find(|(_, x)| x.get_attr("name") == Some(name.to_owned()));
+    let dbg = dbg.unwrap().0;
+    ir.get_entity_entry(EntityId(*dbg)).and_modify(|wire| {
+      if let EntityEnum::IRWire(wire) = wire {
+        wire.debug = Some(BoolAttr(true));
+      }
+    });
+    let pass_manager = run(&mut ir, "dce").unwrap();
+    assert_eq!(pass_manager.statistics()[0].ops_before - 3, pass_manager.statistics()[0].ops_after);
+    let module = ir.print_op(find_module(&ir, "dead").unwrap());
+    assert!(!module.contains("%one") && !module.contains("%t") && !module.contains("%u"));
+    assert!(module.contains("%dbg = comb.xor") && module.contains("%o = hw.wire %s"));
+    let region = ir.get_op(find_module(&ir, "dead").unwrap()).get_regions()[0].1[0].unwrap();
+    assert_eq!(ir.get_region(region).entity_children.len(), 5);
+    assert!(ir.verify().is_ok());
+  }
+
+  #[test]
+  pub fn dce_when_test() {
+    let src = r#"hw.module @guarded(%clk: i1, %go: i1, %a: i8) -> (o: i8) {
+	// hw.input %clk, %go, %a : i1, i1, i8
+	%ev = event.def
+	event.signal %ev === %go
+	%one = hw.constant 1: i8
+	%unused = comb.add %a, %a : i8
+	%reg = seq.compreg %o.w_port ,%clk   : i8
+	%o = hw.wire %reg : i8
+	%select = ILLEGAL.select priority {
+		[TBD] : %wr
+		default : %o
+	} : i8
+	%o.w_port = hw.wire %select : i8
+	ILLEGAL.when %ev {
+		%add = comb.add %o, %one : i8
+		%wr = hw.wire %add : i8
+	}
+	hw.output %o : i8
+}"#;
+    let mut ir = CmtIR::parse(src).unwrap();
+    run(&mut ir, "dce").unwrap();
+    // `%one` is only used in the body of the `when`
+    let module = ir.print_op(find_module(&ir, "guarded").unwrap());
+    assert!(module.contains("%one = hw.constant 1") && !module.contains("%unused"));
+    assert!(module.contains("%wr = hw.wire %add"));
+  }
+
+  #[test]
+  pub fn dce_module_test() {
+    let mut ir = CmtIR::parse(
+      r#"hw.module @inc(%x: i8) -> (y: i8) {
+	// hw.input %x : i8
+	%one = hw.constant 1: i8
+	%y = comb.add %x, %one : i8
+	hw.output %y : i8
+}
+hw.module @unused(%x: i8) -> (y: i8) {
+	// hw.input %x : i8
+	hw.output %x : i8
+}
+hw.module @top(%a: i8) -> (b: i8) {
+	// hw.input %a : i8
+	%b, = hw.instance "inc" @inc(x : %a : i8) -> (y: i8)
+	hw.output %b : i8
+}"#,
+    )
+    .unwrap();
+    for name in ["inc", "unused"] {
+      let module = find_module(&ir, name).unwrap();
+      ir.get_op_entry(module).and_modify(|op| {
+        if let OpEnum::HwModule(module) = op {
+          module.top = Some(BoolAttr(false));
+        }
+      });
+    }
+    run(&mut ir, "dce").unwrap();
+    assert!(find_module(&ir, "unused").is_none());
+    assert!(find_module(&ir, "inc").is_some() && find_module(&ir, "top").is_some());
+    assert_eq!(ir.walk_ops().len(), 9);
+  }
+
+  #[test]
+  pub fn dump_test() {
+    let mut ir = CmtIR::parse(
+      r#"hw.module @unary(%a: i1) -> (o: i1) {
+	// hw.input %a : i1
+	%o = ILLEGAL.not %a : i1
+	hw.output %o : i1
+}"#,
+    )
+    .unwrap();
+    let dir = std::env::temp_dir().join("irony_cmt_dump_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut pass_manager = PassManager::default();
+    pass_manager.dump_dir = Some(dir.to_owned());
+    pass_manager.add_pipeline(PassPipeline::parse("reorder,remove-unary").unwrap());
+    pass_manager.run_passes(&mut ir).unwrap();
+
+    let input = std::fs::read_to_string(dir.join("00_input.mlir")).unwrap();
+    assert!(input.contains("ILLEGAL.not"));
+    assert!(dir.join("01_reorder.mlir").exists());
+    let output = std::fs::read_to_string(dir.join("02_remove-unary.mlir")).unwrap();
+    assert!(!output.contains("ILLEGAL.not") && output.contains("comb.xor"));
+  }
+}
+
+mod analysis_test {
+  use std::rc::Rc;
+
+  use irony::{AnalysisManager, DefUse, Environ, PassManagerTrait, PassPipeline};
+
+  use crate::*;
+
+  const COUNTER: &str = r#"hw.module @inc(%x: i8) -> (y: i8) {
+	// hw.input %x : i8
+	%one = hw.constant 1: i8
+	%y = comb.add %x, %one : i8
+	hw.output %y : i8
+}
+hw.module @counter(%clk: i1, %en: i1) -> (count: i8) {
+	// hw.input %clk, %en : i1, i1
+	%next, = hw.instance "inc" @inc(x : %count : i8) -> (y: i8)
+	%d = comb.mux %en, %count, %next : i8
+	%count = seq.compreg %d ,%clk   : i8
+	hw.output %count : i8
+}"#;
+
+  fn module(ir: &CmtIR, name: &str) -> OpId {
+    ir.get_ops_with_parent(None)
+      .into_iter()
+      .find(|id| match ir.get_op(*id) {
+        OpEnum::HwModule(module) => module.name.as_ref().unwrap().0 == name,
+        _ => false,
+      })
+      .unwrap()
+  }
+
+  #[test]
+  pub fn builtin_analyses_test() {
+    let ir = CmtIR::parse(COUNTER).unwrap();
+    let (inc, counter) = (module(&ir, "inc"), module(&ir, "counter"));
+    let mut analyses = AnalysisManager::default();
+
+    let hierarchy = analyses.get::<InstanceHierarchy, _>(&ir, counter).unwrap();
+    assert_eq!(hierarchy.modules, vec![inc, counter]);
+    assert_eq!(hierarchy.children(counter).map(|(_, x)| x).collect::<Vec<_>>(), vec![inc]);
+
+    // the register breaks the loop through the instance
+    let order = analyses.get::<TopoOrder, _>(&ir, counter).unwrap();
+    let body = ir.get_region(ir.get_op(counter).get_regions()[0].1[0].unwrap());
+    let position = |i: usize| order.0.iter().position(|x| *x == body.op_children[i]);
+    assert_eq!(order.0.len(), body.op_children.len());
+    assert!(position(3) < position(1) && position(1) < position(2));
+    assert!(analyses.get::<CombDependencies, _>(&ir, counter).unwrap().find_cycle().is_none());
+
+    let def_use = analyses.get::<DefUse, _>(&ir, counter).unwrap();
+    let count = ir.get_op(body.op_children[3]).get_defs()[0].1[0].unwrap();
+    assert_eq!(def_use.defs(count), &[body.op_children[3]]);
+    assert_eq!(def_use.uses(count).len(), 3);
+  }
+
+  #[test]
+  pub fn comb_loop_test() {
+    let ir = CmtIR::parse(
+      r#"hw.module @loop(%a: i1) -> (b: i1) {
+	// hw.input %a : i1
+	%b = comb.and %a, %c : i1
+	%c = hw.wire %b : i1
+	hw.output %b : i1
+}"#,
+    )
+    .unwrap();
+    let top = module(&ir, "loop");
+    let mut analyses = AnalysisManager::default();
+    let cycle = analyses.get::<CombDependencies, _>(&ir, top).unwrap().find_cycle();
+    assert_eq!(cycle.map(|x| x.len()), Some(2));
+    let err = analyses.get::<TopoOrder, _>(&ir, top).unwrap_err();
+    assert!(err.message.contains("combinational loop"));
+  }
+
+  #[test]
+  pub fn invalidation_test() {
+    let mut ir = CmtIR::parse(COUNTER).unwrap();
+    let (inc, counter) = (module(&ir, "inc"), module(&ir, "counter"));
+    let mut pass_manager = PassManager::default();
+    let analyses = &mut pass_manager.analyses;
+    analyses.get::<TopoOrder, _>(&ir, counter).unwrap();
+    analyses.get::<DefUse, _>(&ir, counter).unwrap();
+    analyses.get::<DefUse, _>(&ir, inc).unwrap();
+    analyses.get::<InstanceHierarchy, _>(&ir, counter).unwrap();
+
+    pass_manager.add_pipeline(PassPipeline::parse("reorder").unwrap());
+    pass_manager.run_passes(&mut ir).unwrap();
+    let analyses = &mut pass_manager.analyses;
+    assert!(!analyses.is_cached::<TopoOrder>(counter));
+    assert!(analyses.is_cached::<DefUse>(counter));
+    assert!(analyses.is_cached::<InstanceHierarchy>(counter));
+
+    let analyses = analyses.to_owned();
+    let mut pass_manager = PassManager::default();
+    pass_manager.analyses = analyses;
+    pass_manager.add_pipeline(PassPipeline::parse("remove-unary").unwrap());
+    pass_manager.run_passes(&mut ir).unwrap();
+    let analyses = &pass_manager.analyses;
+    assert!(!analyses.is_cached::<DefUse>(counter) && !analyses.is_cached::<DefUse>(inc));
+    assert!(analyses.is_cached::<InstanceHierarchy>(counter));
+  }
+
+  #[test]
+  pub fn pass_analyses_test() {
+    let mut ir = CmtIR::parse(COUNTER).unwrap();
+    let (inc, counter) = (module(&ir, "inc"), module(&ir, "counter"));
+    let mut pass_manager = PassManager::default();
+    pass_manager.add_pipeline(PassPipeline::parse("dce").unwrap());
+    pass_manager.run_passes(&mut ir).unwrap();
+
+    // dce gets the hierarchies of the top modules from the manager and keeps them,
+    // while the def-use chains it gets are invalidated by its changes
+    let analyses = &pass_manager.analyses;
+    assert!(analyses.is_cached::<InstanceHierarchy>(counter));
+    assert!(analyses.is_cached::<InstanceHierarchy>(inc));
+    assert!(!analyses.is_cached::<DefUse>(counter));
+
+    // the next passes get the cached hierarchy instead of computing it again
+    let hierarchy = analyses.get_cached::<InstanceHierarchy>(counter).unwrap();
+    let analyses = analyses.to_owned();
+    let mut pass_manager = PassManager::default();
+    pass_manager.analyses = analyses;
+    pass_manager.add_pipeline(PassPipeline::parse("cse,dce").unwrap());
+    pass_manager.run_passes(&mut ir).unwrap();
+    let analyses = &pass_manager.analyses;
+    assert!(Rc::ptr_eq(&hierarchy, &analyses.get_cached(counter).unwrap()));
+  }
+}
+
+mod sv_test {
+  use irony::Environ;
+
+  use crate::*;
+
+  #[test]
+  pub fn counter_test() {
+    let ir = CmtIR::parse(
+      r#"hw.module @inc(%x: i8) -> (y: i8) {
+	// hw.input %x : i8
+	%one = hw.constant 1: i8
+	%y = comb.add %x, %one : i8
+	hw.output %y : i8
+}
+hw.module @counter(%clk: i1, %en: i1) -> (count: i8) {
+	// hw.input %clk, %en : i1, i1
+	%next, = hw.instance "inc" @inc(x : %count : i8) -> (y: i8)
+	%d = comb.mux %en, %next, %count : i8
+	%count = seq.compreg %d ,%clk   : i8
+	%top = comb.extract %count from 4 : (i8) -> i4
+	%msb = comb.extract %count from 7 : (i8) -> i1
+	%wide = comb.concat %msb, %top : i1, i4
+	hw.output %count : i8
+}"#,
+    )
+    .unwrap();
+    let modules = emit_sv_modules(&ir).unwrap();
+    let names = modules.iter().map(|x| x.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["inc", "counter"]);
+    let inc = strip_comments(&modules[0].text);
+    assert!(inc.contains("  output logic [7:0] y\n);\n"));
+    assert!(inc.contains("  assign one = 8'h01;\n  assign y = x + one;\n"));
+
+    let counter = &strip_comments(&modules[1].text);
+    assert!(counter.contains("  inc inc (\n    .x(count),\n    .y(next)\n  );\n"));
+    assert!(counter.contains("  assign d = en ? next : count;\n"));
+    assert!(
+      counter.contains("  always_ff @(posedge clk) begin\n    count <= d;\n  end\n")
+    );
+    assert!(counter.contains("  assign top = count[7:4];\n  assign msb = count[7];\n"));
+    assert!(counter.contains("  assign wide = {msb, top};\n"));
+    assert!(counter.ends_with("endmodule\n"));
+  }
+
+  #[test]
+  pub fn mux_test() {
+    let ir = CmtIR::parse(
+      r#"hw.module @mux(%c: i1, %a: i8, %b: i8) -> (o: i8) {
+	// hw.input %c, %a, %b : i1, i8, i8
+	%o = comb.mux %c, %a, %b : i8
+	hw.output %o : i8
+}"#,
+    )
+    .unwrap();
+    let modules = emit_sv_modules(&ir).unwrap();
+    let mux = strip_comments(&modules[0].text);
+    assert!(mux.contains("  assign o = c ? a : b;\n"));
+
+    // which selects `a` if `c` is set, like the interpreter
+    let module = ir
+      .op_table
+      .iter()
+      .find_map(|(id, op)| matches!(op, OpEnum::HwModule(_)).then_some(OpId(*id)))
+      .unwrap();
+    let mut interpreter = Interpreter::new(&ir, module).unwrap();
+    interpreter.poke("a", Value::from_u32(1, 8)).unwrap();
+    interpreter.poke("b", Value::from_u32(2, 8)).unwrap();
+    for (cond, selected) in [(true, 1), (false, 2)] {
+      interpreter.poke("c", Value::from_bool(cond)).unwrap();
+      interpreter.eval().unwrap();
+      assert_eq!(interpreter.peek("o"), Some(Value::from_u32(selected, 8)));
+    }
+  }
+
+  fn strip_comments(sv: &str) -> String {
+    sv.lines().map(|x| format!("{}\n", x.split("  //").next().unwrap())).collect()
+  }
+
+  #[test]
+  pub fn location_test() {
+    let ir = CmtIR::parse(
+      r#"hw.module @inc(%x: i8) -> (y: i8) {
+	// hw.input %x : i8
+	%one = hw.constant 1: i8
+	%y = comb.add %x, %one : i8
+	hw.output %y : i8
+}"#,
+    )
+    .unwrap();
+    let modules = emit_sv_modules(&ir).unwrap();
+    let line = modules[0].text.lines().find(|x| x.contains("assign y")).unwrap();
+    assert!(line.starts_with("  assign y = x + one;  // irony_cmt/src/tests.rs:"));
+
+    // ports and declarations take the first 5 lines
+    let lines = modules[0].source_map.iter().map(|(line, _)| *line).collect::<Vec<_>>();
+    assert_eq!(lines, vec![6, 7]);
+    let source_map = sv_source_map(&modules);
+    assert!(
+      source_map.starts_with("{\n  \"inc.sv\": {\n    \"6\": \"irony_cmt/src/tests.rs:")
+    );
+  }
+
+  #[test]
+  pub fn unlowered_test() {
+    let ir = CmtIR::parse(
+      r#"hw.module @sel(%a: i1) -> (o: i1) {
+	// hw.input %a : i1
+	%o = ILLEGAL.select priority {
+		[TBD] : %a
+	} : i1
+	hw.output %o : i1
+}"#,
+    )
+    .unwrap();
+    let module = ir.get_ops_with_parent(None)[0];
+    let err = emit_sv(&ir, module).unwrap_err();
+    assert!(matches!(ir.get_op(err.op.unwrap()), OpEnum::TmpSelect(_)));
+  }
+}
+
+mod firrtl_test {
+  use irony::Environ;
+
+  use crate::*;
+
+  #[test]
+  pub fn counter_test() {
+    let ir = CmtIR::parse(
+      r#"hw.module.extern @ext(%x: i8) -> (y: i8)
+hw.module @inc(%x: i8) -> (y: i8) {
+	// hw.input %x : i8
+	%one = hw.constant 1: i8
+	%y = comb.add %x, %one : i8
+	hw.output %y : i8
+}
+hw.module @counter(%clk: i1, %en: i1) -> (count: i8, e: i8) {
+	// hw.input %clk, %en : i1, i1
+	%next, = hw.instance "inc" @inc(x : %count : i8) -> (y: i8)
+	%e, = hw.instance "ext" @ext(x : %count : i8) -> (y: i8)
+	%d = comb.mux %en, %next, %count : i8
+	%count = seq.compreg %d ,%clk   : i8
+	%top = comb.extract %count from 4 : (i8) -> i4
+	%wide = comb.concat %en, %top, %en : i1, i4, i1
+	hw.output %count, %e : i8, i8
+}"#,
+    )
+    .unwrap();
+    let counter = ir.get_ops_with_parent(None)[2];
+    let firrtl = emit_firrtl(&ir, counter).unwrap();
+    assert!(
+      firrtl.starts_with("FIRRTL version 3.3.0\ncircuit counter :\n  module inc :\n")
+    );
+    assert!(firrtl.contains("    connect y, bits(add(x, one), 7, 0)\n"));
+    assert!(firrtl.contains("  extmodule ext :\n    input x : UInt<8>\n"));
+    // the register can't be the output port itself
+    assert!(firrtl.contains("    reg count_1 : UInt<8>, asClock(clk)\n"));
+    assert!(firrtl.contains("    connect count_1, d\n"));
+    assert!(firrtl.contains("    connect count, count_1\n"));
+    assert!(firrtl.contains("    inst inc of inc\n"));
+    assert!(firrtl.contains("    connect inc.x, count_1\n    connect next, inc.y\n"));
+    assert!(firrtl.contains("    connect d, mux(en, next, count_1)\n"));
+    assert!(firrtl.contains("    connect wide, cat(en, cat(top, en))\n"));
+  }
+
+  #[test]
+  pub fn aggregate_test() {
+    let ir = CmtIR::parse(
+      r#"hw.module @agg(%a: i8, %b: i8) -> (o: i8) {
+	// hw.input %a, %b : i8, i8
+	%arr = hw.array_create %b, %a : i8
+	%flat = hw.bitcast %arr : (!hw.array<2xi8>) -> i16
+	%o = comb.extract %flat from 8 : (i16) -> i8
+	hw.output %o : i8
+}"#,
+    )
+    .unwrap();
+    let module = ir.get_ops_with_parent(None)[0];
+    let firrtl = emit_firrtl(&ir, module).unwrap();
+    assert!(firrtl.contains("    wire arr : UInt<8>[2]\n"));
+    assert!(firrtl.contains("    connect arr[0], b\n    connect arr[1], a\n"));
+    // element 0 is the least significant one
+    assert!(firrtl.contains("    connect flat, cat(arr[1], arr[0])\n"));
+  }
+}
+
+mod yosys_test {
+  use crate::*;
+
+  #[test]
+  pub fn counter_test() {
+    let ir = CmtIR::parse(
+      r#"hw.module @inc(%x: i8) -> (y: i8) {
+	// hw.input %x : i8
+	%one = hw.constant 1: i8
+	%y = comb.add %x, %one : i8
+	hw.output %y : i8
+}
+hw.module @counter(%clk: i1, %en: i1) -> (count: i8) {
+	// hw.input %clk, %en : i1, i1
+	%next, = hw.instance "inc" @inc(x : %count : i8) -> (y: i8)
+	%d = comb.mux %en, %next, %count : i8
+	%count = seq.compreg %d ,%clk   : i8
+	%top = comb.extract %count from 4 : (i8) -> i4
+	hw.output %count : i8
+}"#,
+    )
+    .unwrap();
+    let json = emit_yosys_json(&ir).unwrap();
+    assert!(json.starts_with("{\n  \"creator\": \"cement\",\n  \"modules\": {\n"));
+    // constants and extracted bits share the nets of their operands
+    assert!(
+      json.contains("\"B\": [\"1\", \"0\", \"0\", \"0\", \"0\", \"0\", \"0\", \"0\"]")
+    );
+    assert!(json.contains(
+      "\"top\": {\n          \"hide_name\": 0,\n          \"bits\": [8, 9, 10, 11]"
+    ));
+    // the register drives the output port and the instance
+    let count = "[4, 5, 6, 7, 8, 9, 10, 11]";
+    assert!(json.contains(&format!(
+      "\"count\": {{\n          \"direction\": \"output\",\n          \"bits\": {}",
+      count
+    )));
+    assert!(json.contains(&format!("\"x\": {},\n            \"y\": [12,", count)));
+    assert!(json.contains(&format!("\"Q\": {}", count)));
+    assert!(json.contains("\"type\": \"$dff\""));
+    // the mux keeps the count if `en` is clear
+    assert!(json.contains(&format!("\"A\": {},\n            \"B\": [12,", count)));
+    assert!(json.contains("\"S\": [3],\n            \"Y\": [20,"));
+  }
+}
+
+mod snapshot_test {
+  use irony::{Environ, SnapshotFormat};
+
+  use crate::*;
+
+  fn counter() -> CmtIR {
+    CmtIR::parse(
+      r#"hw.module @inc(%x: i8) -> (y: i8) {
+	// hw.input %x : i8
+	%one = hw.constant 1: i8
+	%y = comb.add %x, %one : i8
+	hw.output %y : i8
+}
+hw.module @counter(%clk: i1, %en: i1) -> (count: i8) {
+	// hw.input %clk, %en : i1, i1
+	%next, = hw.instance "inc" @inc(x : %count : i8) -> (y: i8)
+	%d = comb.mux %en, %count, %next : i8
+	%count = seq.compreg %d ,%clk   : i8
+	hw.output %count : i8
+}"#,
+    )
+    .unwrap()
+  }
+
+  #[test]
+  pub fn round_trip_test() {
+    for format in [SnapshotFormat::Json, SnapshotFormat::Binary] {
+      let mut ir = counter();
+      let mut bytes = vec![];
+      ir.save_snapshot(&mut bytes, format).unwrap();
+      let mut loaded = CmtIR::new();
+      loaded.load_snapshot(bytes.as_slice(), format).unwrap();
+
+      assert_eq!(loaded.op_table.get_map(), ir.op_table.get_map());
+      assert_eq!(loaded.entity_table.get_map(), ir.entity_table.get_map());
+      assert_eq!(loaded.region_table.get_map(), ir.region_table.get_map());
+      let modules = ir.get_ops_with_parent(None);
+      for module in modules.iter() {
+        assert_eq!(loaded.print_op(*module), ir.print_op(*module));
+      }
+      assert!(loaded.verify().is_ok());
+
+      // new entities don't reuse the ids of the loaded ones
+      let wire = IRWire::new(None, None, None, None);
+      assert_eq!(loaded.add_entity(wire.to_owned().into()), ir.add_entity(wire.into()));
+    }
+  }
+
+  #[test]
+  pub fn version_test() {
+    let mut bytes = vec![];
+    counter().save_snapshot(&mut bytes, SnapshotFormat::Json).unwrap();
+    let json = String::from_utf8(bytes).unwrap();
+    assert!(json.starts_with("{\n  \"version\": 1,\n  \"snapshot\": {\n"));
+
+    let json = json.replacen("\"version\": 1", "\"version\": 100", 1);
+    let err =
+      CmtIR::new().load_snapshot(json.as_bytes(), SnapshotFormat::Json).unwrap_err();
+    assert_eq!(
+      err.message,
+      "can't read the snapshot: its version is 100, but version 1 is supported"
+    );
+  }
+}
@@ -0,0 +1,610 @@
+use std::collections::{HashMap, HashSet};
+
+use irony::{Entity, EntityId, Environ, IronyError, IronyResult, Op, OpId};
+
+use crate::interpret::{bitcast, constant};
+use crate::json::Json;
+use crate::sv::{port_names, string_of};
+use crate::{
+  ArrayAttr, ArrayType, AttributeEnum, BoolAttr, CombBinary, CombBinaryPredicate,
+  CombConcat, CombExtract, CombICmp, CombICmpPredicate, CombMux2, CombUnaryPredicate,
+  CombVariadic, CombVariadicPredicate, DataTypeEnum, EntityEnum, HwAggregateConstant,
+  HwArrayConcat, HwArrayCreate, HwArrayGet, HwArraySlice, HwBitCast, HwConstant, HwInput,
+  HwInstance, HwModule, HwOutput, HwStructCreate, HwStructExplode, HwStructExtract,
+  HwStructInject, OpEnum, OpIdAttr, SeqCompReg, StringAttr, StructType, TmpUnary,
+  TypeAttr, UIntAttr, UIntType, Value,
+};
+
+/// Parameters and attributes are bit vectors, written as 32-bit binary strings
+fn param(value: usize) -> Json { Json::Str(format!("{:032b}", value)) }
+
+/// A bit of a net, least significant bit first as in the interpreter
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Bit {
+  Net(usize),
+  Const(bool),
+  X,
+}
+
+fn bits_json(bits: &[Bit]) -> Json {
+  Json::Arr(
+    bits
+      .iter()
+      .map(|x| match x {
+        Bit::Net(x) => Json::Num(*x),
+        Bit::Const(x) => Json::Str(if *x { "1" } else { "0" }.into()),
+        Bit::X => Json::Str("x".into()),
+      })
+      .collect(),
+  )
+}
+
+/// Ops which only rearrange the bits of their operands, so that their results share the
+/// nets of their operands instead of being driven by cells
+fn is_wiring(op: &OpEnum) -> bool {
+  matches!(
+    op,
+    OpEnum::Assign(_)
+      | OpEnum::HwBitCast(_)
+      | OpEnum::HwConstant(_)
+      | OpEnum::HwAggregateConstant(_)
+      | OpEnum::SvConstantX(_)
+      | OpEnum::CombExtract(_)
+      | OpEnum::CombConcat(_)
+      | OpEnum::HwArrayCreate(_)
+      | OpEnum::HwArrayConcat(_)
+      | OpEnum::HwStructCreate(_)
+      | OpEnum::HwStructExtract(_)
+      | OpEnum::HwStructInject(_)
+      | OpEnum::HwStructExplode(_)
+  )
+}
+
+/// Bit offset of `field` in a struct, the first field being the least significant
+fn field_range(
+  fields: &[(String, Box<DataTypeEnum>)], field: &str,
+) -> Option<(usize, usize)> {
+  let mut low = 0;
+  for (name, dtype) in fields {
+    if name == field {
+      return Some((low, low + dtype.width()));
+    }
+    low += dtype.width();
+  }
+  None
+}
+
+struct ModuleBuilder<'a, E> {
+  env: &'a E,
+  bits: HashMap<EntityId, Vec<Bit>>,
+  /// Ops defining the entities which share the nets of other entities
+  wiring: HashMap<EntityId, OpId>,
+  resolving: HashSet<EntityId>,
+  next_net: usize,
+  cells: Vec<(String, Json)>,
+}
+
+impl<'a, E> ModuleBuilder<'a, E>
+where E: Environ<EntityT = EntityEnum, OpT = OpEnum>
+{
+  fn dtype(&self, entity: EntityId) -> IronyResult<DataTypeEnum> {
+    self.env.get_entity(entity).get_dtype().ok_or_else(|| {
+      IronyError::new("entity must have a type").with_entity(self.env, entity)
+    })
+  }
+
+  fn fresh(&mut self, width: usize) -> Vec<Bit> {
+    let bits = (self.next_net..self.next_net + width).map(Bit::Net).collect();
+    self.next_net += width;
+    bits
+  }
+
+  fn bits(&mut self, entity: &Option<EntityId>, op: OpId) -> IronyResult<Vec<Bit>> {
+    let entity = entity.ok_or_else(|| IronyError::new("missing operand").with_op(op))?;
+    if let Some(bits) = self.bits.get(&entity) {
+      return Ok(bits.to_owned());
+    }
+    let bits = match self.wiring.get(&entity).copied() {
+      Some(op_id) => {
+        if !self.resolving.insert(entity) {
+          return Err(
+            IronyError::new("combinational loop through wires")
+              .with_op(op_id)
+              .with_entity(self.env, entity),
+          );
+        }
+        let bits = self.wire_bits(op_id, entity)?;
+        self.resolving.remove(&entity);
+        bits
+      },
+      None => {
+        let width = self.dtype(entity)?.width();
+        self.fresh(width)
+      },
+    };
+    self.bits.insert(entity, bits.to_owned());
+    Ok(bits)
+  }
+
+  fn concat_bits(
+    &mut self, operands: &[Option<EntityId>], op: OpId,
+  ) -> IronyResult<Vec<Bit>> {
+    let mut bits = vec![];
+    for operand in operands {
+      bits.extend(self.bits(operand, op)?);
+    }
+    Ok(bits)
+  }
+
+  fn struct_fields(
+    &self, entity: &Option<EntityId>, op: OpId,
+  ) -> IronyResult<Vec<(String, Box<DataTypeEnum>)>> {
+    match entity.map(|x| self.dtype(x)).transpose()? {
+      Some(DataTypeEnum::Struct(StructType(fields))) => Ok(fields),
+      _ => Err(IronyError::new("operand must be a struct").with_op(op)),
+    }
+  }
+
+  fn field_range(
+    &self, entity: &Option<EntityId>, field: &str, op: OpId,
+  ) -> IronyResult<(usize, usize)> {
+    field_range(&self.struct_fields(entity, op)?, field).ok_or_else(|| {
+      IronyError::new(format!("struct has no field {}", field)).with_op(op)
+    })
+  }
+
+  /// Bits of `entity` defined by the wiring op `op_id`
+  fn wire_bits(&mut self, op_id: OpId, entity: EntityId) -> IronyResult<Vec<Bit>> {
+    let env = self.env;
+    let width = self.dtype(entity)?.width();
+    let constant = |value: AttributeEnum, dtype: DataTypeEnum| {
+      let flat = DataTypeEnum::UInt(UIntType(dtype.width()));
+      match constant(&value, &dtype).map(|x| bitcast(&x, &flat)) {
+        Ok(Value::Bits(bits)) => Ok(bits.into_iter().map(Bit::Const).collect()),
+        Ok(_) => unreachable!(),
+        Err(err) => Err(IronyError::new(err).with_op(op_id)),
+      }
+    };
+    match env.get_op(op_id) {
+      OpEnum::Assign(op) => self.bits(&op.rhs, op_id),
+      OpEnum::HwBitCast(HwBitCast { rhs, .. }) => self.bits(rhs, op_id),
+      OpEnum::HwConstant(HwConstant { value: Some(value), .. }) => {
+        constant(AttributeEnum::ConstantAttr(value.to_owned()), self.dtype(entity)?)
+      },
+      OpEnum::HwAggregateConstant(HwAggregateConstant { attrs: Some(value), .. }) => {
+        constant(AttributeEnum::ArrayAttr(value.to_owned()), self.dtype(entity)?)
+      },
+      OpEnum::SvConstantX(_) => Ok(vec![Bit::X; width]),
+      OpEnum::CombExtract(CombExtract { input, low: Some(UIntAttr(low)), .. }) => {
+        let low = *low as usize;
+        Ok(self.bits(input, op_id)?[low..low + width].to_vec())
+      },
+      // the first operand is the most significant one
+      OpEnum::CombConcat(CombConcat { operands, .. }) => {
+        let operands = operands.iter().rev().copied().collect::<Vec<_>>();
+        self.concat_bits(&operands, op_id)
+      },
+      // element and field 0 are the least significant ones
+      OpEnum::HwArrayCreate(HwArrayCreate { operands, .. })
+      | OpEnum::HwArrayConcat(HwArrayConcat { operands, .. })
+      | OpEnum::HwStructCreate(HwStructCreate { operands, .. }) => {
+        self.concat_bits(operands, op_id)
+      },
+      OpEnum::HwStructExtract(HwStructExtract {
+        struct_input,
+        field: Some(StringAttr(field)),
+        ..
+      }) => {
+        let (low, high) = self.field_range(struct_input, field, op_id)?;
+        Ok(self.bits(struct_input, op_id)?[low..high].to_vec())
+      },
+      OpEnum::HwStructInject(HwStructInject {
+        struct_input,
+        new_value,
+        field: Some(StringAttr(field)),
+        ..
+      }) => {
+        let (low, high) = self.field_range(struct_input, field, op_id)?;
+        let mut bits = self.bits(struct_input, op_id)?;
+        bits.splice(low..high, self.bits(new_value, op_id)?);
+        Ok(bits)
+      },
+      OpEnum::HwStructExplode(HwStructExplode { outputs, struct_input, .. }) => {
+        let fields = self.struct_fields(struct_input, op_id)?;
+        let index = outputs.iter().position(|x| *x == Some(entity)).unwrap();
+        let (low, high) = field_range(&fields, &fields[index].0).unwrap();
+        Ok(self.bits(struct_input, op_id)?[low..high].to_vec())
+      },
+      _ => Err(IronyError::new("op must only rearrange bits").with_op(op_id)),
+    }
+  }
+
+  fn cell(
+    &mut self, name: Option<String>, kind: &str, parameters: Vec<(&str, Json)>,
+    inputs: Vec<(String, Vec<Bit>)>, outputs: Vec<(String, Vec<Bit>)>,
+  ) {
+    let (name, hide_name) = match name {
+      Some(name) => (name, 0),
+      None => (format!("{}${}", kind, self.cells.len()), 1),
+    };
+    let directions = inputs
+      .iter()
+      .map(|(port, _)| (port.to_owned(), Json::Str("input".into())))
+      .chain(
+        outputs.iter().map(|(port, _)| (port.to_owned(), Json::Str("output".into()))),
+      )
+      .collect::<Vec<_>>();
+    let connections = inputs
+      .iter()
+      .chain(outputs.iter())
+      .map(|(port, bits)| (port.to_owned(), bits_json(bits)))
+      .collect::<Vec<_>>();
+    self.cells.push((
+      name,
+      Json::obj([
+        ("hide_name", Json::Num(hide_name)),
+        ("type", Json::Str(kind.into())),
+        ("parameters", Json::obj(parameters)),
+        ("attributes", Json::obj::<String>([])),
+        ("port_directions", Json::Obj(directions)),
+        ("connections", Json::Obj(connections)),
+      ]),
+    ));
+  }
+
+  fn unary(&mut self, kind: &str, a: Vec<Bit>, y: Vec<Bit>) {
+    let parameters = vec![
+      ("A_SIGNED", param(0)),
+      ("A_WIDTH", param(a.len())),
+      ("Y_WIDTH", param(y.len())),
+    ];
+    self.cell(None, kind, parameters, vec![("A".into(), a)], vec![("Y".into(), y)]);
+  }
+
+  fn binary(&mut self, kind: &str, signed: bool, a: Vec<Bit>, b: Vec<Bit>, y: Vec<Bit>) {
+    let parameters = vec![
+      ("A_SIGNED", param(signed as usize)),
+      ("A_WIDTH", param(a.len())),
+      ("B_SIGNED", param(signed as usize)),
+      ("B_WIDTH", param(b.len())),
+      ("Y_WIDTH", param(y.len())),
+    ];
+    let inputs = vec![("A".into(), a), ("B".into(), b)];
+    self.cell(None, kind, parameters, inputs, vec![("Y".into(), y)]);
+  }
+
+  fn mux(&mut self, s: Vec<Bit>, a: Vec<Bit>, b: Vec<Bit>, y: Vec<Bit>) {
+    let parameters = vec![("WIDTH", param(y.len()))];
+    let inputs = vec![("A".into(), a), ("B".into(), b), ("S".into(), s)];
+    self.cell(None, "$mux", parameters, inputs, vec![("Y".into(), y)]);
+  }
+
+  /// Bits `index * width ..` of `array`, selected by a `$shiftx` cell
+  fn select(&mut self, array: Vec<Bit>, index: Vec<Bit>, width: usize, y: Vec<Bit>) {
+    let shift = match width.is_power_of_two() {
+      true => {
+        let zeros = vec![Bit::Const(false); width.trailing_zeros() as usize];
+        zeros.into_iter().chain(index).collect()
+      },
+      false => {
+        let factor = (0..usize::BITS - width.leading_zeros())
+          .map(|i| Bit::Const(width >> i & 1 == 1))
+          .collect::<Vec<_>>();
+        let shift = self.fresh(index.len() + factor.len());
+        self.binary("$mul", false, index, factor, shift.to_owned());
+        shift
+      },
+    };
+    self.binary("$shiftx", false, array, shift, y);
+  }
+
+  fn op(&mut self, op_id: OpId) -> IronyResult<()> {
+    let env = self.env;
+    match env.get_op(op_id) {
+      op if is_wiring(op) => {},
+      OpEnum::HwInput(_) | OpEnum::HwOutput(_) => {},
+      OpEnum::HwArrayGet(HwArrayGet { lhs, array, index, .. }) => {
+        let (array, index) = (self.bits(array, op_id)?, self.bits(index, op_id)?);
+        let y = self.bits(lhs, op_id)?;
+        self.select(array, index, y.len(), y);
+      },
+      OpEnum::HwArraySlice(HwArraySlice { lhs, array, index, .. }) => {
+        let Some(DataTypeEnum::Array(ArrayType(element, _))) =
+          lhs.map(|x| self.dtype(x)).transpose()?
+        else {
+          return Err(IronyError::new("must slice an array").with_op(op_id));
+        };
+        let (array, index) = (self.bits(array, op_id)?, self.bits(index, op_id)?);
+        let y = self.bits(lhs, op_id)?;
+        self.select(array, index, element.width(), y);
+      },
+      OpEnum::CombVariadic(CombVariadic {
+        lhs,
+        operands,
+        predicate: Some(predicate),
+        ..
+      }) => {
+        let kind = match predicate {
+          CombVariadicPredicate::Add => "$add",
+          CombVariadicPredicate::Mul => "$mul",
+          CombVariadicPredicate::And => "$and",
+          CombVariadicPredicate::Or => "$or",
+          CombVariadicPredicate::Xor => "$xor",
+        };
+        let y = self.bits(lhs, op_id)?;
+        let Some((first, rest)) = operands.split_first() else {
+          return Err(IronyError::new("missing operand").with_op(op_id));
+        };
+        let mut a = self.bits(first, op_id)?;
+        if rest.is_empty() {
+          self.unary("$pos", a, y);
+          return Ok(());
+        }
+        for (i, operand) in rest.iter().enumerate() {
+          let b = self.bits(operand, op_id)?;
+          let result = match i + 1 == rest.len() {
+            true => y.to_owned(),
+            false => self.fresh(y.len()),
+          };
+          self.binary(kind, false, a, b, result.to_owned());
+          a = result;
+        }
+      },
+      OpEnum::CombBinary(CombBinary {
+        lhs,
+        op0,
+        op1,
+        predicate: Some(predicate),
+        ..
+      }) => {
+        let (kind, signed) = match predicate {
+          CombBinaryPredicate::Sub => ("$sub", false),
+          CombBinaryPredicate::DivU => ("$div", false),
+          CombBinaryPredicate::ModU => ("$mod", false),
+          CombBinaryPredicate::DivS => ("$div", true),
+          CombBinaryPredicate::ModS => ("$mod", true),
+          CombBinaryPredicate::Shl => ("$shl", false),
+          CombBinaryPredicate::ShrU => ("$shr", false),
+          CombBinaryPredicate::ShrS => ("$sshr", true),
+        };
+        let (a, b) = (self.bits(op0, op_id)?, self.bits(op1, op_id)?);
+        let y = self.bits(lhs, op_id)?;
+        self.binary(kind, signed, a, b, y);
+      },
+      OpEnum::CombICmp(CombICmp {
+        lhs,
+        op0,
+        op1,
+        predicate: Some(predicate),
+        ..
+      }) => {
+        let (kind, signed) = match predicate {
+          CombICmpPredicate::EQ | CombICmpPredicate::WEQ => ("$eq", false),
+          CombICmpPredicate::NE | CombICmpPredicate::WNE => ("$ne", false),
+          CombICmpPredicate::CEQ => ("$eqx", false),
+          CombICmpPredicate::CNE => ("$nex", false),
+          CombICmpPredicate::SLT => ("$lt", true),
+          CombICmpPredicate::SLE => ("$le", true),
+          CombICmpPredicate::SGT => ("$gt", true),
+          CombICmpPredicate::SGE => ("$ge", true),
+          CombICmpPredicate::ULT => ("$lt", false),
+          CombICmpPredicate::ULE => ("$le", false),
+          CombICmpPredicate::UGT => ("$gt", false),
+          CombICmpPredicate::UGE => ("$ge", false),
+        };
+        let (a, b) = (self.bits(op0, op_id)?, self.bits(op1, op_id)?);
+        let y = self.bits(lhs, op_id)?;
+        self.binary(kind, signed, a, b, y);
+      },
+      OpEnum::TmpUnary(TmpUnary { lhs, op, predicate: Some(predicate), .. }) => {
+        let kind = match predicate {
+          CombUnaryPredicate::Not => "$not",
+          CombUnaryPredicate::Neg => "$neg",
+        };
+        let (a, y) = (self.bits(op, op_id)?, self.bits(lhs, op_id)?);
+        self.unary(kind, a, y);
+      },
+      // `$mux` selects `B` if `S` is set
+      OpEnum::CombMux2(mux @ CombMux2 { lhs, cond, .. }) => {
+        let (set, clear) = mux.branches();
+        let (s, y) = (self.bits(cond, op_id)?, self.bits(lhs, op_id)?);
+        let (a, b) = (self.bits(&clear, op_id)?, self.bits(&set, op_id)?);
+        self.mux(s, a, b, y);
+      },
+      // the synchronous reset is a mux in front of the flip-flop
+      OpEnum::SeqCompReg(SeqCompReg { output, input, clk, reset, reset_val, .. }) => {
+        let (q, clk) = (self.bits(output, op_id)?, self.bits(clk, op_id)?);
+        let mut d = self.bits(input, op_id)?;
+        if reset.is_some() {
+          let (s, b) = (self.bits(reset, op_id)?, self.bits(reset_val, op_id)?);
+          let next = self.fresh(q.len());
+          self.mux(s, d, b, next.to_owned());
+          d = next;
+        }
+        let parameters = vec![("CLK_POLARITY", param(1)), ("WIDTH", param(q.len()))];
+        let inputs = vec![("CLK".into(), clk), ("D".into(), d)];
+        self.cell(None, "$dff", parameters, inputs, vec![("Q".into(), q)]);
+      },
+      OpEnum::HwInstance(HwInstance {
+        outputs,
+        inputs,
+        target_op_id: Some(OpIdAttr(target)),
+        name: Some(StringAttr(name)),
+        ..
+      }) => {
+        let OpEnum::HwModule(HwModule { name: Some(StringAttr(kind)), .. }) =
+          env.get_op(*target)
+        else {
+          return Err(IronyError::new("instance must target a module").with_op(op_id));
+        };
+        let (args, results, _) = port_names(env, *target, str::to_string)?;
+        let inputs = args
+          .into_iter()
+          .zip(inputs)
+          .map(|(port, x)| Ok((port, self.bits(x, op_id)?)))
+          .collect::<IronyResult<Vec<_>>>()?;
+        let outputs = results
+          .into_iter()
+          .zip(outputs)
+          .map(|(port, x)| Ok((port, self.bits(x, op_id)?)))
+          .collect::<IronyResult<Vec<_>>>()?;
+        self.cell(Some(name.to_owned()), kind, vec![], inputs, outputs);
+      },
+      // condition checks are only meaningful in simulation
+      OpEnum::ItprtCondCheck(_) => {},
+      op => {
+        return Err(
+          IronyError::new(format!(
+            "`{}` can't be exported to a netlist, it must be lowered first",
+            op.get_op_name()
+          ))
+          .with_op(op_id),
+        );
+      },
+    }
+    Ok(())
+  }
+}
+
+fn module_json<E>(env: &E, module: OpId) -> IronyResult<Json>
+where E: Environ<EntityT = EntityEnum, OpT = OpEnum> {
+  let OpEnum::HwModule(HwModule {
+    is_extern,
+    top,
+    body,
+    arg_types,
+    output_types,
+    ..
+  }) = env.get_op(module)
+  else {
+    return Err(IronyError::new("must be a module").with_op(module));
+  };
+  let (args, results, _) = port_names(env, module, str::to_string)?;
+  let mut builder = ModuleBuilder {
+    env,
+    bits: HashMap::new(),
+    wiring: HashMap::new(),
+    resolving: HashSet::new(),
+    // nets 0 and 1 are reserved for constants
+    next_net: 2,
+    cells: vec![],
+  };
+  let port = |direction: &str, bits: &[Bit]| {
+    Json::obj([("direction", Json::Str(direction.into())), ("bits", bits_json(bits))])
+  };
+
+  if *is_extern == Some(BoolAttr(true)) {
+    let (Some(ArrayAttr(arg_types)), Some(ArrayAttr(output_types))) =
+      (arg_types, output_types)
+    else {
+      return Err(IronyError::new("extern module must have port types").with_op(module));
+    };
+    let mut ports = vec![];
+    for (names, types, direction) in
+      [(args, arg_types, "input"), (results, output_types, "output")]
+    {
+      for (name, dtype) in names.into_iter().zip(types) {
+        let AttributeEnum::TypeAttr(TypeAttr(dtype)) = dtype else {
+          return Err(IronyError::new("port types must be types").with_op(module));
+        };
+        let bits = builder.fresh(dtype.width());
+        ports.push((name, port(direction, &bits)));
+      }
+    }
+    return Ok(Json::obj([
+      ("attributes", Json::obj([("blackbox", param(1))])),
+      ("ports", Json::Obj(ports)),
+      ("cells", Json::obj::<String>([])),
+      ("netnames", Json::obj::<String>([])),
+    ]));
+  }
+
+  let Some(body) = body else {
+    return Err(IronyError::new("module must have a body").with_op(module));
+  };
+  let ops = env.get_region(*body).op_children.to_owned();
+  for op_id in ops.iter() {
+    let op = env.get_op(*op_id);
+    if is_wiring(op) {
+      for entity in op.get_defs().into_iter().flat_map(|(_, x)| x).flatten() {
+        builder.wiring.insert(entity, *op_id);
+      }
+    }
+  }
+  let find = |f: fn(&OpEnum) -> Option<Vec<Option<EntityId>>>| {
+    ops.iter().find_map(|x| f(env.get_op(*x))).unwrap_or_default()
+  };
+  let inputs = find(|x| match x {
+    OpEnum::HwInput(HwInput { inputs, .. }) => Some(inputs.to_owned()),
+    _ => None,
+  });
+  let outputs = find(|x| match x {
+    OpEnum::HwOutput(HwOutput { outputs, .. }) => Some(outputs.to_owned()),
+    _ => None,
+  });
+
+  let mut ports = vec![];
+  for (entity, name) in inputs.iter().zip(args) {
+    ports.push((name, port("input", &builder.bits(entity, module)?)));
+  }
+  for op_id in ops.iter() {
+    builder.op(*op_id)?;
+  }
+  for (entity, name) in outputs.iter().zip(results) {
+    ports.push((name, port("output", &builder.bits(entity, module)?)));
+  }
+
+  // nets are named after the entities carrying them
+  let mut netnames = vec![];
+  let mut named = HashSet::new();
+  for op_id in ops.iter() {
+    let op = env.get_op(*op_id);
+    for entity in op.get_defs().into_iter().flat_map(|(_, x)| x).flatten() {
+      let Some(name) = env.get_entity(entity).get_attr("name") else { continue };
+      if !named.insert(entity) {
+        continue;
+      }
+      let mut name = string_of(&name);
+      while netnames.iter().any(|(x, _)| *x == name) {
+        name = format!("{}_{}", name, entity.0);
+      }
+      let bits = builder.bits(&Some(entity), *op_id)?;
+      netnames.push((
+        name,
+        Json::obj([
+          ("hide_name", Json::Num(0)),
+          ("bits", bits_json(&bits)),
+          ("attributes", Json::obj::<String>([])),
+        ]),
+      ));
+    }
+  }
+
+  let attributes = match top {
+    Some(BoolAttr(true)) => Json::obj([("top", param(1))]),
+    _ => Json::obj::<String>([]),
+  };
+  Ok(Json::obj([
+    ("attributes", attributes),
+    ("ports", Json::Obj(ports)),
+    ("cells", Json::Obj(builder.cells)),
+    ("netnames", Json::Obj(netnames)),
+  ]))
+}
+
+/// Export every top-level module as a Yosys JSON netlist, with cells of the Yosys
+/// internal cell library and extern modules as blackboxes
+pub fn emit_yosys_json<E>(env: &E) -> IronyResult<String>
+where E: Environ<EntityT = EntityEnum, OpT = OpEnum> {
+  let mut modules = vec![];
+  for op_id in env.get_ops_with_parent(None) {
+    if let OpEnum::HwModule(HwModule { name: Some(StringAttr(name)), .. }) =
+      env.get_op(op_id)
+    {
+      modules.push((name.to_owned(), module_json(env, op_id)?));
+    }
+  }
+  let json =
+    Json::obj([("creator", Json::Str("cement".into())), ("modules", Json::Obj(modules))]);
+  Ok(format!("{}\n", json))
+}